@@ -0,0 +1,91 @@
+use std::{
+    fmt,
+    net::{SocketAddr, ToSocketAddrs},
+    path::PathBuf,
+    str::FromStr
+};
+
+use crate::{KvsError, Result};
+
+const UNIX_PREFIX: &str = "unix:";
+
+/// 服务端地址：TCP 地址或 Unix 域套接字路径
+///
+/// 字符串形式的 Unix 域套接字地址以 `unix:` 开头，如 `unix:/run/kvs.sock`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ServerAddr {
+    /// TCP 地址
+    Tcp(SocketAddr),
+    /// Unix 域套接字的路径
+    Unix(PathBuf)
+}
+
+/// 可以转换为一个或多个服务端地址的类型
+///
+/// 与 `ToSocketAddrs` 类似，字符串会被解析为 TCP 地址（支持主机名）或 Unix 域套接字路径
+pub trait ToServerAddrs {
+    /// 转换为服务端地址
+    fn to_server_addrs(&self) -> Result<Vec<ServerAddr>>;
+}
+
+impl FromStr for ServerAddr {
+    type Err = KvsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.to_server_addrs()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| KvsError::StringError(format!("Could not resolve address {}",s)))
+    }
+}
+
+impl fmt::Display for ServerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServerAddr::Tcp(addr) => write!(f, "{}", addr),
+            ServerAddr::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display())
+        }
+    }
+}
+
+impl From<SocketAddr> for ServerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        ServerAddr::Tcp(addr)
+    }
+}
+
+impl ToServerAddrs for ServerAddr {
+    fn to_server_addrs(&self) -> Result<Vec<ServerAddr>> {
+        Ok(vec![self.clone()])
+    }
+}
+
+impl ToServerAddrs for SocketAddr {
+    fn to_server_addrs(&self) -> Result<Vec<ServerAddr>> {
+        Ok(vec![ServerAddr::Tcp(*self)])
+    }
+}
+
+impl ToServerAddrs for str {
+    fn to_server_addrs(&self) -> Result<Vec<ServerAddr>> {
+        if let Some(path) = self.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                return Err(KvsError::StringError("Empty Unix socket path".to_owned()));
+            }
+            return Ok(vec![ServerAddr::Unix(PathBuf::from(path))]);
+        }
+        Ok(self.to_socket_addrs()?.map(ServerAddr::Tcp).collect())
+    }
+}
+
+impl ToServerAddrs for String {
+    fn to_server_addrs(&self) -> Result<Vec<ServerAddr>> {
+        self.as_str().to_server_addrs()
+    }
+}
+
+impl<T: ToServerAddrs + ?Sized> ToServerAddrs for &T {
+    fn to_server_addrs(&self) -> Result<Vec<ServerAddr>> {
+        (**self).to_server_addrs()
+    }
+}
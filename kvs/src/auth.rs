@@ -0,0 +1,192 @@
+//! 用户认证与按键前缀的访问控制
+//!
+//! 用户及其权限保存在服务端的 JSON 凭据文件中，密码（或令牌）只以 PBKDF2-HMAC-SHA256 哈希的形式保存：
+//!
+//! ```json
+//! {
+//!   "users": [
+//!     {
+//!       "name": "app",
+//!       "secret": "pbkdf2-sha256$100000$<salt>$<hash>",
+//!       "rules": [{ "prefix": "app:", "permissions": ["read", "write"] }]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! 规则的 `prefix` 为空时匹配所有键。一个键可以被多条规则匹配，用户拥有这些规则权限的并集。
+//! 没有 `secret` 的用户只能通过双向 TLS 中证书的 Common Name 登录
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    num::NonZeroU32,
+    path::Path,
+    sync::Arc
+};
+
+use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine};
+use ring::{
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom}
+};
+use serde::Deserialize;
+
+use crate::{KvsError, Result};
+
+const ALGORITHM: &str = "pbkdf2-sha256";
+const DEFAULT_ITERATIONS: u32 = 100_000;
+const SALT_LEN: usize = 16;
+const HASH_LEN: usize = 32;
+
+/// 访问权限
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// 读取键
+    Read,
+    /// 写入、删除键
+    Write,
+    /// 执行管理命令
+    Admin
+}
+
+/// 服务端的认证配置，设置后所有连接都必须先登录
+#[derive(Clone)]
+pub struct AuthConfig {
+    users: Arc<HashMap<String, Arc<User>>>,
+    // 用户不存在时也进行一次哈希计算，避免通过响应时间判断用户是否存在
+    dummy: Arc<SecretHash>
+}
+
+/// 已登录的用户
+pub(crate) struct User {
+    name: String,
+    secret: Option<SecretHash>,
+    rules: Vec<Rule>
+}
+
+#[derive(Deserialize)]
+struct CredentialFile {
+    users: Vec<UserEntry>
+}
+
+#[derive(Deserialize)]
+struct UserEntry {
+    name: String,
+    #[serde(default)]
+    secret: Option<String>,
+    #[serde(default)]
+    rules: Vec<Rule>
+}
+
+#[derive(Deserialize)]
+struct Rule {
+    #[serde(default)]
+    prefix: String,
+    permissions: Vec<Permission>
+}
+
+struct SecretHash {
+    iterations: NonZeroU32,
+    salt: Vec<u8>,
+    hash: Vec<u8>
+}
+
+impl AuthConfig {
+    /// 加载凭据文件
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let file: CredentialFile = serde_json::from_slice(&fs::read(path)?)?;
+        let mut users = HashMap::new();
+        for entry in file.users {
+            if users.contains_key(&entry.name) {
+                return Err(KvsError::StringError(format!("Duplicate user {}",entry.name)));
+            }
+            let secret = entry.secret.as_deref().map(SecretHash::parse).transpose()?;
+            let user = User { name: entry.name.clone(), secret, rules: entry.rules };
+            users.insert(entry.name, Arc::new(user));
+        }
+        Ok(AuthConfig {
+            users: Arc::new(users),
+            dummy: Arc::new(SecretHash::parse(&AuthConfig::hash_secret("")?)?)
+        })
+    }
+
+    /// 计算密码或令牌的哈希，用于写入凭据文件
+    pub fn hash_secret(secret: &str) -> Result<String> {
+        let mut salt = [0; SALT_LEN];
+        SystemRandom::new().fill(&mut salt)
+            .map_err(|_| KvsError::StringError("Failed to generate salt".to_owned()))?;
+        let iterations = NonZeroU32::new(DEFAULT_ITERATIONS).unwrap();
+        let mut hash = [0; HASH_LEN];
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &salt, secret.as_bytes(), &mut hash);
+        Ok(format!(
+            "{}${}${}${}",
+            ALGORITHM,
+            iterations,
+            STANDARD_NO_PAD.encode(salt),
+            STANDARD_NO_PAD.encode(hash)
+        ))
+    }
+
+    /// 校验用户名和密码，成功时返回对应的用户
+    ///
+    /// 用户不存在或只能用证书登录时同样计算一次哈希，使耗时不会暴露用户是否存在
+    pub(crate) fn authenticate(&self, name: &str, secret: &str) -> Option<Arc<User>> {
+        match self.users.get(name).and_then(|user| user.secret.as_ref().map(|hash| (user, hash))) {
+            Some((user, hash)) if hash.verify(secret) => Some(Arc::clone(user)),
+            Some(_) => None,
+            None => {
+                self.dummy.verify(secret);
+                None
+            }
+        }
+    }
+
+    /// 按用户名查找用户
+    pub(crate) fn user(&self, name: &str) -> Option<Arc<User>> {
+        self.users.get(name).cloned()
+    }
+
+    /// 按双向 TLS 中客户端证书的身份查找用户
+    pub(crate) fn identify(&self, identity: &str) -> Option<Arc<User>> {
+        self.users.get(identity).cloned()
+    }
+}
+
+impl fmt::Debug for AuthConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthConfig").field("users", &self.users.len()).finish()
+    }
+}
+
+impl User {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 是否拥有对 `key` 的 `permission` 权限
+    pub(crate) fn allows(&self, key: &str, permission: Permission) -> bool {
+        self.rules.iter().any(|rule| key.starts_with(&rule.prefix) && rule.permissions.contains(&permission))
+    }
+}
+
+impl SecretHash {
+    fn parse(s: &str) -> Result<Self> {
+        let invalid = || KvsError::StringError(format!("Invalid secret hash, expected {}$<iterations>$<salt>$<hash>",ALGORITHM));
+        let parts: Vec<&str> = s.split('$').collect();
+        if parts.len() != 4 || parts[0] != ALGORITHM {
+            return Err(invalid());
+        }
+        Ok(SecretHash {
+            iterations: parts[1].parse().map_err(|_| invalid())?,
+            salt: STANDARD_NO_PAD.decode(parts[2]).map_err(|_| invalid())?,
+            hash: STANDARD_NO_PAD.decode(parts[3]).map_err(|_| invalid())?
+        })
+    }
+
+    /// 以常量时间比较哈希
+    fn verify(&self, secret: &str) -> bool {
+        pbkdf2::verify(pbkdf2::PBKDF2_HMAC_SHA256, self.iterations, &self.salt, secret.as_bytes(), &self.hash).is_ok()
+    }
+}
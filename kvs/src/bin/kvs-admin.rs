@@ -0,0 +1,357 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    mem,
+    path::PathBuf,
+    process::exit
+};
+use kvs::*;
+use structopt::StructOpt;
+use structopt::clap::{AppSettings, arg_enum};
+
+const STORE_FORMAT: &str = "ENGINE:DIR";
+const ADDRESS_FORMAT: &str = "IP:PORT|unix:PATH";
+// 导出时每次读取的键数
+const EXPORT_PAGE_SIZE: usize = 1000;
+
+arg_enum! {
+    #[allow(non_camel_case_types)]
+    #[derive(Debug,Copy,Clone,PartialEq,Eq)]
+    enum Engine {
+        kvs,
+        sled
+    }
+}
+
+#[derive(StructOpt,Debug)]
+#[structopt(
+    name = "kvs-admin",
+    raw(global_settings = "&[\
+        AppSettings::DisableHelpSubcommand,\
+        AppSettings::VersionlessSubcommands]")
+)]
+struct Opt{
+    #[structopt(subcommand)]
+    command:Command
+}
+
+#[derive(Debug,StructOpt)]
+enum Command {
+    #[structopt(
+        name = "migrate",
+        about = "Copy all data of a stopped server to a new data directory of another engine and verify it"
+    )]
+    Migrate {
+        #[structopt(
+            long,
+            help = "Sets the engine and data directory to copy from",
+            raw(value_name = "STORE_FORMAT"),
+            parse(try_from_str = "parse_store")
+        )]
+        from: Store,
+        #[structopt(
+            long,
+            help = "Sets the engine and the new data directory, which must be empty or missing",
+            raw(value_name = "STORE_FORMAT"),
+            parse(try_from_str = "parse_store")
+        )]
+        to: Store,
+    },
+    #[structopt(name = "export", about = "Write all key/value pairs of a data directory or a running server as JSON Lines or CSV")]
+    Export {
+        #[structopt(
+            long,
+            help = "Reads from the engine and data directory of a stopped server",
+            raw(value_name = "STORE_FORMAT"),
+            parse(try_from_str = "parse_store"),
+            required_unless = "addr"
+        )]
+        from: Option<Store>,
+        #[structopt(flatten)]
+        server: ServerOpts,
+        #[structopt(long, help = "Only exports keys starting with this prefix", value_name = "PREFIX", default_value = "")]
+        prefix: String,
+        #[structopt(flatten)]
+        format: FormatOpt,
+        #[structopt(long, help = "Writes to this file instead of stdout", value_name = "FILE", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+    #[structopt(name = "import", about = "Write key/value pairs from JSON Lines or CSV into a data directory or a running server")]
+    Import {
+        #[structopt(
+            long,
+            help = "Writes to the engine and data directory of a stopped server, creating it if missing",
+            raw(value_name = "STORE_FORMAT"),
+            parse(try_from_str = "parse_store"),
+            required_unless = "addr"
+        )]
+        to: Option<Store>,
+        #[structopt(flatten)]
+        server: ServerOpts,
+        #[structopt(flatten)]
+        format: FormatOpt,
+        #[structopt(long, help = "Reads from this file instead of stdin", value_name = "FILE", parse(from_os_str))]
+        input: Option<PathBuf>,
+        #[structopt(
+            long,
+            help = "Sets whether existing keys are overwritten or kept",
+            value_name = "MODE",
+            default_value = "overwrite",
+            raw(possible_values = "&[\"overwrite\", \"skip-existing\"]"),
+            parse(try_from_str)
+        )]
+        mode: ImportMode,
+        #[structopt(long = "batch-size", help = "Sets the number of keys written per request", value_name = "N", default_value = "500")]
+        batch_size: usize,
+    },
+    #[structopt(name = "sync", about = "Make one running server's data match another's, transferring only the key ranges that differ")]
+    Sync {
+        #[structopt(long, help = "Sets the server to copy from", raw(value_name = "ADDRESS_FORMAT"), parse(try_from_str))]
+        from: ServerAddr,
+        #[structopt(long, help = "Sets the server to update, keys missing on the source are removed", raw(value_name = "ADDRESS_FORMAT"), parse(try_from_str))]
+        to: ServerAddr,
+        #[structopt(long, help = "Logs in to both servers as this user", value_name = "USER", requires = "password")]
+        user: Option<String>,
+        #[structopt(long, help = "Sets the password or token of the user", value_name = "PASSWORD", requires = "user")]
+        password: Option<String>,
+    },
+}
+
+/// 连接运行中的服务器的参数
+#[derive(Debug,StructOpt)]
+struct ServerOpts {
+    #[structopt(
+        long,
+        help = "Connects to a running server instead of opening a data directory",
+        raw(value_name = "ADDRESS_FORMAT"),
+        parse(try_from_str),
+        raw(conflicts_with_all = r#"&["from", "to"]"#)
+    )]
+    addr: Option<ServerAddr>,
+    #[structopt(long, help = "Logs in to the server as this user", value_name = "USER", requires = "password")]
+    user: Option<String>,
+    #[structopt(long, help = "Sets the password or token of the user", value_name = "PASSWORD", requires = "user")]
+    password: Option<String>,
+}
+
+#[derive(Debug,StructOpt)]
+struct FormatOpt {
+    #[structopt(
+        long,
+        help = "Sets the dump format",
+        value_name = "FORMAT",
+        default_value = "jsonl",
+        raw(possible_values = "&[\"jsonl\", \"csv\"]"),
+        parse(try_from_str)
+    )]
+    format: DumpFormat,
+}
+
+/// 引擎和数据目录
+#[derive(Debug)]
+struct Store {
+    engine: Engine,
+    dir: PathBuf
+}
+
+/// 解析 `ENGINE:DIR`
+fn parse_store(s: &str) -> Result<Store> {
+    let (engine, dir) = s.split_once(':')
+        .ok_or_else(|| KvsError::StringError(format!("Invalid store (expected {}): {}",STORE_FORMAT,s)))?;
+    let engine = engine.parse().map_err(KvsError::StringError)?;
+    Ok(Store { engine, dir: PathBuf::from(dir) })
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(opt) {
+        eprintln!("{}",e);
+        exit(1);
+    }
+}
+
+fn run(opt: Opt) -> Result<()> {
+    match opt.command {
+        Command::Migrate { from, to } => {
+            let stats = migrate_store(&from, &to)?;
+            println!("keys: {}",stats.keys);
+            println!("bytes: {}",stats.bytes);
+            println!("checksum: {}",stats.checksum);
+        }
+        Command::Export { from, server, prefix, format, output } => {
+            let mut dataset = open_dataset(from, &server, false)?;
+            let writer: Box<dyn Write> = match output {
+                Some(path) => Box::new(File::create(path)?),
+                None => Box::new(io::stdout().lock())
+            };
+            let keys = export(dataset.as_mut(), &prefix, DumpWriter::new(BufWriter::new(writer), format.format))?;
+            eprintln!("Exported {} keys",keys);
+        }
+        Command::Import { to, server, format, input, mode, batch_size } => {
+            let mut dataset = open_dataset(to, &server, true)?;
+            let reader: Box<dyn BufRead> = match input {
+                Some(path) => Box::new(BufReader::new(File::open(path)?)),
+                None => Box::new(io::stdin().lock())
+            };
+            let stats = import(dataset.as_mut(), DumpReader::new(reader, format.format), mode, batch_size.max(1))?;
+            println!("written: {}",stats.written);
+            println!("skipped: {}",stats.skipped);
+        }
+        Command::Sync { from, to, user, password } => {
+            let mut builder = KvsClientBuilder::new();
+            if let (Some(user), Some(password)) = (user, password) {
+                builder = builder.credentials(user, password);
+            }
+            let mut source = builder.clone().addr(from).build()?;
+            let mut target = builder.addr(to).build()?;
+            let stats = source.sync_to(&mut target)?;
+            println!("compared_nodes: {}",stats.compared_nodes);
+            println!("transferred_ranges: {}",stats.transferred_ranges);
+            println!("updated_keys: {}",stats.updated_keys);
+            println!("removed_keys: {}",stats.removed_keys);
+        }
+    }
+    Ok(())
+}
+
+/// 导出和导入的对象：停止的服务器的数据目录，或运行中的服务器
+trait Dataset {
+    fn export(&mut self, prefix: &str, after: Option<String>, limit: usize) -> Result<ExportPage>;
+    fn import(&mut self, entries: Vec<(String, String)>, mode: ImportMode) -> Result<ImportStats>;
+}
+
+impl Dataset for KvsClient {
+    fn export(&mut self, prefix: &str, after: Option<String>, limit: usize) -> Result<ExportPage> {
+        KvsClient::export(self, prefix.to_owned(), after, limit)
+    }
+
+    fn import(&mut self, entries: Vec<(String, String)>, mode: ImportMode) -> Result<ImportStats> {
+        KvsClient::import(self, entries, mode)
+    }
+}
+
+/// 直接打开的存储引擎
+struct Local<E: KvsEngine>(E);
+
+impl<E: KvsEngine> Dataset for Local<E> {
+    fn export(&mut self, prefix: &str, after: Option<String>, limit: usize) -> Result<ExportPage> {
+        let keys = self.0.scan(prefix, after.as_deref(), limit)?;
+        let next = if keys.len() >= limit { keys.last().cloned() } else { None };
+        let mut entries = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(value) = self.0.get(key.clone())? {
+                entries.push((key, value));
+            }
+        }
+        Ok(ExportPage { entries, next })
+    }
+
+    fn import(&mut self, entries: Vec<(String, String)>, mode: ImportMode) -> Result<ImportStats> {
+        let mut stats = ImportStats::default();
+        for (key, value) in entries {
+            if mode == ImportMode::SkipExisting && self.0.get(key.clone())?.is_some() {
+                stats.skipped += 1;
+                continue;
+            }
+            self.0.set(key, value)?;
+            stats.written += 1;
+        }
+        self.0.flush()?;
+        Ok(stats)
+    }
+}
+
+/// 打开数据目录或连接服务器，`create` 为真时数据目录不存在则新建
+fn open_dataset(store: Option<Store>, server: &ServerOpts, create: bool) -> Result<Box<dyn Dataset>> {
+    let store = match (store, &server.addr) {
+        (Some(store), _) => store,
+        (None, Some(addr)) => {
+            let mut builder = KvsClientBuilder::new().addr(addr.clone());
+            if let (Some(user), Some(password)) = (&server.user, &server.password) {
+                builder = builder.credentials(user.clone(), password.clone());
+            }
+            return Ok(Box::new(builder.build()?));
+        }
+        (None, None) => return Err(KvsError::StringError("Either a data directory or --addr is required".to_owned()))
+    };
+    if create {
+        Manifest::open(&store.dir, &store.engine.to_string())?;
+    } else {
+        load_manifest(&store)?;
+    }
+    Ok(match store.engine {
+        Engine::kvs if create => Box::new(Local(KvStore::open(&store.dir)?)),
+        Engine::kvs => Box::new(Local(KvStore::open_read_only(&store.dir)?)),
+        Engine::sled => Box::new(Local(SledKvsEngine::new(sled::open(&store.dir)?)))
+    })
+}
+
+/// 按键的字典序分页读取并写出以 `prefix` 开头的键值，返回导出的键数
+fn export<W: Write>(dataset: &mut dyn Dataset, prefix: &str, mut writer: DumpWriter<W>) -> Result<u64> {
+    let mut keys = 0;
+    let mut after = None;
+    loop {
+        let page = dataset.export(prefix, after, EXPORT_PAGE_SIZE)?;
+        for (key, value) in &page.entries {
+            writer.write(key, value)?;
+            keys += 1;
+        }
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break
+        }
+    }
+    writer.finish()?;
+    Ok(keys)
+}
+
+/// 每读取 `batch_size` 个键值写入一批
+fn import<R: BufRead>(
+    dataset: &mut dyn Dataset,
+    reader: DumpReader<R>,
+    mode: ImportMode,
+    batch_size: usize
+) -> Result<ImportStats> {
+    let mut stats = ImportStats::default();
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut entries = reader.peekable();
+    while let Some(entry) = entries.next() {
+        batch.push(entry?);
+        if batch.len() >= batch_size || entries.peek().is_none() {
+            let written = dataset.import(mem::take(&mut batch), mode)?;
+            stats.written += written.written;
+            stats.skipped += written.skipped;
+        }
+    }
+    Ok(stats)
+}
+
+/// 校验两个数据目录后迁移数据，成功后为新的数据目录写入清单
+fn migrate_store(from: &Store, to: &Store) -> Result<MigrationStats> {
+    let source = load_manifest(from)?;
+    if to.dir.exists() && fs::read_dir(&to.dir)?.next().is_some() {
+        return Err(KvsError::StringError(format!("Target directory {} is not empty",to.dir.display())));
+    }
+
+    let stats = match from.engine {
+        Engine::kvs => migrate_from(&KvStore::open_read_only(&from.dir)?, to)?,
+        Engine::sled => migrate_from(&SledKvsEngine::new(sled::open(&from.dir)?), to)?
+    };
+    source.migrated(&to.dir, &to.engine.to_string())?;
+    Ok(stats)
+}
+
+/// 只读取已有数据目录的清单并校验引擎和格式版本，不会新建或升级数据目录
+fn load_manifest(store: &Store) -> Result<Manifest> {
+    let manifest = Manifest::load(&store.dir)?
+        .ok_or_else(|| KvsError::StringError(format!("No data directory at {}",store.dir.display())))?;
+    manifest.verify(&store.engine.to_string())?;
+    Ok(manifest)
+}
+
+fn migrate_from<S: KvsEngine>(source: &S, to: &Store) -> Result<MigrationStats> {
+    match to.engine {
+        Engine::kvs => migrate(source, &KvStore::open(&to.dir)?),
+        Engine::sled => migrate(source, &SledKvsEngine::new(sled::open(&to.dir)?))
+    }
+}
@@ -1,10 +1,15 @@
-use std::{net::SocketAddr, process::exit};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::exit
+};
 use kvs::*;
 use structopt::StructOpt;
 use structopt::clap::AppSettings;
 
 const DEFAULT_LISTENING_ADDRESS: &str = "127.0.0.1:4000";
-const ADDRESS_FORMAT: &str = "IP:PORT";
+const ADDRESS_FORMAT: &str = "IP:PORT|unix:PATH";
 
 #[derive(StructOpt,Debug)]
 #[structopt(
@@ -24,14 +29,8 @@ enum Command {
     Get {
         #[structopt(name = "KEY", help = "A string key")]
         key: String,
-        #[structopt(
-            long,
-            help = "Sets the server address",
-            raw(value_name = "ADDRESS_FORMAT"),
-            raw(default_value = "DEFAULT_LISTENING_ADDRESS"),
-            parse(try_from_str)
-        )]
-        addr: SocketAddr,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
     },
     #[structopt(name = "set", about = "Set the value of a string key to a string")]
     Set {
@@ -39,30 +38,172 @@ enum Command {
         key: String,
         #[structopt(name = "VALUE", help = "The string value of the key")]
         value: String,
-        #[structopt(
-            long,
-            help = "Sets the server address",
-            raw(value_name = "ADDRESS_FORMAT"),
-            raw(default_value = "DEFAULT_LISTENING_ADDRESS"),
-            parse(try_from_str)
-        )]
-        addr: SocketAddr,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
     },
     #[structopt(name = "rm", about = "Remove a given string key")]
     Remove {
         #[structopt(name = "KEY", help = "A string key")]
         key: String,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "rebalance", about = "Move keys to the shard nodes that own them after adding or removing nodes")]
+    Rebalance {
+        #[structopt(
+            long,
+            help = "Sets the shard nodes, comma separated",
+            raw(value_name = "ADDRESS_FORMAT"),
+            raw(use_delimiter = "true", required = "true"),
+            parse(try_from_str)
+        )]
+        nodes: Vec<ServerAddr>,
         #[structopt(
             long,
-            help = "Sets the server address",
+            help = "Sets the removed nodes that still hold keys, comma separated",
             raw(value_name = "ADDRESS_FORMAT"),
-            raw(default_value = "DEFAULT_LISTENING_ADDRESS"),
+            raw(use_delimiter = "true"),
             parse(try_from_str)
         )]
-        addr: SocketAddr,
+        removed: Vec<ServerAddr>,
+        #[structopt(long, help = "Logs in to every node as this user", value_name = "USER", requires = "password")]
+        user: Option<String>,
+        #[structopt(long, help = "Sets the password or token of the user", value_name = "PASSWORD", requires = "user")]
+        password: Option<String>,
+    },
+    #[structopt(name = "backup", about = "Stream a consistent backup of the server's data into a file")]
+    Backup {
+        #[structopt(long, help = "Writes the backup to this file, restore it with kvs-server --restore", value_name = "FILE", parse(from_os_str))]
+        out: PathBuf,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "admin", about = "Inspect and manage a running server")]
+    Admin {
+        #[structopt(subcommand)]
+        command: AdminCommand,
     },
 }
 
+#[derive(Debug,StructOpt)]
+enum AdminCommand {
+    #[structopt(name = "info", about = "Show the server version, engine, uptime, connections, replication and cluster status")]
+    Info {
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "stats", about = "Show the key count and disk usage of the storage engine")]
+    Stats {
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "compact", about = "Compact the storage engine now")]
+    Compact {
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "flush", about = "Flush buffered writes and fsync them to disk")]
+    Flush {
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "promote", about = "Promote a replica to leader, it stops following and accepts writes")]
+    Promote {
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "slowlog", about = "Show the slowest recent requests, newest first")]
+    Slowlog {
+        #[structopt(long, help = "Sets the number of entries to show", value_name = "N")]
+        count: Option<usize>,
+        #[structopt(long, help = "Clears the slow log instead of showing it", conflicts_with = "count")]
+        reset: bool,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(
+        name = "config",
+        about = "Read or change runtime settings (compaction-threshold, log-level, slowlog-threshold, slowlog-max-len)"
+    )]
+    Config {
+        #[structopt(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Debug,StructOpt)]
+enum ConfigCommand {
+    #[structopt(name = "get", about = "Print the value of a setting")]
+    Get {
+        #[structopt(name = "NAME", help = "The setting name")]
+        name: String,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+    #[structopt(name = "set", about = "Change a setting until the server restarts")]
+    Set {
+        #[structopt(name = "NAME", help = "The setting name")]
+        name: String,
+        #[structopt(name = "VALUE", help = "The new value")]
+        value: String,
+        #[structopt(flatten)]
+        conn: ConnectOpts,
+    },
+}
+
+/// 各个子命令共用的连接参数
+#[derive(Debug,StructOpt)]
+struct ConnectOpts {
+    #[structopt(
+        long,
+        help = "Sets the server address",
+        raw(value_name = "ADDRESS_FORMAT"),
+        raw(default_value = "DEFAULT_LISTENING_ADDRESS"),
+        parse(try_from_str)
+    )]
+    addr: ServerAddr,
+    #[structopt(long = "tls-ca", help = "Connects over TLS, trusting only this CA certificate", value_name = "FILE", parse(from_os_str))]
+    tls_ca: Option<PathBuf>,
+    #[structopt(long = "tls-server-name", help = "Sets the name to verify the server certificate against (defaults to the address IP)", value_name = "NAME")]
+    tls_server_name: Option<String>,
+    #[structopt(long = "tls-cert", help = "Sets the client certificate for mutual TLS", value_name = "FILE", parse(from_os_str), requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    #[structopt(long = "tls-key", help = "Sets the private key of the client certificate", value_name = "FILE", parse(from_os_str), requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    #[structopt(long, help = "Logs in as this user", value_name = "USER", requires = "password")]
+    user: Option<String>,
+    #[structopt(long, help = "Sets the password or token of the user", value_name = "PASSWORD", requires = "user")]
+    password: Option<String>,
+    #[structopt(long = "request-id", help = "Tags the request with this id, which appears in the server logs", value_name = "ID")]
+    request_id: Option<String>,
+}
+
+impl ConnectOpts {
+    fn connect(&self) -> Result<KvsClient> {
+        let mut builder = KvsClientBuilder::new().addr(self.addr.clone());
+        if let Some(ca) = &self.tls_ca {
+            let server_name = self.tls_server_name.clone()
+                .unwrap_or_else(|| match &self.addr {
+                    ServerAddr::Tcp(addr) => addr.ip().to_string(),
+                    ServerAddr::Unix(_) => "localhost".to_owned()
+                });
+            let mut tls = TlsClientConfig::from_pem_file(ca, &server_name)?;
+            if let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) {
+                tls = tls.with_client_cert(cert, key)?;
+            }
+            builder = builder.tls(tls);
+        }
+        if let (Some(user), Some(password)) = (&self.user, &self.password) {
+            builder = builder.credentials(user.clone(), password.clone());
+        }
+        let mut client = builder.build()?;
+        if let Some(id) = &self.request_id {
+            client.with_request_id(id.clone());
+        }
+        Ok(client)
+    }
+}
+
 fn main() {
     let opt = Opt::from_args();
     if let Err(e) = run(opt) {
@@ -73,22 +214,141 @@ fn main() {
 
 fn run(opt: Opt) -> Result<()> {
     match opt.command {
-        Command::Get { key, addr } => {
-            let mut client = KvsClient::connect(addr)?;
+        Command::Get { key, conn } => {
+            let mut client = conn.connect()?;
             if let Some(value) = client.get(key)? {
                 println!("{}",value);
             } else {
                 println!("Key not found")
             }
         }
-        Command::Set { key, value, addr } => {
-            let mut client = KvsClient::connect(addr)?;
+        Command::Set { key, value, conn } => {
+            let mut client = conn.connect()?;
             client.set(key, value)?;
         }
-        Command::Remove { key, addr } => {
-            let mut client = KvsClient::connect(addr)?;
+        Command::Remove { key, conn } => {
+            let mut client = conn.connect()?;
             client.remove(key)?;
         }
+        Command::Rebalance { nodes, removed, user, password } => {
+            let mut builder = KvsClientBuilder::new();
+            if let (Some(user), Some(password)) = (user, password) {
+                builder = builder.credentials(user, password);
+            }
+            let moved = ShardedKvsClient::with_builder(builder, nodes)?.rebalance(removed)?;
+            println!("Moved {} keys",moved);
+        }
+        Command::Backup { out, conn } => {
+            let header = backup(&mut conn.connect()?, &out)?;
+            println!("engine: {}",header.engine);
+            println!("bytes: {}",header.len());
+            println!("checksum: {}",header.checksum);
+        }
+        Command::Admin { command } => run_admin(command)?
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// 备份失败时删除不完整的文件
+fn backup(client: &mut KvsClient, out: &Path) -> Result<BackupHeader> {
+    let mut writer = BufWriter::new(File::create(out)?);
+    let res = client.backup(&mut writer).and_then(|header| {
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(header)
+    });
+    if res.is_err() {
+        let _ = fs::remove_file(out);
+    }
+    res
+}
+
+fn run_admin(command: AdminCommand) -> Result<()> {
+    match command {
+        AdminCommand::Info { conn } => {
+            let info = conn.connect()?.info()?;
+            println!("version: {}",info.version);
+            println!("engine: {}",info.engine);
+            println!("uptime_seconds: {}",info.uptime_seconds);
+            println!("connected_clients: {}",info.connected_clients);
+            println!("total_connections: {}",info.total_connections);
+            println!("total_commands: {}",info.total_commands);
+            let replication = info.replication;
+            println!("role: {}",replication.role);
+            println!("replication_id: {}",replication.replication_id);
+            println!("seq: {}",replication.seq);
+            if let Some(leader) = replication.leader {
+                println!("leader: {}",leader);
+            }
+            let optional = [
+                ("link_up", replication.link_up.map(|up| up.to_string())),
+                ("leader_seq", replication.leader_seq.map(|seq| seq.to_string())),
+                ("lag", replication.lag.map(|lag| lag.to_string())),
+                ("last_sync_millis", replication.last_sync_millis.map(|millis| millis.to_string()))
+            ];
+            for (name, value) in optional {
+                if let Some(value) = value {
+                    println!("{}: {}",name,value);
+                }
+            }
+            if let Some(cluster) = info.cluster {
+                println!("cluster_node_id: {}",cluster.node_id);
+                println!("cluster_role: {}",cluster.role);
+                println!("cluster_term: {}",cluster.term);
+                if let Some(leader) = cluster.leader {
+                    println!("cluster_leader: {}",leader);
+                }
+                println!("cluster_last_index: {}",cluster.last_index);
+                println!("cluster_commit_index: {}",cluster.commit_index);
+                println!("cluster_last_applied: {}",cluster.last_applied);
+            }
+        }
+        AdminCommand::Stats { conn } => {
+            let stats = conn.connect()?.engine_stats()?;
+            println!("keys: {}",stats.keys);
+            println!("disk_bytes: {}",stats.disk_bytes);
+            let optional = [
+                ("live_bytes", stats.live_bytes),
+                ("dead_bytes", stats.dead_bytes),
+                ("segments", stats.segments)
+            ];
+            for (name, value) in optional {
+                if let Some(value) = value {
+                    println!("{}: {}",name,value);
+                }
+            }
+        }
+        AdminCommand::Compact { conn } => conn.connect()?.compact()?,
+        AdminCommand::Flush { conn } => conn.connect()?.flush()?,
+        AdminCommand::Promote { conn } => conn.connect()?.promote()?,
+        AdminCommand::Slowlog { reset: true, conn, .. } => conn.connect()?.slowlog_reset()?,
+        AdminCommand::Slowlog { count, conn, .. } => {
+            for entry in conn.connect()?.slowlog(count)? {
+                let mut extra = String::new();
+                if entry.compacted {
+                    extra.push_str(" compacted");
+                }
+                if entry.synced {
+                    extra.push_str(" synced");
+                }
+                println!(
+                    "{} {} {}us {} {} {} {}{}",
+                    entry.id,
+                    entry.timestamp,
+                    entry.duration_micros,
+                    entry.client,
+                    entry.protocol,
+                    entry.command,
+                    entry.key.as_deref().unwrap_or("-"),
+                    extra
+                );
+            }
+        }
+        AdminCommand::Config { command: ConfigCommand::Get { name, conn } } => {
+            println!("{}",conn.connect()?.config_get(name)?);
+        }
+        AdminCommand::Config { command: ConfigCommand::Set { name, value, conn } } => {
+            conn.connect()?.config_set(name, value)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,161 @@
+#[macro_use]
+extern crate log;
+
+use kvs::{*, thread_pool::*};
+use std::{
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::exit,
+    thread,
+    time::Duration
+};
+
+use signal_hook::{consts::{SIGINT, SIGTERM}, iterator::Signals};
+use structopt::{StructOpt, clap::arg_enum};
+
+const DEFAULT_LISTENING_ADDRESS: &str = "127.0.0.1:4000";
+const DEFAULT_POOL_SIZE: &str = "16";
+const DEFAULT_BACKEND_TIMEOUT: &str = "5";
+
+arg_enum! {
+    #[allow(non_camel_case_types)]
+    #[derive(Debug,Copy,Clone,PartialEq,Eq)]
+    enum ProtocolName {
+        native,
+        resp,
+        auto,
+        http
+    }
+}
+
+#[derive(StructOpt,Debug)]
+#[structopt(name = "kvs-proxy")]
+struct Opt {
+    #[structopt(
+        long,
+        help = "Sets the listening address, unix:PATH listens on a Unix socket",
+        value_name = "IP:PORT",
+        raw(default_value = "DEFAULT_LISTENING_ADDRESS"),
+        parse(try_from_str)
+    )]
+    addr: ServerAddr,
+    #[structopt(
+        long,
+        help = "Sets the client protocol, auto accepts both on the same port",
+        value_name = "PROTOCOL",
+        default_value = "native",
+        raw(possible_values = "&ProtocolName::variants()")
+    )]
+    protocol: ProtocolName,
+    #[structopt(
+        long,
+        help = "Routes keys to these backends by consistent hash, comma separated",
+        value_name = "ADDRESS,...",
+        raw(use_delimiter = "true", required_unless = "\"ranges\"", conflicts_with = "\"ranges\""),
+        parse(try_from_str)
+    )]
+    backends: Vec<ServerAddr>,
+    #[structopt(
+        long,
+        help = "Routes keys by range instead, each backend serves keys from START up to the next range",
+        value_name = "START=ADDRESS,...",
+        raw(use_delimiter = "true"),
+        parse(try_from_str = "parse_range")
+    )]
+    ranges: Vec<KeyRange>,
+    #[structopt(long = "backend-user", help = "Logs in to the backends as this user", value_name = "USER", requires = "backend_password")]
+    backend_user: Option<String>,
+    #[structopt(long = "backend-password", help = "Sets the password or token of the backend user", value_name = "PASSWORD", requires = "backend_user")]
+    backend_password: Option<String>,
+    #[structopt(
+        long = "backend-timeout",
+        help = "Fails requests to a backend that does not respond within this many seconds",
+        value_name = "SECONDS",
+        raw(default_value = "DEFAULT_BACKEND_TIMEOUT")
+    )]
+    backend_timeout: u64,
+    #[structopt(
+        long = "pool-size",
+        help = "Sets the maximum number of connections to each backend",
+        value_name = "N",
+        raw(default_value = "DEFAULT_POOL_SIZE")
+    )]
+    pool_size: usize,
+    #[structopt(long = "auth-file", help = "Requires clients to log in with the users of this credential file", value_name = "FILE", parse(from_os_str))]
+    auth_file: Option<PathBuf>
+}
+
+/// 一个键范围的起始键和负责的后端
+#[derive(Debug)]
+struct KeyRange(String, ServerAddr);
+
+/// 解析 `START=ADDRESS`，起始键可以为空，地址中不含 `=`
+fn parse_range(s: &str) -> Result<KeyRange> {
+    let (start, addr) = s.rsplit_once('=')
+        .ok_or_else(|| KvsError::StringError(format!("Invalid key range (expected START=ADDRESS): {}",s)))?;
+    Ok(KeyRange(start.to_owned(), addr.parse()?))
+}
+
+fn main() {
+    let opt = Opt::from_args();
+    tracing_subscriber::fmt()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+    if let Err(e) = run(opt) {
+        error!("{}",e);
+        exit(1);
+    }
+}
+
+fn run(opt: Opt) -> Result<()> {
+    info!("kvs-proxy {}",env!("CARGO_PKG_VERSION"));
+    let routing = if opt.ranges.is_empty() {
+        info!("Routing by consistent hash to {} backends",opt.backends.len());
+        ProxyRouting::Hash(opt.backends)
+    } else {
+        info!("Routing by key range to {} ranges",opt.ranges.len());
+        ProxyRouting::Ranges(opt.ranges.into_iter().map(|KeyRange(start, addr)| (start, addr)).collect())
+    };
+    let timeout = Duration::from_secs(opt.backend_timeout);
+    let mut builder = KvsClientBuilder::new()
+        .connect_timeout(timeout)
+        .read_timeout(timeout)
+        .write_timeout(timeout);
+    if let (Some(user), Some(password)) = (opt.backend_user, opt.backend_password) {
+        builder = builder.credentials(user, password);
+    }
+    let engine = ProxyEngine::new(routing, builder, opt.pool_size)?;
+
+    let mut config = ServerConfig {
+        protocol: match opt.protocol {
+            ProtocolName::native => Protocol::Native,
+            ProtocolName::resp => Protocol::Resp,
+            ProtocolName::auto => Protocol::Auto,
+            ProtocolName::http => Protocol::Http
+        },
+        ..ServerConfig::default()
+    };
+    if let Some(path) = &opt.auth_file {
+        config.auth = Some(AuthConfig::from_file(path)?);
+        info!("Authentication enabled with credentials from {}",path.display());
+    }
+    info!("Listening on {}",opt.addr);
+    let server = KvsServer::new(engine, RayonThreadPool::new(num_cpus::get() as u32)?).with_config(config);
+    handle_signals(server.shutdown_handle())?;
+    server.run(opt.addr)
+}
+
+/// 收到 SIGINT/SIGTERM 时优雅地关闭代理
+fn handle_signals(handle: ShutdownHandle) -> Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    thread::Builder::new()
+        .name("signal".to_owned())
+        .spawn(move || {
+            if let Some(signal) = signals.forever().next() {
+                info!("Received signal {}, shutting down",signal);
+                handle.shutdown();
+            }
+        })?;
+    Ok(())
+}
@@ -2,17 +2,33 @@
 extern crate log;
 
 use kvs::{*, thread_pool::*};
+use log::LevelFilter;
+use serde::Deserialize;
 use std::{
     net::SocketAddr, 
     env::current_dir, 
+    fmt::Display,
     fs, 
-    process::exit
+    io::{self, BufRead, IsTerminal},
+    path::{Path, PathBuf},
+    process::exit,
+    str::FromStr,
+    thread,
+    time::Duration
 };
 
+use signal_hook::{consts::{SIGHUP, SIGINT, SIGTERM}, iterator::Signals};
 use structopt::{StructOpt, clap::arg_enum};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 const DEFAULT_LISTENING_ADDRESS: &str = "127.0.0.1:4000";
 const DEFAULT_ENGINE: Engine = Engine::kvs;
+const DEFAULT_PROTOCOL: ProtocolName = ProtocolName::native;
+const DEFAULT_POOL: PoolKind = PoolKind::Rayon;
+const DEFAULT_LOG_FORMAT: LogFormat = LogFormat::text;
+const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Info;
+// 从节点连接主节点的超时时间
+const REPLICA_TIMEOUT: Duration = Duration::from_secs(10);
 
 arg_enum! {
     #[allow(non_camel_case_types)]
@@ -23,78 +39,610 @@ arg_enum! {
     }
 }
 
-#[derive(StructOpt,Debug)]
+arg_enum! {
+    #[allow(non_camel_case_types)]
+    #[derive(Debug,Copy,Clone,PartialEq,Eq)]
+    enum ProtocolName {
+        native,
+        resp,
+        auto,
+        http
+    }
+}
+
+arg_enum! {
+    #[allow(non_camel_case_types)]
+    #[derive(Debug,Copy,Clone,PartialEq,Eq)]
+    enum LogFormat {
+        text,
+        json
+    }
+}
+
+// 命令行参数，未指定的参数使用配置文件中的值，两者都没有时使用默认值
+#[derive(StructOpt,Debug,Clone)]
 #[structopt(name = "kvs-server")]
 struct Opt {
     #[structopt(
         long,
-        help = "Sets the listening address",
+        help = "Reads settings from this TOML file, command line flags take precedence; SIGHUP reloads it",
+        value_name = "FILE",
+        parse(from_os_str)
+    )]
+    config: Option<PathBuf>,
+    #[structopt(
+        long,
+        help = "Sets the listening address, unix:PATH listens on a Unix socket [default: 127.0.0.1:4000]",
         value_name = "IP:PORT",
-        raw(default_value = "DEFAULT_LISTENING_ADDRESS"),
         parse(try_from_str)
     )]
-    addr: SocketAddr,
+    addr: Option<ServerAddr>,
+    #[structopt(long = "unix-socket-mode", help = "Sets the permissions of the Unix socket file", value_name = "OCTAL-MODE", parse(try_from_str = "parse_mode"))]
+    unix_socket_mode: Option<u32>,
     #[structopt(
         long,
         help = "Sets the storage engine",
         value_name = "ENGINE-NAME",
         raw(possible_values = "&Engine::variants()") 
     )]
-    engine: Option<Engine>
+    engine: Option<Engine>,
+    #[structopt(long = "data-dir", help = "Stores data in this directory [default: the current directory]", value_name = "DIR", parse(from_os_str))]
+    data_dir: Option<PathBuf>,
+    #[structopt(
+        long = "compaction-threshold",
+        help = "Compacts the log after this many bytes of stale data (kvs engine only)",
+        value_name = "BYTES"
+    )]
+    compaction_threshold: Option<u64>,
+    #[structopt(
+        long,
+        help = "Sets the thread pool executing requests: naive, shared_queue or rayon [default: rayon]",
+        value_name = "POOL",
+        parse(try_from_str)
+    )]
+    pool: Option<PoolKind>,
+    #[structopt(long, help = "Sets the number of threads of the pool [default: the number of CPUs]", value_name = "N")]
+    threads: Option<u32>,
+    #[structopt(
+        long,
+        help = "Sets the client protocol, auto accepts both on the same port [default: native]",
+        value_name = "PROTOCOL",
+        raw(possible_values = "&ProtocolName::variants()")
+    )]
+    protocol: Option<ProtocolName>,
+    #[structopt(long = "http-addr", help = "Also serves the HTTP/JSON gateway on this address", value_name = "IP:PORT", parse(try_from_str))]
+    http_addr: Option<SocketAddr>,
+    #[structopt(long = "metrics-addr", help = "Serves Prometheus metrics at /metrics on this address", value_name = "IP:PORT", parse(try_from_str))]
+    metrics_addr: Option<SocketAddr>,
+    #[structopt(long = "max-connections", help = "Sets the maximum number of concurrent connections", value_name = "N")]
+    max_connections: Option<usize>,
+    #[structopt(long = "idle-timeout", help = "Closes connections idle for this many seconds (0 disables)", value_name = "SECONDS")]
+    idle_timeout: Option<u64>,
+    #[structopt(long = "request-timeout", help = "Closes connections that take longer than this many seconds to send a request (0 disables)", value_name = "SECONDS")]
+    request_timeout: Option<u64>,
+    #[structopt(long = "max-request-size", help = "Sets the maximum request size in bytes", value_name = "BYTES")]
+    max_request_size: Option<usize>,
+    #[structopt(long = "rate-limit", help = "Limits the requests per second of each client IP", value_name = "N")]
+    rate_limit: Option<u32>,
+    #[structopt(
+        long = "slowlog-threshold",
+        help = "Records requests taking at least this many microseconds in the slow log (off disables)",
+        value_name = "MICROS"
+    )]
+    slowlog_threshold: Option<String>,
+    #[structopt(long = "slowlog-max-len", help = "Sets the number of entries kept in the slow log", value_name = "N")]
+    slowlog_max_len: Option<usize>,
+    #[structopt(long = "tls-cert", help = "Serves TLS with this PEM certificate chain", value_name = "FILE", parse(from_os_str), requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    #[structopt(long = "tls-key", help = "Sets the PEM private key of the TLS certificate", value_name = "FILE", parse(from_os_str), requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+    #[structopt(long = "tls-client-ca", help = "Requires client certificates signed by this CA (mutual TLS)", value_name = "FILE", parse(from_os_str), requires = "tls_cert")]
+    tls_client_ca: Option<PathBuf>,
+    #[structopt(
+        long = "replica-of",
+        help = "Runs as a read-only replica of this leader until promoted",
+        value_name = "ADDRESS",
+        parse(try_from_str)
+    )]
+    replica_of: Option<ServerAddr>,
+    #[structopt(long = "replica-user", help = "Logs in to the leader or the other cluster nodes as this user", value_name = "USER", requires = "replica_password")]
+    replica_user: Option<String>,
+    #[structopt(long = "replica-password", help = "Sets the password or token of the replica user", value_name = "PASSWORD", requires = "replica_user")]
+    replica_password: Option<String>,
+    #[structopt(long = "replication-backlog", help = "Sets the number of write records kept in memory for replicas", value_name = "N")]
+    replication_backlog: Option<usize>,
+    #[structopt(long = "node-id", help = "Runs as this node of a Raft cluster", value_name = "ID", requires = "peers")]
+    node_id: Option<u64>,
+    #[structopt(
+        long,
+        help = "Sets the other nodes of the cluster",
+        value_name = "ID=ADDRESS,...",
+        parse(try_from_str = "parse_peers"),
+        requires = "node_id"
+    )]
+    peers: Option<Peers>,
+    #[structopt(long = "election-timeout", help = "Sets the Raft election timeout in milliseconds", value_name = "MILLIS", requires = "node_id")]
+    election_timeout: Option<u64>,
+    #[structopt(long = "auth-file", help = "Requires clients to log in with the users of this credential file", value_name = "FILE", parse(from_os_str))]
+    auth_file: Option<PathBuf>,
+    #[structopt(long = "hash-password", help = "Reads a password from stdin, prints its hash for the credential file and exits")]
+    hash_password: bool,
+    #[structopt(long, help = "Restores this backup into the empty data directory before starting", value_name = "FILE", parse(from_os_str))]
+    restore: Option<PathBuf>,
+    #[structopt(
+        long = "log-format",
+        help = "Sets the log format, json writes one object per line with the active spans [default: text]",
+        value_name = "FORMAT",
+        raw(possible_values = "&LogFormat::variants()")
+    )]
+    log_format: Option<LogFormat>,
+    #[structopt(
+        long = "log-level",
+        help = "Sets the log level: off, error, warn, info, debug or trace [default: info]",
+        value_name = "LEVEL",
+        parse(try_from_str)
+    )]
+    log_level: Option<LevelFilter>
 }
 
-fn main() {
-    env_logger::builder().filter_level(log::LevelFilter::Info).init();
+/// 配置文件的内容，所有设置都是可选的
+///
+/// ```toml
+/// [server]
+/// addr = "127.0.0.1:4000"
+/// protocol = "native"
+/// data_dir = "/var/lib/kvs"
+/// log_level = "info"
+///
+/// [engine]
+/// name = "kvs"
+/// compaction_threshold = 1048576
+///
+/// [pool]
+/// kind = "rayon"
+/// threads = 8
+///
+/// [network]
+/// max_connections = 10000
+/// idle_timeout = 300
+/// rate_limit = 1000
+/// ```
+///
+/// 其余设置与同名的命令行参数对应，见 `ServerSection` 等结构体的字段
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    server: ServerSection,
+    engine: EngineSection,
+    pool: PoolSection,
+    network: NetworkSection,
+    tls: TlsSection,
+    auth: AuthSection,
+    replication: ReplicationSection,
+    cluster: ClusterSection
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSection {
+    addr: Option<String>,
+    protocol: Option<String>,
+    http_addr: Option<SocketAddr>,
+    metrics_addr: Option<SocketAddr>,
+    // 八进制的字符串，如 "660"
+    unix_socket_mode: Option<String>,
+    data_dir: Option<PathBuf>,
+    log_format: Option<String>,
+    log_level: Option<String>
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct EngineSection {
+    name: Option<String>,
+    compaction_threshold: Option<u64>
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct PoolSection {
+    kind: Option<String>,
+    threads: Option<u32>
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct NetworkSection {
+    max_connections: Option<usize>,
+    idle_timeout: Option<u64>,
+    request_timeout: Option<u64>,
+    max_request_size: Option<usize>,
+    rate_limit: Option<u32>,
+    slowlog_threshold: Option<SlowlogThreshold>,
+    slowlog_max_len: Option<usize>
+}
 
-    let mut opt = Opt::from_args();
-    let res = current_engine().and_then(move |curr_engine|{
-        if opt.engine.is_none() {
-            opt.engine = curr_engine;
+/// 慢日志的阈值可以是微秒数，也可以是 "off"
+#[derive(Deserialize,Debug)]
+#[serde(untagged)]
+enum SlowlogThreshold {
+    Micros(u64),
+    Named(String)
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct TlsSection {
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    client_ca: Option<PathBuf>
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct AuthSection {
+    file: Option<PathBuf>
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct ReplicationSection {
+    replica_of: Option<String>,
+    user: Option<String>,
+    password: Option<String>,
+    backlog: Option<usize>
+}
+
+#[derive(Deserialize,Debug,Default)]
+#[serde(default, deny_unknown_fields)]
+struct ClusterSection {
+    node_id: Option<u64>,
+    // "ID=ADDRESS" 的列表
+    peers: Option<Vec<String>>,
+    election_timeout: Option<u64>
+}
+
+impl Opt {
+    /// 读取 `--config` 指定的配置文件，用其中的值补全命令行中没有指定的参数
+    fn load(&self) -> Result<Opt> {
+        let mut opt = self.clone();
+        let path = match &self.config {
+            Some(path) => path,
+            None => return Ok(opt)
+        };
+        let content = fs::read_to_string(path)?;
+        let file: ConfigFile = toml::from_str(&content)
+            .map_err(|e| KvsError::StringError(format!("Invalid config file {}: {}",path.display(),e)))?;
+
+        let ServerSection { addr, protocol, http_addr, metrics_addr, unix_socket_mode, data_dir, log_format, log_level } = file.server;
+        fill(&mut opt.addr, parse_setting("server.addr", addr)?);
+        fill(&mut opt.protocol, parse_setting("server.protocol", protocol)?);
+        fill(&mut opt.http_addr, http_addr);
+        fill(&mut opt.metrics_addr, metrics_addr);
+        if let Some(mode) = unix_socket_mode {
+            fill(&mut opt.unix_socket_mode, Some(parse_mode(&mode).map_err(|e| invalid_setting("server.unix_socket_mode", e))?));
         }
-        if curr_engine.is_some() && opt.engine != curr_engine {
-            error!("Wrong engine!");
+        // 配置文件中的相对路径相对于配置文件所在的目录
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        fill(&mut opt.data_dir, data_dir.map(|dir| base.join(dir)));
+        fill(&mut opt.log_format, parse_setting("server.log_format", log_format)?);
+        fill(&mut opt.log_level, parse_setting("server.log_level", log_level)?);
+
+        fill(&mut opt.engine, parse_setting("engine.name", file.engine.name)?);
+        fill(&mut opt.compaction_threshold, file.engine.compaction_threshold);
+        fill(&mut opt.pool, parse_setting("pool.kind", file.pool.kind)?);
+        fill(&mut opt.threads, file.pool.threads);
+
+        let network = file.network;
+        fill(&mut opt.max_connections, network.max_connections);
+        fill(&mut opt.idle_timeout, network.idle_timeout);
+        fill(&mut opt.request_timeout, network.request_timeout);
+        fill(&mut opt.max_request_size, network.max_request_size);
+        fill(&mut opt.rate_limit, network.rate_limit);
+        fill(&mut opt.slowlog_threshold, network.slowlog_threshold.map(|threshold| match threshold {
+            SlowlogThreshold::Micros(micros) => micros.to_string(),
+            SlowlogThreshold::Named(name) => name
+        }));
+        fill(&mut opt.slowlog_max_len, network.slowlog_max_len);
+
+        fill(&mut opt.tls_cert, file.tls.cert.map(|cert| base.join(cert)));
+        fill(&mut opt.tls_key, file.tls.key.map(|key| base.join(key)));
+        fill(&mut opt.tls_client_ca, file.tls.client_ca.map(|ca| base.join(ca)));
+        fill(&mut opt.auth_file, file.auth.file.map(|file| base.join(file)));
+
+        let replication = file.replication;
+        fill(&mut opt.replica_of, parse_setting("replication.replica_of", replication.replica_of)?);
+        fill(&mut opt.replica_user, replication.user);
+        fill(&mut opt.replica_password, replication.password);
+        fill(&mut opt.replication_backlog, replication.backlog);
+
+        let cluster = file.cluster;
+        fill(&mut opt.node_id, cluster.node_id);
+        if let Some(peers) = cluster.peers {
+            fill(&mut opt.peers, Some(parse_peers(&peers.join(","))?));
+        }
+        fill(&mut opt.election_timeout, cluster.election_timeout);
+
+        if opt.tls_cert.is_some() != opt.tls_key.is_some() {
+            return Err(KvsError::StringError("TLS requires both a certificate and a key".to_owned()));
+        }
+        if opt.replica_user.is_some() != opt.replica_password.is_some() {
+            return Err(KvsError::StringError("The replica user and password must be set together".to_owned()));
+        }
+        if opt.node_id.is_some() != opt.peers.is_some() {
+            return Err(KvsError::StringError("A cluster node requires both a node id and peers".to_owned()));
+        }
+        Ok(opt)
+    }
+
+    fn data_dir(&self) -> Result<PathBuf> {
+        match &self.data_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => Ok(current_dir()?)
+        }
+    }
+}
+
+/// 命令行中没有指定的参数使用配置文件中的值
+fn fill<T>(flag: &mut Option<T>, value: Option<T>) {
+    if flag.is_none() {
+        *flag = value;
+    }
+}
+
+/// 解析配置文件中与命令行参数格式相同的字符串
+fn parse_setting<T>(name: &str, value: Option<String>) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display
+{
+    value.map(|value| value.parse().map_err(|e| invalid_setting(name, e))).transpose()
+}
+
+fn invalid_setting(name: &str, e: impl Display) -> KvsError {
+    KvsError::StringError(format!("Invalid {} in config file: {}",name,e))
+}
+
+fn main() {
+    let cli = Opt::from_args();
+    let loaded = cli.load();
+    let opt = loaded.as_ref().unwrap_or(&cli);
+    init_logger(opt.log_format.unwrap_or(DEFAULT_LOG_FORMAT), opt.log_level.unwrap_or(DEFAULT_LOG_LEVEL));
+    let mut opt = match loaded {
+        Ok(opt) => opt,
+        Err(e) => {
+            error!("{}",e);
             exit(1);
         }
-        run(opt)
-    });
+    };
+
+    if opt.hash_password {
+        if let Err(e) = hash_password() {
+            error!("{}",e);
+            exit(1);
+        }
+        return;
+    }
+    let backup = opt.restore.clone();
+    let res = opt.data_dir()
+        .and_then(|dir| restore_backup(backup.as_deref(), dir))
+        .and_then(current_engine)
+        .and_then(move |curr_engine|{
+            if opt.engine.is_none() {
+                opt.engine = curr_engine;
+            }
+            run(cli, opt)
+        });
     if let Err(e) = res {
         error!("{}",e);
         exit(1);
     }
 }
 
-fn run(opt:Opt) -> Result<()> {
+/// `cli` 是原始的命令行参数，重新加载配置文件时命令行参数仍然优先
+fn run(cli: Opt, opt: Opt) -> Result<()> {
     let engine = opt.engine.unwrap_or(DEFAULT_ENGINE);
+    let pool = opt.pool.unwrap_or(DEFAULT_POOL);
+    let threads = opt.threads.unwrap_or_else(|| num_cpus::get() as u32);
+    let addr = opt.addr.clone().unwrap_or_else(|| DEFAULT_LISTENING_ADDRESS.parse().unwrap());
+    let dir = opt.data_dir()?;
     info!("kvs-server {}",env!("CARGO_PKG_VERSION"));
+    if let Some(path) = &opt.config {
+        info!("Configuration file: {}",path.display());
+    }
     info!("Storage engine: {}",engine);
-    info!("Listening on {}",opt.addr);
+    info!("Data directory: {}",dir.display());
+    info!("Thread pool: {} with {} threads",pool,threads);
+    info!("Listening on {}",addr);
 
-    fs::write(current_dir()?.join("engine"), format!("{}",engine))?;
-    let pool = RayonThreadPool::new(num_cpus::get() as u32)?;
+    let manifest = Manifest::open(&dir, &engine.to_string())?;
+    info!("Store id: {}, format version {}",manifest.store_id,manifest.format_version);
+    let config = server_config(&opt)?;
+    let pool = BoxedThreadPool::with_kind(pool, threads)?;
     match engine {
-        Engine::kvs => run_with(KvStore::open(current_dir()?)?,pool,opt.addr),
-        Engine::sled => run_with(SledKvsEngine::new(
-            sled::open(current_dir()?)?),pool, opt.addr)
+        Engine::kvs => run_with(KvStore::open(dir)?,pool,config,addr,cli),
+        Engine::sled => run_with(SledKvsEngine::new(sled::open(dir)?),pool,config,addr,cli)
+    }
+}
+
+/// 初始化日志，日志中带有所在的连接、请求等 span 的字段
+///
+/// 日志级别可以在运行时通过 `config set log-level` 或重新加载配置文件调整，
+/// 由 subscriber 第一层的 `log_level_layer` 同时过滤事件和 span
+fn init_logger(format: LogFormat, level: LevelFilter) {
+    let registry = tracing_subscriber::registry().with(log_level_layer(level));
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal());
+    match format {
+        LogFormat::text => registry.with(layer).init(),
+        LogFormat::json => registry.with(layer.json().with_current_span(false).with_span_list(true)).init()
+    }
+}
+
+/// 根据命令行参数和配置文件生成服务器配置，未指定的参数使用默认值
+fn server_config(opt: &Opt) -> Result<ServerConfig> {
+    let mut config = ServerConfig {
+        protocol: match opt.protocol.unwrap_or(DEFAULT_PROTOCOL) {
+            ProtocolName::native => Protocol::Native,
+            ProtocolName::resp => Protocol::Resp,
+            ProtocolName::auto => Protocol::Auto,
+            ProtocolName::http => Protocol::Http
+        },
+        http_addr: opt.http_addr,
+        metrics_addr: opt.metrics_addr,
+        unix_socket_mode: opt.unix_socket_mode,
+        compaction_threshold: opt.compaction_threshold,
+        ..ServerConfig::default()
+    };
+    let timeout = |secs: u64| if secs == 0 { None } else { Some(Duration::from_secs(secs)) };
+    if let Some(max_connections) = opt.max_connections {
+        config.max_connections = max_connections;
     }
+    if let Some(secs) = opt.idle_timeout {
+        config.idle_timeout = timeout(secs);
+    }
+    if let Some(secs) = opt.request_timeout {
+        config.request_timeout = timeout(secs);
+    }
+    if let Some(max_request_size) = opt.max_request_size {
+        config.max_request_size = max_request_size;
+    }
+    if opt.rate_limit.is_some() {
+        config.rate_limit = opt.rate_limit;
+    }
+    if let Some(threshold) = &opt.slowlog_threshold {
+        config.slowlog_threshold = parse_slowlog_threshold(threshold)?;
+    }
+    if let Some(max_len) = opt.slowlog_max_len {
+        config.slowlog_max_len = max_len;
+    }
+    if let Some(leader) = &opt.replica_of {
+        let mut builder = KvsClientBuilder::new()
+            .addr(leader.clone())
+            .connect_timeout(REPLICA_TIMEOUT)
+            .read_timeout(REPLICA_TIMEOUT)
+            .write_timeout(REPLICA_TIMEOUT);
+        if let (Some(user), Some(password)) = (&opt.replica_user, &opt.replica_password) {
+            builder = builder.credentials(user.clone(), password.clone());
+        }
+        config.replica_of = Some(builder);
+        info!("Running as a replica of {}",leader);
+    }
+    if let Some(backlog) = opt.replication_backlog {
+        config.replication_backlog = backlog;
+    }
+    if let (Some(node_id), Some(Peers(peers))) = (opt.node_id, &opt.peers) {
+        let mut cluster = ClusterConfig::new(node_id, peers.clone(), opt.data_dir()?.join("raft"));
+        if let Some(millis) = opt.election_timeout {
+            cluster.election_timeout = Duration::from_millis(millis);
+            cluster.heartbeat_interval = cluster.election_timeout / 10;
+        }
+        if let (Some(user), Some(password)) = (&opt.replica_user, &opt.replica_password) {
+            cluster.peer_client = cluster.peer_client.credentials(user.clone(), password.clone());
+        }
+        config.cluster = Some(cluster);
+        info!("Running as node {} of a cluster with {} peers",node_id,peers.len());
+    }
+    if let (Some(cert), Some(key)) = (&opt.tls_cert, &opt.tls_key) {
+        config.tls = Some(TlsServerConfig::from_pem_files(cert, key, opt.tls_client_ca.as_deref())?);
+        info!("TLS enabled{}",if opt.tls_client_ca.is_some() { ", client certificates required" } else { "" });
+    }
+    if let Some(path) = &opt.auth_file {
+        config.auth = Some(AuthConfig::from_file(path)?);
+        info!("Authentication enabled with credentials from {}",path.display());
+    }
+    Ok(config)
 }
 
-fn run_with<E:KvsEngine,P:ThreadPool>(engine:E,pool:P,addr:SocketAddr) -> Result<()> {
+/// 解析慢日志的阈值（微秒），off 表示不记录
+fn parse_slowlog_threshold(s: &str) -> Result<Option<Duration>> {
+    match s {
+        "off" => Ok(None),
+        micros => micros.parse()
+            .map(|micros| Some(Duration::from_micros(micros)))
+            .map_err(|_| KvsError::StringError(format!("Invalid slow log threshold: {}",s)))
+    }
+}
+
+/// 集群中其他节点的 ID 和地址
+#[derive(Debug,Clone)]
+struct Peers(Vec<(u64, ServerAddr)>);
+
+/// 解析 `ID=ADDRESS` 的列表，以逗号分隔
+fn parse_peers(s: &str) -> Result<Peers> {
+    s.split(',').map(|peer| {
+        let (id, addr) = peer.split_once('=')
+            .ok_or_else(|| KvsError::StringError(format!("Invalid peer (expected ID=ADDRESS): {}",peer)))?;
+        let id = id.parse().map_err(|_| KvsError::StringError(format!("Invalid node id: {}",id)))?;
+        Ok((id, addr.parse()?))
+    }).collect::<Result<_>>().map(Peers)
+}
+
+/// 解析八进制的文件权限，如 660
+fn parse_mode(s: &str) -> std::result::Result<u32, std::num::ParseIntError> {
+    u32::from_str_radix(s, 8)
+}
+
+/// 从标准输入读取一行密码，输出写入凭据文件的哈希
+fn hash_password() -> Result<()> {
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    let password = password.trim_end_matches(['\r', '\n']);
+    println!("{}",AuthConfig::hash_secret(password)?);
+    Ok(())
+}
+
+fn run_with<E:KvsEngine,P:ThreadPool>(engine:E,pool:P,config:ServerConfig,addr:ServerAddr,cli:Opt) -> Result<()> {
     
-    let server = KvsServer::new(engine,pool);
+    let server = KvsServer::new(engine,pool).with_config(config);
+    handle_signals(server.shutdown_handle(), server.reload_handle(), cli)?;
     server.run(addr)
 }
 
-fn current_engine() -> Result<Option<Engine>> {
-    let engine = current_dir()?.join("engine");
-    if !engine.exists() {
-        return Ok(None);
+/// 收到 SIGINT/SIGTERM 时优雅地关闭服务器，收到 SIGHUP 时重新加载配置文件和凭据文件
+fn handle_signals(shutdown: ShutdownHandle, reload: ReloadHandle, cli: Opt) -> Result<()> {
+    let mut signals = Signals::new([SIGINT, SIGTERM, SIGHUP])?;
+    thread::Builder::new()
+        .name("signal".to_owned())
+        .spawn(move || {
+            for signal in signals.forever() {
+                if signal != SIGHUP {
+                    info!("Received signal {}, shutting down",signal);
+                    shutdown.shutdown();
+                    break;
+                }
+                info!("Received signal {}, reloading configuration",signal);
+                if let Err(e) = reload_config(&cli, &reload) {
+                    error!("Failed to reload configuration, keeping the current settings: {}",e);
+                }
+            }
+        })?;
+    Ok(())
+}
+
+/// 重新读取配置文件，应用日志级别和服务器中运行时可以调整的设置
+fn reload_config(cli: &Opt, reload: &ReloadHandle) -> Result<()> {
+    let opt = cli.load()?;
+    let config = server_config(&opt)?;
+    set_log_level(opt.log_level.unwrap_or(DEFAULT_LOG_LEVEL))?;
+    reload.reload(config);
+    Ok(())
+}
+
+/// 启动前将 `--restore` 指定的备份恢复到数据目录
+fn restore_backup(backup: Option<&Path>, dir: PathBuf) -> Result<PathBuf> {
+    if let Some(backup) = backup {
+        let header = restore(fs::File::open(backup)?, &dir)?;
+        info!("Restored a backup of the {} engine ({} bytes) from {}",header.engine,header.len(),backup.display());
     }
+    Ok(dir)
+}
 
-    match fs::read_to_string(engine)?.parse() {
-        Ok(engine) => Ok(Some(engine)),
-        Err(e) => {
-            warn!("The content of engine file is invalid: {}",e);
-            Ok(None)
-        }
+/// 数据目录的清单中记录的引擎，没有指定 `--engine` 时使用该引擎
+fn current_engine(dir: PathBuf) -> Result<Option<Engine>> {
+    match Manifest::load(dir)? {
+        Some(manifest) => manifest.engine.parse().map(Some).map_err(KvsError::StringError),
+        None => Ok(None)
     }
 }
\ No newline at end of file
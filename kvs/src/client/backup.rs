@@ -0,0 +1,59 @@
+use std::io::Write;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::digest::{Context, SHA256};
+
+use crate::{
+    common::{BackupChunkResponse, BackupResponse, Request},
+    BackupHeader,
+    KvsClient,
+    KvsError,
+    Result
+};
+
+// 每次请求的字节数，服务端最多返回 1 MiB
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+impl KvsClient {
+    /// 在服务端创建一份一致的备份，分块读取后写入 `writer`，需要管理权限
+    ///
+    /// 写入的内容可以用 `kvs-server --restore` 恢复。读取完成后校验数据的校验和，
+    /// 不一致时返回错误，此时 `writer` 中的内容不完整。校验后通知服务端释放备份
+    pub fn backup<W: Write>(&mut self, writer: &mut W) -> Result<BackupHeader> {
+        let session = match self.request(&Request::Backup, false)? {
+            BackupResponse::Ok(session) => session,
+            BackupResponse::Err(e) => return Err(KvsError::StringError(e))
+        };
+        let header = session.header;
+        header.write_to(writer)?;
+
+        let mut context = Context::new(&SHA256);
+        let mut offset = 0;
+        while offset < header.len() {
+            let req = Request::BackupChunk { id: session.id.clone(), offset, len: CHUNK_SIZE };
+            let data = match self.request(&req, true)? {
+                BackupChunkResponse::Ok(data) => STANDARD.decode(data)
+                    .map_err(|e| KvsError::StringError(format!("Invalid backup chunk: {}",e)))?,
+                BackupChunkResponse::Err(e) => return Err(KvsError::StringError(e))
+            };
+            if data.is_empty() {
+                return Err(KvsError::StringError(format!("Backup ended at {} of {} bytes",offset,header.len())));
+            }
+            context.update(&data);
+            writer.write_all(&data)?;
+            offset += data.len() as u64;
+        }
+        writer.flush()?;
+
+        let checksum: String = context.finish().as_ref().iter().map(|byte| format!("{:02x}",byte)).collect();
+        self.admin(&Request::BackupFinish { id: session.id }, false)?;
+        if checksum != header.checksum {
+            return Err(KvsError::StringError(format!(
+                "Backup checksum mismatch: expected {}, received {}",
+                header.checksum,
+                checksum
+            )));
+        }
+        Ok(header)
+    }
+}
@@ -0,0 +1,633 @@
+use std::{
+    io::{BufReader, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    thread,
+    time::Duration
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Deserializer;
+use crate::{
+    Result,
+    common::{
+        Request,
+        GetResponse,
+        SetResponse,
+        RemoveResponse,
+        ScanPage,
+        ScanResponse,
+        PingResponse,
+        AuthResponse,
+        InfoResponse,
+        StatsResponse,
+        ConfigGetResponse,
+        SlowlogResponse,
+        ExportPage,
+        ExportResponse,
+        ImportMode,
+        ImportStats,
+        ImportResponse,
+        SnapshotResponse,
+        TailResponse,
+        AdminResponse,
+        Secret,
+        SnapshotPage,
+        ReplBatch,
+        VoteRequest,
+        VoteReply,
+        VoteResponse,
+        AppendRequest,
+        AppendReply,
+        AppendResponse,
+        RedirectResponse,
+        Traced
+    },
+    EngineStats,
+    KvsError,
+    ServerAddr,
+    ServerInfo,
+    SlowLogEntry,
+    TlsClientConfig,
+    ToServerAddrs
+};
+use self::stream::ClientStream;
+
+pub use self::{pool::{KvsClientPool, PooledClient}, sharded::ShardedKvsClient, sync::SyncStats};
+
+mod backup;
+mod pool;
+pub(crate) mod sharded;
+mod stream;
+mod sync;
+
+/// kvs 客户端
+///
+/// 连接断开时会自动重连，并按照 `KvsClientBuilder` 中配置的策略对幂等操作进行重试。
+/// 连接到集群中的非领导者时，请求会被透明地转发到领导者
+pub struct KvsClient {
+    options: KvsClientBuilder,
+    conn: Option<Connection>,
+    // 当前使用的种子地址
+    seed: usize,
+    // 下一个请求使用的请求 ID
+    next_request_id: Option<String>,
+    // 上一个请求的响应中带回的请求 ID
+    last_request_id: Option<String>,
+}
+
+/// 带有请求 ID 的请求，与 `Request::Traced` 的编码相同，避免复制请求
+#[derive(Serialize)]
+enum TracedRequest<'a, Req> {
+    Traced { id: &'a str, request: &'a Req }
+}
+
+/// 带了请求 ID 的请求的响应，服务端拒绝请求时的错误不带 ID
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MaybeTraced<Resp> {
+    Traced(Traced<Resp>),
+    Plain(Resp)
+}
+
+/// 集群中的非领导者不执行请求，而是返回领导者的地址
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Reply<Resp> {
+    Redirect(RedirectResponse),
+    Response(Resp)
+}
+
+// 最多跟随的重定向次数，集群正在选举时每次重定向前会按重试策略等待
+const MAX_REDIRECTS: u32 = 8;
+
+/// 与服务端之间的一条连接
+struct Connection {
+    stream: BufReader<ClientStream>
+}
+
+/// 用于配置并创建 `KvsClient`
+///
+/// 可以配置多个种子地址，连接失败时按顺序切换到下一个地址
+#[derive(Debug, Clone)]
+pub struct KvsClientBuilder {
+    addrs: Vec<ServerAddr>,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retry_sets: bool,
+    tls: Option<TlsClientConfig>,
+    credentials: Option<(String, Secret)>,
+}
+
+impl Default for KvsClientBuilder {
+    fn default() -> Self {
+        KvsClientBuilder {
+            addrs: Vec::new(),
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            max_retries: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retry_sets: false,
+            tls: None,
+            credentials: None,
+        }
+    }
+}
+
+impl KvsClientBuilder {
+    /// 新建一个没有任何地址的配置
+    pub fn new() -> Self {
+        KvsClientBuilder::default()
+    }
+
+    /// 添加一个种子地址，可以是 TCP 地址或 Unix 域套接字
+    pub fn addr(mut self, addr: impl Into<ServerAddr>) -> Self {
+        self.addrs.push(addr.into());
+        self
+    }
+
+    /// 连接超时时间
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// 读取响应的超时时间
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// 发送请求的超时时间
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// 连接失败或幂等操作失败时的最大重试次数
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// 重试的退避时间，从 `initial` 开始每次翻倍，最大不超过 `max`
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// 是否重试 set 操作
+    ///
+    /// 重复执行 set 结果相同，但如果其他客户端在两次执行之间修改了同一个键，
+    /// 重试会覆盖对方的修改，只有在没有并发写同一个键时才应该开启
+    pub fn retry_sets(mut self, retry: bool) -> Self {
+        self.retry_sets = retry;
+        self
+    }
+
+    /// 使用 TLS 连接服务端，只对 TCP 地址生效
+    pub fn tls(mut self, tls: TlsClientConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// 登录使用的用户名和密码（或令牌），每次建立连接后都会先登录
+    pub fn credentials(mut self, user: String, password: String) -> Self {
+        self.credentials = Some((user, Secret(password)));
+        self
+    }
+
+    pub(crate) fn addrs(&self) -> &[ServerAddr] {
+        &self.addrs
+    }
+
+    /// 连接服务端并创建客户端
+    pub fn build(self) -> Result<KvsClient> {
+        if self.addrs.is_empty() {
+            return Err(KvsError::StringError("No server address".to_owned()));
+        }
+        let mut client = KvsClient {
+            options: self,
+            conn: None,
+            seed: 0,
+            next_request_id: None,
+            last_request_id: None
+        };
+        let mut attempt = 0;
+        loop {
+            match client.connect_any() {
+                Ok(()) => return Ok(client),
+                Err(e) if is_connection_error(&e) && attempt < client.options.max_retries => {
+                    client.backoff(attempt, &e);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
+
+impl KvsClient {
+    /// 连接服务端，地址可以是 TCP 地址或 Unix 域套接字（如 `unix:/run/kvs.sock`）
+    pub fn connect<A: ToServerAddrs>(addr: A) -> Result<Self> {
+        addr.to_server_addrs()?
+            .into_iter()
+            .fold(KvsClientBuilder::new(), KvsClientBuilder::addr)
+            .build()
+    }
+
+    /// 为下一个请求指定请求 ID，服务端在日志中记录该 ID 并在响应中返回
+    ///
+    /// 例如 `client.with_request_id("checkout-42").get(key)`，重试时使用同一个 ID
+    pub fn with_request_id(&mut self, id: impl Into<String>) -> &mut Self {
+        self.next_request_id = Some(id.into());
+        self
+    }
+
+    /// 上一个请求的响应中带回的请求 ID，请求没有指定 ID 时为 `None`
+    pub fn last_request_id(&self) -> Option<&str> {
+        self.last_request_id.as_deref()
+    }
+
+    ///获取数据请求
+    pub fn get(&mut self, key: String) -> Result<Option<String>>{
+        let resp = self.request(&Request::Get { key }, true)?;
+        match resp {
+            GetResponse::Ok(value) => Ok(value),
+            GetResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+
+    }
+
+    /// 添加数据请求
+    pub fn set(&mut self, key: String, value: String) -> Result<()>{
+        let retry = self.options.retry_sets;
+        let resp = self.request(&Request::Set { key,value }, retry)?;
+        match resp {
+            SetResponse::Ok(_) => Ok(()),
+            SetResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+
+    }
+
+    /// 删除数据请求，键不存在时返回 `KvsError::KeyNotFound`
+    pub fn remove(&mut self, key: String) -> Result<()>{
+        let resp = self.request(&Request::Remove { key }, false)?;
+        match resp {
+            RemoveResponse::Ok(_) => Ok(()),
+            RemoveResponse::NotFound => Err(KvsError::KeyNotFound),
+            RemoveResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+
+    }
+
+    /// 按字典序列出以 `prefix` 开头、大于 `after` 的最多 `limit` 个键，服务端最多返回 1000 个
+    pub fn scan(&mut self, prefix: String, after: Option<String>, limit: usize) -> Result<ScanPage> {
+        match self.request(&Request::Scan { prefix, after, limit }, true)? {
+            ScanResponse::Ok(page) => Ok(page),
+            ScanResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 检查服务端是否可用
+    pub fn ping(&mut self) -> Result<()> {
+        let resp = self.request(&Request::Ping, true)?;
+        match resp {
+            PingResponse::Ok(_) => Ok(()),
+            PingResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 获取服务器的运行状态，需要管理权限
+    pub fn info(&mut self) -> Result<ServerInfo> {
+        match self.request(&Request::Info, true)? {
+            InfoResponse::Ok(info) => Ok(*info),
+            InfoResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 获取存储引擎的统计信息，需要管理权限
+    pub fn engine_stats(&mut self) -> Result<EngineStats> {
+        match self.request(&Request::Stats, true)? {
+            StatsResponse::Ok(stats) => Ok(stats),
+            StatsResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 立即压缩存储引擎，需要管理权限
+    pub fn compact(&mut self) -> Result<()> {
+        self.admin(&Request::Compact, false)
+    }
+
+    /// 将服务端缓冲的数据同步到磁盘，需要管理权限
+    pub fn flush(&mut self) -> Result<()> {
+        self.admin(&Request::Flush, true)
+    }
+
+    /// 读取服务端的运行时设置，需要管理权限
+    pub fn config_get(&mut self, name: String) -> Result<String> {
+        match self.request(&Request::ConfigGet { name }, true)? {
+            ConfigGetResponse::Ok(value) => Ok(value),
+            ConfigGetResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 修改服务端的运行时设置，服务器重启后恢复默认值，需要管理权限
+    pub fn config_set(&mut self, name: String, value: String) -> Result<()> {
+        self.admin(&Request::ConfigSet { name, value }, true)
+    }
+
+    /// 获取最近的 `count` 条慢日志，从新到旧，`None` 时由服务端决定条数，需要管理权限
+    pub fn slowlog(&mut self, count: Option<usize>) -> Result<Vec<SlowLogEntry>> {
+        match self.request(&Request::SlowlogGet { count }, true)? {
+            SlowlogResponse::Ok(entries) => Ok(entries),
+            SlowlogResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 清空慢日志，需要管理权限
+    pub fn slowlog_reset(&mut self) -> Result<()> {
+        self.admin(&Request::SlowlogReset, true)
+    }
+
+    /// 按字典序导出以 `prefix` 开头、大于 `after` 的最多 `limit` 个键值，需要管理权限，服务端最多返回 1000 个
+    pub fn export(&mut self, prefix: String, after: Option<String>, limit: usize) -> Result<ExportPage> {
+        match self.request(&Request::Export { prefix, after, limit }, true)? {
+            ExportResponse::Ok(page) => Ok(page),
+            ExportResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 批量写入键值，需要管理权限
+    ///
+    /// 与 set 相同，只有开启 `retry_sets` 时才会重试
+    pub fn import(&mut self, entries: Vec<(String, String)>, mode: ImportMode) -> Result<ImportStats> {
+        let retry = self.options.retry_sets;
+        match self.request(&Request::Import { entries, mode }, retry)? {
+            ImportResponse::Ok(stats) => Ok(stats),
+            ImportResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 将从节点提升为主节点，需要管理权限
+    pub fn promote(&mut self) -> Result<()> {
+        self.admin(&Request::Promote, false)
+    }
+
+    /// 读取主节点全量快照中 `after` 之后的一页
+    pub(crate) fn repl_snapshot(&mut self, after: Option<String>) -> Result<SnapshotPage> {
+        match self.request(&Request::ReplSnapshot { after }, true)? {
+            SnapshotResponse::Ok(page) => Ok(page),
+            SnapshotResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 读取主节点复制日志中序号大于 `after` 的记录，需要重新全量同步时返回 `None`
+    pub(crate) fn repl_tail(&mut self, id: String, after: u64) -> Result<Option<ReplBatch>> {
+        match self.request(&Request::ReplTail { id, after }, true)? {
+            TailResponse::Ok(batch) => Ok(Some(batch)),
+            TailResponse::Resync => Ok(None),
+            TailResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 向集群中的节点请求投票
+    pub(crate) fn raft_vote(&mut self, req: VoteRequest) -> Result<VoteReply> {
+        match self.request(&Request::RaftVote(req), false)? {
+            VoteResponse::Ok(reply) => Ok(reply),
+            VoteResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 向集群中的节点复制日志
+    pub(crate) fn raft_append(&mut self, req: AppendRequest) -> Result<AppendReply> {
+        match self.request(&Request::RaftAppend(req), false)? {
+            AppendResponse::Ok(reply) => Ok(reply),
+            AppendResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    fn admin(&mut self, req: &Request, idempotent: bool) -> Result<()> {
+        match self.request(req, idempotent)? {
+            AdminResponse::Ok(_) => Ok(()),
+            AdminResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 连接是否仍然可用，连接出错后会被丢弃，直到下一次请求时重新连接
+    pub(crate) fn is_connected(&self) -> bool {
+        self.conn.as_ref().is_some_and(Connection::is_alive)
+    }
+
+    /// 发送请求并读取响应
+    ///
+    /// 请求发送失败时服务端不会收到请求，总是可以重新连接后再次发送；
+    /// 请求发出后连接出错，只有 `idempotent` 的请求才会重试。
+    /// 被重定向的请求没有被执行，总是可以发送到领导者
+    fn request<Req, Resp>(&mut self, req: &Req, idempotent: bool) -> Result<Resp>
+    where
+        Req: Serialize,
+        Resp: for<'de> Deserialize<'de>
+    {
+        self.last_request_id = None;
+        let id = self.next_request_id.take();
+        let mut attempt = 0;
+        let mut reconnected = false;
+        let mut redirects = 0;
+        loop {
+            let sent = self.ensure_connected().and_then(|_| match &id {
+                Some(id) => self.send(&TracedRequest::Traced { id, request: req }),
+                None => self.send(req)
+            });
+            let (res, sent) = match sent {
+                Ok(()) if id.is_some() => (self.receive_traced(), true),
+                Ok(()) => (self.receive(), true),
+                Err(e) => (Err(e), false)
+            };
+            let err = match res {
+                Ok(Reply::Response(resp)) => return Ok(resp),
+                Ok(Reply::Redirect(RedirectResponse::Redirect(leader))) => {
+                    if redirects >= MAX_REDIRECTS {
+                        return Err(KvsError::StringError("Too many redirects".to_owned()));
+                    }
+                    self.redirect(leader, redirects)?;
+                    redirects += 1;
+                    continue;
+                }
+                Err(e) if is_connection_error(&e) => e,
+                Err(e) => return Err(e)
+            };
+
+            self.conn = None;
+            if sent && !idempotent {
+                return Err(err);
+            }
+            // 发送失败（如 broken pipe）时立即重连一次，不占用重试次数
+            if !sent && !reconnected {
+                reconnected = true;
+                debug!("Failed to send request: {}, reconnecting",err);
+                continue;
+            }
+            if attempt >= self.options.max_retries {
+                return Err(err);
+            }
+            self.backoff(attempt, &err);
+            attempt += 1;
+        }
+    }
+
+    fn send<Req: Serialize>(&mut self, req: &Req) -> Result<()> {
+        self.conn.as_mut().expect("not connected").send(req)
+    }
+
+    fn receive<Resp: for<'de> Deserialize<'de>>(&mut self) -> Result<Resp> {
+        self.conn.as_mut().expect("not connected").receive()
+    }
+
+    fn receive_traced<Resp: for<'de> Deserialize<'de>>(&mut self) -> Result<Resp> {
+        match self.receive()? {
+            MaybeTraced::Traced(Traced { id, response }) => {
+                self.last_request_id = Some(id);
+                Ok(response)
+            }
+            MaybeTraced::Plain(response) => Ok(response)
+        }
+    }
+
+    /// 切换到领导者，还没有选出领导者时等待一段时间后重试
+    fn redirect(&mut self, leader: Option<String>, attempt: u32) -> Result<()> {
+        match leader {
+            Some(leader) => {
+                let addr: ServerAddr = leader.parse()?;
+                debug!("Redirected from {} to leader {}",self.options.addrs[self.seed],addr);
+                self.seed = match self.options.addrs.iter().position(|known| *known == addr) {
+                    Some(seed) => seed,
+                    None => {
+                        self.options.addrs.push(addr);
+                        self.options.addrs.len() - 1
+                    }
+                };
+                self.conn = None;
+            }
+            None => self.backoff(attempt, &KvsError::NoLeader)
+        }
+        Ok(())
+    }
+
+    /// 确保有可用的连接：服务端已关闭的连接（如空闲超时）会被透明地替换
+    fn ensure_connected(&mut self) -> Result<()> {
+        if let Some(conn) = &self.conn {
+            if conn.is_alive() {
+                return Ok(());
+            }
+            debug!("Connection to {} closed by server, reconnecting",self.options.addrs[self.seed]);
+            self.conn = None;
+        }
+        self.connect_any()
+    }
+
+    /// 从当前种子地址开始依次尝试连接，直到连接成功
+    fn connect_any(&mut self) -> Result<()> {
+        let addrs = &self.options.addrs;
+        let mut last_err = None;
+        for i in 0..addrs.len() {
+            let seed = (self.seed + i) % addrs.len();
+            match Connection::open(&addrs[seed], &self.options) {
+                Ok(conn) => {
+                    if seed != self.seed {
+                        info!("Failed over from {} to {}",addrs[self.seed],addrs[seed]);
+                    }
+                    self.seed = seed;
+                    self.conn = Some(conn);
+                    return Ok(());
+                }
+                // 登录失败等服务端返回的错误换一个地址也不会成功
+                Err(e) if !is_connection_error(&e) => return Err(e),
+                Err(e) => {
+                    warn!("Failed to connect to {}: {}",addrs[seed],e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        Err(last_err.expect("no server address"))
+    }
+
+    fn backoff(&self, attempt: u32, err: &KvsError) {
+        let delay = self.options.initial_backoff
+            .checked_mul(1 << attempt.min(16))
+            .map_or(self.options.max_backoff, |delay| delay.min(self.options.max_backoff));
+        debug!("Request failed: {}, retrying in {:?}",err,delay);
+        thread::sleep(delay);
+    }
+}
+
+impl Connection {
+    fn open(addr: &ServerAddr, options: &KvsClientBuilder) -> Result<Self> {
+        let stream = match addr {
+            ServerAddr::Tcp(addr) => {
+                let stream = match options.connect_timeout {
+                    Some(timeout) => TcpStream::connect_timeout(addr, timeout)?,
+                    None => TcpStream::connect(addr)?
+                };
+                stream.set_read_timeout(options.read_timeout)?;
+                stream.set_write_timeout(options.write_timeout)?;
+                match &options.tls {
+                    Some(tls) => ClientStream::tls(stream, tls)?,
+                    None => ClientStream::Plain(stream)
+                }
+            }
+            // 连接本机的 Unix 域套接字不会阻塞，不需要连接超时
+            ServerAddr::Unix(path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(options.read_timeout)?;
+                stream.set_write_timeout(options.write_timeout)?;
+                ClientStream::Unix(stream)
+            }
+        };
+        let mut conn = Connection { stream: BufReader::new(stream) };
+        if let Some((user, password)) = &options.credentials {
+            conn.login(user, password)?;
+        }
+        Ok(conn)
+    }
+
+    fn login(&mut self, user: &str, password: &Secret) -> Result<()> {
+        self.send(&Request::Auth { user: user.to_owned(), password: password.clone() })?;
+        match self.receive()? {
+            AuthResponse::Ok(_) => Ok(()),
+            AuthResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    fn send<Req: Serialize>(&mut self, req: &Req) -> Result<()> {
+        let buf = serde_json::to_vec(req)?;
+        let stream = self.stream.get_mut();
+        stream.write_all(&buf)?;
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn receive<Resp: for<'de> Deserialize<'de>>(&mut self) -> Result<Resp> {
+        Ok(Resp::deserialize(&mut Deserializer::from_reader(&mut self.stream))?)
+    }
+
+    fn is_alive(&self) -> bool {
+        self.stream.get_ref().is_alive()
+    }
+}
+
+/// 是否是连接层面的错误（连接断开、超时等），而不是服务端返回的错误
+pub(crate) fn is_connection_error(err: &KvsError) -> bool {
+    match err {
+        KvsError::Io(_) => true,
+        KvsError::Serde(e) => e.is_io() || e.is_eof(),
+        _ => false
+    }
+}
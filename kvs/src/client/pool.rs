@@ -0,0 +1,202 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant}
+};
+
+use crate::{KvsClient, KvsClientBuilder, KvsError, Result};
+
+const DEFAULT_MAX_IDLE: Duration = Duration::from_secs(60);
+const DEFAULT_CHECKOUT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 客户端连接池
+///
+/// 连接池维护一组数量有限的到同一服务端的连接，可以被克隆并在多个线程间共享。
+/// 通过 `get` 取出的连接在 `PooledClient` 被丢弃时自动归还，出错的连接不会被归还
+#[derive(Clone)]
+pub struct KvsClientPool {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    builder: KvsClientBuilder,
+    max_size: usize,
+    max_idle: Duration,
+    checkout_timeout: Duration,
+    test_on_checkout: bool,
+    state: Mutex<State>,
+    available: Condvar
+}
+
+struct State {
+    // 空闲连接，最近归还的在末尾
+    idle: Vec<IdleClient>,
+    // 已创建的连接总数，包括被取出的
+    open: usize
+}
+
+struct IdleClient {
+    client: KvsClient,
+    since: Instant
+}
+
+/// 从连接池中取出的客户端，丢弃时归还到连接池
+pub struct PooledClient {
+    client: Option<KvsClient>,
+    pool: Arc<Inner>
+}
+
+impl KvsClientPool {
+    /// 新建连接池，最多同时保持 `max_size` 个连接，连接在首次使用时才会创建
+    pub fn new(builder: KvsClientBuilder, max_size: usize) -> Self {
+        KvsClientPool {
+            inner: Arc::new(Inner {
+                builder,
+                max_size: max_size.max(1),
+                max_idle: DEFAULT_MAX_IDLE,
+                checkout_timeout: DEFAULT_CHECKOUT_TIMEOUT,
+                test_on_checkout: false,
+                state: Mutex::new(State { idle: Vec::new(), open: 0 }),
+                available: Condvar::new()
+            })
+        }
+    }
+
+    /// 连接空闲超过该时间后被关闭
+    pub fn with_max_idle(self, max_idle: Duration) -> Self {
+        self.configure(|inner| inner.max_idle = max_idle)
+    }
+
+    /// 连接池已满时等待其他连接归还的最长时间
+    pub fn with_checkout_timeout(self, timeout: Duration) -> Self {
+        self.configure(|inner| inner.checkout_timeout = timeout)
+    }
+
+    /// 取出连接前是否发送 ping 检查连接，默认只检查连接是否已被服务端关闭
+    pub fn with_test_on_checkout(self, test: bool) -> Self {
+        self.configure(|inner| inner.test_on_checkout = test)
+    }
+
+    /// 从连接池中取出一个健康的连接，没有空闲连接且连接数已满时等待
+    pub fn get(&self) -> Result<PooledClient> {
+        let inner = &self.inner;
+        let deadline = Instant::now() + inner.checkout_timeout;
+        loop {
+            let mut state = inner.state.lock().unwrap();
+            state.evict_idle(inner.max_idle);
+
+            if let Some(IdleClient { mut client, .. }) = state.idle.pop() {
+                drop(state);
+                if inner.is_healthy(&mut client) {
+                    return Ok(self.wrap(client));
+                }
+                debug!("Dropping unhealthy pooled connection");
+                inner.release_slot();
+                continue;
+            }
+
+            if state.open < inner.max_size {
+                state.open += 1;
+                drop(state);
+                return match inner.builder.clone().build() {
+                    Ok(client) => Ok(self.wrap(client)),
+                    Err(e) => {
+                        inner.release_slot();
+                        Err(e)
+                    }
+                };
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(KvsError::StringError("Timed out waiting for a pooled connection".to_owned()));
+            }
+            let _ = inner.available.wait_timeout(state, deadline - now).unwrap();
+        }
+    }
+
+    /// 当前空闲连接数
+    pub fn idle_count(&self) -> usize {
+        self.inner.state.lock().unwrap().idle.len()
+    }
+
+    /// 当前已创建的连接数，包括被取出的连接
+    pub fn open_count(&self) -> usize {
+        self.inner.state.lock().unwrap().open
+    }
+
+    fn wrap(&self, client: KvsClient) -> PooledClient {
+        PooledClient { client: Some(client), pool: Arc::clone(&self.inner) }
+    }
+
+    fn configure<F: FnOnce(&mut Inner)>(mut self, f: F) -> Self {
+        f(Arc::get_mut(&mut self.inner).expect("pool must be configured before it is shared"));
+        self
+    }
+}
+
+impl Inner {
+    fn is_healthy(&self, client: &mut KvsClient) -> bool {
+        if !client.is_connected() {
+            return false;
+        }
+        !self.test_on_checkout || client.ping().is_ok()
+    }
+
+    /// 一个连接被关闭，腾出位置
+    fn release_slot(&self) {
+        self.state.lock().unwrap().open -= 1;
+        self.available.notify_one();
+    }
+
+    /// 归还连接，同时关闭空闲时间过长的连接，连接池长时间没有取出连接时它们也不会一直保留
+    fn put_back(&self, client: KvsClient) {
+        if !client.is_connected() {
+            debug!("Dropping broken pooled connection");
+            self.release_slot();
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let evicted = state.evict_idle(self.max_idle);
+        state.idle.push(IdleClient { client, since: Instant::now() });
+        drop(state);
+        if evicted > 0 {
+            self.available.notify_all();
+        } else {
+            self.available.notify_one();
+        }
+    }
+}
+
+impl State {
+    /// 关闭空闲时间过长的连接，返回关闭的连接数
+    fn evict_idle(&mut self, max_idle: Duration) -> usize {
+        let before = self.idle.len();
+        self.idle.retain(|idle| idle.since.elapsed() < max_idle);
+        let evicted = before - self.idle.len();
+        self.open -= evicted;
+        evicted
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = KvsClient;
+
+    fn deref(&self) -> &KvsClient {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut KvsClient {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            self.pool.put_back(client);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use crate::{common::ScanPage, KvsClient, KvsClientBuilder, KvsError, Result, ServerAddr};
+
+/// 每个节点在哈希环上的虚拟节点数
+///
+/// 所有客户端必须使用相同的值才能把同一个键路由到同一个节点，因此不提供配置
+const VIRTUAL_NODES: usize = 160;
+// 重新平衡时每次扫描的键数
+const REBALANCE_PAGE_SIZE: usize = 1000;
+
+/// 按一致性哈希把键分布到多个服务端的客户端
+///
+/// 每个节点在哈希环上有若干虚拟节点，键由哈希值之后的第一个虚拟节点所属的节点负责。
+/// 虚拟节点的位置只取决于节点的地址，与节点的顺序无关，增加或删除一个节点时只有约 1/n 的键
+/// 需要移动，见 `rebalance`。到各节点的连接在第一次使用时建立
+pub struct ShardedKvsClient {
+    builder: KvsClientBuilder,
+    nodes: Vec<Node>,
+    ring: HashRing
+}
+
+/// 一致性哈希环，把键映射到节点的下标
+pub(crate) struct HashRing {
+    // 虚拟节点的哈希值到节点的下标
+    points: BTreeMap<u64, usize>
+}
+
+struct Node {
+    addr: ServerAddr,
+    client: Option<KvsClient>
+}
+
+impl ShardedKvsClient {
+    /// 使用默认的客户端配置访问 `nodes`
+    pub fn new(nodes: Vec<ServerAddr>) -> Result<Self> {
+        Self::with_builder(KvsClientBuilder::new(), nodes)
+    }
+
+    /// `builder` 为连接各个节点共用的配置（超时、重试、TLS、登录等），其中不应设置地址
+    pub fn with_builder(builder: KvsClientBuilder, nodes: Vec<ServerAddr>) -> Result<Self> {
+        let ring = HashRing::new(&nodes)?;
+        Ok(ShardedKvsClient {
+            builder,
+            nodes: nodes.into_iter().map(|addr| Node { addr, client: None }).collect(),
+            ring
+        })
+    }
+
+    /// 环上的所有节点
+    pub fn nodes(&self) -> impl Iterator<Item = &ServerAddr> {
+        self.nodes.iter().map(|node| &node.addr)
+    }
+
+    /// 负责 `key` 的节点
+    pub fn node_for(&self, key: &str) -> &ServerAddr {
+        &self.nodes[self.ring.index_for(key)].addr
+    }
+
+    /// 从负责 `key` 的节点读取
+    pub fn get(&mut self, key: String) -> Result<Option<String>> {
+        self.client_for(&key)?.get(key)
+    }
+
+    /// 写入负责 `key` 的节点
+    pub fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.client_for(&key)?.set(key, value)
+    }
+
+    /// 从负责 `key` 的节点删除
+    pub fn remove(&mut self, key: String) -> Result<()> {
+        self.client_for(&key)?.remove(key)
+    }
+
+    /// 按字典序列出所有节点上以 `prefix` 开头、大于 `after` 的最多 `limit` 个键，见 `merge_pages`
+    pub fn scan(&mut self, prefix: String, after: Option<String>, limit: usize) -> Result<ScanPage> {
+        let mut pages = Vec::with_capacity(self.nodes.len());
+        for i in 0..self.nodes.len() {
+            pages.push(self.client(i)?.scan(prefix.clone(), after.clone(), limit)?);
+        }
+        Ok(merge_pages(pages, limit))
+    }
+
+    /// 把各节点上不属于自己的键移到负责它的节点上，返回移动的键数
+    ///
+    /// 增加节点后直接调用；删除节点时先用新的节点列表创建客户端，再把仍在运行的旧节点作为 `removed` 传入，
+    /// 它们的所有键都会被移走。目标节点上已经有该键时，认为它是按新的分布写入的更新的值，只删除旧节点上的键。
+    /// 每个键先写入目标节点再从原节点删除，中途失败不会丢失数据，可以重新执行。过期时间不会保留
+    pub fn rebalance(&mut self, removed: Vec<ServerAddr>) -> Result<usize> {
+        let mut sources: Vec<ServerAddr> = self.nodes().cloned().collect();
+        sources.extend(removed.into_iter().filter(|addr| !self.nodes().any(|node| node == addr)));
+        let mut moved = 0;
+        for addr in sources {
+            let mut source = self.builder.clone().addr(addr.clone()).build()?;
+            let mut after = None;
+            loop {
+                let page = source.scan(String::new(), after.take(), REBALANCE_PAGE_SIZE)?;
+                for key in page.keys {
+                    if *self.node_for(&key) != addr && self.move_key(&mut source, key)? {
+                        moved += 1;
+                    }
+                }
+                match page.next {
+                    Some(next) => after = Some(next),
+                    None => break
+                }
+            }
+            info!("Rebalanced keys from {}",addr);
+        }
+        Ok(moved)
+    }
+
+    /// 把 `key` 从 `source` 移到负责它的节点上，键已经不存在时返回 false
+    fn move_key(&mut self, source: &mut KvsClient, key: String) -> Result<bool> {
+        let value = match source.get(key.clone())? {
+            Some(value) => value,
+            None => return Ok(false)
+        };
+        let target = self.client_for(&key)?;
+        if target.get(key.clone())?.is_none() {
+            target.set(key.clone(), value)?;
+        }
+        source.remove(key)?;
+        Ok(true)
+    }
+
+    fn client_for(&mut self, key: &str) -> Result<&mut KvsClient> {
+        let index = self.ring.index_for(key);
+        self.client(index)
+    }
+
+    fn client(&mut self, index: usize) -> Result<&mut KvsClient> {
+        let node = &mut self.nodes[index];
+        if node.client.is_none() {
+            node.client = Some(self.builder.clone().addr(node.addr.clone()).build()?);
+        }
+        Ok(node.client.as_mut().unwrap())
+    }
+}
+
+impl HashRing {
+    /// 每个节点放置若干虚拟节点，节点不能为空或重复
+    pub(crate) fn new(nodes: &[ServerAddr]) -> Result<Self> {
+        if nodes.is_empty() {
+            return Err(KvsError::StringError("No server address".to_owned()));
+        }
+        let mut points = BTreeMap::new();
+        for (i, addr) in nodes.iter().enumerate() {
+            if nodes[..i].contains(addr) {
+                return Err(KvsError::StringError(format!("Duplicate node {}",addr)));
+            }
+            for vnode in 0..VIRTUAL_NODES {
+                match points.entry(hash(format!("{}#{}",addr,vnode).as_bytes())) {
+                    Entry::Vacant(entry) => {
+                        entry.insert(i);
+                    }
+                    // 哈希冲突时保留地址较小的节点，使结果与节点的顺序无关
+                    Entry::Occupied(mut entry) => {
+                        if addr.to_string() < nodes[*entry.get()].to_string() {
+                            entry.insert(i);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(HashRing { points })
+    }
+
+    /// 负责 `key` 的节点的下标
+    pub(crate) fn index_for(&self, key: &str) -> usize {
+        let hash = hash(key.as_bytes());
+        let (_, &index) = self.points.range(hash..).next()
+            .or_else(|| self.points.iter().next())
+            .expect("hash ring is not empty");
+        index
+    }
+}
+
+/// 按字典序合并各个节点以相同的 `after` 和 `limit` 扫描到的一页
+///
+/// 某个节点后面还有键时，合并结果只能到它这一页的最后一个键为止，
+/// 否则可能漏掉它后面比其他节点的键更小的键，因此结果可能少于 `limit` 个键
+pub(crate) fn merge_pages(pages: Vec<ScanPage>, limit: usize) -> ScanPage {
+    let mut keys = Vec::new();
+    let mut bound: Option<String> = None;
+    for page in pages {
+        if let Some(next) = page.next {
+            bound = Some(match bound {
+                Some(bound) if bound < next => bound,
+                _ => next
+            });
+        }
+        keys.extend(page.keys);
+    }
+    keys.sort_unstable();
+    // 重新平衡期间同一个键可能同时存在于两个节点上
+    keys.dedup();
+    if let Some(bound) = &bound {
+        keys.retain(|key| key <= bound);
+    }
+    let mut more = bound.is_some();
+    if keys.len() > limit {
+        keys.truncate(limit);
+        more = true;
+    }
+    let next = if more { keys.last().cloned().or(bound) } else { None };
+    ScanPage { keys, next }
+}
+
+/// 64 位 FNV-1a 哈希，再经过 MurmurHash3 的最终混合使分布更均匀
+///
+/// 路由结果必须在不同的进程和版本间保持一致，因此不使用标准库的哈希
+fn hash(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in bytes {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
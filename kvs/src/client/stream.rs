@@ -0,0 +1,86 @@
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    sync::Arc
+};
+
+use rustls::{ClientConnection, StreamOwned};
+
+use crate::TlsClientConfig;
+
+/// 客户端底层的字节流，明文 TCP、TLS 或 Unix 域套接字
+pub(super) enum ClientStream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+    Unix(UnixStream)
+}
+
+impl ClientStream {
+    /// 在已建立的 TCP 连接上完成 TLS 握手，握手失败（如证书不受信任）会立即返回错误
+    pub(super) fn tls(mut tcp: TcpStream, tls: &TlsClientConfig) -> io::Result<Self> {
+        let mut session = ClientConnection::new(Arc::clone(&tls.config), tls.server_name.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        while session.is_handshaking() {
+            session.complete_io(&mut tcp)?;
+        }
+        Ok(ClientStream::Tls(Box::new(StreamOwned::new(session, tcp))))
+    }
+
+    /// 服务端是否还保持着连接，已关闭或已出错的连接会读到 EOF 或错误
+    pub(super) fn is_alive(&self) -> bool {
+        match self {
+            ClientStream::Plain(stream) => peek_alive(stream),
+            ClientStream::Tls(stream) => peek_alive(stream.get_ref()),
+            ClientStream::Unix(stream) => {
+                // Unix 域套接字不支持 peek，空闲时服务端不会主动发送数据，读到数据同样说明连接不可用
+                if stream.set_nonblocking(true).is_err() {
+                    return false;
+                }
+                let alive = matches!((&*stream).read(&mut [0; 1]), Err(e) if e.kind() == io::ErrorKind::WouldBlock);
+                stream.set_nonblocking(false).is_ok() && alive
+            }
+        }
+    }
+}
+
+fn peek_alive(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut buf = [0; 1];
+    let alive = match stream.peek(&mut buf) {
+        Ok(0) => false,
+        Ok(_) => true,
+        Err(e) => e.kind() == io::ErrorKind::WouldBlock
+    };
+    stream.set_nonblocking(false).is_ok() && alive
+}
+
+impl Read for ClientStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(stream) => stream.read(buf),
+            ClientStream::Tls(stream) => stream.read(buf),
+            ClientStream::Unix(stream) => stream.read(buf)
+        }
+    }
+}
+
+impl Write for ClientStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            ClientStream::Plain(stream) => stream.write(buf),
+            ClientStream::Tls(stream) => stream.write(buf),
+            ClientStream::Unix(stream) => stream.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            ClientStream::Plain(stream) => stream.flush(),
+            ClientStream::Tls(stream) => stream.flush(),
+            ClientStream::Unix(stream) => stream.flush()
+        }
+    }
+}
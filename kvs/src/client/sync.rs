@@ -0,0 +1,113 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    common::{MerkleNode, MerkleResponse, Request},
+    KvsClient,
+    KvsError,
+    Result
+};
+
+// 节点的键数不超过该值时直接逐个键比较，不再向下展开
+const LEAF_KEYS: u64 = 64;
+// 逐个键比较时每次扫描的键数
+const SCAN_PAGE_SIZE: usize = 1000;
+
+/// 一次反熵同步的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    /// 比较过的 Merkle 树节点数
+    pub compared_nodes: u64,
+    /// 逐个键比较的范围数
+    pub transferred_ranges: u64,
+    /// 在目标上写入的键数
+    pub updated_keys: u64,
+    /// 从目标上删除的键数
+    pub removed_keys: u64
+}
+
+impl KvsClient {
+    /// 获取以 `prefix` 开头的键在 Merkle 树中的下一层节点
+    pub fn merkle_children(&mut self, prefix: String) -> Result<Vec<MerkleNode>> {
+        match self.request(&Request::MerkleChildren { prefix }, true)? {
+            MerkleResponse::Ok(children) => Ok(children),
+            MerkleResponse::Err(e) => Err(KvsError::StringError(e))
+        }
+    }
+
+    /// 让 `target` 上的数据与本服务器一致，只传输不同的范围
+    ///
+    /// 从根开始逐层比较两边的 Merkle 树，只展开哈希不同的节点；键不多的节点直接逐个键比较并修正，
+    /// 目标上多出的键会被删除。同步期间两边的写入可能使结果不完全一致，可以重复执行
+    pub fn sync_to(&mut self, target: &mut KvsClient) -> Result<SyncStats> {
+        let mut stats = SyncStats::default();
+        let mut pending = vec![String::new()];
+        while let Some(prefix) = pending.pop() {
+            let source_nodes = self.merkle_children(prefix.clone())?;
+            let target_nodes: HashMap<String, MerkleNode> = target.merkle_children(prefix.clone())?
+                .into_iter()
+                .map(|node| (node.prefix.clone(), node))
+                .collect();
+            let mut prefixes: BTreeSet<&str> = target_nodes.keys().map(String::as_str).collect();
+            prefixes.extend(source_nodes.iter().map(|node| node.prefix.as_str()));
+
+            for child in prefixes {
+                stats.compared_nodes += 1;
+                let source = source_nodes.iter().find(|node| node.prefix == child);
+                let target_node = target_nodes.get(child);
+                if source.is_some() && source == target_node {
+                    continue;
+                }
+                let keys = source.into_iter().chain(target_node).map(|node| node.keys).max().unwrap_or(0);
+                if child == prefix {
+                    // 等于父节点前缀的单个键
+                    stats.transferred_ranges += 1;
+                    self.sync_key(target, child.to_owned(), &mut stats)?;
+                } else if keys <= LEAF_KEYS {
+                    stats.transferred_ranges += 1;
+                    self.sync_range(target, child, &mut stats)?;
+                } else {
+                    pending.push(child.to_owned());
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// 逐个键比较以 `prefix` 开头的所有键
+    fn sync_range(&mut self, target: &mut KvsClient, prefix: &str, stats: &mut SyncStats) -> Result<()> {
+        let mut keys = self.scan_all(prefix)?;
+        keys.extend(target.scan_all(prefix)?);
+        for key in keys {
+            self.sync_key(target, key, stats)?;
+        }
+        Ok(())
+    }
+
+    fn sync_key(&mut self, target: &mut KvsClient, key: String, stats: &mut SyncStats) -> Result<()> {
+        match (self.get(key.clone())?, target.get(key.clone())?) {
+            (Some(value), current) if current.as_ref() != Some(&value) => {
+                target.set(key, value)?;
+                stats.updated_keys += 1;
+            }
+            (None, Some(_)) => {
+                target.remove(key)?;
+                stats.removed_keys += 1;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn scan_all(&mut self, prefix: &str) -> Result<BTreeSet<String>> {
+        let mut keys = BTreeSet::new();
+        let mut after = None;
+        loop {
+            let page = self.scan(prefix.to_owned(), after, SCAN_PAGE_SIZE)?;
+            keys.extend(page.keys);
+            match page.next {
+                Some(next) => after = Some(next),
+                None => return Ok(keys)
+            }
+        }
+    }
+}
@@ -1,10 +1,102 @@
+use std::{fmt, str::FromStr};
+
 use serde::{Deserialize,Serialize};
 
+use crate::{auth::Permission, BackupHeader, EngineStats, KvsError, Result};
+
 #[derive(Debug,Serialize,Deserialize)]
 pub enum Request {
     Get { key: String},
     Set { key: String, value: String},
-    Remove { key: String}
+    Remove { key: String},
+    /// 按字典序列出以 `prefix` 开头、大于 `after` 的最多 `limit` 个键
+    Scan { prefix: String, after: Option<String>, limit: usize },
+    /// 以 `prefix` 开头的键在 Merkle 树中的下一层节点，用于比较两个服务器上的数据
+    MerkleChildren { prefix: String },
+    Ping,
+    Auth { user: String, password: Secret },
+    Info,
+    Stats,
+    Compact,
+    Flush,
+    ConfigGet { name: String },
+    ConfigSet { name: String, value: String },
+    /// 最近的慢日志，从新到旧，`count` 为 `None` 时返回默认条数
+    SlowlogGet { count: Option<usize> },
+    SlowlogReset,
+    /// 按键的字典序分页导出以 `prefix` 开头、大于 `after` 的最多 `limit` 个键值
+    Export { prefix: String, after: Option<String>, limit: usize },
+    /// 批量写入键值，`mode` 决定是否覆盖已存在的键
+    Import { entries: Vec<(String, String)>, mode: ImportMode },
+    /// 创建一份一致的备份，之后通过 `BackupChunk` 分块读取
+    Backup,
+    /// 读取备份 `id` 中 `offset` 处的最多 `len` 个字节
+    BackupChunk { id: String, offset: u64, len: usize },
+    /// 客户端校验完备份后释放它，没有释放的备份在空闲超时后被丢弃
+    BackupFinish { id: String },
+    /// 复制：按键的字典序分页读取全量快照，`after` 为上一页的最后一个键
+    ReplSnapshot { after: Option<String> },
+    /// 复制：读取复制日志中序号大于 `after` 的记录，`id` 为从节点所跟随的复制历史
+    ReplTail { id: String, after: u64 },
+    /// 将从节点提升为主节点
+    Promote,
+    /// 集群：候选者请求投票
+    RaftVote(VoteRequest),
+    /// 集群：领导者复制日志，没有日志时作为心跳
+    RaftAppend(AppendRequest),
+    /// 带有请求 ID 的请求，ID 会出现在服务器日志中，响应以 `Traced` 包装返回
+    Traced { id: String, request: Box<Request> }
+}
+
+impl Request {
+    /// 执行请求需要的权限，`None` 表示未登录也可以执行
+    pub fn required_permission(&self) -> Option<(&str, Permission)> {
+        match self {
+            Request::Get { key } => Some((key, Permission::Read)),
+            Request::Set { key, .. } | Request::Remove { key } => Some((key, Permission::Write)),
+            Request::Scan { prefix, .. } | Request::MerkleChildren { prefix } => Some((prefix, Permission::Read)),
+            Request::Ping | Request::Auth { .. } => None,
+            Request::Info
+            | Request::Stats
+            | Request::Compact
+            | Request::Flush
+            | Request::ConfigGet { .. }
+            | Request::ConfigSet { .. }
+            | Request::SlowlogGet { .. }
+            | Request::SlowlogReset
+            | Request::Export { .. }
+            | Request::Import { .. }
+            | Request::Backup
+            | Request::BackupChunk { .. }
+            | Request::BackupFinish { .. }
+            | Request::ReplSnapshot { .. }
+            | Request::ReplTail { .. }
+            | Request::Promote
+            | Request::RaftVote(_)
+            | Request::RaftAppend(_) => Some(("", Permission::Admin)),
+            Request::Traced { request, .. } => request.required_permission()
+        }
+    }
+}
+
+/// 带有请求 ID 的响应，对应 `Request::Traced`
+///
+/// 与具体请求无关的错误（如超过频率限制）不带 ID
+#[derive(Debug,Serialize,Deserialize)]
+pub struct Traced<T> {
+    pub id: String,
+    pub response: T
+}
+
+/// 密码等敏感字段，打印日志时不会输出内容
+#[derive(Clone,Serialize,Deserialize)]
+#[serde(transparent)]
+pub struct Secret(pub String);
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
 }
 
 #[derive(Debug,Serialize,Deserialize)]
@@ -22,5 +114,357 @@ pub enum SetResponse {
 #[derive(Debug,Serialize,Deserialize)]
 pub enum RemoveResponse {
     Ok(()),
+    /// 键不存在，与其他错误区分开，客户端据此返回 `KvsError::KeyNotFound`
+    NotFound,
+    Err(String)
+}
+
+/// 一页扫描结果
+#[derive(Debug,Clone,PartialEq,Eq,Serialize,Deserialize)]
+pub struct ScanPage {
+    /// 按字典序排列的键
+    pub keys: Vec<String>,
+    /// 后面可能还有更多键时，为下一页的起点（作为 `after` 传入）
+    pub next: Option<String>
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum ScanResponse {
+    Ok(ScanPage),
+    Err(String)
+}
+
+/// 一页导出的键值
+#[derive(Debug,Clone,PartialEq,Eq,Serialize,Deserialize)]
+pub struct ExportPage {
+    /// 按键的字典序排列的键值
+    pub entries: Vec<(String, String)>,
+    /// 后面可能还有更多键时，为下一页的起点（作为 `after` 传入）
+    pub next: Option<String>
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum ExportResponse {
+    Ok(ExportPage),
+    Err(String)
+}
+
+/// 导入时如何处理已存在的键
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize)]
+pub enum ImportMode {
+    /// 覆盖已存在的键
+    Overwrite,
+    /// 保留已存在的键，只写入不存在的键
+    SkipExisting
+}
+
+impl FromStr for ImportMode {
+    type Err = KvsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "overwrite" => Ok(ImportMode::Overwrite),
+            "skip-existing" => Ok(ImportMode::SkipExisting),
+            _ => Err(KvsError::StringError(format!("Unknown import mode {}, expected overwrite or skip-existing",s)))
+        }
+    }
+}
+
+/// 导入的结果
+#[derive(Debug,Clone,Default,PartialEq,Eq,Serialize,Deserialize)]
+pub struct ImportStats {
+    /// 写入的键数
+    pub written: u64,
+    /// 因为已存在而跳过的键数
+    pub skipped: u64
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum ImportResponse {
+    Ok(ImportStats),
+    Err(String)
+}
+
+/// 服务端创建的备份
+#[derive(Debug,Serialize,Deserialize)]
+pub struct BackupSession {
+    /// 读取备份时使用的 ID
+    pub id: String,
+    pub header: BackupHeader
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum BackupResponse {
+    Ok(BackupSession),
+    Err(String)
+}
+
+/// 备份中的一块数据，以 base64 编码
+#[derive(Debug,Serialize,Deserialize)]
+pub enum BackupChunkResponse {
+    Ok(String),
+    Err(String)
+}
+
+/// Merkle 树中的一个节点，概括以 `prefix` 开头的所有键
+///
+/// 节点的子节点为前缀多一个字符的节点；等于父节点前缀的键单独作为一个前缀与父节点相同的子节点
+#[derive(Debug,Clone,PartialEq,Eq,Serialize,Deserialize)]
+pub struct MerkleNode {
+    /// 节点中所有键的公共前缀
+    pub prefix: String,
+    /// 键的数量
+    pub keys: u64,
+    /// 按字典序对所有键和值计算的 SHA-256，十六进制
+    pub hash: String
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum MerkleResponse {
+    Ok(Vec<MerkleNode>),
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum PingResponse {
+    Ok(()),
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum AuthResponse {
+    Ok(()),
+    Err(String)
+}
+
+/// 服务器的运行状态
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ServerInfo {
+    /// 服务器版本
+    pub version: String,
+    /// 存储引擎的名称
+    pub engine: String,
+    /// 运行时间
+    pub uptime_seconds: u64,
+    /// 当前的连接数
+    pub connected_clients: usize,
+    /// 启动以来接收的连接总数
+    pub total_connections: u64,
+    /// 启动以来执行的命令总数
+    pub total_commands: u64,
+    /// 复制状态
+    pub replication: ReplicationInfo,
+    /// 集群状态，没有开启集群模式时为 `None`
+    pub cluster: Option<ClusterInfo>
+}
+
+/// 节点在 Raft 集群中的状态
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ClusterInfo {
+    /// 本节点的 ID
+    pub node_id: u64,
+    /// 角色：leader、follower 或 candidate
+    pub role: String,
+    /// 当前任期
+    pub term: u64,
+    /// 领导者的地址，本节点是领导者或还没有选出领导者时为 `None`
+    pub leader: Option<String>,
+    /// 最后一条日志的序号
+    pub last_index: u64,
+    /// 已提交的日志序号
+    pub commit_index: u64,
+    /// 已应用到存储引擎的日志序号
+    pub last_applied: u64
+}
+
+/// 节点的复制状态，`leader` 等字段只有从节点才有
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ReplicationInfo {
+    /// 角色：leader 或 follower
+    pub role: String,
+    /// 复制历史的 ID，主节点重启后会生成新的 ID，从节点需要重新全量同步
+    pub replication_id: String,
+    /// 本节点已提交（从节点为已应用）的最后一条写操作的序号
+    pub seq: u64,
+    /// 主节点地址
+    pub leader: Option<String>,
+    /// 与主节点的连接是否正常
+    pub link_up: Option<bool>,
+    /// 最近一次同步时主节点的序号
+    pub leader_seq: Option<u64>,
+    /// 落后主节点的记录数
+    pub lag: Option<u64>,
+    /// 距最近一次成功同步的毫秒数，还没有同步过时为 `None`
+    pub last_sync_millis: Option<u64>
+}
+
+/// 复制日志中的一条写操作
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub enum Command {
+    Set { key: String, value: String },
+    Remove { key: String }
+}
+
+/// 带序号的写操作，序号在同一个复制历史中连续递增
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct Record {
+    pub seq: u64,
+    pub command: Command
+}
+
+/// Raft 日志中的一条记录，`command` 为 `None` 的是领导者当选时写入的空记录
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct RaftEntry {
+    pub index: u64,
+    pub term: u64,
+    pub command: Option<Command>
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub struct VoteRequest {
+    pub term: u64,
+    pub candidate: u64,
+    pub last_log_index: u64,
+    pub last_log_term: u64
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub struct VoteReply {
+    pub term: u64,
+    pub granted: bool
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub struct AppendRequest {
+    pub term: u64,
+    pub leader: u64,
+    pub prev_log_index: u64,
+    pub prev_log_term: u64,
+    pub entries: Vec<RaftEntry>,
+    pub leader_commit: u64,
+    /// 领导者确认所有节点都已有的最后一条日志，跟随者只压缩到这里
+    #[serde(default)]
+    pub compact_index: u64
+}
+
+/// 成功时 `last_index` 为与领导者一致的最后一条日志，失败时为本节点最后一条日志，帮助领导者回退
+#[derive(Debug,Serialize,Deserialize)]
+pub struct AppendReply {
+    pub term: u64,
+    pub success: bool,
+    pub last_index: u64
+}
+
+/// 全量快照中的一页
+#[derive(Debug,Serialize,Deserialize)]
+pub struct SnapshotPage {
+    /// 复制历史的 ID
+    pub id: String,
+    /// 读取本页之前主节点的序号，从第一页的序号开始追赶复制日志
+    pub seq: u64,
+    pub entries: Vec<(String, String)>,
+    /// 后面还有数据时为本页的最后一个键
+    pub next: Option<String>
+}
+
+/// 复制日志中的一批记录
+#[derive(Debug,Serialize,Deserialize)]
+pub struct ReplBatch {
+    /// 主节点当前的序号
+    pub seq: u64,
+    pub records: Vec<Record>
+}
+
+/// 慢日志中的一条记录
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct SlowLogEntry {
+    /// 递增的编号
+    pub id: u64,
+    /// 记录时的 Unix 时间戳（秒）
+    pub timestamp: u64,
+    /// 执行耗时（微秒），不包括在线程池中排队的时间
+    pub duration_micros: u64,
+    /// 请求使用的协议
+    pub protocol: String,
+    /// 命令名
+    pub command: String,
+    /// 访问的键，多个键时为第一个，扫描时为前缀
+    pub key: Option<String>,
+    /// 客户端地址
+    pub client: String,
+    /// 执行期间是否触发了压缩
+    pub compacted: bool,
+    /// 执行期间是否将数据同步到了磁盘
+    pub synced: bool
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum InfoResponse {
+    Ok(Box<ServerInfo>),
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum StatsResponse {
+    Ok(EngineStats),
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum SlowlogResponse {
+    Ok(Vec<SlowLogEntry>),
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum SnapshotResponse {
+    Ok(SnapshotPage),
+    Err(String)
+}
+
+/// `Resync` 表示请求的记录已不在复制日志中，或复制历史已经改变，从节点需要重新全量同步
+#[derive(Debug,Serialize,Deserialize)]
+pub enum TailResponse {
+    Ok(ReplBatch),
+    Resync,
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum VoteResponse {
+    Ok(VoteReply),
+    Err(String)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum AppendResponse {
+    Ok(AppendReply),
+    Err(String)
+}
+
+/// 集群中的非领导者收到读写请求时的响应，带有领导者的地址，还没有选出领导者时为 `None`
+///
+/// 请求没有被执行，客户端可以向领导者重新发送
+#[derive(Debug,Serialize,Deserialize)]
+pub enum RedirectResponse {
+    Redirect(Option<String>)
+}
+
+#[derive(Debug,Serialize,Deserialize)]
+pub enum ConfigGetResponse {
+    Ok(String),
+    Err(String)
+}
+
+/// 没有返回值的管理请求（压缩、刷盘、修改设置、清空慢日志、提升为主节点）的响应
+#[derive(Debug,Serialize,Deserialize)]
+pub enum AdminResponse {
+    Ok(()),
+    Err(String)
+}
+
+/// 与具体请求无关的错误（如服务器繁忙），可以被任意一种响应类型解析
+#[derive(Debug,Serialize,Deserialize)]
+pub enum ErrorResponse {
     Err(String)
 }
\ No newline at end of file
@@ -0,0 +1,229 @@
+use std::{
+    fmt,
+    io::{BufRead, Write},
+    mem,
+    str::FromStr
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{KvsError, Result};
+
+const CSV_HEADER: [&str; 2] = ["key", "value"];
+
+/// 逻辑导出文件的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// 每行一个 `{"key":...,"value":...}` JSON 对象
+    JsonLines,
+    /// 以 `key,value` 为表头的 CSV（RFC 4180），字段中可以包含逗号、引号和换行
+    Csv
+}
+
+impl FromStr for DumpFormat {
+    type Err = KvsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "jsonl" => Ok(DumpFormat::JsonLines),
+            "csv" => Ok(DumpFormat::Csv),
+            _ => Err(KvsError::StringError(format!("Unknown dump format {}, expected jsonl or csv",s)))
+        }
+    }
+}
+
+impl fmt::Display for DumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DumpFormat::JsonLines => "jsonl",
+            DumpFormat::Csv => "csv"
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    key: &'a str,
+    value: &'a str
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    key: String,
+    value: String
+}
+
+/// 以指定格式逐条写入键值
+pub struct DumpWriter<W: Write> {
+    writer: W,
+    format: DumpFormat,
+    started: bool
+}
+
+impl<W: Write> DumpWriter<W> {
+    /// 创建写入器，CSV 的表头在写入第一条记录（或 `finish`）时写入
+    pub fn new(writer: W, format: DumpFormat) -> Self {
+        DumpWriter { writer, format, started: false }
+    }
+
+    /// 写入一条键值
+    pub fn write(&mut self, key: &str, value: &str) -> Result<()> {
+        self.start()?;
+        match self.format {
+            DumpFormat::JsonLines => {
+                serde_json::to_writer(&mut self.writer, &EntryRef { key, value })?;
+                self.writer.write_all(b"\n")?;
+            }
+            DumpFormat::Csv => write_csv_record(&mut self.writer, [key, value])?
+        }
+        Ok(())
+    }
+
+    /// 写入尚未写入的表头并刷新，返回内部的写入器
+    pub fn finish(mut self) -> Result<W> {
+        self.start()?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            self.started = true;
+            if self.format == DumpFormat::Csv {
+                write_csv_record(&mut self.writer, CSV_HEADER)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_csv_record<W: Write>(writer: &mut W, fields: [&str; 2]) -> Result<()> {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+        if field.contains([',', '"', '\r', '\n']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            writer.write_all(field.as_bytes())?;
+        }
+    }
+    writer.write_all(b"\r\n")?;
+    Ok(())
+}
+
+/// 逐条读取指定格式的键值，跳过空行
+pub struct DumpReader<R: BufRead> {
+    reader: R,
+    format: DumpFormat,
+    // 已经读取的行数，用于错误信息
+    line: usize,
+    started: bool
+}
+
+impl<R: BufRead> DumpReader<R> {
+    /// 创建读取器，CSV 必须以 `key,value` 表头开始
+    pub fn new(reader: R, format: DumpFormat) -> Self {
+        DumpReader { reader, format, line: 0, started: false }
+    }
+
+    fn read_entry(&mut self) -> Result<Option<(String, String)>> {
+        match self.format {
+            DumpFormat::JsonLines => self.read_json_entry(),
+            DumpFormat::Csv => {
+                if !self.started {
+                    self.started = true;
+                    match self.read_csv_record()? {
+                        Some((_, fields)) if fields == CSV_HEADER => {}
+                        Some((line, _)) => return Err(invalid_record(line, "CSV dump must start with a key,value header")),
+                        None => return Ok(None)
+                    }
+                }
+                match self.read_csv_record()? {
+                    Some((_, fields)) if fields.len() == 2 => {
+                        let mut fields = fields.into_iter();
+                        Ok(fields.next().zip(fields.next()))
+                    }
+                    Some((line, fields)) => {
+                        Err(invalid_record(line, &format!("expected 2 fields, found {}",fields.len())))
+                    }
+                    None => Ok(None)
+                }
+            }
+        }
+    }
+
+    fn read_json_entry(&mut self) -> Result<Option<(String, String)>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)
+                .map_err(|e| invalid_record(self.line, &e.to_string()))?;
+            return Ok(Some((entry.key, entry.value)));
+        }
+    }
+
+    /// 读取一条 CSV 记录及其起始行号，带引号的字段可以跨行
+    fn read_csv_record(&mut self) -> Result<Option<(usize, Vec<String>)>> {
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut start = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line)? == 0 {
+                return match start {
+                    Some(start) => Err(invalid_record(start, "unterminated quoted field")),
+                    None => Ok(None)
+                };
+            }
+            self.line += 1;
+            if start.is_none() {
+                if line.trim_end_matches(['\r', '\n']).is_empty() {
+                    continue;
+                }
+                start = Some(self.line);
+            }
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                match (quoted, c) {
+                    (true, '"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    (true, '"') => quoted = false,
+                    (true, c) => field.push(c),
+                    (false, '"') if field.is_empty() => quoted = true,
+                    (false, ',') => fields.push(mem::take(&mut field)),
+                    (false, '\r') | (false, '\n') => {}
+                    (false, c) => field.push(c)
+                }
+            }
+            if !quoted {
+                fields.push(field);
+                return Ok(start.map(|start| (start, fields)));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for DumpReader<R> {
+    type Item = Result<(String, String)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+fn invalid_record(line: usize, reason: &str) -> KvsError {
+    KvsError::StringError(format!("Invalid record at line {}: {}",line,reason))
+}
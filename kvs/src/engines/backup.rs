@@ -0,0 +1,300 @@
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use ring::digest::{Context, SHA256};
+use serde::{Deserialize, Serialize};
+
+use crate::{DumpFormat, DumpReader, DumpWriter, KvStore, KvsEngine, KvsError, Result, SledKvsEngine};
+
+use super::Manifest;
+
+// 备份文件的第一行
+const BACKUP_MAGIC: &str = "kvs-backup 1";
+// 逻辑备份中导出文件的名称
+const LOGICAL_DUMP_FILE: &str = "dump.jsonl";
+// 按序遍历键时每次扫描的键数
+const SCAN_PAGE_SIZE: usize = 1000;
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+/// 备份中文件的内容
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackupFormat {
+    /// `KvStore` 的日志文件，恢复时原样复制到数据目录
+    Segments,
+    /// JSON Lines 格式的所有键值，恢复时逐条写入引擎
+    Logical
+}
+
+/// 备份中的一个文件
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// 文件名
+    pub name: String,
+    /// 文件的字节数
+    pub len: u64
+}
+
+/// 备份的描述，写在备份文件的开头
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupHeader {
+    /// 备份的引擎，恢复时使用同一个引擎
+    pub engine: String,
+    /// 文件的内容
+    pub format: BackupFormat,
+    /// 按顺序排列的文件，内容依次紧跟在备份文件的开头之后
+    pub files: Vec<BackupFile>,
+    /// 所有文件的内容依次计算的 SHA-256（十六进制）
+    pub checksum: String,
+    /// 备份的时间（Unix 时间戳，秒）
+    pub created_at: u64
+}
+
+impl BackupHeader {
+    /// 所有文件的总字节数
+    pub fn len(&self) -> u64 {
+        self.files.iter().map(|file| file.len).sum()
+    }
+
+    /// 备份中是否没有任何数据
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 写入备份文件的开头，之后依次写入各个文件的内容
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "{}", BACKUP_MAGIC)?;
+        serde_json::to_writer(&mut *writer, self)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// 读取备份文件的开头
+    pub fn read_from<R: BufRead>(reader: &mut R) -> Result<BackupHeader> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim_end() != BACKUP_MAGIC {
+            return Err(KvsError::StringError("Not a kvs backup file".to_owned()));
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+        serde_json::from_str(&line).map_err(|e| KvsError::StringError(format!("Invalid backup header: {}",e)))
+    }
+}
+
+/// 一份一致的备份
+///
+/// 备份中的文件在创建时就已打开，之后引擎的写入和压缩（包括删除这些文件）都不会改变读到的内容，
+/// 备份被丢弃时文件句柄随之关闭
+pub struct Backup {
+    header: BackupHeader,
+    files: Vec<File>
+}
+
+impl Backup {
+    /// 由已经不会再被修改的文件创建备份，计算它们的校验和
+    pub(crate) fn new(engine: &str, format: BackupFormat, files: Vec<(String, File)>) -> Result<Backup> {
+        let mut context = Context::new(&SHA256);
+        let mut buf = vec![0; COPY_BUFFER_SIZE];
+        let mut entries = Vec::with_capacity(files.len());
+        let mut handles = Vec::with_capacity(files.len());
+        for (name, file) in files {
+            let len = file.metadata()?.len();
+            let mut offset = 0;
+            while offset < len {
+                let n = file.read_at(&mut buf, offset)?;
+                if n == 0 {
+                    break;
+                }
+                context.update(&buf[..n]);
+                offset += n as u64;
+            }
+            entries.push(BackupFile { name, len });
+            handles.push(file);
+        }
+        let header = BackupHeader {
+            engine: engine.to_owned(),
+            format,
+            files: entries,
+            checksum: hex(context.finish().as_ref()),
+            created_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+        };
+        Ok(Backup { header, files: handles })
+    }
+
+    /// 将引擎中的所有键值导出到临时文件，创建逻辑备份
+    ///
+    /// 导出期间的写入可能部分出现在备份中，调用者需要在导出期间暂停写入
+    pub fn logical<E: KvsEngine>(engine: &E) -> Result<Backup> {
+        let file = Backup::export(engine)?;
+        Backup::from_export(engine, file)
+    }
+
+    /// 将引擎中的所有键值导出到临时文件，调用者需要在导出期间暂停写入
+    pub(crate) fn export<E: KvsEngine>(engine: &E) -> Result<File> {
+        let path = env::temp_dir().join(format!(
+            "kvs-backup-{}-{}",
+            process::id(),
+            NEXT_TEMP_ID.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        // 备份只通过打开的句柄读取，文件关闭时空间随之释放
+        fs::remove_file(&path)?;
+
+        let mut writer = DumpWriter::new(BufWriter::new(&file), DumpFormat::JsonLines);
+        let mut after: Option<String> = None;
+        loop {
+            let keys = engine.scan("", after.as_deref(), SCAN_PAGE_SIZE)?;
+            let last = keys.last().cloned();
+            for key in keys {
+                if let Some(value) = engine.get(key.clone())? {
+                    writer.write(&key, &value)?;
+                }
+            }
+            match last {
+                Some(last) => after = Some(last),
+                None => break
+            }
+        }
+        writer.finish()?;
+        Ok(file)
+    }
+
+    /// 由 `export` 导出的文件创建逻辑备份，计算校验和时不需要再暂停写入
+    pub(crate) fn from_export<E: KvsEngine>(engine: &E, file: File) -> Result<Backup> {
+        Backup::new(engine.name(), BackupFormat::Logical, vec![(LOGICAL_DUMP_FILE.to_owned(), file)])
+    }
+
+    /// 备份的描述
+    pub fn header(&self) -> &BackupHeader {
+        &self.header
+    }
+
+    /// 从所有文件依次拼接的内容中 `offset` 处读取，返回读取的字节数，到达末尾时返回 0
+    pub fn read_at(&self, buf: &mut [u8], mut offset: u64) -> Result<usize> {
+        for (entry, file) in self.header.files.iter().zip(&self.files) {
+            if offset >= entry.len {
+                offset -= entry.len;
+                continue;
+            }
+            let len = buf.len().min((entry.len - offset) as usize);
+            return Ok(file.read_at(&mut buf[..len], offset)?);
+        }
+        Ok(0)
+    }
+}
+
+/// 将备份文件恢复到 `dir`，使用备份的引擎并写入数据目录的清单
+///
+/// `dir` 必须不存在或为空。校验和不一致或文件不完整时删除已恢复的内容并返回错误
+pub fn restore<R: Read>(reader: R, dir: impl AsRef<Path>) -> Result<BackupHeader> {
+    let dir = dir.as_ref();
+    if dir.exists() && fs::read_dir(dir)?.next().is_some() {
+        return Err(KvsError::StringError(format!("Data directory {} is not empty",dir.display())));
+    }
+    let mut reader = BufReader::new(reader);
+    let header = BackupHeader::read_from(&mut reader)?;
+    fs::create_dir_all(dir)?;
+    match restore_files(&mut reader, dir, &header) {
+        Ok(()) => {
+            Manifest::open(dir, &header.engine)?;
+            Ok(header)
+        }
+        Err(e) => {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                let removed = if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+                if let Err(e) = removed {
+                    error!("{:?} cannot be deleted: {}",path,e);
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+fn restore_files<R: BufRead>(reader: &mut R, dir: &Path, header: &BackupHeader) -> Result<()> {
+    let mut context = Context::new(&SHA256);
+    let mut paths = Vec::with_capacity(header.files.len());
+    for file in &header.files {
+        let path = restore_path(dir, header, &file.name)?;
+        let mut writer = BufWriter::new(File::create(&path)?);
+        let copied = io::copy(&mut Hashing { reader: reader.by_ref().take(file.len), context: &mut context }, &mut writer)?;
+        if copied != file.len {
+            return Err(KvsError::StringError(format!("Backup is truncated in {}",file.name)));
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        paths.push(path);
+    }
+    let checksum = hex(context.finish().as_ref());
+    if checksum != header.checksum {
+        return Err(KvsError::StringError(format!(
+            "Backup checksum mismatch: expected {}, found {}",
+            header.checksum,
+            checksum
+        )));
+    }
+
+    if header.format == BackupFormat::Logical {
+        match header.engine.as_str() {
+            "kvs" => import_dump(&KvStore::open(dir)?, &paths)?,
+            "sled" => import_dump(&SledKvsEngine::new(sled::open(dir)?), &paths)?,
+            engine => return Err(KvsError::StringError(format!("Unknown engine {}",engine)))
+        }
+        for path in paths {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// 备份中的文件名只能是简单的文件名：日志文件为 `<gen>.log`，逻辑备份为导出文件
+fn restore_path(dir: &Path, header: &BackupHeader, name: &str) -> Result<PathBuf> {
+    let valid = match header.format {
+        BackupFormat::Segments => {
+            header.engine == "kvs" && name.strip_suffix(".log").is_some_and(|gen| gen.parse::<u64>().is_ok())
+        }
+        BackupFormat::Logical => name == LOGICAL_DUMP_FILE
+    };
+    if !valid {
+        return Err(KvsError::StringError(format!("Invalid file {} in backup of the {} engine",name,header.engine)));
+    }
+    Ok(dir.join(name))
+}
+
+fn import_dump<E: KvsEngine>(engine: &E, paths: &[PathBuf]) -> Result<()> {
+    for path in paths {
+        for entry in DumpReader::new(BufReader::new(File::open(path)?), DumpFormat::JsonLines) {
+            let (key, value) = entry?;
+            engine.set(key, value)?;
+        }
+    }
+    engine.flush()
+}
+
+/// 读取的同时计算校验和
+struct Hashing<'a, R: Read> {
+    reader: R,
+    context: &'a mut Context
+}
+
+impl<R: Read> Read for Hashing<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.context.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}",byte)).collect()
+}
@@ -1,9 +1,10 @@
 use std::{
-    collections::{HashMap, BTreeMap}, 
+    collections::{BTreeMap, btree_map::Entry}, 
     path::{PathBuf, Path}, 
     fs::{File, self, OpenOptions}, 
     io::{Write, Seek, Read, BufWriter, BufReader, SeekFrom, self}, 
-    ops::Range, sync::{Arc, Mutex, atomic::{AtomicU64, Ordering}}, cell::RefCell, thread::AccessError
+    ops::{Bound, Range}, sync::{Arc, Mutex, MutexGuard, atomic::{AtomicU64, Ordering}}, cell::RefCell,
+    time::Instant
 };
 
 use std::ffi::OsStr;
@@ -13,11 +14,13 @@ use crossbeam_skiplist::SkipMap;
 use serde::{Serialize, Deserialize};
 use serde_json::Deserializer;
 
-use crate::{KvsError,Result};
+use tracing::{debug_span, info_span};
 
-use super::KvsEngine;
+use crate::{metrics::metrics,EngineStats,KvsError,Result};
 
-const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
+use super::{backup::{Backup, BackupFormat}, record_event, KvsEngine};
+
+const DEFAULT_COMPACTION_THRESHOLD: u64 = 1024 * 1024;
 
 /// KvStore存储String类型的kv键值对，使用BTreeMap进行存储
 /// 支持set get rm操作
@@ -25,13 +28,11 @@ const COMPACTION_THRESHOLD: u64 = 1024 * 1024;
 /// 采用BTreeMap 提高查询速度
 #[derive(Clone)]
 pub struct KvStore {
-    // 存储日志等数据的目录
-    // path: PathBuf,
-    path: Arc<PathBuf>,
     // readers: HashMap<u64,BufReaderWithPos<File>>,
     reader: KvsStoreReader,
     // writer: BufWriterWithPos<File>,
-    writer: Arc<Mutex<KvsStoreWriter>>,
+    // 只读打开时为 None
+    writer: Option<Arc<Mutex<KvsStoreWriter>>>,
     // 当前日志名
     // current_gen:u64,
     // index:BTreeMap<String,CommandPos>,
@@ -44,11 +45,44 @@ impl KvStore {
     /// 根据给定的路径打开一个kvStore
     /// 如果目录不存在则创建
     pub fn open(path: impl Into<PathBuf>) -> Result<KvStore> {
-        let path = Arc::new(path.into());
-        fs::create_dir_all(&*path)?;
+        let path = path.into();
+        fs::create_dir_all(&path)?;
+        let (mut store, last_gen, uncompacted) = KvStore::load(path)?;
+
+        // 创建新的日志文件进行数据读写
+        let current_gen = last_gen + 1;
+        let path = Arc::clone(&store.reader.path);
+        let writer = new_log_file(&path,current_gen)?;
 
+        let writer = KvsStoreWriter {
+            reader: store.reader.clone(),
+            writer,
+            current_gen,
+            uncompacted,
+            compaction_threshold: DEFAULT_COMPACTION_THRESHOLD,
+            path,
+            index: Arc::clone(&store.index)
+        };
+        store.writer = Some(Arc::new(Mutex::new(writer)));
+        Ok(store)
+    }
+
+    /// 只读地打开已有的 kvStore，不会创建目录或新的日志文件
+    ///
+    /// 用于导出、迁移等只读取数据的场合，写入、压缩和备份都会返回错误
+    pub fn open_read_only(path: impl Into<PathBuf>) -> Result<KvStore> {
+        let path = path.into();
+        if !path.is_dir() {
+            return Err(KvsError::StringError(format!("No kvs store at {}",path.display())));
+        }
+        Ok(KvStore::load(path)?.0)
+    }
+
+    /// 读取目录中的所有日志文件建立索引，返回没有写入者的 kvStore、最后一个日志文件的编号和可以压缩的字节数
+    fn load(path: PathBuf) -> Result<(KvStore, u64, u64)> {
+        let path = Arc::new(path);
         let mut readers = BTreeMap::new();
-        let mut index =Arc::new(SkipMap::new());
+        let index =Arc::new(SkipMap::new());
 
         // 加载并排序日志文件
         let gen_list = sorted_gen_list(&path)?;
@@ -58,36 +92,31 @@ impl KvStore {
         // 遍历所有日志文件，将数据读取到内存中，并在内存中映射文件和文件流的关系
         for &gen in &gen_list {
             let mut reader = BufReaderWithPos::new(File::open(log_path(&path,gen))?)?;
-            uncompacted += load(gen,&mut reader,&*index)?;
+            uncompacted += load(gen,&mut reader,&index)?;
             readers.insert(gen, reader);
         }
 
-        // 创建新的日志文件进行数据读写
-        let current_gen = gen_list.last().unwrap_or(&0) + 1;
-        let writer = new_log_file(&path,current_gen)?;
         let safe_point = Arc::new(AtomicU64::new(0));
-
         let reader = KvsStoreReader {
-            path: Arc::clone(&path),
+            path,
             safe_point,
             readers: RefCell::new(readers),
         };
 
-        let writer = KvsStoreWriter {
-            reader: reader.clone(),
-            writer,
-            current_gen,
-            uncompacted,
-            path: Arc::clone(&path),
-            index: Arc::clone(&index)
-        };
-
-        Ok(KvStore { 
-            path,
+        let store = KvStore {
             reader,
-            writer: Arc::new(Mutex::new(writer)),
+            writer: None,
             index
-        })
+        };
+        Ok((store, gen_list.last().copied().unwrap_or(0), uncompacted))
+    }
+
+    /// 取得写入者，只读打开时返回错误
+    fn writer(&self) -> Result<MutexGuard<'_, KvsStoreWriter>> {
+        match &self.writer {
+            Some(writer) => Ok(writer.lock().unwrap()),
+            None => Err(KvsError::StringError("The kvs store is opened read-only".to_owned()))
+        }
     }
 
     // 创建KvStore
@@ -169,7 +198,8 @@ impl KvsEngine for KvStore {
         // }
         // Ok(())
 
-        self.writer.lock().unwrap().set(key, value)
+        let _span = debug_span!("engine", op = "set").entered();
+        self.writer()?.set(key, value)
 
     }
 
@@ -191,6 +221,7 @@ impl KvsEngine for KvStore {
         //     Ok(None)
         // }
 
+        let _span = debug_span!("engine", op = "get").entered();
         if let Some(cmd_pos) = self.index.get(&key) {
             if let Command::Set { value,.. } = self.reader.read_command(*cmd_pos.value())? {
                 Ok(Some(value))
@@ -198,7 +229,7 @@ impl KvsEngine for KvStore {
                 Err(KvsError::UnexceptedCommandType)
             }
         } else {
-            Err(KvsError::KeyNotFound)
+            Ok(None)
         }
     }
 
@@ -216,17 +247,84 @@ impl KvsEngine for KvStore {
         // } else {
         //     Err(KvsError::KeyNotFound)
         // }
-        self.writer.lock().unwrap().remove(key)
+        let _span = debug_span!("engine", op = "remove").entered();
+        self.writer()?.remove(key)
+    }
+
+    /// 将写缓冲刷入当前日志文件并同步到磁盘，只读打开时没有需要刷入的数据
+    fn flush(&self) -> Result<()> {
+        match &self.writer {
+            Some(writer) => writer.lock().unwrap().sync(),
+            None => Ok(())
+        }
+    }
+
+    /// 直接遍历内存中的有序索引，不需要读取日志文件
+    fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let start = match after {
+            Some(after) if after >= prefix => Bound::Excluded(after),
+            _ => Bound::Included(prefix)
+        };
+        Ok(self.index.range::<str, _>((start, Bound::Unbounded))
+            .map(|entry| entry.key().clone())
+            .take_while(|key| key.starts_with(prefix))
+            .take(limit)
+            .collect())
+    }
+
+    fn name(&self) -> &'static str {
+        "kvs"
+    }
+
+    /// 有效数据为索引中每条记录在日志中的长度之和，只读打开时不统计可以压缩的数据
+    fn stats(&self) -> Result<EngineStats> {
+        let writer = self.writer.as_ref().map(|writer| writer.lock().unwrap());
+        let gens = sorted_gen_list(&self.reader.path)?;
+        let mut disk_bytes = 0;
+        for &gen in &gens {
+            disk_bytes += fs::metadata(log_path(&self.reader.path, gen))?.len();
+        }
+        Ok(EngineStats {
+            keys: self.index.len() as u64,
+            disk_bytes,
+            live_bytes: Some(self.index.iter().map(|entry| entry.value().len).sum()),
+            dead_bytes: writer.map(|writer| writer.uncompacted),
+            segments: Some(gens.len() as u64)
+        })
+    }
+
+    fn compact(&self) -> Result<()> {
+        self.writer()?.compact()
+    }
+
+    fn compaction_threshold(&self) -> Option<u64> {
+        self.writer.as_ref().map(|writer| writer.lock().unwrap().compaction_threshold)
+    }
+
+    /// 新的阈值在下一次写入时生效
+    fn set_compaction_threshold(&self, bytes: u64) -> Result<()> {
+        self.writer()?.compaction_threshold = bytes;
+        Ok(())
+    }
+
+    /// 切换到新的日志文件后备份之前的所有日志文件，它们已经不会再被写入，不需要暂停写入
+    fn backup(&self) -> Result<Backup> {
+        let files = self.writer()?.seal()?;
+        Backup::new(self.name(), BackupFormat::Segments, files)
+    }
+
+    /// `backup` 只在封存日志文件时持有写入锁
+    fn backup_needs_pause(&self) -> bool {
+        false
     }
 }
 
 /// 新建一个日志文件
 fn new_log_file(path:&Path,gen:u64) -> Result<BufWriterWithPos<File>> {
-    let path = log_path(&path, gen);
+    let path = log_path(path, gen);
     let writer = BufWriterWithPos::new(
         OpenOptions::new()
             .create(true)
-            .write(true)
             .append(true)
             .open(&path)?,
     )?;
@@ -270,7 +368,7 @@ fn log_path(dir:&Path,gen:u64) -> PathBuf {
 
 // 对文件进行排序（按文件名）
 fn sorted_gen_list(path: &Path) -> Result<Vec<u64>> {
-    let mut gen_list:Vec<u64> = fs::read_dir(&path)?
+    let mut gen_list:Vec<u64> = fs::read_dir(path)?
         .flat_map(|res| -> Result<_> {Ok(res?.path())})
         .filter(|path| path.is_file() && path.extension() == Some("log".as_ref()))
         .flat_map(|path|{
@@ -316,10 +414,10 @@ struct  BufReaderWithPos<R: Read + Seek> {
 
 impl<R: Read + Seek> BufReaderWithPos<R> {
     fn new(mut inner:R) -> Result<Self> {
-        let pos = inner.seek(SeekFrom::Current(0))?;
+        let pos = inner.stream_position()?;
         Ok(BufReaderWithPos { 
             reader: BufReader::new(inner), 
-            pos: pos })
+            pos })
     }
 }
 
@@ -346,10 +444,10 @@ struct BufWriterWithPos<W: Write + Seek> {
 
 impl<W: Write + Seek> BufWriterWithPos<W> {
     fn new(mut inner: W) -> Result<Self> {
-        let pos = inner.seek(SeekFrom::Current(0))?;
+        let pos = inner.stream_position()?;
         Ok(BufWriterWithPos { 
             writer: BufWriter::new(inner), 
-            pos: pos })
+            pos })
     }
 }
 
@@ -401,14 +499,17 @@ impl KvsStoreReader {
 
         let mut readers = self.readers.borrow_mut();
         // 文件不存在就新建一个
-        if !readers.contains_key(&cmd_pos.gen) {
-            let reader = BufReaderWithPos::new(File::open(log_path(&self.path, cmd_pos.gen))?)?;
-            readers.insert(cmd_pos.gen, reader);
-        }
+        let reader = match readers.entry(cmd_pos.gen) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let reader = BufReaderWithPos::new(File::open(log_path(&self.path, cmd_pos.gen))?)?;
+                entry.insert(reader)
+            }
+        };
 
         // 读取数据并交与闭包处理
-        let reader = readers.get_mut(&cmd_pos.gen).unwrap();
         reader.seek(SeekFrom::Start(cmd_pos.pos))?;
+        metrics().engine_read_bytes.inc_by(cmd_pos.len);
         let cmd_reader = reader.take(cmd_pos.len);
         f(cmd_reader)
     }
@@ -437,6 +538,7 @@ struct KvsStoreWriter {
     writer: BufWriterWithPos<File>,
     current_gen: u64,
     uncompacted: u64,
+    compaction_threshold: u64,
     path: Arc<PathBuf>,
     index: Arc<SkipMap<String,CommandPos>>
 }
@@ -448,6 +550,7 @@ impl KvsStoreWriter {
         serde_json::to_writer(&mut self.writer, &cmd)?;
         self.writer.flush()?;
 
+        metrics().engine_written_bytes.inc_by(self.writer.pos - pos);
         if let Command::Set { key, ..} = cmd {
             // 判断是否已经存在过这个key,如果存在标识set操作被多次操作，增加未压缩量
             if let Some(old_cmd) = self.index.get(&key) {
@@ -457,7 +560,7 @@ impl KvsStoreWriter {
             self.index.insert(key, (self.current_gen,pos..self.writer.pos).into());
         }
 
-        if self.uncompacted > COMPACTION_THRESHOLD {
+        if self.uncompacted > self.compaction_threshold {
             self.compact()?;
         }
 
@@ -470,6 +573,7 @@ impl KvsStoreWriter {
             let pos = self.writer.pos;
             serde_json::to_writer(&mut self.writer, &cmd)?;
             self.writer.flush()?;
+            metrics().engine_written_bytes.inc_by(self.writer.pos - pos);
             if let Command::Remove { key } = cmd {
                 // 多次remove同一个key,可压缩成最后一次remove
                 let old_cmd = self.index.remove(&key).expect("key not found");
@@ -479,7 +583,7 @@ impl KvsStoreWriter {
 
             }
 
-            if self.uncompacted > COMPACTION_THRESHOLD {
+            if self.uncompacted > self.compaction_threshold {
                 self.compact()?;
             }
 
@@ -489,8 +593,31 @@ impl KvsStoreWriter {
         }
     }
 
+    fn sync(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.writer.writer.get_ref().sync_all()?;
+        record_event(|events| events.synced = true);
+        Ok(())
+    }
+
+    /// 切换到新的日志文件，返回之前所有日志文件的文件名和打开的句柄
+    ///
+    /// 之后的压缩可能删除这些文件，已经打开的句柄仍然可以读取
+    fn seal(&mut self) -> Result<Vec<(String, File)>> {
+        self.writer.flush()?;
+        self.current_gen += 1;
+        self.writer = new_log_file(&self.path, self.current_gen)?;
+        sorted_gen_list(&self.path)?
+            .into_iter()
+            .filter(|&gen| gen < self.current_gen)
+            .map(|gen| Ok((format!("{}.log",gen), File::open(log_path(&self.path, gen))?)))
+            .collect()
+    }
+
     fn compact(&mut self) -> Result<()> {
+        let started = Instant::now();
         let compaction_gen = self.current_gen + 1;
+        let _span = info_span!("compaction", gen = compaction_gen).entered();
         self.current_gen += 2;
         self.writer = new_log_file(&self.path, self.current_gen)?;
         let mut compaction_writer = new_log_file(&self.path, compaction_gen)?;
@@ -510,6 +637,7 @@ impl KvsStoreWriter {
         }
 
         compaction_writer.flush()?;
+        metrics().engine_written_bytes.inc_by(new_pos);
 
         // 设置新的保存点,并删除之前的日志文件
         self.reader.safe_point.store(compaction_gen, Ordering::SeqCst);
@@ -525,7 +653,11 @@ impl KvsStoreWriter {
             }
         }
 
+        info!("Reclaimed {} bytes in {:?}",self.uncompacted,started.elapsed());
         self.uncompacted = 0;
+        record_event(|events| events.compacted = true);
+        metrics().compactions.inc_by(1);
+        metrics().compaction_duration.observe_since(started);
 
         Ok(())
     }
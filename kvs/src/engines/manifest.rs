@@ -0,0 +1,170 @@
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH}
+};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::{KvsError, Result};
+
+const MANIFEST_FILE: &str = "MANIFEST";
+// 引入清单之前只记录引擎名称的文件
+const LEGACY_ENGINE_FILE: &str = "engine";
+const STORE_ID_LEN: usize = 16;
+
+/// 数据目录的清单，记录数据由哪个引擎、以哪个版本的磁盘格式写入
+///
+/// 清单以 JSON 保存在数据目录的 `MANIFEST` 文件中。打开数据目录时校验引擎是否一致，
+/// 格式版本比当前版本旧时依次执行升级，比当前版本新时拒绝打开
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// 引擎的名称，与 `KvsEngine::name` 相同
+    pub engine: String,
+    /// 磁盘格式的版本，0 表示只有 `engine` 文件的旧数据目录
+    pub format_version: u32,
+    /// 数据目录创建的时间（Unix 时间戳，秒）
+    pub created_at: u64,
+    /// 数据目录的唯一标识，创建时随机生成
+    pub store_id: String
+}
+
+impl Manifest {
+    /// 读取数据目录中的清单，没有清单时读取旧版本的 `engine` 文件，两者都没有时返回 `None`
+    pub fn load(dir: impl AsRef<Path>) -> Result<Option<Manifest>> {
+        let dir = dir.as_ref();
+        let path = dir.join(MANIFEST_FILE);
+        if path.exists() {
+            let manifest = serde_json::from_slice(&fs::read(&path)?)
+                .map_err(|e| KvsError::StringError(format!("Invalid manifest {}: {}",path.display(),e)))?;
+            return Ok(Some(manifest));
+        }
+        let legacy = dir.join(LEGACY_ENGINE_FILE);
+        if legacy.exists() {
+            let engine = fs::read_to_string(&legacy)?.trim().to_owned();
+            if current_format_version(&engine).is_none() {
+                return Err(KvsError::StringError(format!("Invalid engine file {}: {:?}",legacy.display(),engine)));
+            }
+            return Ok(Some(Manifest { engine, format_version: 0, created_at: now(), store_id: new_store_id()? }));
+        }
+        Ok(None)
+    }
+
+    /// 以 `engine` 打开数据目录：没有清单时新建，引擎不一致或格式版本过新时返回错误，
+    /// 格式版本过旧时升级数据目录并更新清单
+    pub fn open(dir: impl AsRef<Path>, engine: &str) -> Result<Manifest> {
+        let dir = dir.as_ref();
+        let current = current_format_version(engine)
+            .ok_or_else(|| KvsError::StringError(format!("Unknown engine {}",engine)))?;
+        let mut manifest = match Manifest::load(dir)? {
+            Some(manifest) => manifest,
+            None => {
+                fs::create_dir_all(dir)?;
+                let manifest = Manifest {
+                    engine: engine.to_owned(),
+                    format_version: current,
+                    created_at: now(),
+                    store_id: new_store_id()?
+                };
+                manifest.save(dir)?;
+                info!("Created data directory {} with store id {}",dir.display(),manifest.store_id);
+                return Ok(manifest);
+            }
+        };
+        manifest.check(engine, current)?;
+        while manifest.format_version < current {
+            info!("Upgrading data directory {} from format version {}",dir.display(),manifest.format_version);
+            upgrade(dir, &manifest)?;
+            manifest.format_version += 1;
+            manifest.save(dir)?;
+        }
+        Ok(manifest)
+    }
+
+    /// 校验清单由 `engine` 写入且格式版本就是当前版本，用于只读地打开数据目录，不会升级旧版本
+    pub fn verify(&self, engine: &str) -> Result<()> {
+        let current = current_format_version(engine)
+            .ok_or_else(|| KvsError::StringError(format!("Unknown engine {}",engine)))?;
+        self.check(engine, current)?;
+        if self.format_version < current {
+            return Err(KvsError::StringError(format!(
+                "Data directory format version {} is older than the supported version {}, start kvs-server on it once to upgrade it",
+                self.format_version,
+                current
+            )));
+        }
+        Ok(())
+    }
+
+    /// 校验引擎一致且格式版本不比 `current` 新
+    fn check(&self, engine: &str, current: u32) -> Result<()> {
+        if self.engine != engine {
+            return Err(KvsError::WrongEngine(self.engine.clone(), engine.to_owned()));
+        }
+        if self.format_version > current {
+            return Err(KvsError::StringError(format!(
+                "Data directory format version {} is newer than the supported version {}",
+                self.format_version,
+                current
+            )));
+        }
+        Ok(())
+    }
+
+    /// 为从本数据目录迁移出的 `dir` 写入清单：引擎和格式版本换成 `engine` 的，存储 ID 和创建时间保持不变
+    pub fn migrated(&self, dir: impl AsRef<Path>, engine: &str) -> Result<Manifest> {
+        let format_version = current_format_version(engine)
+            .ok_or_else(|| KvsError::StringError(format!("Unknown engine {}",engine)))?;
+        let manifest = Manifest { engine: engine.to_owned(), format_version, ..self.clone() };
+        manifest.save(dir.as_ref())?;
+        Ok(manifest)
+    }
+
+    /// 先写入临时文件再重命名，避免中途失败时留下不完整的清单
+    fn save(&self, dir: &Path) -> Result<()> {
+        let tmp = dir.join(format!("{}.tmp",MANIFEST_FILE));
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp, dir.join(MANIFEST_FILE))?;
+        Ok(())
+    }
+}
+
+/// 引擎当前的磁盘格式版本，未知的引擎返回 `None`
+fn current_format_version(engine: &str) -> Option<u32> {
+    match engine {
+        "kvs" | "sled" => Some(1),
+        _ => None
+    }
+}
+
+/// 将数据目录从 `manifest.format_version` 升级到下一个版本，完成后由调用者更新清单
+fn upgrade(dir: &Path, manifest: &Manifest) -> Result<()> {
+    match (manifest.engine.as_str(), manifest.format_version) {
+        // 数据文件的格式没有变化，先写入清单再删除旧的 engine 文件，中途失败时可以重新升级
+        (_, 0) => {
+            manifest.save(dir)?;
+            let legacy = dir.join(LEGACY_ENGINE_FILE);
+            if legacy.exists() {
+                fs::remove_file(legacy)?;
+            }
+            Ok(())
+        }
+        (engine, version) => Err(KvsError::StringError(format!(
+            "No upgrade from format version {} of the {} engine",
+            version,
+            engine
+        )))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn new_store_id() -> Result<String> {
+    let mut id = [0; STORE_ID_LEN];
+    SystemRandom::new().fill(&mut id)
+        .map_err(|_| KvsError::StringError("Failed to generate store id".to_owned()))?;
+    Ok(id.iter().map(|byte| format!("{:02x}",byte)).collect())
+}
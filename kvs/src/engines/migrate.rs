@@ -0,0 +1,87 @@
+use ring::digest::{Context, SHA256};
+
+use crate::{KvsEngine, KvsError, Result};
+
+// 按序遍历键时每次扫描的键数
+const SCAN_PAGE_SIZE: usize = 1000;
+
+/// 一次迁移的统计
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationStats {
+    /// 迁移的键数
+    pub keys: u64,
+    /// 迁移的键和值的总字节数
+    pub bytes: u64,
+    /// 所有键值按键的字典序计算的 SHA-256（十六进制），源和目标一致时才会返回
+    pub checksum: String
+}
+
+/// 把 `source` 中的所有键值写入 `target`，完成后重新读取两边校验键数和校验和
+///
+/// 按键的字典序分页读取，不需要把所有数据放进内存。迁移期间 `source` 不能被写入，
+/// `target` 应该是空的，否则校验会失败
+pub fn migrate<S: KvsEngine, T: KvsEngine>(source: &S, target: &T) -> Result<MigrationStats> {
+    let mut copied = 0;
+    for_each_pair(source, |key, value| {
+        target.set(key, value)?;
+        copied += 1;
+        if copied % 100_000 == 0 {
+            info!("Migrated {} keys",copied);
+        }
+        Ok(())
+    })?;
+    target.flush()?;
+
+    let expected = checksum(source)?;
+    let actual = checksum(target)?;
+    if expected != actual {
+        return Err(KvsError::StringError(format!(
+            "Migration verification failed: source has {} keys with checksum {}, target has {} keys with checksum {}",
+            expected.keys,
+            expected.checksum,
+            actual.keys,
+            actual.checksum
+        )));
+    }
+    Ok(actual)
+}
+
+/// 统计引擎中的键数、字节数，并计算所有键值的校验和
+pub fn checksum<E: KvsEngine>(engine: &E) -> Result<MigrationStats> {
+    let mut stats = MigrationStats::default();
+    let mut context = Context::new(&SHA256);
+    for_each_pair(engine, |key, value| {
+        stats.keys += 1;
+        stats.bytes += (key.len() + value.len()) as u64;
+        for field in [key.as_bytes(), value.as_bytes()] {
+            context.update(&(field.len() as u64).to_be_bytes());
+            context.update(field);
+        }
+        Ok(())
+    })?;
+    stats.checksum = context.finish().as_ref().iter().map(|byte| format!("{:02x}",byte)).collect();
+    Ok(stats)
+}
+
+/// 按键的字典序遍历所有键值
+fn for_each_pair<E, F>(engine: &E, mut f: F) -> Result<()>
+where
+    E: KvsEngine,
+    F: FnMut(String, String) -> Result<()>
+{
+    let mut after: Option<String> = None;
+    loop {
+        let keys = engine.scan("", after.as_deref(), SCAN_PAGE_SIZE)?;
+        let last = keys.last().cloned();
+        for key in keys {
+            // 扫描到键之后它可能已经被删除
+            if let Some(value) = engine.get(key.clone())? {
+                f(key, value)?;
+            }
+        }
+        match last {
+            Some(last) => after = Some(last),
+            None => return Ok(())
+        }
+    }
+}
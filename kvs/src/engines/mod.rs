@@ -1,4 +1,8 @@
-use crate::Result;
+use std::cell::Cell;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{KvsError, Result};
 
 /// kvs engine 定义
 pub trait KvsEngine: Clone + Send + 'static {
@@ -8,10 +12,105 @@ pub trait KvsEngine: Clone + Send + 'static {
   fn get(&self, key: String) -> Result<Option<String>>;
   /// 删除数据
   fn remove(&self, key: String) -> Result<()>;
+  /// 将缓冲的数据刷写并同步到磁盘
+  fn flush(&self) -> Result<()> {
+    Err(unsupported(self, "flush"))
+  }
+  /// 按字典序返回以 `prefix` 开头、且大于 `after` 的最多 `limit` 个键
+  fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+    let _ = (prefix, after, limit);
+    Err(unsupported(self, "scan"))
+  }
+  /// 引擎的名称
+  fn name(&self) -> &'static str {
+    "custom"
+  }
+  /// 键的数量及存储空间的使用情况
+  fn stats(&self) -> Result<EngineStats> {
+    Err(unsupported(self, "stats"))
+  }
+  /// 立即压缩存储，回收被覆盖和删除的数据占用的空间
+  fn compact(&self) -> Result<()> {
+    Err(unsupported(self, "compact"))
+  }
+  /// 自动压缩的阈值：可回收的字节数超过该值时自动压缩，引擎不支持时返回 `None`
+  fn compaction_threshold(&self) -> Option<u64> {
+    None
+  }
+  /// 修改自动压缩的阈值
+  fn set_compaction_threshold(&self, bytes: u64) -> Result<()> {
+    let _ = bytes;
+    Err(unsupported(self, "compaction_threshold"))
+  }
+  /// 生成一份一致的备份，默认通过 `scan` 逐个导出所有键值，导出期间调用者需要暂停写入
+  fn backup(&self) -> Result<Backup> {
+    Backup::logical(self)
+  }
+  /// 生成备份期间调用者是否需要暂停写入
+  ///
+  /// 返回 `true` 时服务器在暂停写入期间生成逻辑备份，而不调用 `backup`；
+  /// 自己保证 `backup` 一致的引擎应返回 `false`
+  fn backup_needs_pause(&self) -> bool {
+    true
+  }
+}
+
+/// 引擎没有实现 `operation` 时返回的错误
+pub(crate) fn unsupported<E: KvsEngine>(engine: &E, operation: &str) -> KvsError {
+  KvsError::Unsupported(operation.to_owned(), engine.name().to_owned())
+}
+
+/// 存储引擎的统计信息，引擎不统计的项为 `None`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EngineStats {
+  /// 键的数量
+  pub keys: u64,
+  /// 数据文件占用的磁盘空间
+  pub disk_bytes: u64,
+  /// 有效数据占用的字节数
+  pub live_bytes: Option<u64>,
+  /// 被覆盖或删除、可以通过压缩回收的字节数
+  pub dead_bytes: Option<u64>,
+  /// 日志文件的数量
+  pub segments: Option<u64>
+}
+
+/// 当前线程上的引擎操作顺带完成的额外工作，慢日志据此说明请求慢的原因
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct EngineEvents {
+  /// 触发了压缩
+  pub(crate) compacted: bool,
+  /// 将数据同步到了磁盘
+  pub(crate) synced: bool
+}
+
+thread_local! {
+  static EVENTS: Cell<EngineEvents> = Cell::new(EngineEvents::default());
+}
+
+/// 取出并清空当前线程记录的事件
+pub(crate) fn take_events() -> EngineEvents {
+  EVENTS.with(Cell::take)
+}
+
+fn record_event(f: impl FnOnce(&mut EngineEvents)) {
+  EVENTS.with(|events| {
+    let mut current = events.get();
+    f(&mut current);
+    events.set(current);
+  });
 }
 
+mod backup;
 mod kvs;
+mod manifest;
+mod migrate;
+mod proxy;
 mod sled;
 
+pub use self::backup::{restore, Backup, BackupFile, BackupFormat, BackupHeader};
 pub use self::kvs::KvStore;
+pub use self::manifest::Manifest;
+pub use self::migrate::{checksum, migrate, MigrationStats};
+pub use self::proxy::{ProxyEngine, ProxyRouting};
 pub use self::sled::SledKvsEngine;
@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use crate::{
+    client::{is_connection_error, sharded::{merge_pages, HashRing}},
+    EngineStats,
+    KvsClient,
+    KvsClientBuilder,
+    KvsClientPool,
+    KvsEngine,
+    KvsError,
+    Result,
+    ServerAddr
+};
+
+/// 代理把键分配到后端的方式
+#[derive(Debug, Clone)]
+pub enum ProxyRouting {
+    /// 按一致性哈希分配，与 `ShardedKvsClient` 的路由结果相同
+    Hash(Vec<ServerAddr>),
+    /// 按键的范围分配：每项为范围的起始键和负责的后端，按起始键升序排列，第一项的起始键必须为空。
+    /// 键属于起始键不大于它的最后一个范围
+    Ranges(Vec<(String, ServerAddr)>)
+}
+
+/// 把读写转发到多个后端 kvs-server 的引擎，`kvs-proxy` 用它在 `KvsServer` 中对客户端提供服务
+///
+/// 每个后端使用一个连接池。后端无法连接时返回 `BackendUnavailable`，其他后端的键不受影响；
+/// 扫描需要访问所有后端，任何一个后端不可用时都会失败
+#[derive(Clone)]
+pub struct ProxyEngine {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    backends: Vec<Backend>,
+    router: Router
+}
+
+struct Backend {
+    addr: ServerAddr,
+    pool: KvsClientPool
+}
+
+enum Router {
+    Hash(HashRing),
+    // 范围的起始键和后端的下标
+    Ranges(Vec<(String, usize)>)
+}
+
+impl ProxyEngine {
+    /// `builder` 为连接后端共用的配置（超时、重试、TLS、登录等），其中不应设置地址；
+    /// 每个后端最多同时保持 `pool_size` 个连接
+    pub fn new(routing: ProxyRouting, builder: KvsClientBuilder, pool_size: usize) -> Result<Self> {
+        let (addrs, router) = match routing {
+            ProxyRouting::Hash(addrs) => {
+                let ring = HashRing::new(&addrs)?;
+                (addrs, Router::Hash(ring))
+            }
+            ProxyRouting::Ranges(ranges) => {
+                if ranges.first().is_none_or(|(start, _)| !start.is_empty()) {
+                    return Err(KvsError::StringError("The first key range must start with the empty key".to_owned()));
+                }
+                let mut addrs: Vec<ServerAddr> = Vec::new();
+                let mut table = Vec::with_capacity(ranges.len());
+                for (start, addr) in ranges {
+                    if table.last().is_some_and(|(last, _): &(String, usize)| *last >= start) {
+                        return Err(KvsError::StringError(format!("Key ranges must be sorted by start key: {:?}",start)));
+                    }
+                    let index = match addrs.iter().position(|known| *known == addr) {
+                        Some(index) => index,
+                        None => {
+                            addrs.push(addr);
+                            addrs.len() - 1
+                        }
+                    };
+                    table.push((start, index));
+                }
+                (addrs, Router::Ranges(table))
+            }
+        };
+        let backends = addrs.into_iter()
+            .map(|addr| Backend {
+                pool: KvsClientPool::new(builder.clone().addr(addr.clone()), pool_size),
+                addr
+            })
+            .collect();
+        Ok(ProxyEngine { inner: Arc::new(Inner { backends, router }) })
+    }
+
+    /// 所有后端的地址
+    pub fn backends(&self) -> impl Iterator<Item = &ServerAddr> {
+        self.inner.backends.iter().map(|backend| &backend.addr)
+    }
+
+    /// 负责 `key` 的后端
+    pub fn backend_for(&self, key: &str) -> &ServerAddr {
+        &self.backend(key).addr
+    }
+
+    fn backend(&self, key: &str) -> &Backend {
+        let index = match &self.inner.router {
+            Router::Hash(ring) => ring.index_for(key),
+            Router::Ranges(table) => {
+                let range = table.partition_point(|(start, _)| start.as_str() <= key);
+                table[range - 1].1
+            }
+        };
+        &self.inner.backends[index]
+    }
+
+    /// 所有后端依次执行 `f`
+    fn each<T>(&self, f: impl Fn(&mut KvsClient) -> Result<T>) -> Result<Vec<T>> {
+        self.inner.backends.iter().map(|backend| backend.call(&f)).collect()
+    }
+}
+
+impl Backend {
+    /// 从连接池取出连接执行 `f`，把连接错误转换为 `BackendUnavailable`
+    fn call<T>(&self, f: impl FnOnce(&mut KvsClient) -> Result<T>) -> Result<T> {
+        let res = self.pool.get().and_then(|mut client| f(&mut client));
+        match res {
+            Ok(value) => Ok(value),
+            Err(e) if is_connection_error(&e) => {
+                warn!("Backend {} is unavailable: {}",self.addr,e);
+                Err(KvsError::BackendUnavailable(self.addr.to_string(), e.to_string()))
+            }
+            Err(e) => Err(e)
+        }
+    }
+}
+
+impl KvsEngine for ProxyEngine {
+    fn set(&self, key: String, value: String) -> Result<()> {
+        self.backend(&key).call(|client| client.set(key, value))
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.backend(&key).call(|client| client.get(key))
+    }
+
+    fn remove(&self, key: String) -> Result<()> {
+        self.backend(&key).call(|client| client.remove(key))
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.each(|client| client.flush()).map(|_| ())
+    }
+
+    /// 合并所有后端的扫描结果；合并后不足 `limit` 个键但后端还有更多键时继续扫描，
+    /// 使返回的键数少于 `limit` 时表示已经没有更多的键
+    fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        let mut after = after.map(str::to_owned);
+        while keys.len() < limit {
+            let want = limit - keys.len();
+            let pages = self.each(|client| client.scan(prefix.to_owned(), after.clone(), want))?;
+            let page = merge_pages(pages, want);
+            keys.extend(page.keys);
+            match page.next {
+                Some(next) => after = Some(next),
+                None => break
+            }
+        }
+        Ok(keys)
+    }
+
+    fn name(&self) -> &'static str {
+        "proxy"
+    }
+
+    /// 所有后端的统计之和，某一项只有所有后端都统计时才有值
+    fn stats(&self) -> Result<EngineStats> {
+        let sum = |a: Option<u64>, b: Option<u64>| a.zip(b).map(|(a, b)| a + b);
+        let all = self.each(|client| client.engine_stats())?;
+        Ok(all.into_iter().fold(
+            EngineStats { live_bytes: Some(0), dead_bytes: Some(0), segments: Some(0), ..EngineStats::default() },
+            |total, stats| EngineStats {
+                keys: total.keys + stats.keys,
+                disk_bytes: total.disk_bytes + stats.disk_bytes,
+                live_bytes: sum(total.live_bytes, stats.live_bytes),
+                dead_bytes: sum(total.dead_bytes, stats.dead_bytes),
+                segments: sum(total.segments, stats.segments)
+            }
+        ))
+    }
+
+    fn compact(&self) -> Result<()> {
+        self.each(|client| client.compact()).map(|_| ())
+    }
+
+    fn compaction_threshold(&self) -> Option<u64> {
+        None
+    }
+
+    fn set_compaction_threshold(&self, _bytes: u64) -> Result<()> {
+        Err(KvsError::StringError("The proxy does not compact, configure each backend instead".to_owned()))
+    }
+}
@@ -1,6 +1,11 @@
+use std::ops::Bound;
+
 use sled::{Db, Tree};
+use tracing::debug_span;
+
+use crate::{metrics::metrics, EngineStats, KvsEngine, Result, KvsError};
 
-use crate::{KvsEngine,Result, KvsError};
+use super::record_event;
 
 /// 使用sled进行存储
 #[derive(Clone)]
@@ -15,15 +20,20 @@ impl SledKvsEngine {
 
 impl KvsEngine for SledKvsEngine {
     fn set(&self, key: String, value: String) -> Result<()> {
+        let _span = debug_span!("engine", op = "set").entered();
         let tree: &Tree = &self.0;
+        metrics().engine_written_bytes.inc_by((key.len() + value.len()) as u64);
         tree.insert(key,value.into_bytes()).map(|_| ())?;
         tree.flush()?;
+        record_event(|events| events.synced = true);
         Ok(())
     }
 
     fn get(&self, key: String) -> Result<Option<String>> {
+        let _span = debug_span!("engine", op = "get").entered();
         let tree: &Tree  = &self.0;
         Ok(tree.get(key)?
+            .inspect(|i_vec| metrics().engine_read_bytes.inc_by(i_vec.len() as u64))
             .map(|i_vec| AsRef::<[u8]>::as_ref(&i_vec).to_vec())
             .map(String::from_utf8)
             .transpose()?
@@ -31,9 +41,58 @@ impl KvsEngine for SledKvsEngine {
     }
 
     fn remove(&self, key: String) ->Result<()> {
+        let _span = debug_span!("engine", op = "remove").entered();
         let tree: &Tree = &self.0;
         tree.remove(key)?.ok_or(KvsError::KeyNotFound)?;
         tree.flush()?;
+        record_event(|events| events.synced = true);
+        Ok(())
+    }
+
+    fn flush(&self) -> Result<()> {
+        self.0.flush()?;
+        record_event(|events| events.synced = true);
         Ok(())
     }
-}
\ No newline at end of file
+
+    fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<Vec<String>> {
+        let start = match after {
+            Some(after) if after >= prefix => Bound::Excluded(after.as_bytes()),
+            _ => Bound::Included(prefix.as_bytes())
+        };
+        let mut keys = Vec::new();
+        for entry in self.0.range::<&[u8], _>((start, Bound::Unbounded)) {
+            let key = String::from_utf8(entry?.0.to_vec())?;
+            if !key.starts_with(prefix) || keys.len() >= limit {
+                break;
+            }
+            keys.push(key);
+        }
+        Ok(keys)
+    }
+
+    fn name(&self) -> &'static str {
+        "sled"
+    }
+
+    fn stats(&self) -> Result<EngineStats> {
+        Ok(EngineStats {
+            keys: self.0.len() as u64,
+            disk_bytes: self.0.size_on_disk()?,
+            ..EngineStats::default()
+        })
+    }
+
+    /// sled 在后台自行回收空间，这里只将数据刷写到磁盘
+    fn compact(&self) -> Result<()> {
+        self.flush()
+    }
+
+    fn compaction_threshold(&self) -> Option<u64> {
+        None
+    }
+
+    fn set_compaction_threshold(&self, _bytes: u64) -> Result<()> {
+        Err(KvsError::StringError("sled does not support a compaction threshold".to_owned()))
+    }
+}
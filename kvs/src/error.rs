@@ -1,3 +1,6 @@
+// failure 的 derive 宏会在常量块中生成 impl
+#![allow(non_local_definitions)]
+
 use failure::Fail;
 use std::{io, string::FromUtf8Error};
 
@@ -16,15 +19,36 @@ pub enum KvsError {
     /// 命令不存在
     #[fail(display = "Unexcepted command type")]
     UnexceptedCommandType,
-    ///
+    /// 字符串描述的错误
     #[fail(display = "{}",_0)]
     StringError(String),
-     ///
+     /// UTF-8 解码异常
      #[fail(display = "UTF-8 error: {}",_0)]
      Utf8(#[cause] FromUtf8Error),
-     ///
+     /// sled 异常
      #[fail(display = "sled error: {}", _0)]
      Sled(#[cause] sled::Error),
+     /// TLS 异常
+     #[fail(display = "TLS error: {}", _0)]
+     Tls(#[cause] rustls::Error),
+     /// 从节点只接受读请求
+     #[fail(display = "Server is a read-only replica")]
+     ReadOnly,
+     /// 集群中只有领导者处理读写请求，附带领导者的地址
+     #[fail(display = "Not the leader, the leader is {}", _0)]
+     NotLeader(String),
+     /// 集群还没有选出领导者
+     #[fail(display = "No leader elected")]
+     NoLeader,
+     /// 代理无法访问后端服务器，附带后端的地址和原因
+     #[fail(display = "Backend {} is unavailable: {}", _0, _1)]
+     BackendUnavailable(String, String),
+     /// 数据目录由另一个引擎创建，附带创建它的引擎和要打开它的引擎
+     #[fail(display = "Data directory was created by the {} engine, can not open it with {}", _0, _1)]
+     WrongEngine(String, String),
+     /// 存储引擎没有实现该操作，附带操作和引擎的名称
+     #[fail(display = "{} is not supported by the {} engine", _0, _1)]
+     Unsupported(String, String),
 }
 
 impl From<io::Error> for KvsError {
@@ -51,5 +75,11 @@ impl From<sled::Error> for KvsError {
     }
 }
 
+impl From<rustls::Error> for KvsError {
+    fn from(err: rustls::Error) -> KvsError {
+        KvsError::Tls(err)
+    }
+}
+
 /// Result type for kvs 
 pub type Result<T> = std::result::Result<T,KvsError>;
\ No newline at end of file
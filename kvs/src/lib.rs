@@ -5,9 +5,15 @@
 extern crate log;
 
 pub use error::{KvsError,Result};
-pub use engines::{KvStore,KvsEngine,SledKvsEngine};
-pub use server::KvsServer;
-pub use client::KvsClient;
+pub use engines::{checksum,migrate,restore,Backup,BackupFile,BackupFormat,BackupHeader,EngineStats,KvStore,KvsEngine,Manifest,MigrationStats,ProxyEngine,ProxyRouting,SledKvsEngine};
+pub use server::{ClusterConfig,KvsServer,Protocol,ReloadHandle,ServerConfig,ShutdownHandle};
+pub use client::{KvsClient,KvsClientBuilder,KvsClientPool,PooledClient,ShardedKvsClient,SyncStats};
+pub use tls::{TlsClientConfig,TlsServerConfig};
+pub use auth::{AuthConfig,Permission};
+pub use addr::{ServerAddr,ToServerAddrs};
+pub use common::{ClusterInfo,ExportPage,ImportMode,ImportStats,MerkleNode,ReplicationInfo,ScanPage,ServerInfo,SlowLogEntry};
+pub use dump::{DumpFormat,DumpReader,DumpWriter};
+pub use logging::{log_level_layer,set_log_level,LogLevelLayer};
 // pub use thread_pool::{NativeThreadPool,ThreadPool,SharedQueueThreadPool,RayonThreadPool};
 
 mod error;
@@ -15,4 +21,10 @@ mod engines;
 mod server;
 mod common;
 mod client;
+mod tls;
+mod auth;
+mod addr;
+mod dump;
+mod metrics;
+mod logging;
 pub mod thread_pool;
\ No newline at end of file
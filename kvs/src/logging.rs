@@ -0,0 +1,44 @@
+use std::sync::OnceLock;
+
+use log::LevelFilter;
+use tracing_subscriber::{filter, reload, Registry};
+
+use crate::{KvsError, Result};
+
+/// 可以在运行时调整级别的日志过滤层，需要作为 subscriber 的第一层
+pub type LogLevelLayer = reload::Layer<filter::LevelFilter, Registry>;
+
+static HANDLE: OnceLock<reload::Handle<filter::LevelFilter, Registry>> = OnceLock::new();
+
+/// 创建日志级别的过滤层，之后 `set_log_level` 和 `config set log-level` 通过它同时调整事件和 span 的级别
+///
+/// 只有第一次创建的过滤层可以被调整
+pub fn log_level_layer(level: LevelFilter) -> LogLevelLayer {
+    let (layer, handle) = reload::Layer::new(as_tracing(level));
+    if HANDLE.set(handle).is_err() {
+        warn!("The log level layer is already created");
+    }
+    log::set_max_level(level);
+    layer
+}
+
+/// 设置日志级别，没有创建过滤层时只调整 log 的全局最大级别
+pub fn set_log_level(level: LevelFilter) -> Result<()> {
+    if let Some(handle) = HANDLE.get() {
+        handle.reload(as_tracing(level))
+            .map_err(|e| KvsError::StringError(format!("Failed to change the log level: {}",e)))?;
+    }
+    log::set_max_level(level);
+    Ok(())
+}
+
+fn as_tracing(level: LevelFilter) -> filter::LevelFilter {
+    match level {
+        LevelFilter::Off => filter::LevelFilter::OFF,
+        LevelFilter::Error => filter::LevelFilter::ERROR,
+        LevelFilter::Warn => filter::LevelFilter::WARN,
+        LevelFilter::Info => filter::LevelFilter::INFO,
+        LevelFilter::Debug => filter::LevelFilter::DEBUG,
+        LevelFilter::Trace => filter::LevelFilter::TRACE
+    }
+}
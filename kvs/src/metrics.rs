@@ -0,0 +1,209 @@
+//! 进程内的运行指标，以 Prometheus 文本格式导出
+//!
+//! 指标由服务器、存储引擎和线程池共同更新，保存在进程级的全局注册表中，
+//! 同一个进程中的多个服务器共享这些指标
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write},
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+        Mutex,
+        OnceLock
+    },
+    time::{Duration, Instant}
+};
+
+/// 延迟直方图的桶上界（秒）
+const LATENCY_BUCKETS: [f64; 16] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025,
+    0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0
+];
+
+/// 只增不减的计数器
+#[derive(Default)]
+pub(crate) struct Counter(AtomicU64);
+
+/// 可增可减的瞬时值
+#[derive(Default)]
+pub(crate) struct Gauge(AtomicI64);
+
+/// 耗时的分布
+pub(crate) struct Histogram {
+    // 每个桶单独计数，导出时再累加
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    count: AtomicU64,
+    sum_nanos: AtomicU64
+}
+
+/// 全局的指标注册表
+pub(crate) struct Metrics {
+    /// 从客户端连接读取的字节数
+    pub(crate) network_read_bytes: Counter,
+    /// 写回客户端连接的字节数
+    pub(crate) network_written_bytes: Counter,
+    /// 存储引擎从磁盘读取的字节数
+    pub(crate) engine_read_bytes: Counter,
+    /// 存储引擎写入磁盘的字节数
+    pub(crate) engine_written_bytes: Counter,
+    /// 压缩的次数
+    pub(crate) compactions: Counter,
+    /// 每次压缩的耗时
+    pub(crate) compaction_duration: Histogram,
+    /// 已提交到线程池、尚未开始执行的任务数
+    pub(crate) pool_queue_depth: Gauge,
+    /// 线程池执行过的任务数
+    pub(crate) pool_jobs: Counter,
+    // 按协议和命令名统计的命令数和耗时
+    commands: Mutex<BTreeMap<(&'static str, &'static str), Arc<CommandMetrics>>>
+}
+
+#[derive(Default)]
+struct CommandMetrics {
+    duration: Histogram
+}
+
+/// 全局的指标注册表
+pub(crate) fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| Metrics {
+        network_read_bytes: Counter::default(),
+        network_written_bytes: Counter::default(),
+        engine_read_bytes: Counter::default(),
+        engine_written_bytes: Counter::default(),
+        compactions: Counter::default(),
+        compaction_duration: Histogram::default(),
+        pool_queue_depth: Gauge::default(),
+        pool_jobs: Counter::default(),
+        commands: Mutex::new(BTreeMap::new())
+    })
+}
+
+/// 包装提交到线程池的任务，统计排队中的任务数
+pub(crate) fn track_job<F>(job: F) -> impl FnOnce() + Send + 'static
+where
+    F: FnOnce() + Send + 'static
+{
+    let metrics = metrics();
+    metrics.pool_queue_depth.add(1);
+    move || {
+        metrics.pool_queue_depth.add(-1);
+        metrics.pool_jobs.inc_by(1);
+        job()
+    }
+}
+
+impl Counter {
+    pub(crate) fn inc_by(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Gauge {
+    pub(crate) fn add(&self, n: i64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub(crate) fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_nanos: AtomicU64::new(0)
+        }
+    }
+}
+
+impl Histogram {
+    pub(crate) fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| secs <= le) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_nanos.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// 记录从 `start` 到现在的耗时
+    pub(crate) fn observe_since(&self, start: Instant) {
+        self.observe(start.elapsed());
+    }
+
+    fn write(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (le, bucket) in LATENCY_BUCKETS.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, sep, le, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, sep, count);
+        let sum = self.sum_nanos.load(Ordering::Relaxed) as f64 / 1e9;
+        let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces(labels), count);
+    }
+}
+
+impl Metrics {
+    /// 记录一次命令的执行
+    pub(crate) fn command(&self, protocol: &'static str, command: &'static str, duration: Duration) {
+        let metrics = Arc::clone(self.commands.lock().unwrap().entry((protocol, command)).or_default());
+        metrics.duration.observe(duration);
+    }
+
+    /// 以 Prometheus 文本格式输出所有指标
+    pub(crate) fn render(&self, out: &mut String) {
+        let commands: Vec<_> = self.commands.lock().unwrap()
+            .iter()
+            .map(|(&key, metrics)| (key, Arc::clone(metrics)))
+            .collect();
+        header(out, "kvs_commands_total", "Commands executed", "counter");
+        for ((protocol, command), metrics) in &commands {
+            let count = metrics.duration.count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "kvs_commands_total{{protocol=\"{}\",command=\"{}\"}} {}", protocol, command, count);
+        }
+        header(out, "kvs_command_duration_seconds", "Command latency including time queued in the thread pool", "histogram");
+        for ((protocol, command), metrics) in &commands {
+            let labels = format!("protocol=\"{}\",command=\"{}\"", protocol, command);
+            metrics.duration.write(out, "kvs_command_duration_seconds", &labels);
+        }
+
+        write_metric(out, "kvs_network_read_bytes_total", "Bytes read from client connections", "counter", self.network_read_bytes.get());
+        write_metric(out, "kvs_network_written_bytes_total", "Bytes written to client connections", "counter", self.network_written_bytes.get());
+        write_metric(out, "kvs_engine_read_bytes_total", "Bytes read from disk by the storage engine", "counter", self.engine_read_bytes.get());
+        write_metric(out, "kvs_engine_written_bytes_total", "Bytes written to disk by the storage engine", "counter", self.engine_written_bytes.get());
+        write_metric(out, "kvs_compactions_total", "Compactions run by the storage engine", "counter", self.compactions.get());
+        header(out, "kvs_compaction_duration_seconds", "Duration of compactions", "histogram");
+        self.compaction_duration.write(out, "kvs_compaction_duration_seconds", "");
+        write_metric(out, "kvs_thread_pool_queue_depth", "Jobs waiting for a thread pool thread", "gauge", self.pool_queue_depth.get());
+        write_metric(out, "kvs_thread_pool_jobs_total", "Jobs started by thread pools", "counter", self.pool_jobs.get());
+    }
+}
+
+/// 输出一个没有标签的指标
+pub(crate) fn write_metric(out: &mut String, name: &str, help: &str, kind: &str, value: impl Display) {
+    header(out, name, help, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn braces(labels: &str) -> String {
+    if labels.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", labels)
+    }
+}
@@ -0,0 +1,106 @@
+use std::{str::FromStr, time::Duration};
+
+use log::LevelFilter;
+
+use crate::{common::ServerInfo, engines, metrics::{metrics, write_metric}, KvsEngine, KvsError, Result};
+
+use super::{keyspace::Keyspace, stats::ServerStats};
+
+/// 自动压缩的阈值（字节），只有 kvs 引擎支持
+const COMPACTION_THRESHOLD: &str = "compaction-threshold";
+/// 日志级别：off、error、warn、info、debug、trace
+const LOG_LEVEL: &str = "log-level";
+/// 记录慢日志的阈值（微秒），off 表示不记录
+const SLOWLOG_THRESHOLD: &str = "slowlog-threshold";
+/// 慢日志最多保存的条数
+const SLOWLOG_MAX_LEN: &str = "slowlog-max-len";
+const SETTINGS: [&str; 4] = [COMPACTION_THRESHOLD, LOG_LEVEL, SLOWLOG_THRESHOLD, SLOWLOG_MAX_LEN];
+
+/// 服务器的运行状态
+pub(super) fn server_info<E: KvsEngine>(keyspace: &Keyspace<E>, stats: &ServerStats) -> ServerInfo {
+    ServerInfo {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        engine: keyspace.engine().name().to_owned(),
+        uptime_seconds: stats.uptime().as_secs(),
+        connected_clients: stats.connected_clients(),
+        total_connections: stats.total_connections(),
+        total_commands: stats.total_commands(),
+        replication: keyspace.replication().info(),
+        cluster: keyspace.raft().map(|raft| raft.info())
+    }
+}
+
+/// 以 Prometheus 文本格式输出服务器、存储引擎和线程池的指标
+pub(super) fn render_metrics<E: KvsEngine>(keyspace: &Keyspace<E>, stats: &ServerStats) -> String {
+    let mut out = String::new();
+    write_metric(&mut out, "kvs_uptime_seconds", "Seconds since the server started", "gauge", stats.uptime().as_secs());
+    write_metric(&mut out, "kvs_connections_open", "Open client connections", "gauge", stats.connected_clients());
+    write_metric(&mut out, "kvs_connections_total", "Client connections accepted", "counter", stats.total_connections());
+
+    match keyspace.engine().stats() {
+        Ok(engine) => {
+            write_metric(&mut out, "kvs_engine_keys", "Keys in the storage engine", "gauge", engine.keys);
+            write_metric(&mut out, "kvs_engine_disk_bytes", "Disk space used by the storage engine", "gauge", engine.disk_bytes);
+            if let (Some(live), Some(dead)) = (engine.live_bytes, engine.dead_bytes) {
+                write_metric(&mut out, "kvs_engine_live_bytes", "Bytes of live data", "gauge", live);
+                write_metric(&mut out, "kvs_engine_dead_bytes", "Bytes reclaimable by compaction", "gauge", dead);
+                let ratio = if live + dead == 0 { 0.0 } else { dead as f64 / (live + dead) as f64 };
+                write_metric(&mut out, "kvs_engine_dead_bytes_ratio", "Share of log bytes reclaimable by compaction", "gauge", ratio);
+            }
+            if let Some(segments) = engine.segments {
+                write_metric(&mut out, "kvs_engine_segments", "Log files of the storage engine", "gauge", segments);
+            }
+        }
+        Err(e) => error!("Failed to collect engine stats: {}",e)
+    }
+
+    metrics().render(&mut out);
+    out
+}
+
+/// 读取运行时设置
+pub(super) fn config_get<E: KvsEngine>(keyspace: &Keyspace<E>, stats: &ServerStats, name: &str) -> Result<String> {
+    match name {
+        COMPACTION_THRESHOLD => keyspace.engine().compaction_threshold()
+            .map(|bytes| bytes.to_string())
+            .ok_or_else(|| unsupported(keyspace, name)),
+        LOG_LEVEL => Ok(log::max_level().to_string().to_ascii_lowercase()),
+        SLOWLOG_THRESHOLD => Ok(stats.slowlog().threshold()
+            .map_or_else(|| "off".to_owned(), |threshold| threshold.as_micros().to_string())),
+        SLOWLOG_MAX_LEN => Ok(stats.slowlog().max_len().to_string()),
+        _ => Err(unknown(name))
+    }
+}
+
+/// 修改运行时设置，只在本次运行期间有效
+pub(super) fn config_set<E: KvsEngine>(keyspace: &Keyspace<E>, stats: &ServerStats, name: &str, value: &str) -> Result<()> {
+    let invalid = || KvsError::StringError(format!("Invalid value for {}: {}",name,value));
+    match name {
+        COMPACTION_THRESHOLD => set_compaction_threshold(keyspace, value.parse().map_err(|_| invalid())?)?,
+        LOG_LEVEL => crate::set_log_level(LevelFilter::from_str(value).map_err(|_| invalid())?)?,
+        SLOWLOG_THRESHOLD => stats.slowlog().set_threshold(match value {
+            "off" => None,
+            micros => Some(Duration::from_micros(micros.parse().map_err(|_| invalid())?))
+        }),
+        SLOWLOG_MAX_LEN => stats.slowlog().set_max_len(value.parse().map_err(|_| invalid())?),
+        _ => return Err(unknown(name))
+    }
+    info!("Setting {} changed to {}",name,value);
+    Ok(())
+}
+
+/// 设置存储引擎的自动压缩阈值，引擎不支持时返回错误
+pub(super) fn set_compaction_threshold<E: KvsEngine>(keyspace: &Keyspace<E>, bytes: u64) -> Result<()> {
+    if keyspace.engine().compaction_threshold().is_none() {
+        return Err(unsupported(keyspace, COMPACTION_THRESHOLD));
+    }
+    keyspace.engine().set_compaction_threshold(bytes)
+}
+
+fn unsupported<E: KvsEngine>(keyspace: &Keyspace<E>, name: &str) -> KvsError {
+    engines::unsupported(keyspace.engine(), name)
+}
+
+fn unknown(name: &str) -> KvsError {
+    KvsError::StringError(format!("Unknown setting {}, expected one of {}",name,SETTINGS.join(", ")))
+}
@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    process,
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex},
+    time::{Duration, Instant}
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+use crate::{common::BackupSession, Backup, KvsError, Result};
+
+// 同时保留的备份数，超过时丢弃最久没有读取的备份
+const MAX_BACKUPS: usize = 4;
+// 超过这个时间没有读取的备份被丢弃，释放它占用的文件
+const BACKUP_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+// 每次最多读取的字节数
+const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// 正在被客户端读取的备份
+///
+/// 备份持有它的文件，这些文件在客户端释放备份或备份被丢弃前不会释放。
+/// 读到末尾后备份仍然保留，客户端校验失败时可以重新读取
+#[derive(Default)]
+pub(super) struct Backups {
+    backups: Mutex<HashMap<String, Entry>>,
+    next_id: AtomicU64
+}
+
+struct Entry {
+    backup: Arc<Backup>,
+    last_read: Instant
+}
+
+impl Backups {
+    /// 保存备份，返回读取它使用的 ID
+    pub(super) fn insert(&self, backup: Backup) -> BackupSession {
+        let id = format!("{:x}-{:x}",process::id(),self.next_id.fetch_add(1, Ordering::SeqCst));
+        let header = backup.header().clone();
+        let mut backups = self.backups.lock().unwrap();
+        backups.retain(|_, entry| entry.last_read.elapsed() < BACKUP_IDLE_TIMEOUT);
+        if backups.len() >= MAX_BACKUPS {
+            let oldest = backups.iter().min_by_key(|(_, entry)| entry.last_read).map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                warn!("Dropping unfinished backup {}",oldest);
+                backups.remove(&oldest);
+            }
+        }
+        backups.insert(id.clone(), Entry { backup: Arc::new(backup), last_read: Instant::now() });
+        info!("Created backup {} of {} bytes",id,header.len());
+        BackupSession { id, header }
+    }
+
+    /// 读取备份 `id` 中 `offset` 处的最多 `len` 个字节并以 base64 编码
+    pub(super) fn read(&self, id: &str, offset: u64, len: usize) -> Result<String> {
+        let backup = match self.backups.lock().unwrap().get_mut(id) {
+            Some(entry) => {
+                entry.last_read = Instant::now();
+                Arc::clone(&entry.backup)
+            }
+            None => return Err(KvsError::StringError(format!("Unknown backup {}",id)))
+        };
+        let mut buf = vec![0; len.clamp(1, MAX_CHUNK_SIZE)];
+        let n = backup.read_at(&mut buf, offset)?;
+        buf.truncate(n);
+        Ok(STANDARD.encode(buf))
+    }
+
+    /// 释放备份 `id`
+    pub(super) fn finish(&self, id: &str) -> Result<()> {
+        match self.backups.lock().unwrap().remove(id) {
+            Some(_) => {
+                info!("Finished backup {}",id);
+                Ok(())
+            }
+            None => Err(KvsError::StringError(format!("Unknown backup {}",id)))
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::io;
+
+use crate::{
+    auth::Permission,
+    common::{AuthResponse, Request, Secret, Traced}
+};
+
+use super::{http::Route, resp::{Command, Version}};
+
+/// 从连接中解码出的一次调用，与具体协议无关
+#[derive(Debug)]
+pub(super) enum Call {
+    /// 需要在线程池中访问存储引擎的操作，以及客户端提供的请求 ID
+    Execute(Operation, Option<String>),
+    /// 登录，`success`/`failure` 为按连接协议编码好的响应
+    Login { user: String, password: Secret, success: Vec<u8>, failure: Vec<u8> },
+    /// 不需要执行、直接写回的响应
+    Reply(Vec<u8>)
+}
+
+/// 服务端拒绝请求的原因，决定错误在各个协议中的表示（RESP 错误码、HTTP 状态码）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ErrorKind {
+    /// 请求格式错误
+    Invalid,
+    /// 连接数已满
+    Busy,
+    /// 请求超过大小限制
+    TooLarge,
+    /// 没有在规定时间内收到完整请求
+    Timeout,
+    /// 超过请求频率限制
+    RateLimited,
+    /// 未登录或登录失败
+    Unauthenticated,
+    /// 没有权限
+    Forbidden
+}
+
+impl ErrorKind {
+    pub(super) fn resp_code(self) -> &'static str {
+        match self {
+            ErrorKind::Unauthenticated => "NOAUTH",
+            ErrorKind::Forbidden => "NOPERM",
+            _ => "ERR"
+        }
+    }
+
+    pub(super) fn http_status(self) -> u16 {
+        match self {
+            ErrorKind::Invalid => 400,
+            ErrorKind::Unauthenticated => 401,
+            ErrorKind::Forbidden => 403,
+            ErrorKind::Timeout => 408,
+            ErrorKind::TooLarge => 413,
+            ErrorKind::RateLimited => 429,
+            ErrorKind::Busy => 503
+        }
+    }
+}
+
+/// 请求 ID，出现在请求的 span 中，客户端没有提供时由服务端生成
+#[derive(Debug)]
+pub(super) struct RequestId {
+    pub(super) id: String,
+    // 是否由客户端提供，原生协议只对客户端提供的 ID 回显
+    pub(super) supplied: bool
+}
+
+#[derive(Debug)]
+pub(super) enum Operation {
+    Native(Request),
+    Resp(Command, Version),
+    /// HTTP 操作，以及响应后是否保持连接
+    Http(Route, bool)
+}
+
+impl Call {
+    /// 将原生协议的请求转换为调用
+    pub(super) fn from_native(req: Request) -> io::Result<Self> {
+        Ok(match req {
+            Request::Traced { id, request } => match Call::from_native(*request)? {
+                Call::Login { user, password, success, failure } => Call::Login {
+                    user,
+                    password,
+                    success: traced(&id, &success)?,
+                    failure: traced(&id, &failure)?
+                },
+                Call::Execute(op, _) => Call::Execute(op, Some(id)),
+                call => call
+            },
+            Request::Auth { user, password } => Call::Login {
+                user,
+                password,
+                success: serde_json::to_vec(&AuthResponse::Ok(()))?,
+                failure: serde_json::to_vec(&AuthResponse::Err("Invalid user name or password".to_owned()))?
+            },
+            req => Call::Execute(Operation::Native(req), None)
+        })
+    }
+}
+
+/// 以 `Traced` 包装原生协议编码好的响应
+pub(super) fn traced(id: &str, response: &[u8]) -> io::Result<Vec<u8>> {
+    let response: serde_json::Value = serde_json::from_slice(response)?;
+    Ok(serde_json::to_vec(&Traced { id: id.to_owned(), response })?)
+}
+
+impl Operation {
+    /// 执行操作需要的权限，为空表示未登录也可以执行
+    pub(super) fn required_permissions(&self) -> Vec<(&str, Permission)> {
+        match self {
+            Operation::Native(req) => req.required_permission().into_iter().collect(),
+            Operation::Resp(cmd, _) => cmd.required_permissions(),
+            Operation::Http(route, _) => route.required_permissions()
+        }
+    }
+
+    /// 慢日志中记录的键，多个键时为第一个，扫描时为前缀
+    pub(super) fn key(&self) -> Option<&str> {
+        self.required_permissions().into_iter().map(|(key, _)| key).find(|key| !key.is_empty())
+    }
+
+    /// 指标中使用的协议和命令名
+    pub(super) fn metric_labels(&self) -> (&'static str, &'static str) {
+        match self {
+            Operation::Native(req) => ("native", native_name(req)),
+            Operation::Resp(cmd, _) => ("resp", cmd.name()),
+            Operation::Http(route, _) => ("http", route.name())
+        }
+    }
+}
+
+fn native_name(req: &Request) -> &'static str {
+    match req {
+        Request::Get { .. } => "get",
+        Request::Set { .. } => "set",
+        Request::Remove { .. } => "remove",
+        Request::Scan { .. } => "scan",
+        Request::MerkleChildren { .. } => "merkle_children",
+        Request::Ping => "ping",
+        Request::Auth { .. } => "auth",
+        Request::Info => "info",
+        Request::Stats => "stats",
+        Request::Compact => "compact",
+        Request::Flush => "flush",
+        Request::ConfigGet { .. } => "config_get",
+        Request::ConfigSet { .. } => "config_set",
+        Request::SlowlogGet { .. } => "slowlog_get",
+        Request::SlowlogReset => "slowlog_reset",
+        Request::Export { .. } => "export",
+        Request::Import { .. } => "import",
+        Request::Backup => "backup",
+        Request::BackupChunk { .. } => "backup_chunk",
+        Request::BackupFinish { .. } => "backup_finish",
+        Request::ReplSnapshot { .. } => "repl_snapshot",
+        Request::ReplTail { .. } => "repl_tail",
+        Request::Promote => "promote",
+        Request::RaftVote(_) => "raft_vote",
+        Request::RaftAppend(_) => "raft_append",
+        Request::Traced { request, .. } => native_name(request)
+    }
+}
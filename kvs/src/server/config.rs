@@ -0,0 +1,128 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use crate::{AuthConfig, KvsClientBuilder, ServerAddr, TlsServerConfig};
+
+/// 客户端使用的协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// kvs 原生的 JSON 协议
+    Native,
+    /// Redis 的 RESP2/RESP3 协议
+    Resp,
+    /// 根据连接上收到的第一个字节自动识别，原生协议和 RESP 可以使用同一个端口
+    Auto,
+    /// HTTP/JSON 网关
+    Http
+}
+
+/// 服务器的运行参数
+///
+/// 所有限制都会在触发时记录到服务器日志中
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// 客户端使用的协议
+    pub protocol: Protocol,
+    /// 额外监听的 HTTP/JSON 网关地址，与主端口共享存储引擎和线程池
+    pub http_addr: Option<SocketAddr>,
+    /// 额外监听的 Prometheus 指标地址，只提供 `GET /metrics`
+    pub metrics_addr: Option<SocketAddr>,
+    /// 监听 Unix 域套接字时套接字文件的权限（如 `0o660`），`None` 时由 umask 决定
+    pub unix_socket_mode: Option<u32>,
+    /// 同时保持的最大连接数，超出时返回 "Server busy" 错误并关闭连接
+    pub max_connections: usize,
+    /// 连接空闲（没有未完成的请求）超过该时间后被关闭，`None` 表示不限制
+    pub idle_timeout: Option<Duration>,
+    /// 从收到请求的第一个字节开始，必须在该时间内收到完整请求，`None` 表示不限制
+    pub request_timeout: Option<Duration>,
+    /// 单个请求的最大字节数
+    pub max_request_size: usize,
+    /// 每个客户端 IP 每秒允许的最大请求数，`None` 表示不限制
+    pub rate_limit: Option<u32>,
+    /// 执行时间达到该值的请求记录到慢日志中，`None` 表示不记录
+    pub slowlog_threshold: Option<Duration>,
+    /// 慢日志最多保存的条数
+    pub slowlog_max_len: usize,
+    /// 设置后覆盖存储引擎的自动压缩阈值（字节），只有 kvs 引擎支持
+    pub compaction_threshold: Option<u64>,
+    /// 设置后作为从节点启动：从该主节点全量同步后持续拉取复制日志，只接受读请求，
+    /// 可以通过管理请求提升为主节点。连接主节点的 TLS、登录等使用其中的配置
+    pub replica_of: Option<KvsClientBuilder>,
+    /// 复制日志在内存中保留的记录数，从节点落后更多时需要重新全量同步
+    pub replication_backlog: usize,
+    /// 设置后以 Raft 集群模式运行，不能与 `replica_of` 同时使用
+    pub cluster: Option<ClusterConfig>,
+    /// 关闭时等待正在执行的请求完成的最长时间
+    pub shutdown_timeout: Duration,
+    /// 设置后 TCP 端口只接受 TLS 连接
+    pub tls: Option<TlsServerConfig>,
+    /// 设置后客户端必须先登录，且只能访问被授权的键
+    pub auth: Option<AuthConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            protocol: Protocol::Native,
+            http_addr: None,
+            metrics_addr: None,
+            unix_socket_mode: None,
+            max_connections: 10_000,
+            idle_timeout: Some(Duration::from_secs(300)),
+            request_timeout: Some(Duration::from_secs(30)),
+            max_request_size: 16 * 1024 * 1024,
+            rate_limit: None,
+            slowlog_threshold: Some(Duration::from_millis(10)),
+            slowlog_max_len: 128,
+            compaction_threshold: None,
+            replica_of: None,
+            replication_backlog: 10_000,
+            cluster: None,
+            shutdown_timeout: Duration::from_secs(5),
+            tls: None,
+            auth: None,
+        }
+    }
+}
+
+/// Raft 集群中一个节点的配置
+///
+/// 节点之间通过原生协议通信，因此客户端协议必须是 `Native` 或 `Auto`，且不能同时开启 HTTP 网关。
+/// 写操作在复制到多数节点并提交后才返回，读操作在确认领导权后执行，
+/// 非领导者把读写请求重定向到领导者
+#[derive(Debug, Clone)]
+pub struct ClusterConfig {
+    /// 本节点的 ID，在集群中唯一
+    pub node_id: u64,
+    /// 其他节点的 ID 和客户端地址
+    pub peers: Vec<(u64, ServerAddr)>,
+    /// 保存 Raft 日志和投票的目录
+    pub dir: PathBuf,
+    /// 选举超时时间，实际超时在该值和它的两倍之间随机选取
+    pub election_timeout: Duration,
+    /// 领导者发送心跳的间隔，应远小于选举超时时间
+    pub heartbeat_interval: Duration,
+    /// 连接其他节点使用的客户端配置（TLS、登录等），地址由 `peers` 决定
+    pub peer_client: KvsClientBuilder,
+    /// 内存中保留的最近日志条数，更早的日志在复制给落后的节点时从磁盘读取
+    pub log_cache_entries: usize,
+    /// 所有节点都已有、本节点已应用的日志达到该条数时，从日志文件中丢弃这些日志
+    ///
+    /// 长时间掉线的节点会使日志无法压缩；丢失了 Raft 日志的节点无法再从其他节点追赶，需要从备份恢复
+    pub log_compact_entries: u64
+}
+
+impl ClusterConfig {
+    /// 使用默认的超时时间新建配置
+    pub fn new(node_id: u64, peers: Vec<(u64, ServerAddr)>, dir: impl Into<PathBuf>) -> Self {
+        ClusterConfig {
+            node_id,
+            peers,
+            dir: dir.into(),
+            election_timeout: Duration::from_millis(1000),
+            heartbeat_interval: Duration::from_millis(100),
+            peer_client: KvsClientBuilder::new(),
+            log_cache_entries: 10_000,
+            log_compact_entries: 10_000
+        }
+    }
+}
@@ -0,0 +1,480 @@
+use std::{
+    collections::VecDeque,
+    io,
+    mem,
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use mio::{Interest, Registry, Token};
+use serde_json::Deserializer;
+use tracing::{info_span, Span};
+
+use crate::{auth::User, common::{ErrorResponse, Request, Secret}, AuthConfig, ServerAddr};
+
+use crate::metrics::metrics;
+
+use super::{
+    call::{Call, ErrorKind, RequestId},
+    config::Protocol,
+    http,
+    listener::Endpoint,
+    resp::{self, Value, Version},
+    transport::Transport
+};
+
+// 连接出错后，写回错误响应并等待客户端关闭的最长时间
+const LINGER_TIMEOUT: Duration = Duration::from_secs(1);
+// 每次读取后解码的字节数
+const READ_LIMIT: usize = 64 * 1024;
+// 已解码但尚未执行的请求数上限，超过时暂停读取
+const MAX_PENDING_REQUESTS: usize = 1024;
+// 尚未写回的响应字节数上限，超过时暂停读取和分发请求，直到缓冲区写空
+const MAX_WRITE_BUFFER: usize = 4 * 1024 * 1024;
+
+/// 连接超时的原因
+pub(super) enum Timeout {
+    /// 连接长时间空闲
+    Idle,
+    /// 没有在规定时间内收到完整请求
+    Request,
+    /// 出错后等待客户端关闭超时
+    Linger
+}
+
+/// 事件循环中的一个客户端连接
+///
+/// 连接维护自己的读写缓冲区，同一连接上的请求按顺序逐个执行，
+/// 上一个请求的响应写回之前不会分发下一个请求
+pub(super) struct Connection {
+    // 连接编号，用于生成请求 ID
+    id: usize,
+    // 连接的 span，连接上的请求 span 都以它为父级
+    span: Span,
+    // 连接上已执行的请求数
+    requests: u64,
+    transport: Transport,
+    codec: Codec,
+    peer_addr: ServerAddr,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    // 已解码但尚未执行的请求
+    pending: VecDeque<Call>,
+    // 是否有请求正在线程池中执行
+    in_flight: bool,
+    // 缓冲区超过上限，已取消可读事件的注册
+    read_paused: bool,
+    // 因缓冲区超过上限停止了读取，socket 中可能还有数据
+    read_stopped: bool,
+    // 客户端是否已经关闭了写端
+    read_closed: bool,
+    // 最后一次读写数据的时间
+    last_active: Instant,
+    // 当前未读完的请求开始接收的时间
+    request_started: Option<Instant>,
+    // 连接因错误正在关闭：写完错误响应后关闭写端，并丢弃后续请求
+    closing: Option<Instant>,
+    write_shutdown: bool,
+    // 双向 TLS 中客户端证书标识的身份，握手完成后设置
+    identity: Option<String>,
+    // 服务器是否开启了认证
+    auth_enabled: bool,
+    // 已登录的用户
+    user: Option<Arc<User>>,
+    // HTTP 连接上最近一次提交的认证头，凭据变化时才重新登录
+    http_authorization: Option<String>,
+    // HTTP 客户端要求响应后关闭连接，之后的数据不再解码
+    close_after_response: bool,
+    // 连续登录失败的次数
+    auth_failures: u32
+}
+
+/// 连接上使用的编解码方式
+#[derive(Clone, Copy)]
+enum Codec {
+    /// 尚未收到数据，还不能确定协议
+    Detect,
+    Native,
+    Resp(Version),
+    Http,
+    /// 只接受指标请求的 HTTP
+    Metrics
+}
+
+impl Connection {
+    pub(super) fn new(id: usize, transport: Transport, peer_addr: ServerAddr, endpoint: Endpoint, auth_enabled: bool) -> Self {
+        Connection {
+            id,
+            span: info_span!("connection", id, peer = %peer_addr),
+            requests: 0,
+            transport,
+            codec: match endpoint {
+                Endpoint::Client(Protocol::Native) => Codec::Native,
+                Endpoint::Client(Protocol::Resp) => Codec::Resp(Version::Resp2),
+                Endpoint::Client(Protocol::Auto) => Codec::Detect,
+                Endpoint::Client(Protocol::Http) => Codec::Http,
+                Endpoint::Metrics => Codec::Metrics
+            },
+            peer_addr,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            pending: VecDeque::new(),
+            in_flight: false,
+            read_paused: false,
+            read_stopped: false,
+            read_closed: false,
+            last_active: Instant::now(),
+            request_started: None,
+            closing: None,
+            write_shutdown: false,
+            identity: None,
+            auth_enabled,
+            user: None,
+            http_authorization: None,
+            close_after_response: false,
+            auth_failures: 0
+        }
+    }
+
+    pub(super) fn peer_addr(&self) -> &ServerAddr {
+        &self.peer_addr
+    }
+
+    pub(super) fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// 生成请求 ID，客户端没有提供时使用连接编号和请求序号
+    pub(super) fn request_id(&mut self, supplied: Option<String>) -> RequestId {
+        self.requests += 1;
+        match supplied {
+            Some(id) => RequestId { id, supplied: true },
+            None => RequestId { id: format!("{}-{}", self.id, self.requests), supplied: false }
+        }
+    }
+
+    /// 双向 TLS 中客户端证书标识的身份
+    pub(super) fn identity(&self) -> Option<&str> {
+        self.identity.as_deref()
+    }
+
+    pub(super) fn user(&self) -> Option<&Arc<User>> {
+        self.user.as_ref()
+    }
+
+    /// 登录成功，之后的请求以该用户的身份执行
+    pub(super) fn login(&mut self, user: Arc<User>) {
+        info!("Client {} logged in as {}",self.peer_addr,user.name());
+        self.user = Some(user);
+        self.auth_failures = 0;
+    }
+
+    /// 认证配置重新加载后换成新配置中的同名用户，用户已被删除时需要重新登录
+    pub(super) fn reload_user(&mut self, auth: &AuthConfig) {
+        if let Some(user) = self.user.take() {
+            self.user = auth.user(user.name());
+            if self.user.is_none() {
+                info!("User {} of client {} was removed, logging out",user.name(),self.peer_addr);
+            }
+        }
+    }
+
+    /// 登录失败，之前登录的身份也随之失效，返回连续失败的次数
+    pub(super) fn login_failed(&mut self) -> u32 {
+        self.user = None;
+        self.auth_failures += 1;
+        self.auth_failures
+    }
+
+    /// 读取可读数据并解码出完整的请求，请求超过 `max_request_size` 时关闭连接，`role` 是 RESP `HELLO` 报告的节点角色
+    ///
+    /// 每次最多读取 `READ_LIMIT` 字节后解码，缓冲区超过上限时停止读取，剩下的数据留在 socket 中
+    pub(super) fn on_readable(&mut self, max_request_size: usize, role: &str) -> io::Result<()> {
+        loop {
+            let before = self.read_buf.len();
+            if self.transport.read_into(&mut self.read_buf, before + READ_LIMIT)? {
+                self.read_closed = true;
+            }
+            let read = self.read_buf.len() - before;
+            if read > 0 {
+                self.last_active = Instant::now();
+                metrics().network_read_bytes.inc_by(read as u64);
+            }
+            if self.identity.is_none() {
+                self.identity = self.transport.peer_identity();
+                if let Some(identity) = &self.identity {
+                    info!("Client {} identified as {}",self.peer_addr,identity);
+                }
+            }
+
+            // 正在关闭的连接丢弃所有输入
+            if self.closing.is_some() {
+                self.read_buf.clear();
+            } else if !self.decode(max_request_size, role)? || self.read_buf.len() > max_request_size {
+                warn!("Request from {} exceeds {} bytes, closing connection",self.peer_addr,max_request_size);
+                return self.fail(ErrorKind::TooLarge, "Request too large");
+            }
+
+            // 读到的数据不足 `READ_LIMIT` 说明 socket 中已经没有数据
+            if read < READ_LIMIT || self.read_closed {
+                return Ok(());
+            }
+            if self.closing.is_none() && self.is_over_limit() {
+                self.read_stopped = true;
+                return Ok(());
+            }
+        }
+    }
+
+    /// 将缓冲区中的数据写回客户端
+    pub(super) fn on_writable(&mut self) -> io::Result<()> {
+        self.transport.flush()?;
+        while !self.write_buf.is_empty() {
+            match self.transport.write(&self.write_buf) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => {
+                    self.last_active = Instant::now();
+                    metrics().network_written_bytes.inc_by(n as u64);
+                    self.write_buf.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            }
+        }
+
+        if self.closing.is_some() && self.write_buf.is_empty() && !self.write_shutdown {
+            self.write_shutdown = true;
+            self.transport.shutdown_write()?;
+        }
+        Ok(())
+    }
+
+    /// 请求执行完成，写回响应
+    pub(super) fn on_response(&mut self, response: Vec<u8>) -> io::Result<()> {
+        self.in_flight = false;
+        self.write_buf.extend_from_slice(&response);
+        if self.close_after_response && self.pending.is_empty() && self.closing.is_none() {
+            self.closing = Some(Instant::now());
+        }
+        self.on_writable()
+    }
+
+    /// 写回错误响应并关闭连接，之后收到的请求都会被丢弃
+    pub(super) fn fail(&mut self, kind: ErrorKind, message: &str) -> io::Result<()> {
+        self.closing = Some(Instant::now());
+        self.pending.clear();
+        self.read_buf.clear();
+        self.request_started = None;
+        if !self.in_flight {
+            let resp = self.encode_error(kind, message)?;
+            self.write_buf.extend_from_slice(&resp);
+        }
+        self.on_writable()
+    }
+
+    /// 不执行当前请求，直接写回错误响应
+    pub(super) fn reject(&mut self, kind: ErrorKind, message: &str) -> io::Result<()> {
+        let resp = self.encode_error(kind, message)?;
+        self.on_response(resp)
+    }
+
+    fn encode_error(&self, kind: ErrorKind, message: &str) -> io::Result<Vec<u8>> {
+        Ok(match self.codec {
+            Codec::Resp(version) => Value::error(kind.resp_code(), message).encode(version),
+            Codec::Http | Codec::Metrics => http::error_response(kind, message, !self.close_after_response && self.closing.is_none()),
+            Codec::Native | Codec::Detect => serde_json::to_vec(&ErrorResponse::Err(message.to_owned()))?
+        })
+    }
+
+    /// 取出下一个可以执行的请求，并标记为执行中
+    ///
+    /// 未写回的响应超过上限时等客户端读走后再执行
+    pub(super) fn next_request(&mut self) -> Option<Call> {
+        if self.in_flight || self.closing.is_some() || self.write_buf.len() >= MAX_WRITE_BUFFER {
+            return None;
+        }
+        let req = self.pending.pop_front()?;
+        self.in_flight = true;
+        Some(req)
+    }
+
+    /// 客户端已关闭且所有请求都已处理完毕
+    pub(super) fn is_finished(&self) -> bool {
+        self.read_closed && self.pending.is_empty() && self.is_idle()
+    }
+
+    /// 没有请求在执行，且响应已全部写回
+    pub(super) fn is_idle(&self) -> bool {
+        !self.in_flight && self.write_buf.is_empty() && !self.transport.has_pending_output()
+    }
+
+
+    /// 检查连接是否超时
+    pub(super) fn check_timeout(
+        &self,
+        now: Instant,
+        idle_timeout: Option<Duration>,
+        request_timeout: Option<Duration>
+    ) -> Option<Timeout> {
+        if let Some(since) = self.closing {
+            if now.duration_since(since) >= LINGER_TIMEOUT {
+                return Some(Timeout::Linger);
+            }
+            return None;
+        }
+        if let (Some(started), Some(timeout)) = (self.request_started, request_timeout) {
+            if now.duration_since(started) >= timeout {
+                return Some(Timeout::Request);
+            }
+        }
+        if let Some(timeout) = idle_timeout {
+            let idle = self.is_idle() && self.pending.is_empty() && self.request_started.is_none();
+            if idle && now.duration_since(self.last_active) >= timeout {
+                return Some(Timeout::Idle);
+            }
+        }
+        None
+    }
+
+    /// 根据缓冲区的大小暂停或恢复读取
+    ///
+    /// 待执行的请求或未写回的响应超过上限时取消可读事件，客户端不读取响应时不会无限制地占用内存；
+    /// 响应全部写回且待执行的请求低于上限后恢复
+    pub(super) fn update_interest(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let read_stopped = mem::take(&mut self.read_stopped);
+        let paused = if self.read_paused {
+            !self.write_buf.is_empty() || self.pending.len() >= MAX_PENDING_REQUESTS
+        } else {
+            read_stopped || self.is_over_limit()
+        };
+        if paused == self.read_paused {
+            return Ok(());
+        }
+        self.read_paused = paused;
+        if paused {
+            debug!("Pausing reads from {}",self.peer_addr);
+            self.transport.reregister(registry, token, Interest::WRITABLE)
+        } else {
+            debug!("Resuming reads from {}",self.peer_addr);
+            // 重新注册后，socket 中已有的数据会再次触发可读事件
+            self.transport.reregister(registry, token, Interest::READABLE | Interest::WRITABLE)
+        }
+    }
+
+    fn is_over_limit(&self) -> bool {
+        self.write_buf.len() >= MAX_WRITE_BUFFER || self.pending.len() >= MAX_PENDING_REQUESTS
+    }
+
+    pub(super) fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.transport.deregister(registry)
+    }
+
+    /// 解码缓冲区中的完整请求，遇到超过 `max_request_size` 的请求时返回 false
+    fn decode(&mut self, max_request_size: usize, role: &str) -> io::Result<bool> {
+        if let Codec::Detect = self.codec {
+            // 原生协议的请求是 JSON 对象或字符串，其余按 RESP 处理
+            match self.read_buf.iter().find(|b| !b.is_ascii_whitespace()) {
+                Some(b'{') | Some(b'"') => self.codec = Codec::Native,
+                Some(_) => self.codec = Codec::Resp(Version::Resp2),
+                None => return Ok(true)
+            }
+        }
+        let decoded = match self.codec {
+            Codec::Resp(_) => self.decode_resp(max_request_size, role).map_err(|e| (ErrorKind::Invalid, e)),
+            Codec::Http | Codec::Metrics => self.decode_http(max_request_size),
+            _ => Ok(0)
+        };
+        let consumed = match self.codec {
+            Codec::Resp(_) | Codec::Http | Codec::Metrics => match decoded {
+                Ok(consumed) => consumed,
+                Err((kind, e)) => {
+                    warn!("{} from {}, closing connection",e,self.peer_addr);
+                    self.read_buf.clear();
+                    self.fail(kind, &e)?;
+                    return Ok(true);
+                }
+            },
+            _ => match self.decode_native(max_request_size)? {
+                Some(consumed) => consumed,
+                None => return Ok(false)
+            }
+        };
+        self.read_buf.drain(..consumed);
+
+        if self.read_buf.iter().all(u8::is_ascii_whitespace) {
+            self.request_started = None;
+        } else if consumed > 0 || self.request_started.is_none() {
+            self.request_started = Some(Instant::now());
+        }
+        Ok(true)
+    }
+
+    /// 解码原生协议的请求，返回消耗的字节数，请求过大时返回 `None`
+    fn decode_native(&mut self, max_request_size: usize) -> io::Result<Option<usize>> {
+        let mut stream = Deserializer::from_slice(&self.read_buf).into_iter::<Request>();
+        let mut consumed = 0;
+        loop {
+            match stream.next() {
+                Some(Ok(req)) => {
+                    if stream.byte_offset() - consumed > max_request_size {
+                        return Ok(None);
+                    }
+                    consumed = stream.byte_offset();
+                    self.pending.push_back(Call::from_native(req)?);
+                }
+                // 数据不完整，等待更多数据
+                Some(Err(e)) if e.is_eof() => return Ok(Some(consumed)),
+                Some(Err(e)) => return Err(e.into()),
+                None => return Ok(Some(self.read_buf.len()))
+            }
+        }
+    }
+
+    /// 解码 RESP 命令，返回消耗的字节数，协议错误时返回错误信息
+    fn decode_resp(&mut self, max_request_size: usize, role: &str) -> Result<usize, String> {
+        let mut consumed = 0;
+        while let Some((args, len)) = resp::parse(&self.read_buf[consumed..], max_request_size)? {
+            consumed += len;
+            if args.is_empty() {
+                continue;
+            }
+            if let Codec::Resp(version) = &mut self.codec {
+                self.pending.push_back(resp::decode(args, version, role));
+            }
+        }
+        Ok(consumed)
+    }
+
+    /// 解码 HTTP 请求，返回消耗的字节数
+    ///
+    /// 开启认证时，认证头发生变化的请求之前会插入一次登录
+    fn decode_http(&mut self, max_request_size: usize) -> Result<usize, (ErrorKind, String)> {
+        let mut consumed = 0;
+        while !self.close_after_response {
+            let (req, len) = match http::parse(&self.read_buf[consumed..], max_request_size)? {
+                Some(frame) => frame,
+                None => break
+            };
+            consumed += len;
+            self.close_after_response = !req.keep_alive();
+
+            let authorization = req.authorization().map(str::to_owned);
+            if self.auth_enabled && authorization.is_some() && authorization != self.http_authorization {
+                match authorization.as_deref().and_then(http::basic_credentials) {
+                    // 登录的结果不单独响应，失败时后面的请求会因为未登录被拒绝
+                    Some((user, password)) => self.pending.push_back(Call::Login {
+                        user,
+                        password: Secret(password),
+                        success: Vec::new(),
+                        failure: Vec::new()
+                    }),
+                    None => self.user = None
+                }
+                self.http_authorization = authorization;
+            }
+            self.pending.push_back(http::decode(req, matches!(self.codec, Codec::Metrics)));
+        }
+        Ok(consumed)
+    }
+}
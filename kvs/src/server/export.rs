@@ -0,0 +1,45 @@
+use crate::{
+    common::{ExportPage, ImportMode, ImportStats},
+    KvsEngine, Result
+};
+
+use super::keyspace::{Condition, Keyspace};
+
+/// 读取以 `prefix` 开头、大于 `after` 的最多 `limit` 个键值
+pub(super) fn export<E: KvsEngine>(
+    keyspace: &Keyspace<E>,
+    prefix: &str,
+    after: Option<&str>,
+    limit: usize
+) -> Result<ExportPage> {
+    let page = keyspace.scan(prefix, after, limit)?;
+    let mut entries = Vec::with_capacity(page.keys.len());
+    for key in page.keys {
+        // 扫描到键之后它可能已经被删除
+        if let Some(value) = keyspace.get(key.clone())? {
+            entries.push((key, value));
+        }
+    }
+    Ok(ExportPage { entries, next: page.next })
+}
+
+/// 依次写入一批键值，中途出错时已写入的键不会回滚
+pub(super) fn import<E: KvsEngine>(
+    keyspace: &Keyspace<E>,
+    entries: Vec<(String, String)>,
+    mode: ImportMode
+) -> Result<ImportStats> {
+    let condition = match mode {
+        ImportMode::Overwrite => None,
+        ImportMode::SkipExisting => Some(Condition::IfAbsent)
+    };
+    let mut stats = ImportStats::default();
+    for (key, value) in entries {
+        if keyspace.set(key, value, None, condition)? {
+            stats.written += 1;
+        } else {
+            stats.skipped += 1;
+        }
+    }
+    Ok(stats)
+}
@@ -0,0 +1,369 @@
+//! HTTP/JSON 网关
+//!
+//! - `GET /keys/{key}`：返回 `{"key": .., "value": ..}`，键不存在时返回 404
+//! - `PUT /keys/{key}`：请求体为 `{"value": ..}`，成功时返回 204
+//! - `DELETE /keys/{key}`：成功时返回 204，键不存在时返回 404
+//! - `GET /keys?prefix=&after=&limit=`：按字典序列出键，返回 `{"keys": [..], "next": ..}`，
+//!   `next` 不为 null 时作为下一页的 `after` 参数
+//! - `GET /admin/ping`、`GET /admin/info`、`POST /admin/flush`
+//!
+//! 错误响应的格式为 `{"error": ..}`。开启认证时使用 HTTP Basic 认证，
+//! 同一个连接上相同的凭据只校验一次
+//!
+//! 请求头 `X-Request-Id` 作为请求 ID 记录在服务器日志中，没有时由服务端生成，
+//! 执行的请求都会在响应头 `X-Request-Id` 中返回
+//!
+//! 指标端口上只提供 `GET /metrics`，以 Prometheus 文本格式返回，不需要认证
+use std::{fmt::Write as _, str};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{auth::Permission, KvsEngine, KvsError};
+
+use super::{
+    admin,
+    call::{Call, ErrorKind, Operation},
+    keyspace::Keyspace,
+    stats::ServerStats
+};
+
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// 解析出的 HTTP 请求
+pub(super) struct HttpRequest {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    keep_alive: bool
+}
+
+/// 网关支持的操作
+#[derive(Debug)]
+pub(super) enum Route {
+    Get { key: String },
+    Put { key: String, value: String },
+    Delete { key: String },
+    List { prefix: String, after: Option<String>, limit: usize },
+    Ping,
+    Info,
+    Flush,
+    Metrics
+}
+
+#[derive(Deserialize)]
+struct PutBody {
+    value: String
+}
+
+/// 一个 HTTP 请求和它占用的字节数
+type Frame = (HttpRequest, usize);
+
+/// 从缓冲区中解析一个完整的请求，数据不完整时返回 `None`
+///
+/// 只支持 `Content-Length` 指定长度的请求体
+pub(super) fn parse(buf: &[u8], max_size: usize) -> Result<Option<Frame>, (ErrorKind, String)> {
+    let invalid = |message: &str| (ErrorKind::Invalid, message.to_owned());
+    let head_len = match buf.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(pos) => pos + 4,
+        None => return Ok(None)
+    };
+    let head = str::from_utf8(&buf[..head_len - 4]).map_err(|_| invalid("Request head is not valid UTF-8"))?;
+    let mut lines = head.split("\r\n");
+
+    let request_line = lines.next().unwrap_or_default();
+    let mut parts = request_line.split(' ');
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version), None) => (method, target, version),
+        _ => return Err(invalid("Malformed request line"))
+    };
+    let http10 = match version {
+        "HTTP/1.1" => false,
+        "HTTP/1.0" => true,
+        _ => return Err(invalid("Unsupported HTTP version"))
+    };
+
+    let mut headers = Vec::new();
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or_else(|| invalid("Malformed header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+    }
+    let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+
+    if header("transfer-encoding").is_some() {
+        return Err(invalid("Transfer-Encoding is not supported, use Content-Length"));
+    }
+    let content_length = match header("content-length") {
+        Some(len) => len.parse::<usize>().map_err(|_| invalid("Invalid Content-Length"))?,
+        None => 0
+    };
+    if content_length > max_size {
+        return Err((ErrorKind::TooLarge, "Request too large".to_owned()));
+    }
+    if buf.len() < head_len + content_length {
+        return Ok(None);
+    }
+
+    let connection = header("connection").map(str::to_ascii_lowercase);
+    let keep_alive = match connection.as_deref() {
+        Some("close") => false,
+        Some("keep-alive") => true,
+        _ => !http10
+    };
+    let req = HttpRequest {
+        method: method.to_owned(),
+        target: target.to_owned(),
+        body: buf[head_len..head_len + content_length].to_vec(),
+        headers,
+        keep_alive
+    };
+    Ok(Some((req, head_len + content_length)))
+}
+
+impl HttpRequest {
+    /// 是否保持连接，否则在响应后关闭连接
+    pub(super) fn keep_alive(&self) -> bool {
+        self.keep_alive
+    }
+
+    /// `Authorization` 请求头
+    pub(super) fn authorization(&self) -> Option<&str> {
+        self.header("authorization")
+    }
+
+    /// 请求头，`name` 为小写
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// 解析 Basic 认证头中的用户名和密码
+pub(super) fn basic_credentials(authorization: &str) -> Option<(String, String)> {
+    let decoded = STANDARD.decode(authorization.strip_prefix("Basic ")?.trim()).ok()?;
+    let (user, password) = str::from_utf8(&decoded).ok()?.split_once(':')?;
+    Some((user.to_owned(), password.to_owned()))
+}
+
+/// 将请求转换为待执行的调用，路径或参数错误时直接生成错误响应
+///
+/// `metrics` 为 true 时按指标端口的路由处理
+pub(super) fn decode(req: HttpRequest, metrics: bool) -> Call {
+    let keep_alive = req.keep_alive;
+    let request_id = req.header("x-request-id").map(str::to_owned);
+    let route = if metrics { metrics_route(req) } else { route(req) };
+    match route {
+        Ok(route) => Call::Execute(Operation::Http(route, keep_alive), request_id),
+        Err((status, message, allow)) => {
+            let extra: Vec<(&str, &str)> = allow.iter().map(|allow| ("Allow", *allow)).collect();
+            Call::Reply(response(status, Some(&json!({ "error": message })), keep_alive, &extra))
+        }
+    }
+}
+
+fn metrics_route(req: HttpRequest) -> Result<Route, (u16, String, Option<&'static str>)> {
+    let path = req.target.split_once('?').map_or(req.target.as_str(), |(path, _)| path);
+    match (path, req.method.as_str()) {
+        ("/metrics", "GET") => Ok(Route::Metrics),
+        ("/metrics", _) => Err((405, "Method not allowed".to_owned(), Some("GET"))),
+        _ => Err((404, "Not found".to_owned(), None))
+    }
+}
+
+fn route(req: HttpRequest) -> Result<Route, (u16, String, Option<&'static str>)> {
+    let not_allowed = |allow| Err((405, "Method not allowed".to_owned(), Some(allow)));
+    let (path, query) = req.target.split_once('?').unwrap_or((&req.target, ""));
+    let path = percent_decode(path).ok_or((400, "Invalid percent-encoding in path".to_owned(), None))?;
+
+    match path.as_str() {
+        "/keys" | "/keys/" => {
+            if req.method != "GET" {
+                return not_allowed("GET");
+            }
+            let mut prefix = String::new();
+            let mut after = None;
+            let mut limit = DEFAULT_LIST_LIMIT;
+            for (name, value) in parse_query(query).ok_or((400, "Invalid query string".to_owned(), None))? {
+                match name.as_str() {
+                    "prefix" => prefix = value,
+                    "after" => after = Some(value),
+                    "limit" => {
+                        limit = value.parse().ok().filter(|&n| n > 0 && n <= MAX_LIST_LIMIT)
+                            .ok_or((400, format!("limit must be between 1 and {}",MAX_LIST_LIMIT), None))?;
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Route::List { prefix, after, limit })
+        }
+        "/admin/ping" if req.method == "GET" => Ok(Route::Ping),
+        "/admin/info" if req.method == "GET" => Ok(Route::Info),
+        "/admin/flush" if req.method == "POST" => Ok(Route::Flush),
+        "/admin/ping" | "/admin/info" => not_allowed("GET"),
+        "/admin/flush" => not_allowed("POST"),
+        _ => {
+            let key = match path.strip_prefix("/keys/") {
+                Some(key) => key.to_owned(),
+                None => return Err((404, "Not found".to_owned(), None))
+            };
+            match req.method.as_str() {
+                "GET" => Ok(Route::Get { key }),
+                "DELETE" => Ok(Route::Delete { key }),
+                "PUT" => {
+                    let body: PutBody = serde_json::from_slice(&req.body)
+                        .map_err(|e| (400, format!("Invalid request body: {}",e), None))?;
+                    Ok(Route::Put { key, value: body.value })
+                }
+                _ => not_allowed("GET, PUT, DELETE")
+            }
+        }
+    }
+}
+
+impl Route {
+    /// 执行操作需要的权限
+    pub(super) fn required_permissions(&self) -> Vec<(&str, Permission)> {
+        match self {
+            Route::Get { key } => vec![(key, Permission::Read)],
+            Route::Put { key, .. } | Route::Delete { key } => vec![(key, Permission::Write)],
+            Route::List { prefix, .. } => vec![(prefix, Permission::Read)],
+            Route::Ping | Route::Metrics => Vec::new(),
+            Route::Info | Route::Flush => vec![("", Permission::Admin)]
+        }
+    }
+
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Route::Get { .. } => "get",
+            Route::Put { .. } => "put",
+            Route::Delete { .. } => "delete",
+            Route::List { .. } => "list",
+            Route::Ping => "ping",
+            Route::Info => "info",
+            Route::Flush => "flush",
+            Route::Metrics => "metrics"
+        }
+    }
+}
+
+/// 执行操作并编码响应，`KeyNotFound` 映射为 404，从节点拒绝写操作映射为 403，其余引擎错误映射为 500
+pub(super) fn execute<E: KvsEngine>(
+    keyspace: &Keyspace<E>,
+    stats: &ServerStats,
+    route: Route,
+    keep_alive: bool,
+    request_id: &str
+) -> Vec<u8> {
+    let extra = [("X-Request-Id", request_id)];
+    let not_found = || (404, Some(json!({ "error": "Key not found" })));
+    let result = match route {
+        Route::Get { key } => keyspace.get(key.clone()).map(|value| match value {
+            Some(value) => (200, Some(json!({ "key": key, "value": value }))),
+            None => not_found()
+        }),
+        Route::Put { key, value } => keyspace.set(key, value, None, None).map(|_| (204, None)),
+        Route::Delete { key } => keyspace.remove(key).map(|_| (204, None)),
+        Route::List { prefix, after, limit } => keyspace.scan(&prefix, after.as_deref(), limit)
+            .map(|page| (200, Some(json!({ "keys": page.keys, "next": page.next })))),
+        Route::Ping => Ok((200, Some(json!({ "status": "ok" })))),
+        Route::Info => serde_json::to_value(admin::server_info(keyspace, stats))
+            .map(|info| (200, Some(info)))
+            .map_err(KvsError::from),
+        Route::Flush => keyspace.engine().flush().map(|_| (204, None)),
+        Route::Metrics => {
+            let body = admin::render_metrics(keyspace, stats);
+            return raw_response(200, METRICS_CONTENT_TYPE, body.as_bytes(), keep_alive, &extra);
+        }
+    };
+    let (status, body) = match result {
+        Ok(resp) => resp,
+        Err(KvsError::KeyNotFound) => not_found(),
+        Err(KvsError::ReadOnly) => (403, Some(json!({ "error": KvsError::ReadOnly.to_string() }))),
+        Err(e @ KvsError::BackendUnavailable(..)) => (503, Some(json!({ "error": e.to_string() }))),
+        Err(e) => {
+            error!("HTTP request failed: {}",e);
+            (500, Some(json!({ "error": e.to_string() })))
+        }
+    };
+    response(status, body.as_ref(), keep_alive, &extra)
+}
+
+/// 编码错误响应
+pub(super) fn error_response(kind: ErrorKind, message: &str, keep_alive: bool) -> Vec<u8> {
+    let extra: &[(&str, &str)] = if kind == ErrorKind::Unauthenticated {
+        &[("WWW-Authenticate", "Basic realm=\"kvs\"")]
+    } else {
+        &[]
+    };
+    response(kind.http_status(), Some(&json!({ "error": message })), keep_alive, extra)
+}
+
+fn response(status: u16, body: Option<&Value>, keep_alive: bool, extra: &[(&str, &str)]) -> Vec<u8> {
+    let body = body.map(Value::to_string).unwrap_or_default();
+    raw_response(status, "application/json", body.as_bytes(), keep_alive, extra)
+}
+
+fn raw_response(status: u16, content_type: &str, body: &[u8], keep_alive: bool, extra: &[(&str, &str)]) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", status, reason(status));
+    if !body.is_empty() {
+        let _ = write!(head, "Content-Type: {}\r\n", content_type);
+    }
+    let _ = write!(head, "Content-Length: {}\r\n", body.len());
+    if !keep_alive {
+        head.push_str("Connection: close\r\n");
+    }
+    for (name, value) in extra {
+        let _ = write!(head, "{}: {}\r\n", name, value);
+    }
+    head.push_str("\r\n");
+    let mut resp = head.into_bytes();
+    resp.extend_from_slice(body);
+    resp
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error"
+    }
+}
+
+fn parse_query(query: &str) -> Option<Vec<(String, String)>> {
+    query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Some((percent_decode(&name.replace('+', " "))?, percent_decode(&value.replace('+', " "))?))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
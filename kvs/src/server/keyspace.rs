@@ -0,0 +1,333 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet, HashMap},
+    hash::{Hash, Hasher},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant}
+};
+
+use crate::{common::{BackupSession, Command, Record, ScanPage}, Backup, KvsEngine, KvsError, Result};
+
+use super::{backup::Backups, raft::{Apply, Flush, Raft}, replication::Replication};
+
+// 写操作按键的哈希分配到固定数量的锁上
+const LOCK_STRIPES: usize = 64;
+
+/// 服务端对存储引擎的封装，所有协议的请求都通过它访问引擎
+///
+/// 在引擎之上提供几个引擎本身没有的能力：
+/// - 同一个键上的写操作互斥，使 `SET NX/XX` 这类先检查再写入的操作是原子的
+/// - 键的过期时间。过期时间只保存在内存中，服务器重启后键不再过期
+/// - 复制：写操作在持有键的锁时记录到复制日志，同一个键上的记录顺序与写入引擎的顺序一致。
+///   过期时间不会复制，切换主节点后会丢失，因此参与复制的节点拒绝带过期时间的写入
+/// - 集群模式下写操作通过 Raft 提交后才写入引擎，读操作先确认领导权。
+///   过期时间同样无法在节点间保留，集群模式下拒绝带过期时间的写入
+pub(super) struct Keyspace<E: KvsEngine> {
+    engine: E,
+    shared: Arc<Shared>
+}
+
+struct Shared {
+    locks: Vec<Mutex<()>>,
+    expiry: Mutex<Expiry>,
+    // 是否有线程正在清理过期的键
+    sweeping: AtomicBool,
+    replication: Replication,
+    raft: Option<Raft>,
+    backups: Backups
+}
+
+#[derive(Default)]
+struct Expiry {
+    deadlines: HashMap<String, Instant>,
+    // 按过期时间排序，用于定期清理
+    queue: BTreeSet<(Instant, String)>
+}
+
+/// 写入的前提条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Condition {
+    /// 键不存在时才写入
+    IfAbsent,
+    /// 键存在时才写入
+    IfPresent
+}
+
+impl<E: KvsEngine> Clone for Keyspace<E> {
+    fn clone(&self) -> Self {
+        Keyspace { engine: self.engine.clone(), shared: Arc::clone(&self.shared) }
+    }
+}
+
+impl<E: KvsEngine> Keyspace<E> {
+    pub(super) fn new(engine: E, replication: Replication, raft: Option<Raft>) -> Self {
+        Keyspace {
+            engine,
+            shared: Arc::new(Shared {
+                locks: (0..LOCK_STRIPES).map(|_| Mutex::new(())).collect(),
+                expiry: Mutex::new(Expiry::default()),
+                sweeping: AtomicBool::new(false),
+                replication,
+                raft,
+                backups: Backups::default()
+            })
+        }
+    }
+
+    pub(super) fn engine(&self) -> &E {
+        &self.engine
+    }
+
+    pub(super) fn replication(&self) -> &Replication {
+        &self.shared.replication
+    }
+
+    pub(super) fn raft(&self) -> Option<&Raft> {
+        self.shared.raft.as_ref()
+    }
+
+    /// RESP 中节点的角色：集群的跟随者和只读的从节点为 `replica`，其余为 `master`
+    pub(super) fn role(&self) -> &'static str {
+        let replica = match self.raft() {
+            Some(raft) => !raft.is_leader(),
+            None => self.replication().is_read_only()
+        };
+        if replica { "replica" } else { "master" }
+    }
+
+    pub(super) fn backups(&self) -> &Backups {
+        &self.shared.backups
+    }
+
+    /// 创建一份一致的备份，引擎需要时在导出期间暂停所有键上的写操作
+    ///
+    /// Raft 提交的写操作不经过键的锁，集群中应该从不处理写请求的跟随者上备份
+    pub(super) fn backup(&self) -> Result<BackupSession> {
+        let backup = if self.engine.backup_needs_pause() {
+            let guards: Vec<_> = self.shared.locks.iter().map(|lock| lock.lock().unwrap()).collect();
+            let file = Backup::export(&self.engine);
+            drop(guards);
+            Backup::from_export(&self.engine, file?)?
+        } else {
+            self.engine.backup()?
+        };
+        Ok(self.shared.backups.insert(backup))
+    }
+
+    /// Raft 提交的写操作的应用方式：写入引擎并记录到复制日志
+    pub(super) fn applier(&self) -> Apply {
+        let engine = self.engine.clone();
+        let shared: Weak<Shared> = Arc::downgrade(&self.shared);
+        Box::new(move |command| {
+            match command {
+                Command::Set { key, value } => engine.set(key.clone(), value.clone())?,
+                Command::Remove { key } => match engine.remove(key.clone()) {
+                    Ok(()) | Err(KvsError::KeyNotFound) => {}
+                    Err(e) => return Err(e)
+                }
+            }
+            if let Some(shared) = shared.upgrade() {
+                shared.replication.append(command.clone());
+            }
+            Ok(())
+        })
+    }
+
+    /// Raft 记录已应用的序号前持久化存储引擎的方式
+    pub(super) fn flusher(&self) -> Flush {
+        let engine = self.engine.clone();
+        Box::new(move || engine.flush())
+    }
+
+    /// 获取键的值，已过期的键会被删除
+    pub(super) fn get(&self, key: String) -> Result<Option<String>> {
+        self.read_barrier()?;
+        if self.is_expired(&key) {
+            let _guard = self.lock(&key);
+            self.remove_expired(&key)?;
+            return Ok(None);
+        }
+        self.engine.get(key)
+    }
+
+    /// 写入键值并清除原有的过期时间，`ttl` 为新的过期时间
+    ///
+    /// 不满足 `condition` 时不写入并返回 false。集群模式或参与复制时不支持 `ttl`
+    pub(super) fn set(
+        &self,
+        key: String,
+        value: String,
+        ttl: Option<Duration>,
+        condition: Option<Condition>
+    ) -> Result<bool> {
+        self.shared.replication.check_writable()?;
+        if ttl.is_some() && (self.shared.raft.is_some() || self.shared.replication.is_replicated()) {
+            return Err(KvsError::StringError(
+                "Expiration is not supported in cluster or replicated mode: expiration times are kept only in this \
+                 node's memory and would be lost on failover".to_owned()
+            ));
+        }
+        let _guard = self.lock(&key);
+        if let Some(condition) = condition {
+            self.read_barrier()?;
+            self.remove_expired(&key)?;
+            let exists = self.engine.get(key.clone())?.is_some();
+            if exists != (condition == Condition::IfPresent) {
+                return Ok(false);
+            }
+        }
+        self.write(Command::Set { key: key.clone(), value })?;
+        let mut expiry = self.shared.expiry.lock().unwrap();
+        expiry.clear(&key);
+        if let Some(ttl) = ttl {
+            expiry.insert(key, Instant::now() + ttl);
+        }
+        Ok(true)
+    }
+
+    /// 删除键，键不存在或已过期时返回 `KeyNotFound`
+    pub(super) fn remove(&self, key: String) -> Result<()> {
+        self.shared.replication.check_writable()?;
+        let _guard = self.lock(&key);
+        if self.remove_expired(&key)? {
+            return Err(KvsError::KeyNotFound);
+        }
+        // 集群中删除不存在的键不需要提交
+        if self.shared.raft.is_some() {
+            self.read_barrier()?;
+            if self.engine.get(key.clone())?.is_none() {
+                return Err(KvsError::KeyNotFound);
+            }
+        }
+        self.write(Command::Remove { key: key.clone() })?;
+        self.shared.expiry.lock().unwrap().clear(&key);
+        Ok(())
+    }
+
+    /// 从节点应用主节点的一条记录
+    pub(super) fn apply(&self, record: Record) -> Result<()> {
+        match &record.command {
+            Command::Set { key, value } => self.restore(key.clone(), Some(value.clone()))?,
+            Command::Remove { key } => self.restore(key.clone(), None)?
+        }
+        self.shared.replication.append_applied(record);
+        Ok(())
+    }
+
+    /// 写入（`value` 为 `None` 时删除）主节点同步来的数据，不检查只读，也不记录到复制日志
+    pub(super) fn restore(&self, key: String, value: Option<String>) -> Result<()> {
+        let _guard = self.lock(&key);
+        match value {
+            Some(value) => self.engine.set(key.clone(), value)?,
+            None => match self.engine.remove(key.clone()) {
+                Ok(()) | Err(KvsError::KeyNotFound) => {}
+                Err(e) => return Err(e)
+            }
+        }
+        self.shared.expiry.lock().unwrap().clear(&key);
+        Ok(())
+    }
+
+    /// 按字典序扫描以 `prefix` 开头、大于 `after` 的最多 `limit` 个键，已过期的键不会被返回
+    ///
+    /// 后面可能还有更多键时，`next` 为本页扫描到的最后一个键（可能是已过期被过滤掉的键）
+    pub(super) fn scan(&self, prefix: &str, after: Option<&str>, limit: usize) -> Result<ScanPage> {
+        self.read_barrier()?;
+        let mut keys = self.engine.scan(prefix, after, limit)?;
+        let next = if keys.len() >= limit { keys.last().cloned() } else { None };
+        if !self.shared.expiry.lock().unwrap().deadlines.is_empty() {
+            keys.retain(|key| !self.is_expired(key));
+        }
+        Ok(ScanPage { keys, next })
+    }
+
+    /// 是否有键已经到期，且当前没有正在进行的清理
+    ///
+    /// 返回 true 时调用方需要调用 `expire_due` 完成清理
+    pub(super) fn start_sweep(&self, now: Instant) -> bool {
+        // 集群中只有领导者删除过期的键
+        if self.shared.raft.as_ref().is_some_and(|raft| !raft.is_leader()) {
+            return false;
+        }
+        let due = self.shared.expiry.lock().unwrap().queue.first().is_some_and(|(at, _)| *at <= now);
+        due && !self.shared.sweeping.swap(true, Ordering::AcqRel)
+    }
+
+    /// 从引擎中删除所有已经过期的键
+    pub(super) fn expire_due(&self) -> Result<()> {
+        let res = self.expire_due_keys();
+        self.shared.sweeping.store(false, Ordering::Release);
+        res
+    }
+
+    fn expire_due_keys(&self) -> Result<()> {
+        let now = Instant::now();
+        loop {
+            let key = match self.shared.expiry.lock().unwrap().queue.first() {
+                Some((at, key)) if *at <= now => key.clone(),
+                _ => return Ok(())
+            };
+            let _guard = self.lock(&key);
+            self.remove_expired(&key)?;
+        }
+    }
+
+    fn is_expired(&self, key: &str) -> bool {
+        let expiry = self.shared.expiry.lock().unwrap();
+        expiry.deadlines.get(key).is_some_and(|at| *at <= Instant::now())
+    }
+
+    /// 如果键已过期则从引擎中删除，调用方需要持有该键的锁
+    fn remove_expired(&self, key: &str) -> Result<bool> {
+        if !self.is_expired(key) {
+            return Ok(false);
+        }
+        debug!("Key {} expired",key);
+        match self.write(Command::Remove { key: key.to_owned() }) {
+            Ok(()) | Err(KvsError::KeyNotFound) => {}
+            Err(e) => return Err(e)
+        }
+        self.shared.expiry.lock().unwrap().clear(key);
+        Ok(true)
+    }
+
+    /// 写入引擎并记录到复制日志，集群模式下提交到 Raft 日志后由应用线程写入
+    fn write(&self, command: Command) -> Result<()> {
+        if let Some(raft) = &self.shared.raft {
+            return raft.propose(command);
+        }
+        match &command {
+            Command::Set { key, value } => self.engine.set(key.clone(), value.clone())?,
+            Command::Remove { key } => self.engine.remove(key.clone())?
+        }
+        self.shared.replication.append(command);
+        Ok(())
+    }
+
+    /// 集群模式下确认领导权并等待本地数据追上，使之后的读取线性一致
+    fn read_barrier(&self) -> Result<()> {
+        match &self.shared.raft {
+            Some(raft) => raft.read_index(),
+            None => Ok(())
+        }
+    }
+
+    fn lock(&self, key: &str) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let stripe = hasher.finish() as usize % self.shared.locks.len();
+        self.shared.locks[stripe].lock().unwrap()
+    }
+}
+
+impl Expiry {
+    fn insert(&mut self, key: String, at: Instant) {
+        self.queue.insert((at, key.clone()));
+        self.deadlines.insert(key, at);
+    }
+
+    fn clear(&mut self, key: &str) {
+        if let Some(at) = self.deadlines.remove(key) {
+            self.queue.remove(&(at, key.to_owned()));
+        }
+    }
+}
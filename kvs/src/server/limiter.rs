@@ -0,0 +1,46 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant}
+};
+
+// 长时间没有请求的客户端会被清理掉
+const BUCKET_EXPIRY: Duration = Duration::from_secs(60);
+
+/// 按客户端 IP 限制请求速率的令牌桶
+pub(super) struct RateLimiter {
+    rate: u32,
+    buckets: HashMap<IpAddr,Bucket>
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant
+}
+
+impl RateLimiter {
+    /// `rate` 为每秒允许的请求数，同时也是允许的突发请求数
+    pub(super) fn new(rate: u32) -> Self {
+        RateLimiter { rate, buckets: HashMap::new() }
+    }
+
+    /// 尝试为一次请求获取令牌，超出速率时返回 false
+    pub(super) fn try_acquire(&mut self, ip: IpAddr, now: Instant) -> bool {
+        let rate = f64::from(self.rate);
+        let bucket = self.buckets.entry(ip).or_insert(Bucket { tokens: rate, last_refill: now });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(rate);
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 清理长时间没有请求的客户端
+    pub(super) fn evict_expired(&mut self, now: Instant) {
+        self.buckets.retain(|_, bucket| now.duration_since(bucket.last_refill) < BUCKET_EXPIRY);
+    }
+}
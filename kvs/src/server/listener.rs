@@ -0,0 +1,113 @@
+use std::{
+    fs::{self, Permissions},
+    io,
+    os::unix::{fs::{FileTypeExt, PermissionsExt}, net::UnixStream},
+    path::{Path, PathBuf}
+};
+
+use mio::{
+    net::{TcpListener, UnixListener},
+    Interest,
+    Registry,
+    Token
+};
+
+use crate::{KvsError, Result, ServerAddr};
+
+use super::{config::Protocol, transport::Transport};
+
+/// 监听端口的用途
+#[derive(Clone, Copy)]
+pub(super) enum Endpoint {
+    /// 以指定协议服务客户端请求
+    Client(Protocol),
+    /// 只提供 `GET /metrics`，不需要认证
+    Metrics
+}
+
+/// 监听 TCP 端口或 Unix 域套接字
+pub(super) enum Listener {
+    Tcp(TcpListener),
+    /// Unix 域套接字及其路径，关闭时删除套接字文件
+    Unix(UnixListener, PathBuf)
+}
+
+impl Listener {
+    /// 绑定地址，`unix_mode` 为 Unix 域套接字文件的权限
+    ///
+    /// 路径上残留的套接字文件（上次没有正常退出）会被删除，但仍在被其他进程监听时返回错误
+    pub(super) fn bind(addr: &ServerAddr, unix_mode: Option<u32>) -> Result<Self> {
+        match addr {
+            ServerAddr::Tcp(addr) => {
+                let listener = std::net::TcpListener::bind(addr)?;
+                listener.set_nonblocking(true)?;
+                Ok(Listener::Tcp(TcpListener::from_std(listener)))
+            }
+            ServerAddr::Unix(path) => {
+                remove_stale_socket(path)?;
+                let listener = UnixListener::bind(path)?;
+                if let Some(mode) = unix_mode {
+                    fs::set_permissions(path, Permissions::from_mode(mode))?;
+                }
+                Ok(Listener::Unix(listener, path.clone()))
+            }
+        }
+    }
+
+    /// 接收一个连接，返回明文的传输层和对端地址
+    ///
+    /// Unix 域套接字的客户端没有地址，以监听的路径代替
+    pub(super) fn accept(&self) -> io::Result<(Transport, ServerAddr)> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer_addr) = listener.accept()?;
+                Ok((Transport::Plain(stream), ServerAddr::Tcp(peer_addr)))
+            }
+            Listener::Unix(listener, path) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Transport::Unix(stream), ServerAddr::Unix(path.clone())))
+            }
+        }
+    }
+
+    pub(super) fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => registry.register(listener, token, Interest::READABLE),
+            Listener::Unix(listener, _) => registry.register(listener, token, Interest::READABLE)
+        }
+    }
+
+    pub(super) fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Listener::Tcp(listener) => registry.deregister(listener),
+            Listener::Unix(listener, _) => registry.deregister(listener)
+        }
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = self {
+            if let Err(e) = fs::remove_file(&path) {
+                warn!("Failed to remove socket file {}: {}",path.display(),e);
+            }
+        }
+    }
+}
+
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into())
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(KvsError::StringError(format!("{} exists and is not a socket",path.display())));
+    }
+    if UnixStream::connect(path).is_ok() {
+        return Err(KvsError::StringError(format!("{} is already in use",path.display())));
+    }
+    info!("Removing stale socket file {}",path.display());
+    fs::remove_file(path)?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use ring::digest::{Context, SHA256};
+
+use crate::{common::MerkleNode, KvsEngine, Result};
+
+use super::keyspace::Keyspace;
+
+// 按序遍历键时每次扫描的键数
+const SCAN_PAGE_SIZE: usize = 1000;
+
+/// 计算以 `prefix` 开头的键在 Merkle 树中的下一层节点
+///
+/// 按字典序遍历这些键，前缀多一个字符后相同的键是连续的，因此每个子节点的哈希可以边遍历边计算，
+/// 不需要在内存中保存整棵树。每次调用都要读取前缀下的所有键和值
+pub(super) fn children<E: KvsEngine>(keyspace: &Keyspace<E>, prefix: &str) -> Result<Vec<MerkleNode>> {
+    let mut children = Vec::new();
+    let mut current: Option<(MerkleNode, Context)> = None;
+    let mut after: Option<String> = None;
+    loop {
+        let page = keyspace.scan(prefix, after.as_deref(), SCAN_PAGE_SIZE)?;
+        for key in page.keys {
+            // 扫描到键之后它可能已经被删除
+            let value = match keyspace.get(key.clone())? {
+                Some(value) => value,
+                None => continue
+            };
+            let child = child_prefix(prefix, &key);
+            if current.as_ref().is_none_or(|(node, _)| node.prefix != child) {
+                children.extend(current.take().map(finish));
+                let node = MerkleNode { prefix: child.to_owned(), keys: 0, hash: String::new() };
+                current = Some((node, Context::new(&SHA256)));
+            }
+            let (node, context) = current.as_mut().unwrap();
+            node.keys += 1;
+            for field in [key.as_bytes(), value.as_bytes()] {
+                context.update(&(field.len() as u64).to_be_bytes());
+                context.update(field);
+            }
+        }
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break
+        }
+    }
+    children.extend(current.map(finish));
+    Ok(children)
+}
+
+/// `key` 所属的子节点的前缀：`prefix` 加上 `key` 的下一个字符，`key` 等于 `prefix` 时为 `prefix` 本身
+fn child_prefix<'a>(prefix: &str, key: &'a str) -> &'a str {
+    let next = key[prefix.len()..].chars().next().map_or(0, char::len_utf8);
+    &key[..prefix.len() + next]
+}
+
+fn finish((mut node, context): (MerkleNode, Context)) -> MerkleNode {
+    node.hash = context.finish().as_ref().iter().map(|byte| format!("{:02x}",byte)).collect();
+    node
+}
@@ -0,0 +1,887 @@
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::{Duration, Instant}
+};
+
+use crossbeam::channel::{self, Receiver, Sender};
+use mio::{event::Event, Events, Poll, Token, Waker};
+use rustls::ServerConnection;
+use tracing::info_span;
+
+use crate::{
+    Result,
+    KvsEngine,
+    KvsError,
+    AuthConfig,
+    ServerAddr,
+    ToServerAddrs,
+    auth::User,
+    engines,
+    metrics::metrics,
+    common::{
+        Request,
+        GetResponse,
+        SetResponse,
+        RemoveResponse,
+        ScanResponse,
+        MerkleResponse,
+        PingResponse,
+        AuthResponse,
+        InfoResponse,
+        StatsResponse,
+        ConfigGetResponse,
+        SlowlogResponse,
+        ExportResponse,
+        ImportResponse,
+        BackupResponse,
+        BackupChunkResponse,
+        SnapshotResponse,
+        TailResponse,
+        VoteResponse,
+        AppendResponse,
+        RedirectResponse,
+        AdminResponse,
+        ErrorResponse,
+        Secret
+    },
+    thread_pool::ThreadPool
+};
+
+use self::{
+    call::{Call, ErrorKind, Operation, RequestId},
+    connection::{Connection, Timeout},
+    keyspace::Keyspace,
+    limiter::RateLimiter,
+    listener::{Endpoint, Listener},
+    raft::Raft,
+    replication::Replication,
+    slowlog::{Execution, SlowLog},
+    stats::ServerStats,
+    transport::Transport
+};
+pub use self::{config::{ClusterConfig, Protocol, ServerConfig}, reload::ReloadHandle, shutdown::ShutdownHandle};
+
+mod admin;
+mod backup;
+mod call;
+mod config;
+mod connection;
+mod export;
+mod http;
+mod keyspace;
+mod limiter;
+mod listener;
+mod merkle;
+mod raft;
+mod reload;
+mod replica;
+mod replication;
+mod resp;
+mod shutdown;
+mod slowlog;
+mod stats;
+mod transport;
+
+const LISTENER: Token = Token(0);
+const HTTP_LISTENER: Token = Token(1);
+const METRICS_LISTENER: Token = Token(2);
+const WAKER: Token = Token(3);
+const FIRST_CONNECTION: usize = 4;
+// 检查连接超时的间隔
+const TICK: Duration = Duration::from_millis(200);
+// 连续登录失败达到该次数后关闭连接
+const MAX_AUTH_FAILURES: u32 = 3;
+const CLUSTER_DISABLED: &str = "Cluster mode is not enabled";
+// 一次扫描最多返回的键数
+const MAX_SCAN_LIMIT: usize = 1000;
+
+/// kvs 服务器端
+///
+/// 基于 mio(epoll) 的事件循环实现：所有连接的读写都在事件循环线程中以非阻塞方式完成，
+/// 只有解码完成的请求才会交给线程池执行，因此空闲连接不会占用线程池中的线程
+pub struct KvsServer<E: KvsEngine,P: ThreadPool> {
+    engine: E,
+    pool: P,
+    config: ServerConfig,
+    shutdown: ShutdownHandle,
+    reload: ReloadHandle,
+}
+
+/// 线程池执行完请求后返回给事件循环的结果
+struct Completion {
+    token: Token,
+    response: Vec<u8>,
+    // 登录请求的结果
+    login: Option<Login>
+}
+
+enum Login {
+    Success(Arc<User>),
+    Failure
+}
+
+impl<E: KvsEngine,P: ThreadPool> KvsServer<E,P> {
+    /// 新建一个服务器
+    pub fn new(engine: E, pool: P) -> Self {
+        KvsServer {
+            engine,
+            pool,
+            config: ServerConfig::default(),
+            shutdown: ShutdownHandle::default(),
+            reload: ReloadHandle::default()
+        }
+    }
+
+    /// 设置服务器的运行参数
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// 获取用于关闭服务器的句柄，需要在调用 `run` 之前获取
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// 获取用于重新加载配置的句柄，需要在调用 `run` 之前获取
+    pub fn reload_handle(&self) -> ReloadHandle {
+        self.reload.clone()
+    }
+
+    /// 绑定 TCP 地址或 Unix 域套接字（如 `unix:/run/kvs.sock`），对外提供服务，
+    /// 配置了 `http_addr` 时同时监听 HTTP 网关，配置了 `metrics_addr` 时同时提供 Prometheus 指标，
+    /// 配置了 `replica_of` 时作为从节点运行，配置了 `cluster` 时作为 Raft 集群的节点运行
+    ///
+    /// 该方法会一直阻塞，直到通过 `ShutdownHandle` 关闭服务器
+    pub fn run<A: ToServerAddrs>(self,addr: A) -> Result<()> {
+        if self.config.cluster.is_some() {
+            if self.config.replica_of.is_some() {
+                return Err(KvsError::StringError("A cluster node can not be a replica".to_owned()));
+            }
+            // 节点之间使用原生协议通信，HTTP 网关没有重定向到领导者的方式
+            if !matches!(self.config.protocol, Protocol::Native | Protocol::Auto) || self.config.http_addr.is_some() {
+                return Err(KvsError::StringError("Cluster mode requires the native or auto protocol without the HTTP gateway".to_owned()));
+            }
+        }
+        let raft = self.config.cluster.clone().map(Raft::new).transpose()?;
+        let poll = Poll::new()?;
+        let mut listeners = HashMap::new();
+        let mut listener = bind(addr, self.config.unix_socket_mode)?;
+        listener.register(poll.registry(), LISTENER)?;
+        listeners.insert(LISTENER, (listener, Endpoint::Client(self.config.protocol)));
+        if let Some(addr) = self.config.http_addr {
+            let mut listener = Listener::bind(&ServerAddr::Tcp(addr), None)?;
+            listener.register(poll.registry(), HTTP_LISTENER)?;
+            info!("HTTP gateway listening on {}",addr);
+            listeners.insert(HTTP_LISTENER, (listener, Endpoint::Client(Protocol::Http)));
+        }
+        if let Some(addr) = self.config.metrics_addr {
+            let mut listener = Listener::bind(&ServerAddr::Tcp(addr), None)?;
+            listener.register(poll.registry(), METRICS_LISTENER)?;
+            info!("Metrics endpoint listening on {}",addr);
+            listeners.insert(METRICS_LISTENER, (listener, Endpoint::Metrics));
+        }
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        self.shutdown.set_waker(Arc::clone(&waker));
+        self.reload.set_waker(Arc::clone(&waker));
+        let (tx,rx) = channel::unbounded();
+
+        let keyspace = Keyspace::new(self.engine, Replication::new(self.config.replication_backlog), raft);
+        if let Some(bytes) = self.config.compaction_threshold {
+            admin::set_compaction_threshold(&keyspace, bytes)?;
+        }
+        if let Some(raft) = keyspace.raft() {
+            raft.start(keyspace.applier(), keyspace.flusher(), self.shutdown.clone())?;
+        }
+        if let Some(leader) = self.config.replica_of.clone() {
+            let addrs: Vec<String> = leader.addrs().iter().map(ToString::to_string).collect();
+            let follower = keyspace.replication().follow(addrs.join(","));
+            replica::spawn(keyspace.clone(), leader, follower, self.shutdown.clone())?;
+        }
+
+        let mut reactor = Reactor {
+            keyspace,
+            stats: Arc::new(ServerStats::new(SlowLog::new(self.config.slowlog_threshold, self.config.slowlog_max_len))),
+            pool: self.pool,
+            poll,
+            waker,
+            tx,
+            rx,
+            listeners,
+            connections: HashMap::new(),
+            rejected: HashMap::new(),
+            next_token: FIRST_CONNECTION,
+            limiter: self.config.rate_limit.map(RateLimiter::new),
+            config: self.config,
+            shutdown: self.shutdown,
+            reload: self.reload,
+            draining: false
+        };
+        reactor.run()
+    }
+}
+
+/// 依次尝试绑定每个地址，直到成功
+fn bind<A: ToServerAddrs>(addr: A, unix_mode: Option<u32>) -> Result<Listener> {
+    let mut last_err = None;
+    for addr in addr.to_server_addrs()? {
+        match Listener::bind(&addr, unix_mode) {
+            Ok(listener) => return Ok(listener),
+            Err(e) => last_err = Some(e)
+        }
+    }
+    Err(last_err.unwrap_or_else(|| KvsError::StringError("No address to listen on".to_owned())))
+}
+
+/// 事件循环，负责接收连接、读写数据并将请求分发到线程池
+struct Reactor<E: KvsEngine,P: ThreadPool> {
+    keyspace: Keyspace<E>,
+    stats: Arc<ServerStats>,
+    pool: P,
+    poll: Poll,
+    waker: Arc<Waker>,
+    tx: Sender<Completion>,
+    rx: Receiver<Completion>,
+    // 监听的端口及其用途
+    listeners: HashMap<Token,(Listener,Endpoint)>,
+    connections: HashMap<Token,Connection>,
+    // 因连接数超限被拒绝的连接，写完错误响应、等客户端关闭后移除，不计入连接数
+    rejected: HashMap<Token,Connection>,
+    next_token: usize,
+    config: ServerConfig,
+    limiter: Option<RateLimiter>,
+    shutdown: ShutdownHandle,
+    reload: ReloadHandle,
+    // 已开始关闭，不再接收新连接和新请求
+    draining: bool
+}
+
+impl<E: KvsEngine,P: ThreadPool> Reactor<E,P> {
+    fn run(&mut self) -> Result<()> {
+        let mut events = Events::with_capacity(1024);
+        let mut deadline = None;
+        loop {
+            if !self.draining && self.shutdown.is_shutdown() {
+                info!("Shutting down, waiting for {} connections",self.connections.len());
+                for (listener, _) in self.listeners.values_mut() {
+                    listener.deregister(self.poll.registry())?;
+                }
+                self.draining = true;
+                deadline = Some(Instant::now() + self.config.shutdown_timeout);
+            }
+
+            if let Some(deadline) = deadline {
+                self.close_idle_connections();
+                if self.connections.is_empty() {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    warn!("Shutdown timed out with {} connections still busy",self.connections.len());
+                    break;
+                }
+            }
+
+            let timeout = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()).min(TICK),
+                None => TICK
+            };
+            if let Err(e) = self.poll.poll(&mut events, Some(timeout)) {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e.into());
+            }
+            if let Some(config) = self.reload.take() {
+                self.apply_config(config);
+            }
+
+            let max_request_size = self.config.max_request_size;
+            let role = self.keyspace.role();
+            for event in events.iter() {
+                match event.token() {
+                    WAKER => self.complete(),
+                    token if self.listeners.contains_key(&token) => {
+                        if !self.draining {
+                            self.accept(token);
+                        }
+                    }
+                    token if self.rejected.contains_key(&token) => self.linger(token, event, max_request_size, role),
+                    token => {
+                        if !self.draining && (event.is_readable() || event.is_read_closed()) {
+                            self.with_connection(token, |conn| conn.on_readable(max_request_size, role));
+                        }
+                        if event.is_writable() {
+                            self.with_connection(token, Connection::on_writable);
+                        }
+                    }
+                }
+            }
+
+            self.check_timeouts();
+            self.stats.set_connected_clients(self.connections.len());
+        }
+
+        self.listeners.clear();
+        self.connections.clear();
+        self.rejected.clear();
+        match self.keyspace.engine().flush() {
+            Err(KvsError::Unsupported(..)) => {}
+            result => result?
+        }
+        info!("Server stopped");
+        Ok(())
+    }
+
+    /// 应用重新加载的配置中运行时可以调整的设置，其余设置保持不变
+    fn apply_config(&mut self, config: ServerConfig) {
+        match (config.auth, &mut self.config.auth) {
+            (Some(auth), Some(current)) => {
+                // 已登录的连接换成新配置中的同名用户，权限随之更新
+                for conn in self.connections.values_mut() {
+                    conn.reload_user(&auth);
+                }
+                *current = auth;
+            }
+            (None, None) => {}
+            _ => warn!("Enabling or disabling authentication requires a restart")
+        }
+        if let Some(bytes) = config.compaction_threshold {
+            if let Err(e) = admin::set_compaction_threshold(&self.keyspace, bytes) {
+                error!("Failed to reload compaction threshold: {}",e);
+            }
+        }
+        if config.rate_limit != self.config.rate_limit {
+            self.limiter = config.rate_limit.map(RateLimiter::new);
+        }
+        self.stats.slowlog().set_threshold(config.slowlog_threshold);
+        self.stats.slowlog().set_max_len(config.slowlog_max_len);
+        self.config.max_connections = config.max_connections;
+        self.config.idle_timeout = config.idle_timeout;
+        self.config.request_timeout = config.request_timeout;
+        self.config.max_request_size = config.max_request_size;
+        self.config.rate_limit = config.rate_limit;
+        self.config.slowlog_threshold = config.slowlog_threshold;
+        self.config.slowlog_max_len = config.slowlog_max_len;
+        self.config.compaction_threshold = config.compaction_threshold;
+        info!("Configuration reloaded");
+    }
+
+    /// 关闭过程中移除没有请求在执行、响应也已写完的连接
+    fn close_idle_connections(&mut self) {
+        let registry = self.poll.registry();
+        self.connections.retain(|_, conn| {
+            if !conn.is_idle() {
+                return true;
+            }
+            if let Err(e) = conn.deregister(registry) {
+                warn!("Failed to deregister connection: {}",e);
+            }
+            false
+        });
+    }
+
+    /// 关闭空闲超时、请求接收超时的连接
+    fn check_timeouts(&mut self) {
+        let now = Instant::now();
+        let (idle_timeout, request_timeout) = (self.config.idle_timeout, self.config.request_timeout);
+        let mut expired = Vec::new();
+        for (&token, conn) in self.connections.iter_mut() {
+            match conn.check_timeout(now, idle_timeout, request_timeout) {
+                Some(Timeout::Request) => {
+                    warn!("Request from {} timed out, closing connection",conn.peer_addr());
+                    if let Err(e) = conn.fail(ErrorKind::Timeout, "Request timed out") {
+                        debug!("Failed to send timeout to {}: {}",conn.peer_addr(),e);
+                        expired.push(token);
+                    }
+                }
+                Some(Timeout::Idle) => {
+                    info!("Closing idle connection from {}",conn.peer_addr());
+                    expired.push(token);
+                }
+                Some(Timeout::Linger) => expired.push(token),
+                None => {}
+            }
+        }
+        for token in expired {
+            if let Some(mut conn) = self.connections.remove(&token) {
+                if let Err(e) = conn.deregister(self.poll.registry()) {
+                    warn!("Failed to deregister connection: {}",e);
+                }
+            }
+        }
+        let registry = self.poll.registry();
+        self.rejected.retain(|_, conn| {
+            if conn.check_timeout(now, None, None).is_none() {
+                return true;
+            }
+            if let Err(e) = conn.deregister(registry) {
+                warn!("Failed to deregister connection: {}",e);
+            }
+            false
+        });
+        if let Some(limiter) = self.limiter.as_mut() {
+            limiter.evict_expired(now);
+        }
+        if self.keyspace.start_sweep(now) {
+            let keyspace = self.keyspace.clone();
+            self.pool.spawn(move || {
+                if let Err(e) = keyspace.expire_due() {
+                    error!("Failed to remove expired keys: {}",e);
+                }
+            });
+        }
+    }
+
+    /// 接收所有就绪的连接并注册到事件循环中，超过最大连接数时返回繁忙错误
+    ///
+    /// 被拒绝的连接放在 `rejected` 中等待错误响应写完，数量同样达到上限时直接关闭
+    fn accept(&mut self, listener: Token) {
+        let (listener, endpoint) = match self.listeners.get(&listener) {
+            Some((listener, endpoint)) => (listener, *endpoint),
+            None => return
+        };
+        loop {
+            match listener.accept() {
+                Ok((transport,peer_addr)) => {
+                    // Unix 域套接字的访问由文件权限控制，只有 TCP 连接使用 TLS
+                    let mut transport = match (transport, &self.config.tls) {
+                        (Transport::Plain(stream), Some(tls)) => match ServerConnection::new(Arc::clone(&tls.0)) {
+                            Ok(session) => Transport::Tls(stream, Box::new(session)),
+                            Err(e) => {
+                                error!("Failed to start TLS session with {}: {}",peer_addr,e);
+                                continue;
+                            }
+                        },
+                        (transport, _) => transport
+                    };
+                    let token = Token(self.next_token);
+                    self.next_token += 1;
+                    if let Err(e) = transport.register(self.poll.registry(), token) {
+                        error!("Failed to register connection from {}: {}",peer_addr,e);
+                        continue;
+                    }
+                    let auth_enabled = self.config.auth.is_some();
+                    let mut conn = Connection::new(token.0, transport, peer_addr.clone(), endpoint, auth_enabled);
+                    if self.connections.len() < self.config.max_connections {
+                        debug!("Accept connection from {}",peer_addr);
+                        self.stats.connection_accepted();
+                        self.connections.insert(token, conn);
+                        continue;
+                    }
+                    warn!("Too many connections ({}), rejecting {}",self.connections.len(),peer_addr);
+                    let res = conn.fail(ErrorKind::Busy, "Server busy");
+                    if let Err(e) = &res {
+                        debug!("Failed to reject {}: {}",peer_addr,e);
+                    }
+                    if res.is_ok() && self.rejected.len() < self.config.max_connections {
+                        self.rejected.insert(token, conn);
+                    } else if let Err(e) = conn.deregister(self.poll.registry()) {
+                        warn!("Failed to deregister connection: {}",e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!("Connection failed: {}",e);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// 处理被拒绝的连接上的事件，丢弃收到的数据，错误响应写完且客户端关闭后将其移除
+    fn linger(&mut self, token: Token, event: &Event, max_request_size: usize, role: &str) {
+        let conn = match self.rejected.get_mut(&token) {
+            Some(conn) => conn,
+            None => return
+        };
+        let mut res = Ok(());
+        if event.is_readable() || event.is_read_closed() {
+            res = conn.on_readable(max_request_size, role);
+        }
+        if event.is_writable() {
+            res = res.and_then(|_| conn.on_writable());
+        }
+        let done = match res {
+            Ok(()) => conn.is_finished(),
+            Err(e) => {
+                debug!("Failed to reject {}: {}",conn.peer_addr(),e);
+                true
+            }
+        };
+        if done {
+            if let Some(mut conn) = self.rejected.remove(&token) {
+                if let Err(e) = conn.deregister(self.poll.registry()) {
+                    warn!("Failed to deregister connection: {}",e);
+                }
+            }
+        }
+    }
+
+    /// 处理线程池返回的响应
+    fn complete(&mut self) {
+        while let Ok(Completion { token, response, login }) = self.rx.try_recv() {
+            self.with_connection(token, |conn| {
+                conn.on_response(response)?;
+                match login {
+                    Some(Login::Success(user)) => conn.login(user),
+                    Some(Login::Failure) if conn.login_failed() >= MAX_AUTH_FAILURES => {
+                        warn!("Too many authentication failures from {}, closing connection",conn.peer_addr());
+                        return conn.fail(ErrorKind::Unauthenticated, "Too many authentication failures");
+                    }
+                    _ => {}
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// 对连接执行操作，随后分发已解码的请求，并在连接结束时将其移除
+    fn with_connection<F>(&mut self, token: Token, f: F)
+    where
+        F: FnOnce(&mut Connection) -> io::Result<()>
+    {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) => conn,
+            None => return
+        };
+        let span = conn.span().clone();
+        let _entered = span.enter();
+
+        let res = f(conn).and_then(|_| {
+            if self.draining {
+                return Ok(());
+            }
+            while let Some(call) = conn.next_request() {
+                let allowed = match self.limiter.as_mut() {
+                    Some(limiter) => {
+                        // Unix 域套接字的客户端都在本机，共用回环地址的配额
+                        let ip = match conn.peer_addr() {
+                            ServerAddr::Tcp(addr) => addr.ip(),
+                            ServerAddr::Unix(_) => IpAddr::V4(Ipv4Addr::LOCALHOST)
+                        };
+                        limiter.try_acquire(ip, Instant::now())
+                    }
+                    None => true
+                };
+                if !allowed {
+                    warn!("Rate limit exceeded by {}, rejecting {:?}",conn.peer_addr(),call);
+                    conn.reject(ErrorKind::RateLimited, "Rate limit exceeded")?;
+                    continue;
+                }
+
+                match call {
+                    Call::Reply(resp) => conn.on_response(resp)?,
+                    Call::Login { user, password, success, failure } => match &self.config.auth {
+                        Some(auth) => {
+                            let login = LoginJob { auth: auth.clone(), user, password, success, failure };
+                            dispatch_login(login, &self.pool, &self.tx, &self.waker, token, conn.peer_addr().clone());
+                            break;
+                        }
+                        None => conn.reject(ErrorKind::Invalid, "Authentication is not enabled")?
+                    },
+                    Call::Execute(op, request_id) => {
+                        if let Some(auth) = &self.config.auth {
+                            if let Err((kind, message)) = authorize(auth, conn, &op) {
+                                conn.reject(kind, message)?;
+                                continue;
+                            }
+                        }
+                        self.stats.command_processed();
+                        let request_id = conn.request_id(request_id);
+                        dispatch(&self.keyspace, &self.stats, &self.pool, &self.tx, &self.waker, token, conn.peer_addr().clone(), op, request_id);
+                        break;
+                    }
+                }
+            }
+            Ok(())
+        });
+
+        let res = res.and_then(|_| conn.update_interest(self.poll.registry(), token));
+        let done = match res {
+            Ok(()) => conn.is_finished(),
+            Err(e) => {
+                error!("Error on serving client {}: {}",conn.peer_addr(),e);
+                true
+            }
+        };
+
+        if done {
+            if let Some(mut conn) = self.connections.remove(&token) {
+                debug!("Connection from {} closed",conn.peer_addr());
+                if let Err(e) = conn.deregister(self.poll.registry()) {
+                    warn!("Failed to deregister connection: {}",e);
+                }
+            }
+        }
+    }
+}
+
+/// 将请求交给线程池执行，执行完成后通过 channel 和 waker 通知事件循环
+///
+/// 请求在以连接 span 为父级的请求 span 中执行，原生协议的请求带有 ID 时以 `Traced` 包装响应
+#[allow(clippy::too_many_arguments)]
+fn dispatch<E: KvsEngine,P: ThreadPool>(
+    keyspace: &Keyspace<E>,
+    stats: &Arc<ServerStats>,
+    pool: &P,
+    tx: &Sender<Completion>,
+    waker: &Arc<Waker>,
+    token: Token,
+    peer_addr: ServerAddr,
+    op: Operation,
+    request_id: RequestId
+) {
+    let keyspace = keyspace.clone();
+    let stats = Arc::clone(stats);
+    let tx = tx.clone();
+    let waker = Arc::clone(waker);
+    // 耗时从分发开始计算，包括在线程池中排队的时间
+    let started = Instant::now();
+    let (protocol, command) = op.metric_labels();
+    let span = info_span!("request", id = %request_id.id, protocol, command);
+    let key = op.key().map(str::to_owned);
+    pool.spawn(move || {
+        let _entered = span.enter();
+        debug!("Receive request from {}:{:?}",peer_addr,op);
+        engines::take_events();
+        let executed = Instant::now();
+        let response = match op {
+            Operation::Native(req) => {
+                // 出错时也要返回响应，否则连接会一直等待这个请求完成
+                let response = handle_request(&keyspace, &stats, &peer_addr, req).unwrap_or_else(|e| {
+                    error!("Error on serving client {}: {}",peer_addr,e);
+                    error_response(&e)
+                });
+                if request_id.supplied {
+                    call::traced(&request_id.id, &response).unwrap_or_else(|e| {
+                        error!("Error on serving client {}: {}",peer_addr,e);
+                        error_response(&KvsError::Io(e))
+                    })
+                } else {
+                    response
+                }
+            }
+            Operation::Resp(cmd, version) => resp::execute(&keyspace, &stats, cmd, version),
+            Operation::Http(route, keep_alive) => http::execute(&keyspace, &stats, route, keep_alive, &request_id.id)
+        };
+        stats.slowlog().record(Execution {
+            duration: executed.elapsed(),
+            protocol,
+            command,
+            key: key.as_deref(),
+            client: &peer_addr,
+            events: engines::take_events()
+        });
+        metrics().command(protocol, command, started.elapsed());
+        complete(&tx, &waker, Completion { token, response, login: None });
+    })
+}
+
+/// 原生协议的错误响应，可以被解码为任何请求的响应
+fn error_response(e: &KvsError) -> Vec<u8> {
+    serde_json::to_vec(&ErrorResponse::Err(format!("{}",e))).expect("error responses always serialize")
+}
+
+/// 一次登录请求
+struct LoginJob {
+    auth: AuthConfig,
+    user: String,
+    password: Secret,
+    success: Vec<u8>,
+    failure: Vec<u8>
+}
+
+/// 在线程池中校验密码，哈希计算较慢，不能阻塞事件循环
+fn dispatch_login<P: ThreadPool>(
+    login: LoginJob,
+    pool: &P,
+    tx: &Sender<Completion>,
+    waker: &Arc<Waker>,
+    token: Token,
+    peer_addr: ServerAddr
+) {
+    let tx = tx.clone();
+    let waker = Arc::clone(waker);
+    pool.spawn(move || {
+        let (response, result) = match login.auth.authenticate(&login.user, &login.password.0) {
+            Some(user) => (login.success, Login::Success(user)),
+            None => {
+                warn!("Authentication failed for user {} from {}",login.user,peer_addr);
+                (login.failure, Login::Failure)
+            }
+        };
+        complete(&tx, &waker, Completion { token, response, login: Some(result) });
+    })
+}
+
+/// 将结果交还给事件循环
+fn complete(tx: &Sender<Completion>, waker: &Waker, completion: Completion) {
+    if tx.send(completion).is_ok() {
+        if let Err(e) = waker.wake() {
+            error!("Failed to wake up the event loop: {}",e);
+        }
+    }
+}
+
+/// 检查连接上的用户是否有权限执行请求，双向 TLS 的客户端按证书身份自动登录
+///
+/// 拒绝时返回拒绝原因和错误信息
+fn authorize(
+    auth: &AuthConfig,
+    conn: &mut Connection,
+    op: &Operation
+) -> std::result::Result<(), (ErrorKind, &'static str)> {
+    let required = op.required_permissions();
+    if required.is_empty() {
+        return Ok(());
+    }
+    if conn.user().is_none() {
+        if let Some(user) = conn.identity().and_then(|identity| auth.identify(identity)) {
+            conn.login(user);
+        }
+    }
+    let user = match conn.user() {
+        Some(user) => user,
+        None => {
+            warn!("Unauthenticated request from {}: {:?}",conn.peer_addr(),op);
+            return Err((ErrorKind::Unauthenticated, "Authentication required"));
+        }
+    };
+    match required.into_iter().find(|(key, permission)| !user.allows(key, *permission)) {
+        None => Ok(()),
+        Some((key, permission)) => {
+            warn!("User {} from {} denied {:?} on {}",user.name(),conn.peer_addr(),permission,key);
+            Err((ErrorKind::Forbidden, "Permission denied"))
+        }
+    }
+}
+
+/// 执行请求并序列化响应
+fn handle_request<E: KvsEngine>(
+    keyspace: &Keyspace<E>,
+    stats: &ServerStats,
+    peer_addr: &ServerAddr,
+    req: Request
+) -> Result<Vec<u8>> {
+    macro_rules! encode_resp {
+        ($resp:expr) => {{
+            let resp = $resp;
+            debug!("Response to {} : {:?}",peer_addr,resp);
+            serde_json::to_vec(&resp)?
+        }};
+    }
+
+    // 集群中只有领导者处理读写，其他节点让客户端重定向到领导者
+    let data_request = matches!(
+        req,
+        Request::Get { .. }
+            | Request::Set { .. }
+            | Request::Remove { .. }
+            | Request::Scan { .. }
+            | Request::MerkleChildren { .. }
+            | Request::Export { .. }
+            | Request::Import { .. }
+    );
+    if let (true, Some(raft)) = (data_request, keyspace.raft()) {
+        match raft.check_leader() {
+            Err(KvsError::NotLeader(leader)) => return Ok(encode_resp!(RedirectResponse::Redirect(Some(leader)))),
+            Err(KvsError::NoLeader) => return Ok(encode_resp!(RedirectResponse::Redirect(None))),
+            _ => {}
+        }
+    }
+
+    let resp = match req {
+        Request::Get { key } => encode_resp!(match keyspace.get(key) {
+            Ok(value) => GetResponse::Ok(value),
+            Err(e) => GetResponse::Err(format!("{}",e))
+        }),
+        Request::Set { key, value } => encode_resp!(match keyspace.set(key, value, None, None) {
+            Ok(_) => SetResponse::Ok(()),
+            Err(e) => SetResponse::Err(format!("{}",e))
+        }),
+        Request::Remove { key } => encode_resp!(match keyspace.remove(key) {
+            Ok(_) => RemoveResponse::Ok(()),
+            Err(KvsError::KeyNotFound) => RemoveResponse::NotFound,
+            Err(e) => RemoveResponse::Err(format!("{}",e))
+        }),
+        Request::Scan { prefix, after, limit } => encode_resp!(match keyspace.scan(&prefix, after.as_deref(), limit.clamp(1, MAX_SCAN_LIMIT)) {
+            Ok(page) => ScanResponse::Ok(page),
+            Err(e) => ScanResponse::Err(format!("{}",e))
+        }),
+        Request::MerkleChildren { prefix } => encode_resp!(match merkle::children(keyspace, &prefix) {
+            Ok(children) => MerkleResponse::Ok(children),
+            Err(e) => MerkleResponse::Err(format!("{}",e))
+        }),
+        Request::Ping => encode_resp!(PingResponse::Ok(())),
+        Request::Auth { .. } => encode_resp!(AuthResponse::Err("Authentication is not enabled".to_owned())),
+        Request::Info => encode_resp!(InfoResponse::Ok(Box::new(admin::server_info(keyspace, stats)))),
+        Request::Stats => encode_resp!(match keyspace.engine().stats() {
+            Ok(stats) => StatsResponse::Ok(stats),
+            Err(e) => StatsResponse::Err(format!("{}",e))
+        }),
+        Request::Compact => encode_resp!(admin_response(keyspace.engine().compact())),
+        Request::Flush => encode_resp!(admin_response(keyspace.engine().flush())),
+        Request::ConfigGet { name } => encode_resp!(match admin::config_get(keyspace, stats, &name) {
+            Ok(value) => ConfigGetResponse::Ok(value),
+            Err(e) => ConfigGetResponse::Err(format!("{}",e))
+        }),
+        Request::ConfigSet { name, value } => encode_resp!(admin_response(admin::config_set(keyspace, stats, &name, &value))),
+        Request::SlowlogGet { count } => {
+            encode_resp!(SlowlogResponse::Ok(stats.slowlog().get(count.unwrap_or(slowlog::DEFAULT_GET_COUNT))))
+        }
+        Request::SlowlogReset => {
+            stats.slowlog().reset();
+            encode_resp!(AdminResponse::Ok(()))
+        }
+        Request::Export { prefix, after, limit } => encode_resp!(match export::export(keyspace, &prefix, after.as_deref(), limit.clamp(1, MAX_SCAN_LIMIT)) {
+            Ok(page) => ExportResponse::Ok(page),
+            Err(e) => ExportResponse::Err(format!("{}",e))
+        }),
+        Request::Import { entries, mode } => encode_resp!(match export::import(keyspace, entries, mode) {
+            Ok(stats) => ImportResponse::Ok(stats),
+            Err(e) => ImportResponse::Err(format!("{}",e))
+        }),
+        Request::Backup => encode_resp!(match keyspace.backup() {
+            Ok(session) => BackupResponse::Ok(session),
+            Err(e) => BackupResponse::Err(format!("{}",e))
+        }),
+        Request::BackupChunk { id, offset, len } => encode_resp!(match keyspace.backups().read(&id, offset, len) {
+            Ok(data) => BackupChunkResponse::Ok(data),
+            Err(e) => BackupChunkResponse::Err(format!("{}",e))
+        }),
+        Request::BackupFinish { id } => encode_resp!(admin_response(keyspace.backups().finish(&id))),
+        Request::ReplSnapshot { after } => encode_resp!(match replication::snapshot(keyspace, after.as_deref()) {
+            Ok(page) => SnapshotResponse::Ok(page),
+            Err(e) => SnapshotResponse::Err(format!("{}",e))
+        }),
+        Request::ReplTail { id, after } => encode_resp!(match keyspace.replication().read(&id, after, replication::MAX_BATCH) {
+            Some(batch) => TailResponse::Ok(batch),
+            None => TailResponse::Resync
+        }),
+        Request::Promote => encode_resp!(admin_response(keyspace.replication().promote())),
+        Request::RaftVote(req) => encode_resp!(match keyspace.raft().map(|raft| raft.handle_vote(req)) {
+            Some(Ok(reply)) => VoteResponse::Ok(reply),
+            Some(Err(e)) => VoteResponse::Err(format!("{}",e)),
+            None => VoteResponse::Err(CLUSTER_DISABLED.to_owned())
+        }),
+        Request::RaftAppend(req) => encode_resp!(match keyspace.raft().map(|raft| raft.handle_append(req)) {
+            Some(Ok(reply)) => AppendResponse::Ok(reply),
+            Some(Err(e)) => AppendResponse::Err(format!("{}",e)),
+            None => AppendResponse::Err(CLUSTER_DISABLED.to_owned())
+        }),
+        // 解码时已经拆开，不会出现在这里
+        Request::Traced { request, .. } => return handle_request(keyspace, stats, peer_addr, *request)
+    };
+    Ok(resp)
+}
+
+fn admin_response(res: Result<()>) -> AdminResponse {
+    match res {
+        Ok(()) => AdminResponse::Ok(()),
+        Err(e) => AdminResponse::Err(format!("{}",e))
+    }
+}
@@ -0,0 +1,495 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    hash::{BuildHasher, Hasher},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant}
+};
+
+use crate::{
+    common::{AppendReply, AppendRequest, ClusterInfo, Command, RaftEntry, VoteReply, VoteRequest},
+    KvsError,
+    Result,
+    ServerAddr
+};
+
+use super::{config::ClusterConfig, shutdown::ShutdownHandle};
+use self::storage::RaftLog;
+
+mod peer;
+mod storage;
+
+// 后台线程检查关闭、超时的间隔
+const TICK: Duration = Duration::from_millis(20);
+// 等待写操作提交、读操作确认领导权的最长时间
+const COMMIT_TIMEOUT: Duration = Duration::from_secs(5);
+// 每应用这么多条日志持久化一次存储引擎并记录已应用的序号
+const APPLIED_CHECKPOINT: u64 = 1000;
+
+/// 将提交的写操作应用到存储引擎
+pub(super) type Apply = Box<dyn Fn(&Command) -> Result<()> + Send>;
+/// 将已应用的写操作持久化到磁盘
+pub(super) type Flush = Box<dyn Fn() -> Result<()> + Send>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Follower,
+    Candidate,
+    Leader
+}
+
+/// Raft 共识层
+///
+/// 写操作先追加到领导者的日志中，复制到多数节点后提交，再由每个节点的应用线程按顺序写入存储引擎。
+/// 读操作使用 ReadIndex：领导者记下当前的提交序号，通过一轮心跳确认自己仍是领导者，
+/// 等本地应用到该序号后再读取，从而保证线性一致。
+///
+/// 每个节点有一个选举线程、一个应用线程，以及每个其他节点一个复制线程。
+/// 应用线程定期记录已应用的序号，重启后已应用的日志不再重新写入存储引擎。
+/// 所有节点都已应用的日志达到 `log_compact_entries` 条后从日志文件中丢弃
+pub(super) struct Raft {
+    inner: Arc<Inner>
+}
+
+struct Inner {
+    id: u64,
+    peers: HashMap<u64, ServerAddr>,
+    config: ClusterConfig,
+    state: Mutex<State>,
+    // 新日志、提交、应用、角色变化和读确认都会通知所有等待者
+    changed: Condvar
+}
+
+struct State {
+    role: Role,
+    leader: Option<u64>,
+    log: RaftLog,
+    commit_index: u64,
+    last_applied: u64,
+    election_deadline: Instant,
+    votes: HashSet<u64>,
+    progress: HashMap<u64, Progress>,
+    // 已知所有节点都有的最后一条日志的序号，只增不减
+    compact_index: u64,
+    // 读操作确认领导权的轮次
+    read_round: u64
+}
+
+/// 领导者记录的每个其他节点的复制进度
+#[derive(Default)]
+struct Progress {
+    next_index: u64,
+    match_index: u64,
+    // 已经在该任期向这个节点请求过投票
+    vote_term: u64,
+    // 已发送的和被确认的读确认轮次
+    sent_round: u64,
+    acked_round: u64,
+    last_sent: Option<Instant>
+}
+
+impl Raft {
+    /// 打开持久化的日志，调用 `start` 后才开始参与选举
+    pub(super) fn new(config: ClusterConfig) -> Result<Self> {
+        let log = RaftLog::open(&config.dir, config.log_cache_entries)?;
+        // 已应用的日志一定已经提交
+        let applied = log.applied();
+        if applied > 0 {
+            info!("Resuming Raft log after applied entry {}",applied);
+        }
+        let peers: HashMap<u64, ServerAddr> = config.peers.iter()
+            .filter(|(id, _)| *id != config.node_id)
+            .cloned()
+            .collect();
+        let state = State {
+            role: Role::Follower,
+            leader: None,
+            log,
+            commit_index: applied,
+            last_applied: applied,
+            election_deadline: Instant::now() + random_timeout(config.election_timeout),
+            votes: HashSet::new(),
+            progress: peers.keys().map(|&id| (id, Progress::default())).collect(),
+            compact_index: 0,
+            read_round: 0
+        };
+        Ok(Raft {
+            inner: Arc::new(Inner {
+                id: config.node_id,
+                peers,
+                config,
+                state: Mutex::new(state),
+                changed: Condvar::new()
+            })
+        })
+    }
+
+    /// 启动选举、应用和复制线程，服务器关闭后线程退出
+    pub(super) fn start(&self, apply: Apply, flush: Flush, shutdown: ShutdownHandle) -> Result<()> {
+        info!("Raft node {} starting with {} peers",self.inner.id,self.inner.peers.len());
+        let inner = Arc::clone(&self.inner);
+        let stop = shutdown.clone();
+        thread::Builder::new()
+            .name("raft-election".to_owned())
+            .spawn(move || inner.run_elections(&stop))?;
+        let inner = Arc::clone(&self.inner);
+        let stop = shutdown.clone();
+        thread::Builder::new()
+            .name("raft-apply".to_owned())
+            .spawn(move || inner.run_apply(apply, flush, &stop))?;
+        for &peer in self.inner.peers.keys() {
+            let inner = Arc::clone(&self.inner);
+            let stop = shutdown.clone();
+            thread::Builder::new()
+                .name(format!("raft-peer-{}",peer))
+                .spawn(move || peer::run(inner, peer, &stop))?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn is_leader(&self) -> bool {
+        self.inner.lock().role == Role::Leader
+    }
+
+    /// 本节点不是领导者时返回重定向错误
+    pub(super) fn check_leader(&self) -> Result<()> {
+        let state = self.inner.lock();
+        match state.role {
+            Role::Leader => Ok(()),
+            _ => Err(self.inner.redirect(&state))
+        }
+    }
+
+    /// 提交一次写操作，返回时已经应用到本地存储引擎
+    ///
+    /// 等待期间失去领导权时返回错误，此时写操作可能仍会被新的领导者提交
+    pub(super) fn propose(&self, command: Command) -> Result<()> {
+        let mut state = self.inner.lock();
+        if state.role != Role::Leader {
+            return Err(self.inner.redirect(&state));
+        }
+        let term = state.log.term();
+        let index = state.log.last_index() + 1;
+        state.log.append(vec![RaftEntry { index, term, command: Some(command) }])?;
+        self.inner.advance_commit(&mut state)?;
+        self.inner.changed.notify_all();
+
+        let deadline = Instant::now() + COMMIT_TIMEOUT;
+        loop {
+            if state.last_applied >= index {
+                if state.log.term_at(index)? == Some(term) {
+                    return Ok(());
+                }
+                return Err(KvsError::StringError("Write was overwritten by a new leader".to_owned()));
+            }
+            if state.role != Role::Leader || state.log.term() != term {
+                return Err(KvsError::StringError("Leadership lost before the write was committed, it may still be applied".to_owned()));
+            }
+            state = self.inner.wait_until(state, deadline, "Timed out waiting for the write to be committed")?;
+        }
+    }
+
+    /// 确认本节点仍是领导者，并等待本地应用到确认时的提交序号，之后的读取是线性一致的
+    pub(super) fn read_index(&self) -> Result<()> {
+        let deadline = Instant::now() + COMMIT_TIMEOUT;
+        let mut state = self.inner.lock();
+        // 新领导者要先提交一条本任期的日志，才能知道之前的哪些日志已经提交
+        loop {
+            if state.role != Role::Leader {
+                return Err(self.inner.redirect(&state));
+            }
+            if state.log.term_at(state.commit_index)? == Some(state.log.term()) {
+                break;
+            }
+            state = self.inner.wait_until(state, deadline, "Timed out waiting for the leader to commit")?;
+        }
+
+        let (term, read_index) = (state.log.term(), state.commit_index);
+        state.read_round += 1;
+        let round = state.read_round;
+        self.inner.changed.notify_all();
+        loop {
+            if state.role != Role::Leader || state.log.term() != term {
+                return Err(self.inner.redirect(&state));
+            }
+            let acked = 1 + state.progress.values().filter(|progress| progress.acked_round >= round).count();
+            if acked >= self.inner.majority() && state.last_applied >= read_index {
+                return Ok(());
+            }
+            state = self.inner.wait_until(state, deadline, "Timed out confirming leadership")?;
+        }
+    }
+
+    /// 处理候选者的投票请求
+    pub(super) fn handle_vote(&self, req: VoteRequest) -> Result<VoteReply> {
+        let mut state = self.inner.lock();
+        if req.term > state.log.term() {
+            self.inner.step_down(&mut state, req.term)?;
+        }
+        let up_to_date = (req.last_log_term, req.last_log_index) >= (state.log.last_term(), state.log.last_index());
+        let granted = req.term == state.log.term()
+            && state.log.voted_for().is_none_or(|id| id == req.candidate)
+            && up_to_date;
+        if granted {
+            let term = state.log.term();
+            state.log.set_vote(term, Some(req.candidate))?;
+            state.election_deadline = Instant::now() + random_timeout(self.inner.config.election_timeout);
+            debug!("Voted for node {} in term {}",req.candidate,term);
+        }
+        Ok(VoteReply { term: state.log.term(), granted })
+    }
+
+    /// 处理领导者的日志复制请求
+    pub(super) fn handle_append(&self, req: AppendRequest) -> Result<AppendReply> {
+        let mut state = self.inner.lock();
+        if req.term < state.log.term() {
+            return Ok(AppendReply { term: state.log.term(), success: false, last_index: state.log.last_index() });
+        }
+        if req.term > state.log.term() || state.role != Role::Follower {
+            self.inner.step_down(&mut state, req.term)?;
+        }
+        if state.leader != Some(req.leader) {
+            info!("Following leader {} in term {}",req.leader,req.term);
+            state.leader = Some(req.leader);
+        }
+        state.election_deadline = Instant::now() + random_timeout(self.inner.config.election_timeout);
+
+        // 压缩掉的日志都已提交，与领导者的一定一致
+        let base_index = state.log.base_index();
+        if req.prev_log_index >= base_index && state.log.term_at(req.prev_log_index)? != Some(req.prev_log_term) {
+            let last_index = state.log.last_index().min(req.prev_log_index.saturating_sub(1));
+            return Ok(AppendReply { term: state.log.term(), success: false, last_index });
+        }
+        let last_new = req.prev_log_index + req.entries.len() as u64;
+        let mut new_entries = Vec::new();
+        for entry in req.entries {
+            if entry.index <= base_index {
+                continue;
+            }
+            match state.log.term_at(entry.index)? {
+                Some(term) if term == entry.term => {}
+                Some(_) => {
+                    warn!("Discarding conflicting Raft entries from {}",entry.index);
+                    state.log.truncate(entry.index)?;
+                    new_entries.push(entry);
+                }
+                None => new_entries.push(entry)
+            }
+        }
+        state.log.append(new_entries)?;
+        if req.leader_commit > state.commit_index {
+            state.commit_index = req.leader_commit.min(last_new);
+        }
+        state.compact_index = state.compact_index.max(req.compact_index);
+        self.inner.changed.notify_all();
+        Ok(AppendReply { term: state.log.term(), success: true, last_index: last_new })
+    }
+
+    pub(super) fn info(&self) -> ClusterInfo {
+        let state = self.inner.lock();
+        ClusterInfo {
+            node_id: self.inner.id,
+            role: match state.role {
+                Role::Follower => "follower",
+                Role::Candidate => "candidate",
+                Role::Leader => "leader"
+            }.to_owned(),
+            term: state.log.term(),
+            leader: state.leader
+                .filter(|&id| id != self.inner.id)
+                .and_then(|id| self.inner.peers.get(&id))
+                .map(ToString::to_string),
+            last_index: state.log.last_index(),
+            commit_index: state.commit_index,
+            last_applied: state.last_applied
+        }
+    }
+}
+
+impl Inner {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    fn majority(&self) -> usize {
+        let nodes = self.peers.len() + 1;
+        nodes / 2 + 1
+    }
+
+    /// 等待状态变化，超过 `deadline` 时返回错误
+    fn wait_until<'a>(&self, state: MutexGuard<'a, State>, deadline: Instant, message: &str) -> Result<MutexGuard<'a, State>> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(KvsError::StringError(message.to_owned()));
+        }
+        Ok(self.changed.wait_timeout(state, deadline - now).unwrap().0)
+    }
+
+    /// 非领导者返回的错误，带有已知的领导者地址
+    fn redirect(&self, state: &State) -> KvsError {
+        match state.leader.and_then(|id| self.peers.get(&id)) {
+            Some(addr) => KvsError::NotLeader(addr.to_string()),
+            None => KvsError::NoLeader
+        }
+    }
+
+    /// 选举超时后发起选举
+    fn run_elections(&self, shutdown: &ShutdownHandle) {
+        let mut state = self.lock();
+        while !shutdown.is_shutdown() {
+            if state.role != Role::Leader && Instant::now() >= state.election_deadline {
+                if let Err(e) = self.start_election(&mut state) {
+                    error!("Failed to start an election: {}",e);
+                }
+            }
+            let timeout = state.election_deadline.saturating_duration_since(Instant::now()).clamp(Duration::from_millis(1), TICK);
+            state = self.changed.wait_timeout(state, timeout).unwrap().0;
+        }
+        self.changed.notify_all();
+    }
+
+    fn start_election(&self, state: &mut State) -> Result<()> {
+        let term = state.log.term() + 1;
+        state.log.set_vote(term, Some(self.id))?;
+        state.role = Role::Candidate;
+        state.leader = None;
+        state.votes = HashSet::from([self.id]);
+        state.election_deadline = Instant::now() + random_timeout(self.config.election_timeout);
+        info!("Starting election for term {}",term);
+        if state.votes.len() >= self.majority() {
+            self.become_leader(state)?;
+        }
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    fn become_leader(&self, state: &mut State) -> Result<()> {
+        let term = state.log.term();
+        info!("Elected leader for term {}",term);
+        state.role = Role::Leader;
+        state.leader = Some(self.id);
+        let next_index = state.log.last_index() + 1;
+        for progress in state.progress.values_mut() {
+            *progress = Progress { next_index, ..Progress::default() };
+        }
+        state.read_round = 0;
+        state.log.append(vec![RaftEntry { index: next_index, term, command: None }])?;
+        self.advance_commit(state)?;
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// 发现更高的任期或当前任期的领导者时成为跟随者
+    fn step_down(&self, state: &mut State, term: u64) -> Result<()> {
+        if term > state.log.term() {
+            state.log.set_vote(term, None)?;
+            state.leader = None;
+        }
+        if state.role == Role::Leader {
+            info!("Stepping down as leader in term {}",term);
+        }
+        state.role = Role::Follower;
+        state.votes.clear();
+        self.changed.notify_all();
+        Ok(())
+    }
+
+    /// 当前任期中已复制到多数节点的日志提交
+    fn advance_commit(&self, state: &mut State) -> Result<()> {
+        if state.role != Role::Leader {
+            return Ok(());
+        }
+        let term = state.log.term();
+        for index in (state.commit_index + 1..=state.log.last_index()).rev() {
+            if state.log.term_at(index)? != Some(term) {
+                break;
+            }
+            let replicated = 1 + state.progress.values().filter(|progress| progress.match_index >= index).count();
+            if replicated >= self.majority() {
+                state.commit_index = index;
+                self.changed.notify_all();
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// 按顺序把提交的日志写入存储引擎
+    ///
+    /// 每应用 `APPLIED_CHECKPOINT` 条日志以及退出前，持久化存储引擎并记录已应用的序号
+    fn run_apply(&self, apply: Apply, flush: Flush, shutdown: &ShutdownHandle) {
+        let mut persisted = self.lock().last_applied;
+        while !shutdown.is_shutdown() {
+            let state = self.lock();
+            if state.last_applied - persisted >= APPLIED_CHECKPOINT {
+                let applied = state.last_applied;
+                drop(state);
+                match self.persist_applied(&flush, applied) {
+                    Ok(()) => persisted = applied,
+                    Err(e) => {
+                        error!("Failed to record applied Raft entry {}: {}",applied,e);
+                        thread::sleep(TICK);
+                    }
+                }
+                continue;
+            }
+            if state.last_applied >= state.commit_index {
+                drop(self.changed.wait_timeout(state, TICK).unwrap());
+                continue;
+            }
+            let count = (state.commit_index - state.last_applied) as usize;
+            let entries = match state.log.entries_from(state.last_applied + 1, count.min(APPLIED_CHECKPOINT as usize)) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    drop(state);
+                    error!("Failed to read Raft entries: {}, retrying",e);
+                    thread::sleep(TICK);
+                    continue;
+                }
+            };
+            drop(state);
+
+            for entry in entries {
+                if let Some(command) = &entry.command {
+                    while let Err(e) = apply(command) {
+                        error!("Failed to apply Raft entry {}: {}, retrying",entry.index,e);
+                        if shutdown.is_shutdown() {
+                            return;
+                        }
+                        thread::sleep(TICK);
+                    }
+                }
+                self.lock().last_applied = entry.index;
+                self.changed.notify_all();
+            }
+        }
+
+        let applied = self.lock().last_applied;
+        if applied > persisted {
+            if let Err(e) = self.persist_applied(&flush, applied) {
+                error!("Failed to record applied Raft entry {}: {}",applied,e);
+            }
+        }
+    }
+
+    /// 持久化存储引擎并记录已应用的序号，之后压缩所有节点都已应用的日志
+    fn persist_applied(&self, flush: &Flush, applied: u64) -> Result<()> {
+        flush()?;
+        let mut state = self.lock();
+        state.log.set_applied(applied)?;
+        let everyone = if self.peers.is_empty() { applied } else { state.compact_index };
+        let index = everyone.min(applied);
+        if index >= state.log.base_index() + self.config.log_compact_entries {
+            info!("Compacting the Raft log up to entry {}",index);
+            state.log.compact(index)?;
+        }
+        Ok(())
+    }
+}
+
+/// 在 `base` 和它的两倍之间随机选取选举超时时间，避免多个节点同时发起选举
+fn random_timeout(base: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    let millis = (base.as_millis() as u64).max(1);
+    base + Duration::from_millis(random % millis)
+}
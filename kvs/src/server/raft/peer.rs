@@ -0,0 +1,169 @@
+use std::{
+    sync::Arc,
+    thread,
+    time::Instant
+};
+
+use crate::{
+    common::{AppendRequest, VoteRequest},
+    KvsClient,
+    Result
+};
+
+use super::{Inner, Role, ShutdownHandle, State, TICK};
+
+// 每次复制的最大日志条数
+const MAX_ENTRIES: usize = 500;
+
+/// 发给一个节点的消息
+enum Message {
+    Vote(VoteRequest),
+    /// 日志复制请求，以及发送时的读确认轮次
+    Append(AppendRequest, u64)
+}
+
+/// 与一个节点通信：候选者请求投票，领导者复制日志和发送心跳
+///
+/// 每个节点一个线程，消息依次发送，同一时刻最多有一个请求在等待响应
+pub(super) fn run(inner: Arc<Inner>, peer: u64, shutdown: &ShutdownHandle) {
+    let builder = inner.config.peer_client.clone()
+        .addr(inner.peers[&peer].clone())
+        .connect_timeout(inner.config.election_timeout)
+        .read_timeout(inner.config.election_timeout)
+        .write_timeout(inner.config.election_timeout);
+    let mut client: Option<KvsClient> = None;
+    while !shutdown.is_shutdown() {
+        let message = {
+            let mut state = inner.lock();
+            match next_message(&inner, &mut state, peer) {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    let _ = inner.changed.wait_timeout(state, TICK).unwrap();
+                    continue;
+                }
+                Err(e) => {
+                    error!("Failed to read Raft entries for node {}: {}",peer,e);
+                    let _ = inner.changed.wait_timeout(state, TICK).unwrap();
+                    continue;
+                }
+            }
+        };
+
+        let res = match &mut client {
+            Some(client) => send(&inner, client, peer, message),
+            None => match builder.clone().build() {
+                Ok(connected) => send(&inner, client.insert(connected), peer, message),
+                Err(e) => Err(e)
+            }
+        };
+        if let Err(e) = res {
+            debug!("Failed to reach Raft node {}: {}",peer,e);
+            client = None;
+            // 请求投票失败后允许在同一个任期内重试
+            if let Some(progress) = inner.lock().progress.get_mut(&peer) {
+                progress.vote_term = 0;
+            }
+            thread::sleep(inner.config.heartbeat_interval);
+        }
+    }
+}
+
+/// 根据当前角色决定要发送的消息，没有需要发送的消息时返回 `None`
+fn next_message(inner: &Inner, state: &mut State, peer: u64) -> Result<Option<Message>> {
+    let term = state.log.term();
+    let (last_index, last_term) = (state.log.last_index(), state.log.last_term());
+    let read_round = state.read_round;
+    match state.role {
+        Role::Candidate => {
+            let progress = match state.progress.get_mut(&peer) {
+                Some(progress) => progress,
+                None => return Ok(None)
+            };
+            if progress.vote_term >= term {
+                return Ok(None);
+            }
+            progress.vote_term = term;
+            Ok(Some(Message::Vote(VoteRequest {
+                term,
+                candidate: inner.id,
+                last_log_index: last_index,
+                last_log_term: last_term
+            })))
+        }
+        Role::Leader => {
+            let progress = match state.progress.get(&peer) {
+                Some(progress) => progress,
+                None => return Ok(None)
+            };
+            let heartbeat_due = progress.last_sent.is_none_or(|sent| sent.elapsed() >= inner.config.heartbeat_interval);
+            if progress.next_index > last_index && !heartbeat_due && read_round <= progress.sent_round {
+                return Ok(None);
+            }
+            let prev_log_index = progress.next_index - 1;
+            let req = AppendRequest {
+                term,
+                leader: inner.id,
+                prev_log_index,
+                prev_log_term: state.log.term_at(prev_log_index)?.unwrap_or(0),
+                entries: state.log.entries_from(progress.next_index, MAX_ENTRIES)?,
+                leader_commit: state.commit_index,
+                compact_index: state.compact_index
+            };
+            if let Some(progress) = state.progress.get_mut(&peer) {
+                progress.last_sent = Some(Instant::now());
+                progress.sent_round = read_round;
+            }
+            Ok(Some(Message::Append(req, read_round)))
+        }
+        Role::Follower => Ok(None)
+    }
+}
+
+/// 发送消息并根据响应更新状态
+fn send(inner: &Inner, client: &mut KvsClient, peer: u64, message: Message) -> Result<()> {
+    match message {
+        Message::Vote(req) => {
+            let term = req.term;
+            let reply = client.raft_vote(req)?;
+            let mut state = inner.lock();
+            if reply.term > state.log.term() {
+                return inner.step_down(&mut state, reply.term);
+            }
+            if reply.granted && state.role == Role::Candidate && state.log.term() == term {
+                state.votes.insert(peer);
+                if state.votes.len() >= inner.majority() {
+                    inner.become_leader(&mut state)?;
+                }
+            }
+        }
+        Message::Append(req, round) => {
+            let (term, prev_log_index) = (req.term, req.prev_log_index);
+            let reply = client.raft_append(req)?;
+            let mut state = inner.lock();
+            if reply.term > state.log.term() {
+                return inner.step_down(&mut state, reply.term);
+            }
+            if state.role != Role::Leader || state.log.term() != term {
+                return Ok(());
+            }
+            let progress = match state.progress.get_mut(&peer) {
+                Some(progress) => progress,
+                None => return Ok(())
+            };
+            // 无论日志是否一致，同一任期的响应都说明对方仍然承认本节点是领导者
+            progress.acked_round = progress.acked_round.max(round);
+            if reply.success {
+                progress.match_index = progress.match_index.max(reply.last_index);
+                progress.next_index = progress.match_index + 1;
+                if let Some(everyone) = state.progress.values().map(|progress| progress.match_index).min() {
+                    state.compact_index = state.compact_index.max(everyone);
+                }
+                inner.advance_commit(&mut state)?;
+            } else {
+                progress.next_index = (reply.last_index + 1).min(prev_log_index).max(1);
+            }
+            inner.changed.notify_all();
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf}
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Deserializer;
+
+use crate::{common::RaftEntry, KvsError, Result};
+
+const LOG_FILE: &str = "raft.log";
+const STATE_FILE: &str = "raft.state";
+// 每隔这么多条日志记录一次它在文件中的位置，从磁盘读取时最多需要跳过这么多条
+const INDEX_INTERVAL: u64 = 1024;
+
+/// Raft 日志以及当前任期、投票对象，都持久化到磁盘后才会对外可见
+///
+/// 日志文件中每条记录是一个 JSON 对象，内存中只保留最近的若干条，更早的日志需要时从文件中读取。
+/// 写入中途崩溃留下的不完整记录在打开时被截掉。
+///
+/// 压缩后的日志文件以一条序号大于 1、没有写操作的记录开头，它是被丢弃的最后一条日志，只保留任期
+pub(super) struct RaftLog {
+    dir: PathBuf,
+    file: File,
+    // 文件的长度，即下一条日志写入的位置
+    len: u64,
+    // 压缩时丢弃的最后一条日志的序号和任期，没有压缩过时都为 0
+    base_index: u64,
+    base_term: u64,
+    last_index: u64,
+    // 最近的日志及其在文件中的位置，序号从 last_index - cache.len() + 1 到 last_index
+    cache: VecDeque<(u64, RaftEntry)>,
+    cache_size: usize,
+    // 第 i 项是序号为 base_index + i * INDEX_INTERVAL + 1 的日志在文件中的位置
+    checkpoints: Vec<u64>,
+    state: HardState
+}
+
+/// 需要持久化的任期、投票和已应用到存储引擎的日志序号
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+struct HardState {
+    term: u64,
+    voted_for: Option<u64>,
+    #[serde(default)]
+    applied: u64
+}
+
+impl RaftLog {
+    /// 打开 `dir` 中的日志，目录不存在时创建，内存中最多保留 `cache_size` 条日志
+    pub(super) fn open(dir: &Path, cache_size: usize) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let state = match fs::read(dir.join(STATE_FILE)) {
+            Ok(buf) => serde_json::from_slice(&buf)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HardState::default(),
+            Err(e) => return Err(e.into())
+        };
+
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(dir.join(LOG_FILE))?;
+        let mut log = RaftLog {
+            dir: dir.to_owned(),
+            file: file.try_clone()?,
+            len: 0,
+            base_index: 0,
+            base_term: 0,
+            last_index: 0,
+            cache: VecDeque::new(),
+            cache_size: cache_size.max(1),
+            checkpoints: Vec::new(),
+            state
+        };
+        let mut stream = Deserializer::from_reader(BufReader::new(&file)).into_iter::<RaftEntry>();
+        while let Some(Ok(entry)) = stream.next() {
+            let offset = log.len;
+            log.len = stream.byte_offset() as u64;
+            if offset == 0 && entry.index > 1 {
+                log.base_index = entry.index;
+                log.base_term = entry.term;
+                log.last_index = entry.index;
+                continue;
+            }
+            log.push(offset, entry);
+        }
+        if file.metadata()?.len() > log.len {
+            warn!("Discarding an incomplete entry at the end of the Raft log");
+            file.set_len(log.len)?;
+        }
+        file.seek(SeekFrom::End(0))?;
+        Ok(log)
+    }
+
+    pub(super) fn term(&self) -> u64 {
+        self.state.term
+    }
+
+    pub(super) fn voted_for(&self) -> Option<u64> {
+        self.state.voted_for
+    }
+
+    /// 已经应用并持久化到存储引擎的最后一条日志的序号
+    pub(super) fn applied(&self) -> u64 {
+        self.state.applied.min(self.last_index)
+    }
+
+    /// 持久化任期和投票
+    pub(super) fn set_vote(&mut self, term: u64, voted_for: Option<u64>) -> Result<()> {
+        self.save_state(HardState { term, voted_for, ..self.state })
+    }
+
+    /// 持久化已应用的日志序号，调用前存储引擎必须已经持久化了这些日志的写操作
+    pub(super) fn set_applied(&mut self, applied: u64) -> Result<()> {
+        self.save_state(HardState { applied, ..self.state })
+    }
+
+    /// 先写入临时文件再重命名
+    fn save_state(&mut self, state: HardState) -> Result<()> {
+        let tmp = self.dir.join(format!("{}.tmp",STATE_FILE));
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, &state)?;
+        file.sync_all()?;
+        fs::rename(tmp, self.dir.join(STATE_FILE))?;
+        self.state = state;
+        Ok(())
+    }
+
+    pub(super) fn last_index(&self) -> u64 {
+        self.last_index
+    }
+
+    pub(super) fn last_term(&self) -> u64 {
+        self.cache.back().map_or(self.base_term, |(_, entry)| entry.term)
+    }
+
+    /// 压缩时丢弃的最后一条日志的序号，之前的日志都已被所有节点应用
+    pub(super) fn base_index(&self) -> u64 {
+        self.base_index
+    }
+
+    /// 序号为 `index` 的日志的任期，序号 0 的任期为 0，日志不存在或已被压缩时返回 `None`
+    pub(super) fn term_at(&self, index: u64) -> Result<Option<u64>> {
+        match index {
+            0 => Ok(Some(0)),
+            index if index == self.base_index => Ok(Some(self.base_term)),
+            index if index > self.last_index || index < self.base_index => Ok(None),
+            index => Ok(self.entries_from(index, 1)?.first().map(|entry| entry.term))
+        }
+    }
+
+    /// 从 `index` 开始的最多 `limit` 条日志，不在内存中的日志从文件中读取，日志已被压缩时返回错误
+    pub(super) fn entries_from(&self, index: u64, limit: usize) -> Result<Vec<RaftEntry>> {
+        if index <= self.base_index && self.base_index > 0 {
+            return Err(KvsError::StringError(format!("Raft entry {} has been compacted",index)));
+        }
+        let index = index.max(1);
+        if index > self.last_index {
+            return Ok(Vec::new());
+        }
+        if let Some(start) = self.cache_position(index) {
+            return Ok(self.cache.range(start..).take(limit).map(|(_, entry)| entry.clone()).collect());
+        }
+        let mut entries = Vec::with_capacity(limit.min((self.last_index - index + 1) as usize));
+        self.read_from_disk(index, |_, entry| {
+            entries.push(entry);
+            entries.len() < limit
+        })?;
+        Ok(entries)
+    }
+
+    /// 追加日志并同步到磁盘
+    pub(super) fn append(&mut self, entries: Vec<RaftEntry>) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let mut writer = BufWriter::new(&self.file);
+        let mut offsets = Vec::with_capacity(entries.len());
+        let mut len = self.len;
+        for entry in &entries {
+            let buf = serde_json::to_vec(entry)?;
+            writer.write_all(&buf)?;
+            offsets.push(len);
+            len += buf.len() as u64;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.file.sync_data()?;
+        self.len = len;
+        for (offset, entry) in offsets.into_iter().zip(entries) {
+            self.push(offset, entry);
+        }
+        Ok(())
+    }
+
+    /// 删除序号从 `index` 开始的日志，与领导者冲突的日志才会被删除
+    pub(super) fn truncate(&mut self, index: u64) -> Result<()> {
+        let index = index.max(self.base_index + 1);
+        if index > self.last_index {
+            return Ok(());
+        }
+        let position = self.cache_position(index);
+        let offset = match position {
+            Some(position) => self.cache[position].0,
+            None => {
+                let mut offset = None;
+                self.read_from_disk(index, |found, _| {
+                    offset = Some(found);
+                    false
+                })?;
+                offset.ok_or_else(|| KvsError::StringError(format!("Raft entry {} is missing from the log",index)))?
+            }
+        };
+        self.file.set_len(offset)?;
+        self.file.sync_all()?;
+        self.len = offset;
+        self.last_index = index - 1;
+        self.cache.truncate(position.unwrap_or(0));
+        self.checkpoints.truncate((self.last_index - self.base_index).div_ceil(INDEX_INTERVAL) as usize);
+        // 内存中至少保留最后一条日志，用于取得最后的任期
+        if self.cache.is_empty() && self.last_index > self.base_index {
+            let mut last = None;
+            self.read_from_disk(self.last_index, |offset, entry| {
+                last = Some((offset, entry));
+                false
+            })?;
+            self.cache.extend(last);
+        }
+        Ok(())
+    }
+
+    /// 丢弃序号不超过 `index` 的日志，调用前这些日志必须已被所有节点应用
+    ///
+    /// 之后的日志连同一条只有 `index` 处任期的记录写入新文件，再替换原来的日志文件
+    pub(super) fn compact(&mut self, index: u64) -> Result<()> {
+        if index <= self.base_index || index > self.last_index {
+            return Ok(());
+        }
+        let term = self.term_at(index)?
+            .ok_or_else(|| KvsError::StringError(format!("Raft entry {} is missing from the log",index)))?;
+        let tmp = self.dir.join(format!("{}.tmp",LOG_FILE));
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        serde_json::to_writer(&mut writer, &RaftEntry { index, term, command: None })?;
+        if index < self.last_index {
+            let mut res = Ok(());
+            self.read_from_disk(index + 1, |_, entry| {
+                res = serde_json::to_writer(&mut writer, &entry);
+                res.is_ok()
+            })?;
+            res?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(tmp, self.dir.join(LOG_FILE))?;
+        *self = RaftLog::open(&self.dir, self.cache_size)?;
+        Ok(())
+    }
+
+    fn push(&mut self, offset: u64, entry: RaftEntry) {
+        if (self.last_index - self.base_index).is_multiple_of(INDEX_INTERVAL) {
+            self.checkpoints.push(offset);
+        }
+        self.last_index += 1;
+        self.cache.push_back((offset, entry));
+        if self.cache.len() > self.cache_size {
+            self.cache.pop_front();
+        }
+    }
+
+    /// 序号为 `index` 的日志在内存中的位置
+    fn cache_position(&self, index: u64) -> Option<usize> {
+        let first = self.last_index + 1 - self.cache.len() as u64;
+        if index >= first && index <= self.last_index {
+            Some((index - first) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// 从序号为 `index` 的日志开始依次读取日志及其位置，直到 `f` 返回 false 或读完文件
+    fn read_from_disk<F>(&self, index: u64, mut f: F) -> Result<()>
+    where
+        F: FnMut(u64, RaftEntry) -> bool
+    {
+        let checkpoint = (index - self.base_index - 1) / INDEX_INTERVAL;
+        let start = self.checkpoints[checkpoint as usize];
+        let mut file = File::open(self.dir.join(LOG_FILE))?;
+        file.seek(SeekFrom::Start(start))?;
+        let mut skip = index - self.base_index - 1 - checkpoint * INDEX_INTERVAL;
+        let mut offset = start;
+        let mut stream = Deserializer::from_reader(BufReader::new(file)).into_iter::<RaftEntry>();
+        while offset < self.len {
+            let entry = match stream.next() {
+                Some(entry) => entry?,
+                None => break
+            };
+            let entry_offset = offset;
+            offset = start + stream.byte_offset() as u64;
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            if !f(entry_offset, entry) {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
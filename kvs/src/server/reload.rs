@@ -0,0 +1,42 @@
+use std::sync::{Arc, Mutex};
+
+use mio::Waker;
+
+use super::config::ServerConfig;
+
+/// 用于在运行时重新加载 `KvsServer` 的配置
+///
+/// 只有运行时可以调整的设置会生效：最大连接数、超时、请求大小、限速、慢日志、认证配置和压缩阈值，
+/// 监听地址、协议、TLS、复制和集群等设置需要重启服务器。句柄可以被克隆并在其他线程中使用
+#[derive(Clone, Default)]
+pub struct ReloadHandle {
+    inner: Arc<Inner>
+}
+
+#[derive(Default)]
+struct Inner {
+    // 事件循环尚未处理的配置，多次重新加载时只保留最后一次
+    pending: Mutex<Option<ServerConfig>>,
+    // 事件循环启动后才会设置
+    waker: Mutex<Option<Arc<Waker>>>
+}
+
+impl ReloadHandle {
+    /// 请求使用新的配置，由事件循环在下一次唤醒时应用
+    pub fn reload(&self, config: ServerConfig) {
+        *self.inner.pending.lock().unwrap() = Some(config);
+        if let Some(waker) = &*self.inner.waker.lock().unwrap() {
+            if let Err(e) = waker.wake() {
+                error!("Failed to wake up the event loop: {}",e);
+            }
+        }
+    }
+
+    pub(super) fn take(&self) -> Option<ServerConfig> {
+        self.inner.pending.lock().unwrap().take()
+    }
+
+    pub(super) fn set_waker(&self, waker: Arc<Waker>) {
+        *self.inner.waker.lock().unwrap() = Some(waker);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{collections::HashSet, sync::Arc, thread, time::Duration};
+
+use crate::{KvsClient, KvsClientBuilder, KvsEngine, Result};
+
+use super::{
+    keyspace::Keyspace,
+    replication::{Follower, MAX_BATCH},
+    shutdown::ShutdownHandle
+};
+
+// 已经追上主节点时拉取复制日志的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// 与主节点的连接出错后重新连接的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+// 全量同步前列出本地键时每页的键数
+const SCAN_PAGE_SIZE: usize = 1000;
+
+/// 在后台线程中跟随主节点，直到被提升或服务器关闭
+///
+/// 启动时总是先全量同步，之后按序号拉取复制日志；主节点的复制历史改变（如重启）
+/// 或需要的记录已被丢弃时重新全量同步
+pub(super) fn spawn<E: KvsEngine>(
+    keyspace: Keyspace<E>,
+    leader: KvsClientBuilder,
+    follower: Arc<Follower>,
+    shutdown: ShutdownHandle
+) -> Result<()> {
+    thread::Builder::new()
+        .name("replica".to_owned())
+        .spawn(move || {
+            info!("Replicating from {}",follower.leader());
+            let mut synced = false;
+            while !follower.is_promoted() && !shutdown.is_shutdown() {
+                if let Err(e) = replicate(&keyspace, &leader, &follower, &shutdown, &mut synced) {
+                    warn!("Replication from {} failed: {}, retrying in {:?}",follower.leader(),e,RETRY_INTERVAL);
+                    follower.link_down();
+                    thread::sleep(RETRY_INTERVAL);
+                }
+            }
+            info!("Stopped replicating from {}",follower.leader());
+        })?;
+    Ok(())
+}
+
+/// 连接主节点并持续同步，直到出错、被提升或服务器关闭
+fn replicate<E: KvsEngine>(
+    keyspace: &Keyspace<E>,
+    leader: &KvsClientBuilder,
+    follower: &Follower,
+    shutdown: &ShutdownHandle,
+    synced: &mut bool
+) -> Result<()> {
+    let mut client = leader.clone().build()?;
+    while !follower.is_promoted() && !shutdown.is_shutdown() {
+        if !*synced {
+            full_sync(keyspace, &mut client, follower)?;
+            *synced = true;
+            continue;
+        }
+        let (id, seq) = keyspace.replication().position();
+        let batch = match client.repl_tail(id, seq)? {
+            Some(batch) => batch,
+            None => {
+                info!("Replication history of {} changed or was truncated, starting a full sync",follower.leader());
+                *synced = false;
+                continue;
+            }
+        };
+        let count = batch.records.len();
+        follower.apply(batch.seq, || batch.records.into_iter().try_for_each(|record| keyspace.apply(record)))?;
+        if count < MAX_BATCH {
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    Ok(())
+}
+
+/// 按页读取主节点的全量快照并替换本地数据，本地多出的键在最后删除
+///
+/// 同步期间本地数据一直可读，但可能混合了新旧数据
+fn full_sync<E: KvsEngine>(keyspace: &Keyspace<E>, client: &mut KvsClient, follower: &Follower) -> Result<()> {
+    info!("Starting a full sync from {}",follower.leader());
+    let mut stale = HashSet::new();
+    let mut after = None;
+    loop {
+        let page = keyspace.scan("", after.as_deref(), SCAN_PAGE_SIZE)?;
+        stale.extend(page.keys);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break
+        }
+    }
+
+    let mut start = None;
+    let mut after = None;
+    loop {
+        let page = client.repl_snapshot(after.take())?;
+        let (id, seq) = start.get_or_insert_with(|| (page.id.clone(), page.seq)).clone();
+        let applied = follower.apply(seq, || {
+            for (key, value) in page.entries {
+                stale.remove(&key);
+                keyspace.restore(key, Some(value))?;
+            }
+            Ok(())
+        })?;
+        if applied.is_none() {
+            return Ok(());
+        }
+        match page.next {
+            Some(next) => after = Some(next),
+            None => {
+                let applied = follower.apply(seq, || {
+                    for key in stale.drain() {
+                        keyspace.restore(key, None)?;
+                    }
+                    keyspace.replication().reset(id, seq);
+                    Ok(())
+                })?;
+                if applied.is_some() {
+                    info!("Full sync from {} finished at seq {}",follower.leader(),seq);
+                }
+                return Ok(());
+            }
+        }
+    }
+}
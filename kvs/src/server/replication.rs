@@ -0,0 +1,253 @@
+use std::{
+    collections::VecDeque,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard
+    },
+    time::{Instant, SystemTime, UNIX_EPOCH}
+};
+
+use crate::{
+    common::{Command, ReplBatch, Record, ReplicationInfo, SnapshotPage},
+    KvsEngine,
+    KvsError,
+    Result
+};
+
+use super::keyspace::Keyspace;
+
+/// 全量快照每页的键数
+const SNAPSHOT_PAGE_SIZE: usize = 1000;
+/// 每次最多返回的复制日志记录数
+pub(super) const MAX_BATCH: usize = 1000;
+
+/// 节点的复制日志和角色
+///
+/// 所有写操作都按提交顺序带上递增的序号记录到复制日志中，内存中只保留最近的若干条，
+/// 从节点落后太多时需要重新全量同步。从节点把应用的记录以主节点的序号记录到自己的日志中，
+/// 被提升后可以继续同一个复制历史
+pub(super) struct Replication {
+    log: Mutex<Log>,
+    backlog: usize,
+    // 从节点拒绝客户端的写操作
+    read_only: AtomicBool,
+    // 是否曾经作为从节点运行或向从节点提供过复制数据
+    replicated: AtomicBool,
+    follower: Mutex<Option<Arc<Follower>>>
+}
+
+struct Log {
+    id: String,
+    seq: u64,
+    // 序号为 seq - records.len() + 1 到 seq 的记录
+    records: VecDeque<Record>
+}
+
+/// 从节点与主节点之间的同步状态
+pub(super) struct Follower {
+    leader: String,
+    promoted: AtomicBool,
+    status: Mutex<LinkStatus>
+}
+
+#[derive(Default)]
+struct LinkStatus {
+    link_up: bool,
+    leader_seq: u64,
+    last_sync: Option<Instant>
+}
+
+impl Replication {
+    /// `backlog` 为内存中保留的记录数
+    pub(super) fn new(backlog: usize) -> Self {
+        Replication {
+            log: Mutex::new(Log { id: new_id(), seq: 0, records: VecDeque::new() }),
+            backlog,
+            read_only: AtomicBool::new(false),
+            replicated: AtomicBool::new(false),
+            follower: Mutex::new(None)
+        }
+    }
+
+    /// 从节点上返回 `ReadOnly` 错误
+    pub(super) fn check_writable(&self) -> Result<()> {
+        if self.read_only.load(Ordering::Acquire) {
+            return Err(KvsError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// 是否是只读的从节点
+    pub(super) fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Acquire)
+    }
+
+    /// 节点是否参与了复制：曾经作为从节点运行，或有从节点读取过复制数据
+    ///
+    /// 过期时间只保存在内存中，不会复制给从节点，参与复制的节点不接受带过期时间的写入
+    pub(super) fn is_replicated(&self) -> bool {
+        self.replicated.load(Ordering::Acquire)
+    }
+
+    /// 记录一次已提交的写操作
+    pub(super) fn append(&self, command: Command) {
+        let mut log = self.log.lock().unwrap();
+        let seq = log.seq + 1;
+        log.push(Record { seq, command }, self.backlog);
+    }
+
+    /// 记录从主节点应用的写操作，沿用主节点的序号
+    pub(super) fn append_applied(&self, record: Record) {
+        self.log.lock().unwrap().push(record, self.backlog);
+    }
+
+    /// 全量同步完成后切换到主节点的复制历史
+    pub(super) fn reset(&self, id: String, seq: u64) {
+        let mut log = self.log.lock().unwrap();
+        log.id = id;
+        log.seq = seq;
+        log.records.clear();
+    }
+
+    /// 复制历史的 ID 和最后一条记录的序号
+    pub(super) fn position(&self) -> (String, u64) {
+        let log = self.log.lock().unwrap();
+        (log.id.clone(), log.seq)
+    }
+
+    /// 读取序号大于 `after` 的最多 `limit` 条记录
+    ///
+    /// `id` 与本节点的复制历史不同，或需要的记录已经被丢弃时返回 `None`
+    pub(super) fn read(&self, id: &str, after: u64, limit: usize) -> Option<ReplBatch> {
+        self.replicated.store(true, Ordering::Release);
+        let log = self.log.lock().unwrap();
+        let oldest = log.seq - log.records.len() as u64;
+        if id != log.id || after > log.seq || after < oldest {
+            return None;
+        }
+        let records = log.records.iter()
+            .skip((after - oldest) as usize)
+            .take(limit)
+            .cloned()
+            .collect();
+        Some(ReplBatch { seq: log.seq, records })
+    }
+
+    /// 作为 `leader` 的从节点运行，此后拒绝客户端的写操作
+    pub(super) fn follow(&self, leader: String) -> Arc<Follower> {
+        let follower = Arc::new(Follower {
+            leader,
+            promoted: AtomicBool::new(false),
+            status: Mutex::new(LinkStatus::default())
+        });
+        self.read_only.store(true, Ordering::Release);
+        self.replicated.store(true, Ordering::Release);
+        *self.follower.lock().unwrap() = Some(Arc::clone(&follower));
+        follower
+    }
+
+    /// 将从节点提升为主节点：停止同步并开始接受写操作
+    pub(super) fn promote(&self) -> Result<()> {
+        let follower = self.follower.lock().unwrap().take()
+            .ok_or_else(|| KvsError::StringError("Server is not a replica".to_owned()))?;
+        // 等待正在应用的一批记录完成，之后不会再应用主节点的数据
+        let _status = follower.status.lock().unwrap();
+        follower.promoted.store(true, Ordering::Release);
+        self.read_only.store(false, Ordering::Release);
+        info!("Promoted to leader at seq {}",self.position().1);
+        Ok(())
+    }
+
+    pub(super) fn info(&self) -> ReplicationInfo {
+        let (replication_id, seq) = self.position();
+        let mut info = ReplicationInfo {
+            role: "leader".to_owned(),
+            replication_id,
+            seq,
+            leader: None,
+            link_up: None,
+            leader_seq: None,
+            lag: None,
+            last_sync_millis: None
+        };
+        if let Some(follower) = &*self.follower.lock().unwrap() {
+            let status = follower.status.lock().unwrap();
+            info.role = "follower".to_owned();
+            info.leader = Some(follower.leader.clone());
+            info.link_up = Some(status.link_up);
+            if let Some(last_sync) = status.last_sync {
+                info.leader_seq = Some(status.leader_seq);
+                info.lag = Some(status.leader_seq.saturating_sub(seq));
+                info.last_sync_millis = Some(last_sync.elapsed().as_millis() as u64);
+            }
+        }
+        info
+    }
+}
+
+impl Log {
+    fn push(&mut self, record: Record, backlog: usize) {
+        self.seq = record.seq;
+        self.records.push_back(record);
+        while self.records.len() > backlog {
+            self.records.pop_front();
+        }
+    }
+}
+
+impl Follower {
+    pub(super) fn leader(&self) -> &str {
+        &self.leader
+    }
+
+    pub(super) fn is_promoted(&self) -> bool {
+        self.promoted.load(Ordering::Acquire)
+    }
+
+    /// 在没有被提升的前提下应用主节点的数据，成功后记录主节点的序号
+    ///
+    /// 已被提升时不执行 `f` 并返回 `None`
+    pub(super) fn apply<T>(&self, leader_seq: u64, f: impl FnOnce() -> Result<T>) -> Result<Option<T>> {
+        let mut status = self.lock_status();
+        if self.is_promoted() {
+            return Ok(None);
+        }
+        let res = f()?;
+        status.link_up = true;
+        status.leader_seq = leader_seq;
+        status.last_sync = Some(Instant::now());
+        Ok(Some(res))
+    }
+
+    pub(super) fn link_down(&self) {
+        self.lock_status().link_up = false;
+    }
+
+    fn lock_status(&self) -> MutexGuard<'_, LinkStatus> {
+        self.status.lock().unwrap()
+    }
+}
+
+/// 读取全量快照中 `after` 之后的一页
+///
+/// 读取期间的写操作可能已经包含在快照中，从节点从第一页的序号开始重放复制日志，
+/// 重复应用的记录不会改变最终结果
+pub(super) fn snapshot<E: KvsEngine>(keyspace: &Keyspace<E>, after: Option<&str>) -> Result<SnapshotPage> {
+    keyspace.replication().replicated.store(true, Ordering::Release);
+    let (id, seq) = keyspace.replication().position();
+    let page = keyspace.scan("", after, SNAPSHOT_PAGE_SIZE)?;
+    let mut entries = Vec::with_capacity(page.keys.len());
+    for key in page.keys {
+        if let Some(value) = keyspace.get(key.clone())? {
+            entries.push((key, value));
+        }
+    }
+    Ok(SnapshotPage { id, seq, entries, next: page.next })
+}
+
+/// 生成复制历史的 ID，每次启动都不同
+fn new_id() -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos());
+    format!("{:x}-{:x}",nanos,process::id())
+}
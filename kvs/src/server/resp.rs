@@ -0,0 +1,666 @@
+//! Redis 序列化协议（RESP2/RESP3）
+//!
+//! 支持 `GET`、`SET`（`EX/PX/NX/XX`）、`DEL`、`EXISTS`、`SCAN`、`PING`、`INFO`、`SLOWLOG`、`REPLICAOF NO ONE`，
+//! 以及客户端连接时常用的 `HELLO`、`AUTH`、`SELECT 0`、`CLIENT`、`COMMAND`、`QUIT`，
+//! 使 `redis-cli` 和常见的 Redis 客户端库可以直接访问 kvs。
+//! 过期时间只保存在内存中，集群模式或参与复制的节点上 `SET` 的 `EX/PX` 返回错误
+use std::{
+    fmt::Write as _,
+    str,
+    time::Duration
+};
+
+use crate::{auth::Permission, common::Secret, KvsEngine, KvsError, Result};
+
+use super::{
+    call::{Call, Operation},
+    keyspace::{Condition, Keyspace},
+    slowlog,
+    stats::ServerStats,
+    MAX_SCAN_LIMIT
+};
+
+// 对外声称兼容的 Redis 版本，部分客户端库会根据版本判断可以使用的特性
+const REDIS_COMPAT_VERSION: &str = "6.0.0";
+// 单条命令最多的参数个数
+const MAX_ARGS: usize = 1024 * 1024;
+const DEFAULT_SCAN_COUNT: usize = 10;
+
+/// 协议版本，通过 `HELLO` 切换
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Version {
+    Resp2,
+    Resp3
+}
+
+/// RESP 值，`Nil` 和 `Map` 在两个版本中的编码不同
+#[derive(Debug)]
+pub(super) enum Value {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(String),
+    Nil,
+    Array(Vec<Value>),
+    Map(Vec<(Value, Value)>)
+}
+
+/// 需要访问存储引擎的命令
+#[derive(Debug)]
+pub(super) enum Command {
+    Get { key: String },
+    Set { key: String, value: String, ttl: Option<Duration>, condition: Option<Condition> },
+    Del { keys: Vec<String> },
+    Exists { keys: Vec<String> },
+    Scan { after: Option<String>, pattern: Option<String>, count: usize },
+    Ping { message: Option<String> },
+    Info { section: Option<String> },
+    SlowlogGet { count: usize },
+    SlowlogLen,
+    SlowlogReset,
+    /// `REPLICAOF NO ONE`，将从节点提升为主节点
+    Promote
+}
+
+impl Value {
+    pub(super) fn ok() -> Self {
+        Value::Simple("OK".to_owned())
+    }
+
+    /// 带错误码的错误，如 `ERR`、`NOAUTH`
+    pub(super) fn error(code: &str, message: &str) -> Self {
+        Value::Error(format!("{} {}",code,message))
+    }
+
+    pub(super) fn encode(&self, version: Version) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.write(&mut buf, version);
+        buf
+    }
+
+    fn write(&self, buf: &mut Vec<u8>, version: Version) {
+        match self {
+            Value::Simple(s) => write_line(buf, '+', s),
+            Value::Error(e) => write_line(buf, '-', e),
+            Value::Integer(n) => write_line(buf, ':', n),
+            Value::Bulk(s) => {
+                write_line(buf, '$', s.len());
+                buf.extend_from_slice(s.as_bytes());
+                buf.extend_from_slice(b"\r\n");
+            }
+            Value::Nil if version == Version::Resp3 => buf.extend_from_slice(b"_\r\n"),
+            Value::Nil => buf.extend_from_slice(b"$-1\r\n"),
+            Value::Array(items) => {
+                write_line(buf, '*', items.len());
+                for item in items {
+                    item.write(buf, version);
+                }
+            }
+            Value::Map(entries) => {
+                match version {
+                    Version::Resp3 => write_line(buf, '%', entries.len()),
+                    Version::Resp2 => write_line(buf, '*', entries.len() * 2)
+                }
+                for (key, value) in entries {
+                    key.write(buf, version);
+                    value.write(buf, version);
+                }
+            }
+        }
+    }
+}
+
+fn write_line(buf: &mut Vec<u8>, prefix: char, content: impl std::fmt::Display) {
+    let mut line = String::new();
+    let _ = write!(line, "{}{}\r\n", prefix, content);
+    buf.extend_from_slice(line.as_bytes());
+}
+
+/// 一条命令的参数和它占用的字节数
+type Frame = (Vec<Vec<u8>>, usize);
+
+/// 从缓冲区中解析一条完整的命令，返回命令参数和消耗的字节数，数据不完整时返回 `None`
+///
+/// 除了标准的数组格式，也支持 telnet 中直接输入的以空格分隔的内联命令。
+/// 返回的错误是协议错误，连接无法继续使用
+pub(super) fn parse(buf: &[u8], max_size: usize) -> std::result::Result<Option<Frame>, String> {
+    if buf.first() != Some(&b'*') {
+        return Ok(read_line(buf, 0).map(|(line, next)| {
+            let args = line.split(u8::is_ascii_whitespace)
+                .filter(|arg| !arg.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+            (args, next)
+        }));
+    }
+
+    let (line, mut pos) = match read_line(buf, 1) {
+        Some(line) => line,
+        None => return Ok(None)
+    };
+    let count = parse_length(line).filter(|&n| n <= MAX_ARGS)
+        .ok_or_else(|| "Protocol error: invalid multibulk length".to_owned())?;
+    let mut args = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        if pos >= buf.len() {
+            return Ok(None);
+        }
+        if buf[pos] != b'$' {
+            return Err(format!("Protocol error: expected '$', got '{}'",buf[pos] as char));
+        }
+        let (line, start) = match read_line(buf, pos + 1) {
+            Some(line) => line,
+            None => return Ok(None)
+        };
+        let len = parse_length(line).filter(|&n| n <= max_size)
+            .ok_or_else(|| "Protocol error: invalid bulk length".to_owned())?;
+        let end = start + len;
+        if buf.len() < end + 2 {
+            return Ok(None);
+        }
+        if &buf[end..end + 2] != b"\r\n" {
+            return Err("Protocol error: bulk string not terminated by CRLF".to_owned());
+        }
+        args.push(buf[start..end].to_vec());
+        pos = end + 2;
+    }
+    Ok(Some((args, pos)))
+}
+
+/// 读取从 `start` 开始的一行，返回不含换行符的内容和下一行的起始位置
+fn read_line(buf: &[u8], start: usize) -> Option<(&[u8], usize)> {
+    let end = start + buf.get(start..)?.iter().position(|&b| b == b'\n')?;
+    let line = &buf[start..end];
+    Some((line.strip_suffix(b"\r").unwrap_or(line), end + 1))
+}
+
+fn parse_length(line: &[u8]) -> Option<usize> {
+    str::from_utf8(line).ok()?.parse().ok()
+}
+
+/// 将命令参数转换为待执行的调用
+///
+/// 只影响连接状态的命令在这里直接生成响应，`HELLO` 会修改连接的协议版本，
+/// 之后的命令都使用新版本编码响应，`role` 是 `HELLO` 报告的节点角色
+pub(super) fn decode(args: Vec<Vec<u8>>, version: &mut Version, role: &str) -> Call {
+    let reply = |value: Value, version: Version| Call::Reply(value.encode(version));
+    let args = match args.into_iter().map(String::from_utf8).collect::<std::result::Result<Vec<_>, _>>() {
+        Ok(args) => args,
+        Err(_) => return reply(Value::error("ERR", "arguments must be valid UTF-8"), *version)
+    };
+    let name = args[0].to_ascii_lowercase();
+    let mut args = args.into_iter().skip(1);
+
+    match name.as_str() {
+        "hello" => hello(args.collect(), version, role),
+        "auth" => {
+            let args: Vec<String> = args.collect();
+            let (user, password) = match args.len() {
+                1 => ("default".to_owned(), args[0].clone()),
+                2 => (args[0].clone(), args[1].clone()),
+                _ => return reply(wrong_arity(&name), *version)
+            };
+            login(user, password, Value::ok(), *version)
+        }
+        "select" => match args.next().as_deref() {
+            Some("0") => reply(Value::ok(), *version),
+            Some(_) => reply(Value::error("ERR", "DB index is out of range"), *version),
+            None => reply(wrong_arity(&name), *version)
+        },
+        "client" => match args.next().map(|sub| sub.to_ascii_lowercase()).as_deref() {
+            Some("setname") | Some("setinfo") => reply(Value::ok(), *version),
+            _ => reply(Value::error("ERR", "unsupported CLIENT subcommand"), *version)
+        },
+        "command" => reply(Value::Array(Vec::new()), *version),
+        "quit" => reply(Value::ok(), *version),
+        _ => match parse_command(&name, args.collect()) {
+            Ok(cmd) => Call::Execute(Operation::Resp(cmd, *version), None),
+            Err(e) => reply(e, *version)
+        }
+    }
+}
+
+/// `HELLO [protover [AUTH username password] [SETNAME clientname]]`
+fn hello(args: Vec<String>, version: &mut Version, role: &str) -> Call {
+    let mut requested = *version;
+    let mut credentials = None;
+    let mut args = args.into_iter();
+    if let Some(protover) = args.next() {
+        requested = match protover.as_str() {
+            "2" => Version::Resp2,
+            "3" => Version::Resp3,
+            _ => return Call::Reply(Value::error("NOPROTO", "unsupported protocol version").encode(*version))
+        };
+    }
+    while let Some(option) = args.next() {
+        match (option.to_ascii_lowercase().as_str(), args.next()) {
+            ("auth", Some(user)) => match args.next() {
+                Some(password) => credentials = Some((user, password)),
+                None => return Call::Reply(Value::error("ERR", "syntax error").encode(*version))
+            },
+            ("setname", Some(_)) => {}
+            _ => return Call::Reply(Value::error("ERR", "syntax error").encode(*version))
+        }
+    }
+
+    *version = requested;
+    let info = Value::Map(vec![
+        (Value::Bulk("server".to_owned()), Value::Bulk("kvs".to_owned())),
+        (Value::Bulk("version".to_owned()), Value::Bulk(REDIS_COMPAT_VERSION.to_owned())),
+        (Value::Bulk("proto".to_owned()), Value::Integer(if requested == Version::Resp3 { 3 } else { 2 })),
+        (Value::Bulk("mode".to_owned()), Value::Bulk("standalone".to_owned())),
+        (Value::Bulk("role".to_owned()), Value::Bulk(role.to_owned())),
+        (Value::Bulk("modules".to_owned()), Value::Array(Vec::new()))
+    ]);
+    match credentials {
+        Some((user, password)) => login(user, password, info, requested),
+        None => Call::Reply(info.encode(requested))
+    }
+}
+
+fn login(user: String, password: String, success: Value, version: Version) -> Call {
+    Call::Login {
+        user,
+        password: Secret(password),
+        success: success.encode(version),
+        failure: Value::error("WRONGPASS", "invalid username-password pair or user is disabled.").encode(version)
+    }
+}
+
+fn parse_command(name: &str, args: Vec<String>) -> std::result::Result<Command, Value> {
+    let arity = |min: usize, max: usize| {
+        if args.len() < min || args.len() > max { Err(wrong_arity(name)) } else { Ok(()) }
+    };
+    let mut iter = args.iter().cloned();
+    let mut next = || iter.next().unwrap_or_default();
+
+    match name {
+        "get" => {
+            arity(1, 1)?;
+            Ok(Command::Get { key: next() })
+        }
+        "set" => {
+            arity(2, usize::MAX)?;
+            let (key, value) = (next(), next());
+            let (ttl, condition) = parse_set_options(&args[2..])?;
+            Ok(Command::Set { key, value, ttl, condition })
+        }
+        "del" => {
+            arity(1, usize::MAX)?;
+            Ok(Command::Del { keys: args })
+        }
+        "exists" => {
+            arity(1, usize::MAX)?;
+            Ok(Command::Exists { keys: args })
+        }
+        "scan" => {
+            arity(1, usize::MAX)?;
+            parse_scan(&args)
+        }
+        "ping" => {
+            arity(0, 1)?;
+            Ok(Command::Ping { message: args.into_iter().next() })
+        }
+        "info" => {
+            arity(0, 1)?;
+            Ok(Command::Info { section: args.into_iter().next().map(|s| s.to_ascii_lowercase()) })
+        }
+        "slowlog" => {
+            arity(1, 2)?;
+            parse_slowlog(&args)
+        }
+        // 从节点只能在启动时通过 --replica-of 指定主节点
+        "replicaof" | "slaveof" => {
+            arity(2, 2)?;
+            if args[0].eq_ignore_ascii_case("no") && args[1].eq_ignore_ascii_case("one") {
+                Ok(Command::Promote)
+            } else {
+                Err(Value::error("ERR", "only REPLICAOF NO ONE is supported, start the server with --replica-of to follow a leader"))
+            }
+        }
+        _ => Err(Value::error("ERR", &format!("unknown command '{}'",name)))
+    }
+}
+
+fn parse_set_options(options: &[String]) -> std::result::Result<(Option<Duration>, Option<Condition>), Value> {
+    let syntax_error = || Value::error("ERR", "syntax error");
+    let mut ttl = None;
+    let mut condition = None;
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        match option.to_ascii_lowercase().as_str() {
+            "nx" | "xx" if condition.is_some() => return Err(syntax_error()),
+            "nx" => condition = Some(Condition::IfAbsent),
+            "xx" => condition = Some(Condition::IfPresent),
+            unit @ ("ex" | "px") if ttl.is_none() => {
+                let amount = iter.next()
+                    .ok_or_else(syntax_error)?
+                    .parse::<u64>()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| Value::error("ERR", "invalid expire time in 'set' command"))?;
+                ttl = Some(if unit == "ex" { Duration::from_secs(amount) } else { Duration::from_millis(amount) });
+            }
+            _ => return Err(syntax_error())
+        }
+    }
+    Ok((ttl, condition))
+}
+
+/// `SCAN cursor [MATCH pattern] [COUNT count]`
+fn parse_scan(args: &[String]) -> std::result::Result<Command, Value> {
+    let after = decode_cursor(&args[0]).ok_or_else(|| Value::error("ERR", "invalid cursor"))?;
+    let mut pattern = None;
+    let mut count = DEFAULT_SCAN_COUNT;
+    let mut iter = args[1..].iter();
+    while let Some(option) = iter.next() {
+        let value = iter.next().ok_or_else(|| Value::error("ERR", "syntax error"))?;
+        match option.to_ascii_lowercase().as_str() {
+            "match" => pattern = Some(value.clone()),
+            "count" => {
+                count = value.parse::<usize>().ok().filter(|&n| n > 0)
+                    .ok_or_else(|| Value::error("ERR", "value is out of range, must be positive"))?
+                    .min(MAX_SCAN_LIMIT);
+            }
+            _ => return Err(Value::error("ERR", "syntax error"))
+        }
+    }
+    Ok(Command::Scan { after, pattern, count })
+}
+
+/// `SLOWLOG GET [count] | LEN | RESET`
+fn parse_slowlog(args: &[String]) -> std::result::Result<Command, Value> {
+    match (args[0].to_ascii_lowercase().as_str(), args.get(1)) {
+        ("get", None) => Ok(Command::SlowlogGet { count: slowlog::DEFAULT_GET_COUNT }),
+        ("get", Some(count)) => {
+            let count = count.parse().map_err(|_| Value::error("ERR", "value is not an integer or out of range"))?;
+            Ok(Command::SlowlogGet { count })
+        }
+        ("len", None) => Ok(Command::SlowlogLen),
+        ("reset", None) => Ok(Command::SlowlogReset),
+        _ => Err(Value::error("ERR", "unknown SLOWLOG subcommand, expected GET, LEN or RESET"))
+    }
+}
+
+fn wrong_arity(name: &str) -> Value {
+    Value::error("ERR", &format!("wrong number of arguments for '{}' command",name))
+}
+
+impl Command {
+    pub(super) fn name(&self) -> &'static str {
+        match self {
+            Command::Get { .. } => "get",
+            Command::Set { .. } => "set",
+            Command::Del { .. } => "del",
+            Command::Exists { .. } => "exists",
+            Command::Scan { .. } => "scan",
+            Command::Ping { .. } => "ping",
+            Command::Info { .. } => "info",
+            Command::SlowlogGet { .. } | Command::SlowlogLen | Command::SlowlogReset => "slowlog",
+            Command::Promote => "replicaof"
+        }
+    }
+
+    /// 执行命令需要的权限
+    pub(super) fn required_permissions(&self) -> Vec<(&str, Permission)> {
+        match self {
+            Command::Get { key } => vec![(key, Permission::Read)],
+            Command::Set { key, .. } => vec![(key, Permission::Write)],
+            Command::Del { keys } => keys.iter().map(|key| (key.as_str(), Permission::Write)).collect(),
+            Command::Exists { keys } => keys.iter().map(|key| (key.as_str(), Permission::Read)).collect(),
+            Command::Scan { pattern, .. } => vec![(literal_prefix(pattern.as_deref().unwrap_or("")), Permission::Read)],
+            Command::Ping { .. } => Vec::new(),
+            Command::Info { .. }
+            | Command::SlowlogGet { .. }
+            | Command::SlowlogLen
+            | Command::SlowlogReset
+            | Command::Promote => vec![("", Permission::Admin)]
+        }
+    }
+}
+
+/// 执行命令并编码响应，引擎返回的错误作为 `ERR` 响应返回给客户端，从节点拒绝写操作时返回 `READONLY`，
+/// 集群中的非领导者返回 `MOVED` 或 `CLUSTERDOWN`
+pub(super) fn execute<E: KvsEngine>(
+    keyspace: &Keyspace<E>,
+    stats: &ServerStats,
+    cmd: Command,
+    version: Version
+) -> Vec<u8> {
+    let value = match run(keyspace, stats, cmd) {
+        Ok(value) => value,
+        Err(KvsError::ReadOnly) => Value::error("READONLY", "You can't write against a read only replica."),
+        Err(KvsError::NotLeader(leader)) => Value::error("MOVED", &format!("0 {}",leader)),
+        Err(KvsError::NoLeader) => Value::error("CLUSTERDOWN", "The cluster is down"),
+        Err(e) => Value::error("ERR", &e.to_string())
+    };
+    value.encode(version)
+}
+
+fn run<E: KvsEngine>(keyspace: &Keyspace<E>, stats: &ServerStats, cmd: Command) -> Result<Value> {
+    Ok(match cmd {
+        Command::Get { key } => keyspace.get(key)?.map_or(Value::Nil, Value::Bulk),
+        Command::Set { key, value, ttl, condition } => {
+            if keyspace.set(key, value, ttl, condition)? { Value::ok() } else { Value::Nil }
+        }
+        Command::Del { keys } => {
+            let mut removed = 0;
+            for key in keys {
+                match keyspace.remove(key) {
+                    Ok(()) => removed += 1,
+                    Err(KvsError::KeyNotFound) => {}
+                    Err(e) => return Err(e)
+                }
+            }
+            Value::Integer(removed)
+        }
+        Command::Exists { keys } => {
+            let mut found = 0;
+            for key in keys {
+                if keyspace.get(key)?.is_some() {
+                    found += 1;
+                }
+            }
+            Value::Integer(found)
+        }
+        Command::Scan { after, pattern, count } => scan(keyspace, after, pattern, count)?,
+        Command::Ping { message: Some(message) } => Value::Bulk(message),
+        Command::Ping { message: None } => Value::Simple("PONG".to_owned()),
+        Command::Info { section } => Value::Bulk(info(keyspace, stats, section.as_deref())),
+        // 与 Redis 的格式相同：编号、时间戳、耗时（微秒）、命令及参数、客户端地址、客户端名称
+        Command::SlowlogGet { count } => Value::Array(stats.slowlog().get(count).into_iter().map(|entry| {
+            let args = std::iter::once(entry.command).chain(entry.key).map(Value::Bulk).collect();
+            Value::Array(vec![
+                Value::Integer(entry.id as i64),
+                Value::Integer(entry.timestamp as i64),
+                Value::Integer(entry.duration_micros as i64),
+                Value::Array(args),
+                Value::Bulk(entry.client),
+                Value::Bulk(String::new())
+            ])
+        }).collect()),
+        Command::SlowlogLen => Value::Integer(stats.slowlog().len() as i64),
+        Command::SlowlogReset => {
+            stats.slowlog().reset();
+            Value::ok()
+        }
+        Command::Promote => {
+            keyspace.replication().promote()?;
+            Value::ok()
+        }
+    })
+}
+
+/// 从游标中的键之后按字典序扫描 `count` 个键，再按 `MATCH` 过滤，返回的键可能少于 `count` 个
+///
+/// 游标编码的是本页扫描到的最后一个键，扫描期间新增或删除的键不会使其余的键被遗漏或重复返回
+fn scan<E: KvsEngine>(keyspace: &Keyspace<E>, after: Option<String>, pattern: Option<String>, count: usize) -> Result<Value> {
+    let prefix = literal_prefix(pattern.as_deref().unwrap_or(""));
+    let page = keyspace.scan(prefix, after.as_deref(), count)?;
+    let next = page.next.as_deref().map_or_else(|| "0".to_owned(), encode_cursor);
+    let matched = page.keys.into_iter()
+        .filter(|key| pattern.as_deref().is_none_or(|pattern| glob_match(pattern.as_bytes(), key.as_bytes())))
+        .map(Value::Bulk)
+        .collect();
+    Ok(Value::Array(vec![Value::Bulk(next), Value::Array(matched)]))
+}
+
+/// 将键编码为游标：`1` 之后每个字节对应三位十进制数
+///
+/// 游标只由数字组成且不以 0 开头，把游标当作整数解析的客户端库也能使用，`0` 表示从头开始
+fn encode_cursor(key: &str) -> String {
+    let mut cursor = String::with_capacity(1 + key.len() * 3);
+    cursor.push('1');
+    for byte in key.bytes() {
+        let _ = write!(cursor, "{:03}", byte);
+    }
+    cursor
+}
+
+/// 解码游标，`0` 解码为 `Some(None)`，游标无效时返回 `None`
+fn decode_cursor(cursor: &str) -> Option<Option<String>> {
+    if cursor == "0" {
+        return Some(None);
+    }
+    let digits = cursor.strip_prefix('1')?.as_bytes();
+    if digits.len() % 3 != 0 {
+        return None;
+    }
+    let bytes = digits.chunks(3)
+        .map(|digits| str::from_utf8(digits).ok()?.parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok().map(Some)
+}
+
+fn info<E: KvsEngine>(keyspace: &Keyspace<E>, stats: &ServerStats, section: Option<&str>) -> String {
+    let all = matches!(section, None | Some("all") | Some("everything") | Some("default"));
+    let mut out = String::new();
+    if all || section == Some("server") {
+        let _ = write!(
+            out,
+            "# Server\r\nredis_version:{}\r\nkvs_version:{}\r\nredis_mode:standalone\r\nuptime_in_seconds:{}\r\n\r\n",
+            REDIS_COMPAT_VERSION,
+            env!("CARGO_PKG_VERSION"),
+            stats.uptime().as_secs()
+        );
+    }
+    if all || section == Some("clients") {
+        let _ = write!(out, "# Clients\r\nconnected_clients:{}\r\n\r\n", stats.connected_clients());
+    }
+    if all || section == Some("stats") {
+        let _ = write!(
+            out,
+            "# Stats\r\ntotal_connections_received:{}\r\ntotal_commands_processed:{}\r\n\r\n",
+            stats.total_connections(),
+            stats.total_commands()
+        );
+    }
+    if all || section == Some("replication") {
+        let replication = keyspace.replication().info();
+        let role = if replication.leader.is_some() { "slave" } else { "master" };
+        let _ = write!(out, "# Replication\r\nrole:{}\r\n", role);
+        if let Some(leader) = &replication.leader {
+            let link = if replication.link_up == Some(true) { "up" } else { "down" };
+            let _ = write!(out, "master_host:{}\r\nmaster_link_status:{}\r\n", leader, link);
+            if let (Some(lag), Some(millis)) = (replication.lag, replication.last_sync_millis) {
+                let _ = write!(out, "master_last_io_seconds_ago:{}\r\nkvs_replication_lag:{}\r\n", millis / 1000, lag);
+            }
+        }
+        let _ = write!(
+            out,
+            "master_replid:{}\r\nmaster_repl_offset:{}\r\n\r\n",
+            replication.replication_id,
+            replication.seq
+        );
+    }
+    if all || section == Some("cluster") {
+        let _ = write!(out, "# Cluster\r\ncluster_enabled:{}\r\n", u8::from(keyspace.raft().is_some()));
+        if let Some(cluster) = keyspace.raft().map(|raft| raft.info()) {
+            let _ = write!(
+                out,
+                "kvs_node_id:{}\r\nkvs_raft_role:{}\r\nkvs_raft_term:{}\r\nkvs_raft_commit_index:{}\r\nkvs_raft_last_applied:{}\r\n",
+                cluster.node_id,
+                cluster.role,
+                cluster.term,
+                cluster.commit_index,
+                cluster.last_applied
+            );
+        }
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// 模式中第一个通配符之前的部分，用于缩小扫描范围
+fn literal_prefix(pattern: &str) -> &str {
+    let end = pattern.find(['*', '?', '[', '\\']).unwrap_or(pattern.len());
+    &pattern[..end]
+}
+
+/// Redis 风格的通配符匹配，支持 `*`、`?`、`[abc]`、`[^a]`、`[a-z]` 和 `\` 转义
+///
+/// 只记录最近一个 `*` 作为回溯点，时间不超过模式长度与字符串长度之积
+fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // 最近一个 `*` 之后的模式位置，以及这个 `*` 匹配到的字符串末尾
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, i));
+            continue;
+        }
+        if let Some(len) = match_one(&pattern[p..], s[i]) {
+            p += len;
+            i += 1;
+            continue;
+        }
+        // 让最近的 `*` 多匹配一个字符后重试，更早的 `*` 不需要再调整
+        match star {
+            Some((after_star, matched)) => {
+                p = after_star;
+                i = matched + 1;
+                star = Some((after_star, i));
+            }
+            None => return false
+        }
+    }
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// 模式开头的一个字符或字符集合匹配 `c` 时返回它在模式中占的字节数
+fn match_one(pattern: &[u8], c: u8) -> Option<usize> {
+    match pattern {
+        [] => None,
+        [b'?', ..] => Some(1),
+        [b'\\', x, ..] => (*x == c).then_some(2),
+        [b'[', class @ ..] => {
+            let (negate, mut j) = match class.first() {
+                Some(b'^') => (true, 1),
+                _ => (false, 0)
+            };
+            let mut matched = false;
+            loop {
+                match &class[j..] {
+                    // 没有闭合的 `[` 不匹配任何字符
+                    [] => return None,
+                    [b']', ..] => break,
+                    [b'\\', x, ..] => {
+                        matched |= *x == c;
+                        j += 2;
+                    }
+                    [lo, b'-', hi, ..] if *hi != b']' => {
+                        matched |= (*lo.min(hi)..=*lo.max(hi)).contains(&c);
+                        j += 3;
+                    }
+                    [x, ..] => {
+                        matched |= *x == c;
+                        j += 1;
+                    }
+                }
+            }
+            (matched != negate).then_some(j + 2)
+        }
+        [x, ..] => (*x == c).then_some(1)
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex
+};
+
+use mio::Waker;
+
+/// 用于停止正在运行的 `KvsServer`
+///
+/// 调用 `shutdown` 后服务器停止接收新连接，等待正在执行的请求完成（最多等待关闭超时时间），
+/// 刷写存储引擎后从 `run` 返回。句柄可以被克隆并在其他线程中使用
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    inner: Arc<Inner>
+}
+
+#[derive(Default)]
+struct Inner {
+    requested: AtomicBool,
+    // 事件循环启动后才会设置
+    waker: Mutex<Option<Arc<Waker>>>
+}
+
+impl ShutdownHandle {
+    /// 请求关闭服务器，可以重复调用
+    pub fn shutdown(&self) {
+        self.inner.requested.store(true, Ordering::SeqCst);
+        if let Some(waker) = &*self.inner.waker.lock().unwrap() {
+            if let Err(e) = waker.wake() {
+                error!("Failed to wake up the event loop: {}",e);
+            }
+        }
+    }
+
+    /// 是否已经请求关闭
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.requested.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn set_waker(&self, waker: Arc<Waker>) {
+        *self.inner.waker.lock().unwrap() = Some(waker);
+    }
+}
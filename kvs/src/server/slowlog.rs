@@ -0,0 +1,113 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+
+use crate::{engines::EngineEvents, common::SlowLogEntry, ServerAddr};
+
+// 阈值为该值时不记录慢日志
+const DISABLED: u64 = u64::MAX;
+/// `SLOWLOG GET` 没有指定条数时返回的条数
+pub(super) const DEFAULT_GET_COUNT: usize = 10;
+
+/// 有界的慢日志，保存执行时间超过阈值的最近若干个请求
+///
+/// 阈值和容量可以在运行时修改，超出容量时丢弃最旧的记录
+pub(super) struct SlowLog {
+    threshold_micros: AtomicU64,
+    max_len: AtomicUsize,
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<SlowLogEntry>>
+}
+
+/// 一次请求的执行情况
+pub(super) struct Execution<'a> {
+    pub(super) duration: Duration,
+    pub(super) protocol: &'static str,
+    pub(super) command: &'static str,
+    pub(super) key: Option<&'a str>,
+    pub(super) client: &'a ServerAddr,
+    pub(super) events: EngineEvents
+}
+
+impl SlowLog {
+    /// `threshold` 为 `None` 时不记录
+    pub(super) fn new(threshold: Option<Duration>, max_len: usize) -> Self {
+        let slowlog = SlowLog {
+            threshold_micros: AtomicU64::new(DISABLED),
+            max_len: AtomicUsize::new(max_len),
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::new())
+        };
+        slowlog.set_threshold(threshold);
+        slowlog
+    }
+
+    pub(super) fn threshold(&self) -> Option<Duration> {
+        match self.threshold_micros.load(Ordering::Relaxed) {
+            DISABLED => None,
+            micros => Some(Duration::from_micros(micros))
+        }
+    }
+
+    pub(super) fn set_threshold(&self, threshold: Option<Duration>) {
+        let micros = threshold.map_or(DISABLED, |threshold| threshold.as_micros().min(u128::from(DISABLED - 1)) as u64);
+        self.threshold_micros.store(micros, Ordering::Relaxed);
+    }
+
+    pub(super) fn max_len(&self) -> usize {
+        self.max_len.load(Ordering::Relaxed)
+    }
+
+    /// 修改容量，超出的旧记录立即丢弃
+    pub(super) fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Ordering::Relaxed);
+        self.entries.lock().unwrap().truncate(max_len);
+    }
+
+    /// 执行时间达到阈值时记录请求
+    pub(super) fn record(&self, execution: Execution) {
+        let threshold = self.threshold_micros.load(Ordering::Relaxed);
+        let micros = execution.duration.as_micros().min(u128::from(u64::MAX)) as u64;
+        if threshold == DISABLED || micros < threshold {
+            return;
+        }
+        warn!("Slow {} from {} took {:?}",execution.command,execution.client,execution.duration);
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp,
+            duration_micros: micros,
+            protocol: execution.protocol.to_owned(),
+            command: execution.command.to_owned(),
+            key: execution.key.map(str::to_owned),
+            client: execution.client.to_string(),
+            compacted: execution.events.compacted,
+            synced: execution.events.synced
+        };
+        let max_len = self.max_len();
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    /// 最近的 `count` 条记录，从新到旧
+    pub(super) fn get(&self, count: usize) -> Vec<SlowLogEntry> {
+        self.entries.lock().unwrap().iter().take(count).cloned().collect()
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub(super) fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::{Duration, Instant}
+};
+
+use super::slowlog::SlowLog;
+
+/// 服务器运行状态的计数器和慢日志，由事件循环和线程池更新，在线程池中执行的 `INFO` 等命令读取
+pub(super) struct ServerStats {
+    started: Instant,
+    slowlog: SlowLog,
+    connected_clients: AtomicUsize,
+    total_connections: AtomicU64,
+    total_commands: AtomicU64
+}
+
+impl ServerStats {
+    pub(super) fn new(slowlog: SlowLog) -> Self {
+        ServerStats {
+            started: Instant::now(),
+            slowlog,
+            connected_clients: AtomicUsize::new(0),
+            total_connections: AtomicU64::new(0),
+            total_commands: AtomicU64::new(0)
+        }
+    }
+
+    pub(super) fn slowlog(&self) -> &SlowLog {
+        &self.slowlog
+    }
+
+    pub(super) fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub(super) fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn set_connected_clients(&self, n: usize) {
+        self.connected_clients.store(n, Ordering::Relaxed);
+    }
+
+    pub(super) fn total_connections(&self) -> u64 {
+        self.total_connections.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn connection_accepted(&self) {
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(super) fn total_commands(&self) -> u64 {
+        self.total_commands.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn command_processed(&self) {
+        self.total_commands.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    io::{self, Read, Write},
+    net::Shutdown
+};
+
+use mio::{net::{TcpStream, UnixStream}, Interest, Registry, Token};
+use rustls::ServerConnection;
+
+use crate::tls;
+
+const READ_CHUNK: usize = 4096;
+
+/// 连接的传输层：明文 TCP、TLS 或 Unix 域套接字
+///
+/// 所有操作都是非阻塞的，遇到 `WouldBlock` 时返回，等待下一次读写事件
+pub(super) enum Transport {
+    Plain(TcpStream),
+    Tls(TcpStream, Box<ServerConnection>),
+    Unix(UnixStream)
+}
+
+impl Transport {
+    /// 读取可读数据追加到 `buf`，直到没有数据或 `buf` 达到 `limit` 字节，返回对端是否已关闭
+    pub(super) fn read_into(&mut self, buf: &mut Vec<u8>, limit: usize) -> io::Result<bool> {
+        match self {
+            Transport::Plain(stream) => read_available(stream, buf, limit),
+            Transport::Unix(stream) => read_available(stream, buf, limit),
+            Transport::Tls(stream, tls) => {
+                let mut closed = false;
+                while buf.len() < limit {
+                    match tls.read_tls(stream) {
+                        Ok(0) => {
+                            closed = true;
+                            break;
+                        }
+                        Ok(_) => {}
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e)
+                    }
+                    let state = match tls.process_new_packets() {
+                        Ok(state) => state,
+                        Err(e) => {
+                            // 尽量把 alert 发给对端
+                            let _ = tls.write_tls(stream);
+                            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                        }
+                    };
+                    if state.peer_has_closed() {
+                        closed = true;
+                    }
+                    // 每次都取出全部明文，TLS 中不留下未读的数据
+                    closed |= read_available(&mut tls.reader(), buf, usize::MAX)?;
+                }
+                // 握手过程中需要回复对端
+                self.flush()?;
+                Ok(closed)
+            }
+        }
+    }
+
+    /// 写出 `data`，返回写出（或交给 TLS 缓冲）的字节数
+    pub(super) fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Plain(stream) => stream.write(data),
+            Transport::Unix(stream) => stream.write(data),
+            Transport::Tls(_, tls) => {
+                let n = tls.writer().write(data)?;
+                self.flush()?;
+                Ok(n)
+            }
+        }
+    }
+
+    /// 将 TLS 缓冲中的数据写到 socket
+    pub(super) fn flush(&mut self) -> io::Result<()> {
+        if let Transport::Tls(stream, tls) = self {
+            while tls.wants_write() {
+                match tls.write_tls(stream) {
+                    Ok(_) => {}
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e)
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 是否还有数据没有写到 socket
+    pub(super) fn has_pending_output(&self) -> bool {
+        match self {
+            Transport::Plain(_) | Transport::Unix(_) => false,
+            Transport::Tls(_, tls) => tls.wants_write()
+        }
+    }
+
+    /// 关闭写端，TLS 连接会先发送 close_notify
+    pub(super) fn shutdown_write(&mut self) -> io::Result<()> {
+        if let Transport::Tls(_, tls) = self {
+            tls.send_close_notify();
+            self.flush()?;
+        }
+        let res = match self {
+            Transport::Plain(stream) | Transport::Tls(stream, _) => stream.shutdown(Shutdown::Write),
+            Transport::Unix(stream) => stream.shutdown(Shutdown::Write)
+        };
+        match res {
+            Err(e) if e.kind() != io::ErrorKind::NotConnected => Err(e),
+            _ => Ok(())
+        }
+    }
+
+    /// 双向 TLS 中客户端证书标识的身份
+    pub(super) fn peer_identity(&self) -> Option<String> {
+        match self {
+            Transport::Plain(_) | Transport::Unix(_) => None,
+            Transport::Tls(_, tls) => tls.peer_certificates().and_then(tls::peer_identity)
+        }
+    }
+
+    pub(super) fn register(&mut self, registry: &Registry, token: Token) -> io::Result<()> {
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        match self {
+            Transport::Plain(stream) | Transport::Tls(stream, _) => registry.register(stream, token, interest),
+            Transport::Unix(stream) => registry.register(stream, token, interest)
+        }
+    }
+
+    /// 修改注册的事件
+    pub(super) fn reregister(&mut self, registry: &Registry, token: Token, interest: Interest) -> io::Result<()> {
+        match self {
+            Transport::Plain(stream) | Transport::Tls(stream, _) => registry.reregister(stream, token, interest),
+            Transport::Unix(stream) => registry.reregister(stream, token, interest)
+        }
+    }
+
+    pub(super) fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        match self {
+            Transport::Plain(stream) | Transport::Tls(stream, _) => registry.deregister(stream),
+            Transport::Unix(stream) => registry.deregister(stream)
+        }
+    }
+}
+
+/// 读取直到 `WouldBlock` 或 `buf` 达到 `limit` 字节，返回是否读到 EOF
+fn read_available<R: Read>(reader: &mut R, buf: &mut Vec<u8>, limit: usize) -> io::Result<bool> {
+    let mut chunk = [0; READ_CHUNK];
+    while buf.len() < limit {
+        match reader.read(&mut chunk) {
+            Ok(0) => return Ok(true),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            // TLS 对端没有发送 close_notify 就关闭了连接
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(true),
+            Err(e) => return Err(e)
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,90 @@
+use std::{fmt, str::FromStr};
+
+use super::{NaiveThreadPool, RayonThreadPool, SharedQueueThreadPool, ThreadPool};
+use crate::{KvsError, Result};
+
+/// 线程池的实现
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolKind {
+    /// `NaiveThreadPool`
+    Naive,
+    /// `SharedQueueThreadPool`
+    SharedQueue,
+    /// `RayonThreadPool`
+    Rayon
+}
+
+impl PoolKind {
+    /// 所有实现的名称，与 `FromStr` 接受的值相同，`FromStr` 也接受 `shared-queue`
+    pub const NAMES: [&'static str; 3] = ["naive", "shared_queue", "rayon"];
+}
+
+impl FromStr for PoolKind {
+    type Err = KvsError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "naive" => Ok(PoolKind::Naive),
+            "shared_queue" | "shared-queue" => Ok(PoolKind::SharedQueue),
+            "rayon" => Ok(PoolKind::Rayon),
+            _ => Err(KvsError::StringError(format!("Unknown thread pool {}, expected one of {}",s,PoolKind::NAMES.join(", "))))
+        }
+    }
+}
+
+impl fmt::Display for PoolKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PoolKind::Naive => "naive",
+            PoolKind::SharedQueue => "shared_queue",
+            PoolKind::Rayon => "rayon"
+        })
+    }
+}
+
+/// 在运行时选择实现的线程池
+///
+/// `ThreadPool` 的 `new` 和泛型的 `spawn` 无法作为 trait 对象使用，这里把任务装箱后交给内部的线程池，
+/// 因此服务器可以使用由配置决定的线程池，而不需要为每种实现单独实例化
+pub struct BoxedThreadPool(Box<dyn SpawnBoxed + Send + Sync>);
+
+/// `ThreadPool` 中可以作为 trait 对象使用的部分
+trait SpawnBoxed {
+    fn spawn_boxed(&self, job: Box<dyn FnOnce() + Send + 'static>);
+}
+
+impl<P: ThreadPool> SpawnBoxed for P {
+    fn spawn_boxed(&self, job: Box<dyn FnOnce() + Send + 'static>) {
+        self.spawn(job)
+    }
+}
+
+impl BoxedThreadPool {
+    /// 创建指定实现和线程数的线程池，线程数至少为 1
+    pub fn with_kind(kind: PoolKind, threads: u32) -> Result<Self> {
+        if threads == 0 {
+            return Err(KvsError::StringError("The thread pool needs at least one thread".to_owned()));
+        }
+        Ok(match kind {
+            PoolKind::Naive => BoxedThreadPool::from_pool(NaiveThreadPool::new(threads)?),
+            PoolKind::SharedQueue => BoxedThreadPool::from_pool(SharedQueueThreadPool::new(threads)?),
+            PoolKind::Rayon => BoxedThreadPool::from_pool(RayonThreadPool::new(threads)?)
+        })
+    }
+
+    /// 包装一个已创建的线程池
+    pub fn from_pool<P: ThreadPool + Send + Sync + 'static>(pool: P) -> Self {
+        BoxedThreadPool(Box::new(pool))
+    }
+}
+
+impl ThreadPool for BoxedThreadPool {
+    /// 使用 `RayonThreadPool`
+    fn new(threads: u32) -> Result<Self> {
+        BoxedThreadPool::with_kind(PoolKind::Rayon, threads)
+    }
+
+    fn spawn<F>(&self,job:F) where F: FnOnce() + Send + 'static {
+        self.0.spawn_boxed(Box::new(job))
+    }
+}
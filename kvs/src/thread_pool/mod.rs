@@ -1,19 +1,21 @@
 //! This module provides various thread pools. All thread pools should implement
-//! the `ThreadPool` trait.
+//! the `ThreadPool` trait. Every pool reports its queue depth to the process metrics.
 use crate::Result;
 
+mod boxed;
 mod naive;
 mod shared_queue;
 mod rayon;
 
 
+pub use boxed::{BoxedThreadPool, PoolKind};
 pub use naive::NaiveThreadPool;
 pub use shared_queue::SharedQueueThreadPool;
 pub use rayon::RayonThreadPool;
 
 /// 定义线程池
 pub trait ThreadPool {
-    ///
+    /// 创建指定线程数的线程池
     fn new(threads: u32) -> Result<Self> where Self: Sized;
     
     /// spawn a thread
@@ -1,7 +1,7 @@
 use std::thread;
 
 use super::ThreadPool;
-use crate::Result;
+use crate::{metrics, Result};
 
 /// 并非真正意义上的线程池，每次都会产生一个新的线程
 pub struct NaiveThreadPool;
@@ -12,7 +12,7 @@ impl ThreadPool for NaiveThreadPool {
     }
 
     fn spawn<F>(&self,job:F) where F: FnOnce() + Send + 'static {
-        thread::spawn(job);
+        thread::spawn(metrics::track_job(job));
     }
 }
 
@@ -1,5 +1,5 @@
 use super::ThreadPool;
-use crate::{KvsError,Result};
+use crate::{metrics,KvsError,Result};
 
 /// wrapper of rayon threadpool
 pub struct RayonThreadPool(rayon::ThreadPool);
@@ -14,7 +14,7 @@ impl ThreadPool for RayonThreadPool {
     }
 
     fn spawn<F>(&self,job:F) where F: FnOnce() + Send + 'static {
-        self.0.spawn(job)
+        self.0.spawn(metrics::track_job(job))
     }
 }
 
@@ -2,17 +2,20 @@ use std::thread;
 
 use crossbeam::Receiver;
 use crossbeam::{Sender,channel};
-use crate::Result;
+use crate::{metrics, KvsError, Result};
 
 use super::ThreadPool;
 
-/// 
+/// 多个线程共享同一个任务队列的线程池
 pub struct SharedQueueThreadPool {
     tx: Sender<Box<dyn FnOnce() + Send + 'static>>
 }
 
 impl ThreadPool for SharedQueueThreadPool {
     fn new(threads: u32) -> Result<Self>{
+        if threads == 0 {
+            return Err(KvsError::StringError("The thread pool needs at least one thread".to_owned()));
+        }
         let (tx,rx) = channel::unbounded::<Box<dyn FnOnce() + Send + 'static>>();
         for _ in 0..threads {
             let rx = TaskReceiver(rx.clone());
@@ -23,7 +26,7 @@ impl ThreadPool for SharedQueueThreadPool {
     }
 
     fn spawn<F>(&self,job:F) where F: FnOnce() + Send + 'static {
-        self.tx.send(Box::new(job)).expect("The thread pool has no thread.");
+        self.tx.send(Box::new(metrics::track_job(job))).expect("The thread pool has no thread.");
     }
 }
 
@@ -47,7 +50,10 @@ fn run_tasks(rx: TaskReceiver) {
             Ok(task) => {
                 task();
             }
-            Err(_) => debug!("Thread exits because the thread pool is destroyed.")
+            Err(_) => {
+                debug!("Thread exits because the thread pool is destroyed.");
+                break;
+            }
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,121 @@
+//! TLS 相关配置，基于 rustls 实现
+//!
+//! 服务端使用证书和私钥对外提供 TLS 服务，可以要求客户端提供由指定 CA 签发的证书（双向 TLS），
+//! 客户端证书中的 Common Name 会作为调用方的身份。客户端只信任指定的 CA（CA pinning）
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::Arc
+};
+
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    server::WebPkiClientVerifier,
+    ClientConfig, RootCertStore, ServerConfig
+};
+
+use crate::{KvsError, Result};
+
+/// 服务端 TLS 配置
+#[derive(Debug, Clone)]
+pub struct TlsServerConfig(pub(crate) Arc<ServerConfig>);
+
+impl TlsServerConfig {
+    /// 从 PEM 格式的证书链和私钥文件加载配置
+    ///
+    /// 指定 `client_ca` 时开启双向 TLS，客户端必须提供由该 CA 签发的证书
+    pub fn from_pem_files(
+        cert: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+        client_ca: Option<&Path>
+    ) -> Result<Self> {
+        let builder = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?;
+        let builder = match client_ca {
+            Some(ca) => {
+                let verifier = WebPkiClientVerifier::builder_with_provider(
+                    Arc::new(load_roots(ca)?),
+                    provider()
+                ).build().map_err(|e| KvsError::StringError(format!("Invalid client CA: {}",e)))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth()
+        };
+        let config = builder.with_single_cert(load_certs(cert.as_ref())?, load_key(key.as_ref())?)?;
+        Ok(TlsServerConfig(Arc::new(config)))
+    }
+}
+
+/// 客户端 TLS 配置
+#[derive(Debug, Clone)]
+pub struct TlsClientConfig {
+    pub(crate) config: Arc<ClientConfig>,
+    pub(crate) server_name: ServerName<'static>,
+    roots: Arc<RootCertStore>
+}
+
+impl TlsClientConfig {
+    /// 只信任 `ca` 文件中的 CA 证书，`server_name` 为服务端证书中的域名或 IP
+    pub fn from_pem_file(ca: impl AsRef<Path>, server_name: &str) -> Result<Self> {
+        let roots = Arc::new(load_roots(ca.as_ref())?);
+        let config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(Arc::clone(&roots))
+            .with_no_client_auth();
+        Ok(TlsClientConfig {
+            config: Arc::new(config),
+            server_name: parse_server_name(server_name)?,
+            roots
+        })
+    }
+
+    /// 使用客户端证书进行双向 TLS 认证
+    pub fn with_client_cert(mut self, cert: impl AsRef<Path>, key: impl AsRef<Path>) -> Result<Self> {
+        let config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(Arc::clone(&self.roots))
+            .with_client_auth_cert(load_certs(cert.as_ref())?, load_key(key.as_ref())?)?;
+        self.config = Arc::new(config);
+        Ok(self)
+    }
+}
+
+/// 从客户端证书中取出 Common Name 作为调用方身份
+pub(crate) fn peer_identity(certs: &[CertificateDer<'_>]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(certs.first()?).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(str::to_owned)
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn parse_server_name(name: &str) -> Result<ServerName<'static>> {
+    ServerName::try_from(name.to_owned())
+        .map_err(|e| KvsError::StringError(format!("Invalid server name {}: {}",name,e)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<std::io::Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(KvsError::StringError(format!("No certificate found in {}",path.display())));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| KvsError::StringError(format!("No private key found in {}",path.display())))
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
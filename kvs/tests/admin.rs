@@ -0,0 +1,133 @@
+mod common;
+
+use common::{start_engine, start_server};
+use kvs::{KvsClient, KvsEngine, Result, ServerConfig, SledKvsEngine};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+// Info reports the server version, engine and connection counters.
+#[test]
+fn admin_info() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4070".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key".to_owned(), "value".to_owned())?;
+
+    let info = client.info()?;
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(info.engine, "kvs");
+    assert_eq!(info.connected_clients, 1);
+    assert!(info.total_commands >= 2);
+    Ok(())
+}
+
+// Stats track live and dead bytes, and compaction reclaims the dead ones.
+#[test]
+fn admin_stats_and_compact() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4071".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+    let mut client = KvsClient::connect(addr)?;
+    for i in 0..100 {
+        client.set(format!("key{}", i % 10), format!("value{}", i))?;
+    }
+
+    let before = client.engine_stats()?;
+    assert_eq!(before.keys, 10);
+    assert!(before.dead_bytes.unwrap() > 0);
+    assert!(before.live_bytes.unwrap() > 0);
+
+    client.compact()?;
+    client.flush()?;
+    let after = client.engine_stats()?;
+    assert_eq!(after.keys, 10);
+    assert_eq!(after.dead_bytes, Some(0));
+    assert_eq!(after.live_bytes, before.live_bytes);
+    assert!(after.disk_bytes < before.disk_bytes);
+    assert_eq!(client.get("key3".to_owned())?, Some("value93".to_owned()));
+    Ok(())
+}
+
+// Runtime settings can be read and changed, bad names and values are rejected.
+#[test]
+fn admin_config() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4072".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+    let mut client = KvsClient::connect(addr)?;
+
+    assert_eq!(client.config_get("compaction-threshold".to_owned())?, "1048576");
+    client.config_set("compaction-threshold".to_owned(), "100".to_owned())?;
+    assert_eq!(client.config_get("compaction-threshold".to_owned())?, "100");
+    for i in 0..10 {
+        client.set("key".to_owned(), format!("value{}", i))?;
+    }
+    // The lowered threshold triggers automatic compaction.
+    assert!(client.engine_stats()?.dead_bytes.unwrap() <= 100);
+
+    client.config_set("log-level".to_owned(), "debug".to_owned())?;
+    assert_eq!(client.config_get("log-level".to_owned())?, "debug");
+    client.config_set("log-level".to_owned(), "info".to_owned())?;
+
+    assert!(client.config_get("no-such-setting".to_owned()).is_err());
+    assert!(client.config_set("log-level".to_owned(), "loud".to_owned()).is_err());
+    assert!(client.config_set("compaction-threshold".to_owned(), "-1".to_owned()).is_err());
+    Ok(())
+}
+
+// The sled engine reports what it can and rejects kvs-only settings.
+#[test]
+fn admin_sled_engine() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4073".parse().unwrap();
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let engine = SledKvsEngine::new(sled::open(temp_dir.path())?);
+    start_engine(addr, engine, ServerConfig::default())?;
+
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(client.info()?.engine, "sled");
+    let stats = client.engine_stats()?;
+    assert_eq!(stats.keys, 1);
+    assert_eq!(stats.segments, None);
+    client.compact()?;
+    assert!(client.config_get("compaction-threshold".to_owned()).is_err());
+    Ok(())
+}
+
+// An engine that only implements get, set and remove.
+#[derive(Clone, Default)]
+struct MapEngine(Arc<Mutex<HashMap<String, String>>>);
+
+impl KvsEngine for MapEngine {
+    fn set(&self, key: String, value: String) -> Result<()> {
+        self.0.lock().unwrap().insert(key, value);
+        Ok(())
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(&key).cloned())
+    }
+
+    fn remove(&self, key: String) -> Result<()> {
+        self.0.lock().unwrap().remove(&key);
+        Ok(())
+    }
+}
+
+// The optional engine operations report that they are unsupported instead of failing the server.
+#[test]
+fn admin_minimal_engine() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4175".parse().unwrap();
+    start_engine(addr, MapEngine::default(), ServerConfig::default())?;
+
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+    assert_eq!(client.info()?.engine, "custom");
+    let err = client.engine_stats().unwrap_err();
+    assert_eq!(err.to_string(), "stats is not supported by the custom engine");
+    assert!(client.compact().is_err());
+    assert!(client.flush().is_err());
+    assert!(client.config_get("compaction-threshold".to_owned()).is_err());
+    Ok(())
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use kvs::{AuthConfig, KvsClient, KvsClientBuilder, Result, ServerConfig};
+use std::fs;
+use std::net::SocketAddr;
+use tempfile::TempDir;
+
+// Starts a server whose credential file holds an admin with full access,
+// an app user limited to `app:` keys and a read-only user.
+fn start_server(addr: SocketAddr) -> Result<TempDir> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let credentials = serde_json::json!({
+        "users": [
+            {
+                "name": "admin",
+                "secret": AuthConfig::hash_secret("admin-password")?,
+                "rules": [{ "permissions": ["read", "write", "admin"] }]
+            },
+            {
+                "name": "app",
+                "secret": AuthConfig::hash_secret("app-token")?,
+                "rules": [{ "prefix": "app:", "permissions": ["read", "write"] }]
+            },
+            {
+                "name": "reader",
+                "secret": AuthConfig::hash_secret("reader-password")?,
+                "rules": [{ "prefix": "", "permissions": ["read"] }]
+            }
+        ]
+    });
+    let path = temp_dir.path().join("credentials.json");
+    fs::write(&path, credentials.to_string())?;
+
+    let config = ServerConfig { auth: Some(AuthConfig::from_file(&path)?), ..ServerConfig::default() };
+    common::start_server_in(addr, config, temp_dir.path())?;
+    Ok(temp_dir)
+}
+
+fn login(addr: SocketAddr, user: &str, password: &str) -> Result<KvsClient> {
+    KvsClientBuilder::new()
+        .addr(addr)
+        .credentials(user.to_owned(), password.to_owned())
+        .build()
+}
+
+// Only ping works before logging in, and a wrong password is rejected.
+#[test]
+fn authentication_required() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4040".parse().unwrap();
+    let _dir = start_server(addr)?;
+
+    let mut client = KvsClient::connect(addr)?;
+    client.ping()?;
+    assert!(client.get("key".to_owned()).is_err());
+    assert!(client.set("key".to_owned(), "value".to_owned()).is_err());
+
+    assert!(login(addr, "admin", "wrong").is_err());
+    assert!(login(addr, "nobody", "admin-password").is_err());
+
+    let mut client = login(addr, "admin", "admin-password")?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+    Ok(())
+}
+
+// Users can only touch the key prefixes their rules grant.
+#[test]
+fn per_prefix_permissions() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4041".parse().unwrap();
+    let _dir = start_server(addr)?;
+
+    let mut admin = login(addr, "admin", "admin-password")?;
+    admin.set("other".to_owned(), "secret".to_owned())?;
+
+    let mut app = login(addr, "app", "app-token")?;
+    app.set("app:key".to_owned(), "value".to_owned())?;
+    assert_eq!(app.get("app:key".to_owned())?, Some("value".to_owned()));
+    assert!(app.get("other".to_owned()).is_err());
+    assert!(app.set("other".to_owned(), "value".to_owned()).is_err());
+    assert!(app.remove("other".to_owned()).is_err());
+    // The connection stays usable after a denied request.
+    app.remove("app:key".to_owned())?;
+
+    let mut reader = login(addr, "reader", "reader-password")?;
+    assert_eq!(reader.get("other".to_owned())?, Some("secret".to_owned()));
+    assert!(reader.set("other".to_owned(), "value".to_owned()).is_err());
+    assert!(reader.remove("other".to_owned()).is_err());
+    assert_eq!(admin.get("other".to_owned())?, Some("secret".to_owned()));
+
+    // Admin requests need the admin permission.
+    assert!(reader.info().is_err());
+    assert!(app.compact().is_err());
+    admin.info()?;
+    Ok(())
+}
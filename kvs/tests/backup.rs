@@ -0,0 +1,177 @@
+mod common;
+
+use common::{start_engine, start_server};
+use assert_cmd::prelude::*;
+use kvs::{
+    checksum, restore, BackupFormat, KvStore, KvsClient, KvsEngine, Manifest, Result, ServerConfig, SledKvsEngine
+};
+use predicates::str::contains;
+use serde_json::{json, Deserializer, Value};
+use std::fs;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// The sealed segments keep the state at backup time even after later writes and a compaction delete them.
+#[test]
+fn kvs_backup_survives_compaction() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = KvStore::open(temp_dir.path().join("data"))?;
+    for i in 0..500 {
+        store.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    store.remove("key1".to_owned())?;
+    let expected = checksum(&store)?;
+
+    let backup = store.backup()?;
+    assert_eq!(backup.header().format, BackupFormat::Segments);
+    for i in 0..500 {
+        store.set(format!("key{}", i), "overwritten".to_owned())?;
+    }
+    store.compact()?;
+
+    let mut file = Vec::new();
+    backup.header().write_to(&mut file)?;
+    let mut buf = [0; 1000];
+    let mut offset = 0;
+    loop {
+        let n = backup.read_at(&mut buf, offset)?;
+        if n == 0 {
+            break;
+        }
+        file.extend_from_slice(&buf[..n]);
+        offset += n as u64;
+    }
+    assert_eq!(offset, backup.header().len());
+
+    let restored_dir = temp_dir.path().join("restored");
+    let header = restore(file.as_slice(), &restored_dir)?;
+    assert_eq!(Manifest::load(&restored_dir)?.unwrap().engine, "kvs");
+    let restored = KvStore::open(&restored_dir)?;
+    assert_eq!(checksum(&restored)?, expected);
+    assert_eq!(restored.get("key1".to_owned())?, None);
+    drop(restored);
+
+    // A corrupted backup is refused and leaves the target directory empty.
+    let last = file.len() - 2;
+    file[last] ^= 1;
+    let corrupted_dir = temp_dir.path().join("corrupted");
+    let err = restore(file.as_slice(), &corrupted_dir).unwrap_err();
+    assert!(err.to_string().contains("checksum mismatch"), "{}", err);
+    assert_eq!(fs::read_dir(&corrupted_dir)?.count(), 0);
+    assert!(restore(&b"not a backup"[..], &corrupted_dir).is_err());
+    assert!(restore(&b""[..], &restored_dir).unwrap_err().to_string().contains("not empty"));
+    assert_eq!(header.engine, "kvs");
+    Ok(())
+}
+
+// A sled server streams a logical backup to the client, which restores into a sled data directory.
+#[test]
+fn sled_backup_over_protocol() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4160".parse().unwrap();
+    let temp_dir = TempDir::new()?;
+    let engine = SledKvsEngine::new(sled::open(temp_dir.path().join("data"))?);
+    start_engine(addr, engine.clone(), ServerConfig::default())?;
+
+    let mut client = KvsClient::connect(addr)?;
+    for i in 0..200 {
+        // Large values make the backup span several chunks.
+        client.set(format!("key{}", i), format!("{:08}", i).repeat(1000))?;
+    }
+    let mut file = Vec::new();
+    let header = client.backup(&mut file)?;
+    assert_eq!((header.engine.as_str(), header.format), ("sled", BackupFormat::Logical));
+    assert!(header.len() > 1024 * 1024);
+    client.set("after".to_owned(), "backup".to_owned())?;
+
+    let restored_dir = temp_dir.path().join("restored");
+    restore(file.as_slice(), &restored_dir)?;
+    assert_eq!(Manifest::load(&restored_dir)?.unwrap().engine, "sled");
+    let restored = SledKvsEngine::new(sled::open(&restored_dir)?);
+    assert_eq!(restored.get("key7".to_owned())?, Some("00000007".repeat(1000)));
+    assert_eq!(restored.get("after".to_owned())?, None);
+    assert_eq!(checksum(&restored)?.keys, 200);
+    Ok(())
+}
+
+// The server keeps a fully read backup until the client finishes it, so a failed verification can read it again.
+#[test]
+fn backup_kept_until_finished() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4176".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+    KvsClient::connect(addr)?.set("key".to_owned(), "value".to_owned())?;
+
+    let mut stream = TcpStream::connect(addr)?;
+    let mut responses = Deserializer::from_reader(stream.try_clone()?).into_iter::<Value>();
+    let mut call = |req: Value| -> Result<Value> {
+        serde_json::to_writer(&mut stream, &req)?;
+        stream.flush()?;
+        Ok(responses.next().unwrap()?)
+    };
+    let session = call(json!("Backup"))?;
+    let id = session["Ok"]["id"].as_str().unwrap().to_owned();
+    let chunk = json!({"BackupChunk": {"id": id, "offset": 0, "len": 1024 * 1024}});
+    let first = call(chunk.clone())?;
+    assert!(first["Ok"].is_string(), "{}", first);
+    assert_eq!(call(chunk.clone())?, first);
+
+    assert_eq!(call(json!({"BackupFinish": {"id": id}}))?, json!({"Ok": null}));
+    assert!(call(chunk)?["Err"].as_str().unwrap().contains("Unknown backup"));
+    assert!(call(json!({"BackupFinish": {"id": id}}))?["Err"].is_string());
+    Ok(())
+}
+
+// `kvs-client backup --out` writes a file that `kvs-server --restore` starts from.
+#[test]
+fn cli_backup_and_restore() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut server = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--addr", "127.0.0.1:4161", "--data-dir"])
+        .arg(temp_dir.path().join("data"))
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+    let mut client = KvsClient::connect("127.0.0.1:4161").unwrap();
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    client.set("key2".to_owned(), "value2".to_owned()).unwrap();
+
+    let backup = temp_dir.path().join("kvs.backup");
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["backup", "--addr", "127.0.0.1:4161", "--out"])
+        .arg(&backup)
+        .assert()
+        .success()
+        .stdout(contains("engine: kvs\n"));
+    server.kill().expect("server exited before killed");
+    server.wait().unwrap();
+
+    let mut restored = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--addr", "127.0.0.1:4162", "--data-dir"])
+        .arg(temp_dir.path().join("restored"))
+        .arg("--restore")
+        .arg(&backup)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+    let mut client = KvsClient::connect("127.0.0.1:4162").unwrap();
+    assert_eq!(client.get("key2".to_owned()).unwrap(), Some("value2".to_owned()));
+    restored.kill().expect("server exited before killed");
+    restored.wait().unwrap();
+
+    // The restored directory now holds data, so restoring into it again fails.
+    Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--addr", "127.0.0.1:4162", "--data-dir"])
+        .arg(temp_dir.path().join("restored"))
+        .arg("--restore")
+        .arg(&backup)
+        .assert()
+        .failure()
+        .stderr(contains("is not empty"));
+}
@@ -1,6 +1,9 @@
 use assert_cmd::prelude::*;
+use kvs::{KvsClientBuilder, Manifest, ServerAddr};
+use predicates::prelude::*;
 use predicates::str::{contains, is_empty};
 use std::fs::{self, File};
+use std::os::unix::fs::PermissionsExt;
 use std::process::Command;
 use std::sync::mpsc;
 use std::thread;
@@ -212,6 +215,176 @@ fn cli_wrong_engine() {
     }
 }
 
+// `kvs-server` exits successfully on SIGTERM
+#[test]
+fn cli_graceful_shutdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut cmd = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = cmd
+        .args(["--engine", "kvs", "--addr", "127.0.0.1:4006"])
+        .current_dir(&temp_dir)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .assert()
+        .success();
+    assert!(child.wait().unwrap().success());
+}
+
+// `kvs-server --auth-file` only serves clients logging in with `--user/--password`.
+#[test]
+fn cli_authentication() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .arg("--hash-password")
+        .with_stdin()
+        .buffer("secret\n")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let hash = String::from_utf8(output.stdout).unwrap();
+    let credentials = temp_dir.path().join("credentials.json");
+    fs::write(
+        &credentials,
+        format!(
+            r#"{{"users": [{{"name": "alice", "secret": "{}", "rules": [{{"permissions": ["read", "write"]}}]}}]}}"#,
+            hash.trim()
+        ),
+    )
+    .unwrap();
+
+    let mut server = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = server
+        .args(["--addr", "127.0.0.1:4007", "--auth-file"])
+        .arg(&credentials)
+        .current_dir(&temp_dir)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--addr", "127.0.0.1:4007"])
+        .assert()
+        .failure()
+        .stderr(contains("Authentication required"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--addr", "127.0.0.1:4007"])
+        .args(["--user", "alice", "--password", "wrong"])
+        .assert()
+        .failure()
+        .stderr(contains("Invalid user name or password"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--addr", "127.0.0.1:4007"])
+        .args(["--user", "alice", "--password", "secret"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--addr", "127.0.0.1:4007"])
+        .args(["--user", "alice", "--password", "secret"])
+        .assert()
+        .success()
+        .stdout("value1\n");
+
+    child.kill().expect("server exited before killed");
+    child.wait().unwrap();
+}
+
+// Admin subcommands inspect the server and change runtime settings.
+#[test]
+fn cli_admin() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut server = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = server
+        .args(["--addr", "127.0.0.1:4008"])
+        .current_dir(&temp_dir)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "info", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .success()
+        .stdout(contains("engine: kvs"));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "stats", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .success()
+        .stdout(contains("keys: 1\n").and(contains("segments: ")));
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "compact", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "config", "set", "log-level", "warn", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "config", "get", "log-level", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .success()
+        .stdout("warn\n");
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "config", "get", "bogus", "--addr", "127.0.0.1:4008"])
+        .assert()
+        .failure()
+        .stderr(contains("Unknown setting"));
+
+    child.kill().expect("server exited before killed");
+    child.wait().unwrap();
+}
+
+// The server listens on a Unix socket with the requested mode and the client reaches it.
+#[test]
+fn cli_unix_socket() {
+    let temp_dir = TempDir::new().unwrap();
+    let socket = temp_dir.path().join("kvs.sock");
+    let addr = format!("unix:{}", socket.display());
+    let mut server = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = server
+        .args(["--addr", &addr, "--unix-socket-mode", "600"])
+        .current_dir(&temp_dir)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+    let mode = fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--addr", &addr])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["get", "key1", "--addr", &addr])
+        .assert()
+        .success()
+        .stdout("value1\n");
+
+    child.kill().expect("server exited before killed");
+    child.wait().unwrap();
+}
+
 fn cli_access_server(engine: &str, addr: &str) {
     let (sender, receiver) = mpsc::sync_channel(0);
     let temp_dir = TempDir::new().unwrap();
@@ -334,4 +507,209 @@ fn cli_access_server_kvs_engine() {
 #[test]
 fn cli_access_server_sled_engine() {
     cli_access_server("sled", "127.0.0.1:4005");
-}
\ No newline at end of file
+}
+// With JSON logs, a request tagged on the command line is logged inside its request span.
+#[test]
+fn cli_request_id_json_logs() {
+    let temp_dir = TempDir::new().unwrap();
+    let stderr_path = temp_dir.path().join("stderr");
+    let mut server = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = server
+        .args(["--addr", "127.0.0.1:4009", "--log-format", "json"])
+        .current_dir(&temp_dir)
+        .stderr(File::create(&stderr_path).unwrap())
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "config", "set", "log-level", "debug", "--addr", "127.0.0.1:4009"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key1", "value1", "--request-id", "cli-trace-1", "--addr", "127.0.0.1:4009"])
+        .assert()
+        .success();
+    // Lowering the level again turns the request spans off along with the events.
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["admin", "config", "set", "log-level", "warn", "--addr", "127.0.0.1:4009"])
+        .assert()
+        .success();
+    Command::cargo_bin("kvs-client")
+        .unwrap()
+        .args(["set", "key2", "value2", "--request-id", "cli-trace-2", "--addr", "127.0.0.1:4009"])
+        .assert()
+        .success();
+    thread::sleep(Duration::from_millis(200));
+    child.kill().expect("server exited before killed");
+    child.wait().unwrap();
+
+    let content = fs::read_to_string(&stderr_path).expect("unable to read from stderr file");
+    let lines: Vec<serde_json::Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("log line is not JSON"))
+        .collect();
+    let traced = lines
+        .iter()
+        .find(|line| {
+            line["spans"]
+                .as_array()
+                .is_some_and(|spans| spans.iter().any(|span| span["id"] == "cli-trace-1"))
+        })
+        .expect("no log line in the request span");
+    let spans = traced["spans"].as_array().unwrap();
+    assert_eq!(spans[0]["name"], "connection");
+    assert_eq!(spans[1]["name"], "request");
+    assert_eq!(spans[1]["command"], "set");
+    assert!(!content.contains("cli-trace-2"));
+}
+
+// `kvs-server --config` reads every setting from a TOML file, and command line flags take precedence.
+#[test]
+fn cli_config_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = temp_dir.path().join("kvs.toml");
+    fs::write(
+        &config,
+        r#"
+[server]
+addr = "127.0.0.1:4140"
+data_dir = "data"
+log_level = "warn"
+
+[engine]
+name = "kvs"
+compaction_threshold = 4096
+
+[pool]
+kind = "shared_queue"
+threads = 2
+
+[network]
+slowlog_threshold = "off"
+slowlog_max_len = 7
+"#,
+    )
+    .unwrap();
+
+    let mut server = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = server
+        .arg("--config")
+        .arg(&config)
+        .args(["--slowlog-max-len", "9"])
+        .current_dir(&temp_dir)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    let config_get = |name: &str, expected: &str| {
+        Command::cargo_bin("kvs-client")
+            .unwrap()
+            .args(["admin", "config", "get", name, "--addr", "127.0.0.1:4140"])
+            .assert()
+            .success()
+            .stdout(format!("{}\n", expected));
+    };
+    config_get("log-level", "warn");
+    config_get("compaction-threshold", "4096");
+    config_get("slowlog-threshold", "off");
+    config_get("slowlog-max-len", "9");
+    assert_eq!(Manifest::load(temp_dir.path().join("data")).unwrap().unwrap().engine, "kvs");
+
+    child.kill().expect("server exited before killed");
+    child.wait().unwrap();
+
+    fs::write(&config, "[server]\nbogus = 1\n").unwrap();
+    Command::cargo_bin("kvs-server")
+        .unwrap()
+        .arg("--config")
+        .arg(&config)
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("Invalid config file"));
+}
+
+// SIGHUP reloads the runtime settings and the credential file; an invalid file keeps the current settings.
+#[test]
+fn cli_config_reload() {
+    let temp_dir = TempDir::new().unwrap();
+    let output = Command::cargo_bin("kvs-server")
+        .unwrap()
+        .arg("--hash-password")
+        .with_stdin()
+        .buffer("secret\n")
+        .output()
+        .unwrap();
+    let hash = String::from_utf8(output.stdout).unwrap();
+    let credentials = temp_dir.path().join("credentials.json");
+    let write_credentials = |permissions: &str| {
+        fs::write(
+            &credentials,
+            format!(
+                r#"{{"users": [{{"name": "alice", "secret": "{}", "rules": [{{"permissions": {}}}]}}]}}"#,
+                hash.trim(),
+                permissions
+            ),
+        )
+        .unwrap();
+    };
+    write_credentials(r#"["read", "write", "admin"]"#);
+    let config = temp_dir.path().join("kvs.toml");
+    let write_config = |log_level: &str, max_len: usize| {
+        fs::write(
+            &config,
+            format!(
+                "[server]\nlog_level = \"{}\"\n[network]\nslowlog_max_len = {}\n[auth]\nfile = \"credentials.json\"\n",
+                log_level, max_len
+            ),
+        )
+        .unwrap();
+    };
+    write_config("info", 5);
+
+    let mut server = Command::cargo_bin("kvs-server").unwrap();
+    let mut child = server
+        .arg("--config")
+        .arg(&config)
+        .args(["--addr", "127.0.0.1:4141"])
+        .current_dir(&temp_dir)
+        .spawn()
+        .unwrap();
+    thread::sleep(Duration::from_secs(1));
+
+    let mut client = KvsClientBuilder::new()
+        .addr("127.0.0.1:4141".parse::<ServerAddr>().unwrap())
+        .credentials("alice".to_owned(), "secret".to_owned())
+        .build()
+        .unwrap();
+    client.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    assert_eq!(client.config_get("slowlog-max-len".to_owned()).unwrap(), "5");
+
+    let hangup = || {
+        Command::new("kill")
+            .args(["-HUP", &child.id().to_string()])
+            .assert()
+            .success();
+        thread::sleep(Duration::from_secs(2));
+    };
+    write_config("debug", 20);
+    write_credentials(r#"["read", "admin"]"#);
+    hangup();
+    assert_eq!(client.config_get("slowlog-max-len".to_owned()).unwrap(), "20");
+    assert_eq!(client.config_get("log-level".to_owned()).unwrap(), "debug");
+    // The logged-in connection picks up the new permissions.
+    assert_eq!(client.get("key1".to_owned()).unwrap(), Some("value1".to_owned()));
+    let err = client.set("key2".to_owned(), "value2".to_owned()).unwrap_err();
+    assert!(err.to_string().contains("Permission denied"), "{}", err);
+
+    fs::write(&config, "not toml").unwrap();
+    hangup();
+    assert_eq!(client.config_get("slowlog-max-len".to_owned()).unwrap(), "20");
+
+    child.kill().expect("server exited before killed");
+    child.wait().unwrap();
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::start_server;
+use kvs::{KvsClient, KvsClientBuilder, KvsClientPool, Result, ServerConfig};
+use std::net::{SocketAddr, TcpListener};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The client fails over to the next seed when the first one is down.
+#[test]
+fn failover_between_seeds() -> Result<()> {
+    let live: SocketAddr = "127.0.0.1:4020".parse().unwrap();
+    let _dir = start_server(live, ServerConfig::default())?;
+
+    let mut client = KvsClientBuilder::new()
+        .addr("127.0.0.1:4029".parse::<SocketAddr>().unwrap())
+        .addr(live)
+        .connect_timeout(Duration::from_secs(1))
+        .build()?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+    Ok(())
+}
+
+// A connection closed by the server is replaced transparently.
+#[test]
+fn reconnect_after_server_closed_connection() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4021".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        idle_timeout: Some(Duration::from_millis(300)),
+        ..ServerConfig::default()
+    })?;
+
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    thread::sleep(Duration::from_secs(1));
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+    client.remove("key".to_owned())?;
+    Ok(())
+}
+
+// Connecting retries with backoff until the server comes up.
+#[test]
+fn retry_until_server_starts() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4022".parse().unwrap();
+    let starter = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(500));
+        start_server(addr, ServerConfig::default())
+    });
+
+    let mut client = KvsClientBuilder::new()
+        .addr(addr)
+        .max_retries(10)
+        .backoff(Duration::from_millis(50), Duration::from_millis(400))
+        .build()?;
+    assert_eq!(client.get("key".to_owned())?, None);
+    let _dir = starter.join().unwrap()?;
+    Ok(())
+}
+
+// Without retries the error is returned right away.
+#[test]
+fn no_retry_by_default() {
+    assert!(KvsClient::connect("127.0.0.1:4023").is_err());
+}
+
+// A server that never answers trips the read timeout.
+#[test]
+fn read_timeout() -> Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:4024")?;
+    let mut client = KvsClientBuilder::new()
+        .addr(listener.local_addr()?)
+        .read_timeout(Duration::from_millis(200))
+        .max_retries(1)
+        .backoff(Duration::from_millis(10), Duration::from_millis(10))
+        .build()?;
+
+    let start = Instant::now();
+    assert!(client.get("key".to_owned()).is_err());
+    assert!(start.elapsed() < Duration::from_secs(2));
+    Ok(())
+}
+
+// Connections are reused, bounded, and the pool can be shared between threads.
+#[test]
+fn client_pool() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4025".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+
+    let pool = KvsClientPool::new(KvsClientBuilder::new().addr(addr), 4)
+        .with_checkout_timeout(Duration::from_millis(200))
+        .with_test_on_checkout(true);
+
+    let handles: Vec<_> = (0..8)
+        .map(|i| {
+            let pool = pool.clone();
+            thread::spawn(move || -> Result<()> {
+                for j in 0..20 {
+                    let mut client = pool.get()?;
+                    client.set(format!("key{}-{}", i, j), "value".to_owned())?;
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap()?;
+    }
+    assert!(pool.open_count() <= 4);
+
+    let held: Vec<_> = (0..4).map(|_| pool.get()).collect::<Result<_>>()?;
+    assert!(pool.get().is_err());
+    drop(held);
+    assert_eq!(pool.idle_count(), 4);
+    assert_eq!(pool.get()?.get("key7-19".to_owned())?, Some("value".to_owned()));
+    Ok(())
+}
+
+// Idle connections older than max_idle are closed.
+#[test]
+fn client_pool_evicts_idle() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4026".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+
+    let pool = KvsClientPool::new(KvsClientBuilder::new().addr(addr), 2)
+        .with_max_idle(Duration::from_millis(200));
+    pool.get()?.ping()?;
+    assert_eq!(pool.open_count(), 1);
+    thread::sleep(Duration::from_millis(400));
+    pool.get()?.ping()?;
+    assert_eq!(pool.open_count(), 1);
+    assert_eq!(pool.idle_count(), 1);
+
+    // Returning a connection also closes the ones that have been idle too long.
+    let mut busy = pool.get()?;
+    pool.get()?.ping()?;
+    assert_eq!(pool.open_count(), 2);
+    thread::sleep(Duration::from_millis(400));
+    busy.ping()?;
+    drop(busy);
+    assert_eq!(pool.open_count(), 1);
+    assert_eq!(pool.idle_count(), 1);
+    Ok(())
+}
@@ -0,0 +1,226 @@
+mod common;
+
+use common::start_server_in;
+use kvs::thread_pool::{RayonThreadPool, ThreadPool};
+use kvs::{
+    ClusterConfig, ClusterInfo, KvStore, KvsClient, KvsClientBuilder, KvsServer, Result, ServerAddr, ServerConfig,
+    ShutdownHandle
+};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+// Starts a cluster with one node per address, using short timeouts to keep the tests fast.
+fn start_cluster(addrs: &[SocketAddr]) -> Result<Vec<(TempDir, ShutdownHandle)>> {
+    let peers: Vec<(u64, ServerAddr)> = addrs.iter().enumerate().map(|(i, addr)| (i as u64 + 1, (*addr).into())).collect();
+    let mut nodes = Vec::new();
+    for (i, addr) in addrs.iter().enumerate() {
+        let temp_dir = TempDir::new()?;
+        let mut cluster = ClusterConfig::new(i as u64 + 1, peers.clone(), temp_dir.path().join("raft"));
+        cluster.election_timeout = Duration::from_millis(300);
+        cluster.heartbeat_interval = Duration::from_millis(50);
+        let config = ServerConfig { cluster: Some(cluster), ..ServerConfig::default() };
+        let shutdown = start_server_in(*addr, config, temp_dir.path())?;
+        nodes.push((temp_dir, shutdown));
+    }
+    Ok(nodes)
+}
+
+fn cluster_info(addr: SocketAddr) -> Result<ClusterInfo> {
+    Ok(KvsClient::connect(addr)?.info()?.cluster.expect("cluster info"))
+}
+
+// Waits until exactly one of the nodes is the leader and returns its index.
+fn wait_for_leader(addrs: &[SocketAddr]) -> Result<usize> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let mut leaders = Vec::new();
+        for (i, addr) in addrs.iter().enumerate() {
+            if cluster_info(*addr)?.role == "leader" {
+                leaders.push(i);
+            }
+        }
+        if leaders.len() == 1 {
+            return Ok(leaders[0]);
+        }
+        assert!(Instant::now() < deadline, "no single leader elected: {:?}", leaders);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// Waits until every node has applied the leader's committed entries.
+fn wait_for_apply(addrs: &[SocketAddr], leader: SocketAddr) -> Result<()> {
+    let commit_index = cluster_info(leader)?.commit_index;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    for addr in addrs {
+        loop {
+            let info = cluster_info(*addr)?;
+            if info.last_applied >= commit_index {
+                break;
+            }
+            assert!(Instant::now() < deadline, "node {} did not catch up: {:?}", addr, info);
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+    Ok(())
+}
+
+// Requests sent to a follower are redirected to the leader, and committed writes reach every node.
+#[test]
+fn follower_redirects_to_leader() -> Result<()> {
+    let addrs: Vec<SocketAddr> = (4100..4103).map(|port| format!("127.0.0.1:{}", port).parse().unwrap()).collect();
+    let _nodes = start_cluster(&addrs)?;
+    let leader = wait_for_leader(&addrs)?;
+    let follower = (leader + 1) % addrs.len();
+
+    let info = cluster_info(addrs[follower])?;
+    assert_eq!(info.role, "follower");
+    assert_eq!(info.leader, Some(addrs[leader].to_string()));
+
+    let mut client = KvsClient::connect(addrs[follower])?;
+    for i in 0..20 {
+        client.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    client.remove("key0".to_owned())?;
+    assert_eq!(client.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(client.get("key0".to_owned())?, None);
+    assert!(client.remove("missing".to_owned()).is_err());
+
+    wait_for_apply(&addrs, addrs[leader])?;
+    let leader_info = cluster_info(addrs[leader])?;
+    for addr in &addrs {
+        let info = cluster_info(*addr)?;
+        assert_eq!(info.term, leader_info.term);
+        assert_eq!(info.last_index, leader_info.last_index);
+    }
+    Ok(())
+}
+
+// After the leader shuts down the remaining majority elects a new leader that keeps every committed write.
+#[test]
+fn new_leader_after_failure() -> Result<()> {
+    let addrs: Vec<SocketAddr> = (4103..4106).map(|port| format!("127.0.0.1:{}", port).parse().unwrap()).collect();
+    let nodes = start_cluster(&addrs)?;
+    let leader = wait_for_leader(&addrs)?;
+    let old_term = cluster_info(addrs[leader])?.term;
+
+    let mut client = KvsClient::connect(addrs[leader])?;
+    client.set("key1".to_owned(), "value1".to_owned())?;
+    client.set("key2".to_owned(), "value2".to_owned())?;
+    drop(client);
+
+    nodes[leader].1.shutdown();
+    let remaining: Vec<SocketAddr> = addrs.iter().enumerate().filter(|(i, _)| *i != leader).map(|(_, addr)| *addr).collect();
+    let new_leader = remaining[wait_for_leader(&remaining)?];
+    assert!(cluster_info(new_leader)?.term > old_term);
+
+    let mut client = remaining.iter().fold(KvsClientBuilder::new(), |builder, addr| builder.addr(*addr)).build()?;
+    assert_eq!(client.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(client.get("key2".to_owned())?, Some("value2".to_owned()));
+    client.set("key3".to_owned(), "value3".to_owned())?;
+    assert_eq!(client.get("key3".to_owned())?, Some("value3".to_owned()));
+    Ok(())
+}
+
+// Cluster nodes reject configurations that clients could not use.
+#[test]
+fn rejects_invalid_cluster_config() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let addr: SocketAddr = "127.0.0.1:4106".parse().unwrap();
+    let config = ServerConfig {
+        cluster: Some(ClusterConfig::new(1, vec![(1, addr.into())], temp_dir.path().join("raft"))),
+        replica_of: Some(KvsClientBuilder::new().addr(addr)),
+        ..ServerConfig::default()
+    };
+    let server = KvsServer::new(KvStore::open(temp_dir.path())?, RayonThreadPool::new(1)?).with_config(config);
+    assert!(server.run(addr).is_err());
+    Ok(())
+}
+
+// A follower that missed more entries than the leader keeps in memory catches up from the
+// log on disk, and a restarted node resumes from its recorded applied index without a leader.
+#[test]
+fn catch_up_from_disk_and_resume_applied() -> Result<()> {
+    let addrs: Vec<SocketAddr> = (4172..4175).map(|port| format!("127.0.0.1:{}", port).parse().unwrap()).collect();
+    let peers: Vec<(u64, ServerAddr)> = addrs.iter().enumerate().map(|(i, addr)| (i as u64 + 1, (*addr).into())).collect();
+    let dirs = (0..3).map(|_| TempDir::new()).collect::<std::io::Result<Vec<_>>>()?;
+    let config = |i: usize| {
+        let mut cluster = ClusterConfig::new(i as u64 + 1, peers.clone(), dirs[i].path().join("raft"));
+        cluster.election_timeout = Duration::from_millis(300);
+        cluster.heartbeat_interval = Duration::from_millis(50);
+        cluster.log_cache_entries = 16;
+        ServerConfig { cluster: Some(cluster), ..ServerConfig::default() }
+    };
+    let mut nodes = (0..3).map(|i| start_server_in(addrs[i], config(i), dirs[i].path())).collect::<Result<Vec<_>>>()?;
+    let leader = wait_for_leader(&addrs)?;
+
+    let lagging = (leader + 1) % 3;
+    nodes[lagging].shutdown();
+    thread::sleep(Duration::from_millis(500));
+    let mut client = KvsClient::connect(addrs[leader])?;
+    for i in 0..100 {
+        client.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    nodes[lagging] = start_server_in(addrs[lagging], config(lagging), dirs[lagging].path())?;
+    wait_for_apply(&addrs, addrs[leader])?;
+    let applied = cluster_info(addrs[lagging])?.last_applied;
+    assert!(applied > 100, "{}", applied);
+
+    // Alone, the node cannot elect a leader or commit anything, so everything it reports as
+    // applied comes from its own record.
+    for node in &nodes {
+        node.shutdown();
+    }
+    thread::sleep(Duration::from_secs(1));
+    let _node = start_server_in(addrs[lagging], config(lagging), dirs[lagging].path())?;
+    let info = cluster_info(addrs[lagging])?;
+    assert_ne!(info.role, "leader");
+    assert!(info.last_applied >= applied, "{:?}", info);
+    Ok(())
+}
+
+// Entries applied on every node are dropped from the Raft log, and the nodes restart from the
+// compacted log and keep serving.
+#[test]
+fn compact_log_applied_everywhere() -> Result<()> {
+    let addrs: Vec<SocketAddr> = (4177..4180).map(|port| format!("127.0.0.1:{}", port).parse().unwrap()).collect();
+    let peers: Vec<(u64, ServerAddr)> = addrs.iter().enumerate().map(|(i, addr)| (i as u64 + 1, (*addr).into())).collect();
+    let dirs = (0..3).map(|_| TempDir::new()).collect::<std::io::Result<Vec<_>>>()?;
+    let config = |i: usize| {
+        let mut cluster = ClusterConfig::new(i as u64 + 1, peers.clone(), dirs[i].path().join("raft"));
+        cluster.election_timeout = Duration::from_millis(300);
+        cluster.heartbeat_interval = Duration::from_millis(50);
+        cluster.log_compact_entries = 10;
+        ServerConfig { cluster: Some(cluster), ..ServerConfig::default() }
+    };
+    let nodes = (0..3).map(|i| start_server_in(addrs[i], config(i), dirs[i].path())).collect::<Result<Vec<_>>>()?;
+    let leader = wait_for_leader(&addrs)?;
+    let mut client = KvsClient::connect(addrs[leader])?;
+    for i in 0..50 {
+        client.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    wait_for_apply(&addrs, addrs[leader])?;
+    // Let the heartbeats tell the followers how far every node has got.
+    thread::sleep(Duration::from_millis(300));
+    for node in &nodes {
+        node.shutdown();
+    }
+    thread::sleep(Duration::from_secs(1));
+
+    // The first record of a compacted log is the last entry dropped from it.
+    for dir in &dirs {
+        let log = std::fs::read_to_string(dir.path().join("raft").join("raft.log"))?;
+        let first: serde_json::Value = serde_json::Deserializer::from_str(&log).into_iter().next().unwrap()?;
+        assert!(first["index"].as_u64().unwrap() > 10, "{}", first);
+    }
+
+    let _nodes = (0..3).map(|i| start_server_in(addrs[i], config(i), dirs[i].path())).collect::<Result<Vec<_>>>()?;
+    let leader = wait_for_leader(&addrs)?;
+    let mut client = KvsClient::connect(addrs[leader])?;
+    client.set("key50".to_owned(), "value50".to_owned())?;
+    wait_for_apply(&addrs, addrs[leader])?;
+    assert_eq!(client.get("key0".to_owned())?, Some("value0".to_owned()));
+    assert_eq!(client.get("key50".to_owned())?, Some("value50".to_owned()));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+// Helpers shared by the integration tests; each test binary uses only some of them.
+#![allow(dead_code)]
+
+use kvs::thread_pool::{RayonThreadPool, ThreadPool};
+use kvs::{KvStore, KvsEngine, KvsServer, Result, ServerConfig, ShutdownHandle};
+use std::net::{SocketAddr, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+// How long a server may take to start listening.
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Starts a server over a `KvStore` in a new temporary directory.
+pub fn start_server(addr: SocketAddr, config: ServerConfig) -> Result<TempDir> {
+    let temp_dir = TempDir::new()?;
+    start_server_in(addr, config, temp_dir.path())?;
+    Ok(temp_dir)
+}
+
+// Starts a server over a `KvStore` in `dir`, keeping any data already there.
+pub fn start_server_in(addr: SocketAddr, config: ServerConfig, dir: &Path) -> Result<ShutdownHandle> {
+    start_engine(addr, KvStore::open(dir)?, config)
+}
+
+// Runs a server over `engine` in a background thread and returns once every address it
+// listens on accepts connections.
+pub fn start_engine<E: KvsEngine>(addr: SocketAddr, engine: E, config: ServerConfig) -> Result<ShutdownHandle> {
+    let extra: Vec<SocketAddr> = config.http_addr.into_iter().chain(config.metrics_addr).collect();
+    let server = KvsServer::new(engine, RayonThreadPool::new(2)?).with_config(config);
+    let shutdown = server.shutdown_handle();
+    thread::spawn(move || server.run(addr).unwrap());
+    wait_until_listening(addr);
+    for addr in extra {
+        wait_until_listening(addr);
+    }
+    Ok(shutdown)
+}
+
+// Retries connecting to `addr` until it succeeds.
+pub fn wait_until_listening(addr: SocketAddr) {
+    let deadline = Instant::now() + START_TIMEOUT;
+    while TcpStream::connect(addr).is_err() {
+        assert!(Instant::now() < deadline, "server on {} did not start", addr);
+        thread::sleep(Duration::from_millis(10));
+    }
+}
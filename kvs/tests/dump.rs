@@ -0,0 +1,134 @@
+mod common;
+
+use common::start_server;
+use assert_cmd::prelude::*;
+use kvs::{
+    DumpFormat, DumpReader, DumpWriter, ImportMode, ImportStats, KvsClient, KvsEngine, Result,
+    ServerConfig, SledKvsEngine
+};
+use predicates::str::contains;
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::process::Command;
+use tempfile::TempDir;
+
+// Keys and values with separators, quotes, newlines and non-ASCII text survive both formats.
+#[test]
+fn dump_format_round_trip() -> Result<()> {
+    let pairs = vec![
+        ("".to_owned(), "empty key".to_owned()),
+        ("a,b".to_owned(), "say \"hi\"".to_owned()),
+        ("line".to_owned(), "one\ntwo\r\nthree".to_owned()),
+        ("键".to_owned(), "".to_owned()),
+    ];
+    for format in [DumpFormat::JsonLines, DumpFormat::Csv] {
+        let mut writer = DumpWriter::new(Vec::new(), format);
+        for (key, value) in &pairs {
+            writer.write(key, value)?;
+        }
+        let dump = writer.finish()?;
+        let read = DumpReader::new(Cursor::new(dump), format).collect::<Result<Vec<_>>>()?;
+        assert_eq!(read, pairs, "{}", format);
+    }
+
+    // A CSV dump without the header and a record with a missing field are rejected with the line number.
+    let err = DumpReader::new(Cursor::new("a,1\n"), DumpFormat::Csv).next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("header"), "{}", err);
+    let mut reader = DumpReader::new(Cursor::new("key,value\na,1\n\nb\n"), DumpFormat::Csv);
+    assert_eq!(reader.next().unwrap()?, ("a".to_owned(), "1".to_owned()));
+    let err = reader.next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("line 4"), "{}", err);
+    let err = DumpReader::new(Cursor::new("{\"key\":\"a\"}\n"), DumpFormat::JsonLines).next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("line 1"), "{}", err);
+    Ok(())
+}
+
+// Export pages through a prefix on a live server, and import either overwrites or keeps existing keys.
+#[test]
+fn export_import_live_server() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4150".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+    let mut client = KvsClient::connect(addr)?;
+    for i in 0..1500 {
+        client.set(format!("user:{:04}", i), format!("value{}", i))?;
+    }
+    client.set("other".to_owned(), "value".to_owned())?;
+
+    let page = client.export("user:".to_owned(), None, 5000)?;
+    assert_eq!(page.entries.len(), 1000);
+    assert_eq!(page.entries[0], ("user:0000".to_owned(), "value0".to_owned()));
+    let page = client.export("user:".to_owned(), page.next, 5000)?;
+    assert_eq!((page.entries.len(), page.next), (500, None));
+
+    let entries = vec![
+        ("user:0000".to_owned(), "imported".to_owned()),
+        ("new".to_owned(), "imported".to_owned()),
+    ];
+    let stats = client.import(entries.clone(), ImportMode::SkipExisting)?;
+    assert_eq!(stats, ImportStats { written: 1, skipped: 1 });
+    assert_eq!(client.get("user:0000".to_owned())?, Some("value0".to_owned()));
+    let stats = client.import(entries, ImportMode::Overwrite)?;
+    assert_eq!(stats, ImportStats { written: 2, skipped: 0 });
+    assert_eq!(client.get("user:0000".to_owned())?, Some("imported".to_owned()));
+
+    // `kvs-admin export --addr` streams the filtered keys, and importing them into an
+    // offline sled directory reproduces the data.
+    let temp_dir = TempDir::new()?;
+    let dump = temp_dir.path().join("users.csv");
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["export", "--addr", &addr.to_string(), "--prefix", "user:", "--format", "csv"])
+        .arg("--output")
+        .arg(&dump)
+        .assert()
+        .success()
+        .stderr(contains("Exported 1500 keys"));
+    let sled_dir = temp_dir.path().join("sled");
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["import", "--to", &format!("sled:{}", sled_dir.display()), "--format", "csv", "--batch-size", "64"])
+        .arg("--input")
+        .arg(&dump)
+        .assert()
+        .success()
+        .stdout(contains("written: 1500\nskipped: 0\n"));
+    let sled = SledKvsEngine::new(sled::open(&sled_dir)?);
+    assert_eq!(sled.get("user:0000".to_owned())?, Some("imported".to_owned()));
+    assert_eq!(sled.get("other".to_owned())?, None);
+    assert_eq!(sled.scan("", None, 5000)?.len(), 1500);
+    Ok(())
+}
+
+// `kvs-admin import --addr` writes a JSON Lines dump from stdin in batches, skipping existing keys on request.
+#[test]
+fn cli_import_to_server() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4151".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key1".to_owned(), "kept".to_owned())?;
+
+    let lines: Vec<String> = (0..10).map(|i| format!("{{\"key\":\"key{}\",\"value\":\"value{}\"}}", i, i)).collect();
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["import", "--addr", &addr.to_string(), "--mode", "skip-existing", "--batch-size", "3"])
+        .with_stdin()
+        .buffer(lines.join("\n"))
+        .assert()
+        .success()
+        .stdout(contains("written: 9\nskipped: 1\n"));
+    assert_eq!(client.get("key1".to_owned())?, Some("kept".to_owned()));
+    assert_eq!(client.get("key9".to_owned())?, Some("value9".to_owned()));
+
+    let temp_dir = TempDir::new()?;
+    // Exporting needs either a data directory or a server, and a missing directory is an error.
+    Command::cargo_bin("kvs-admin").unwrap().args(["export"]).assert().failure();
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .arg("export")
+        .arg("--from")
+        .arg(format!("kvs:{}", temp_dir.path().join("missing").display()))
+        .assert()
+        .failure()
+        .stderr(contains("No data directory"));
+    Ok(())
+}
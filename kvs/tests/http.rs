@@ -0,0 +1,263 @@
+mod common;
+
+use kvs::{AuthConfig, KvsClient, Result, ServerConfig};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+use tempfile::TempDir;
+
+// A minimal HTTP/1.1 client reusing one keep-alive connection.
+struct HttpClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    authorization: Option<String>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Option<Value>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl HttpClient {
+    fn connect(addr: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        Ok(HttpClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            authorization: None,
+        })
+    }
+
+    // Sends Basic credentials with every following request.
+    fn basic_auth(&mut self, user: &str, password: &str) {
+        use base64::Engine;
+        let token = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", user, password));
+        self.authorization = Some(format!("Basic {}", token));
+    }
+
+    fn send(&mut self, method: &str, target: &str, body: Option<Value>) -> Result<()> {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let mut req = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n",
+            method,
+            target,
+            body.len()
+        );
+        if let Some(authorization) = &self.authorization {
+            req.push_str(&format!("Authorization: {}\r\n", authorization));
+        }
+        req.push_str("\r\n");
+        req.push_str(&body);
+        self.writer.write_all(req.as_bytes())?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Response> {
+        let mut status_line = String::new();
+        self.reader.read_line(&mut status_line)?;
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            self.reader.read_line(&mut line)?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line.split_once(':').unwrap();
+            headers.push((name.to_owned(), value.trim().to_owned()));
+        }
+        let mut response = Response { status, headers, body: None };
+        let len: usize = response.header("Content-Length").unwrap().parse().unwrap();
+        if len > 0 {
+            let mut body = vec![0; len];
+            self.reader.read_exact(&mut body)?;
+            response.body = Some(serde_json::from_slice(&body)?);
+        }
+        Ok(response)
+    }
+
+    fn call(&mut self, method: &str, target: &str, body: Option<Value>) -> Result<Response> {
+        self.send(method, target, body)?;
+        self.read()
+    }
+}
+
+fn start_server(addr: SocketAddr, http_addr: SocketAddr, auth: bool) -> Result<TempDir> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let auth = if auth {
+        let credentials = json!({
+            "users": [
+                {
+                    "name": "admin",
+                    "secret": AuthConfig::hash_secret("admin-password")?,
+                    "rules": [{ "permissions": ["read", "write", "admin"] }]
+                },
+                {
+                    "name": "app",
+                    "secret": AuthConfig::hash_secret("app-token")?,
+                    "rules": [{ "prefix": "app:", "permissions": ["read", "write"] }]
+                }
+            ]
+        });
+        let path = temp_dir.path().join("credentials.json");
+        fs::write(&path, credentials.to_string())?;
+        Some(AuthConfig::from_file(&path)?)
+    } else {
+        None
+    };
+    let config = ServerConfig { http_addr: Some(http_addr), auth, ..ServerConfig::default() };
+    common::start_server_in(addr, config, temp_dir.path())?;
+    Ok(temp_dir)
+}
+
+// Keys can be written, read and removed, missing keys map to 404.
+#[test]
+fn http_key_operations() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4060".parse().unwrap();
+    let http_addr: SocketAddr = "127.0.0.1:4061".parse().unwrap();
+    let _dir = start_server(addr, http_addr, false)?;
+    let mut client = HttpClient::connect(http_addr)?;
+
+    let resp = client.call("PUT", "/keys/key1", Some(json!({ "value": "value1" })))?;
+    assert_eq!(resp.status, 204);
+    let resp = client.call("GET", "/keys/key1", None)?;
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.body, Some(json!({ "key": "key1", "value": "value1" })));
+
+    // Keys are percent-decoded.
+    client.call("PUT", "/keys/a%20b%2Fc", Some(json!({ "value": "spaced" })))?;
+    let resp = client.call("GET", "/keys/a%20b%2Fc", None)?;
+    assert_eq!(resp.body.unwrap()["key"], "a b/c");
+
+    let resp = client.call("GET", "/keys/missing", None)?;
+    assert_eq!(resp.status, 404);
+    assert_eq!(resp.body.unwrap()["error"], "Key not found");
+    assert_eq!(client.call("DELETE", "/keys/key1", None)?.status, 204);
+    assert_eq!(client.call("DELETE", "/keys/key1", None)?.status, 404);
+    assert_eq!(client.call("GET", "/keys/key1", None)?.status, 404);
+
+    // The gateway shares the keyspace with the native protocol.
+    let mut native = KvsClient::connect(addr)?;
+    native.set("shared".to_owned(), "native".to_owned())?;
+    let resp = client.call("GET", "/keys/shared", None)?;
+    assert_eq!(resp.body.unwrap()["value"], "native");
+    Ok(())
+}
+
+// Malformed requests get proper 4xx statuses without closing the connection.
+#[test]
+fn http_errors() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4062".parse().unwrap();
+    let http_addr: SocketAddr = "127.0.0.1:4063".parse().unwrap();
+    let _dir = start_server(addr, http_addr, false)?;
+    let mut client = HttpClient::connect(http_addr)?;
+
+    assert_eq!(client.call("GET", "/nowhere", None)?.status, 404);
+    let resp = client.call("POST", "/keys/key", None)?;
+    assert_eq!(resp.status, 405);
+    assert_eq!(resp.header("Allow"), Some("GET, PUT, DELETE"));
+    assert_eq!(client.call("GET", "/admin/flush", None)?.status, 405);
+    assert_eq!(client.call("PUT", "/keys/key", Some(json!({ "val": 1 })))?.status, 400);
+    assert_eq!(client.call("GET", "/keys?limit=0", None)?.status, 400);
+    assert_eq!(client.call("GET", "/admin/ping", None)?.status, 200);
+
+    // Connection: close is honoured after the response.
+    client.writer.write_all(b"GET /admin/ping HTTP/1.1\r\nConnection: close\r\n\r\n")?;
+    let resp = client.read()?;
+    assert_eq!(resp.status, 200);
+    assert_eq!(resp.header("Connection"), Some("close"));
+    let mut rest = Vec::new();
+    client.reader.read_to_end(&mut rest)?;
+    assert!(rest.is_empty());
+    Ok(())
+}
+
+// Listing pages through keys by prefix with limit and the returned cursor.
+#[test]
+fn http_list_keys() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4064".parse().unwrap();
+    let http_addr: SocketAddr = "127.0.0.1:4065".parse().unwrap();
+    let _dir = start_server(addr, http_addr, false)?;
+    let mut client = HttpClient::connect(http_addr)?;
+    for i in 0..5 {
+        client.call("PUT", &format!("/keys/user:{}", i), Some(json!({ "value": "x" })))?;
+    }
+    client.call("PUT", "/keys/other", Some(json!({ "value": "x" })))?;
+
+    let mut keys = Vec::new();
+    let mut target = "/keys?prefix=user%3A&limit=2".to_owned();
+    loop {
+        let body = client.call("GET", &target, None)?.body.unwrap();
+        keys.extend(body["keys"].as_array().unwrap().iter().map(|k| k.as_str().unwrap().to_owned()));
+        match body["next"].as_str() {
+            Some(next) => target = format!("/keys?prefix=user:&limit=2&after={}", next),
+            None => break,
+        }
+    }
+    assert_eq!(keys, vec!["user:0", "user:1", "user:2", "user:3", "user:4"]);
+
+    let body = client.call("GET", "/keys", None)?.body.unwrap();
+    assert_eq!(body["keys"].as_array().unwrap().len(), 6);
+    assert_eq!(body["next"], Value::Null);
+    Ok(())
+}
+
+// Admin endpoints report server stats and flush the engine.
+#[test]
+fn http_admin() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4066".parse().unwrap();
+    let http_addr: SocketAddr = "127.0.0.1:4067".parse().unwrap();
+    let _dir = start_server(addr, http_addr, false)?;
+    let mut client = HttpClient::connect(http_addr)?;
+
+    let resp = client.call("GET", "/admin/ping", None)?;
+    assert_eq!(resp.body, Some(json!({ "status": "ok" })));
+    let info = client.call("GET", "/admin/info", None)?.body.unwrap();
+    assert!(info["version"].is_string());
+    assert!(info["total_commands"].as_u64().unwrap() >= 2);
+    assert_eq!(client.call("POST", "/admin/flush", None)?.status, 204);
+    Ok(())
+}
+
+// With authentication enabled the gateway uses HTTP Basic credentials.
+#[test]
+fn http_basic_auth() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4068".parse().unwrap();
+    let http_addr: SocketAddr = "127.0.0.1:4069".parse().unwrap();
+    let _dir = start_server(addr, http_addr, true)?;
+    let mut client = HttpClient::connect(http_addr)?;
+
+    let resp = client.call("GET", "/keys/app:key", None)?;
+    assert_eq!(resp.status, 401);
+    assert!(resp.header("WWW-Authenticate").unwrap().starts_with("Basic"));
+    assert_eq!(client.call("GET", "/admin/ping", None)?.status, 200);
+
+    client.basic_auth("app", "wrong");
+    assert_eq!(client.call("GET", "/keys/app:key", None)?.status, 401);
+
+    client.basic_auth("app", "app-token");
+    let resp = client.call("PUT", "/keys/app:key", Some(json!({ "value": "v" })))?;
+    assert_eq!(resp.status, 204);
+    assert_eq!(client.call("GET", "/keys/app:key", None)?.status, 200);
+    assert_eq!(client.call("GET", "/keys/other", None)?.status, 403);
+    assert_eq!(client.call("GET", "/admin/info", None)?.status, 403);
+
+    client.basic_auth("admin", "admin-password");
+    assert_eq!(client.call("GET", "/admin/info", None)?.status, 200);
+    assert_eq!(client.call("GET", "/keys/other", None)?.status, 404);
+    Ok(())
+}
@@ -81,6 +81,46 @@ fn remove_key() -> Result<()> {
     Ok(())
 }
 
+// Should list keys in order by prefix, page by page
+#[test]
+fn scan_keys() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    for key in ["b2", "a1", "b1", "b3", "c1"] {
+        store.set(key.to_owned(), "value".to_owned())?;
+    }
+    store.remove("b3".to_owned())?;
+
+    assert_eq!(store.scan("", None, 10)?, vec!["a1", "b1", "b2", "c1"]);
+    assert_eq!(store.scan("b", None, 10)?, vec!["b1", "b2"]);
+    assert_eq!(store.scan("b", None, 1)?, vec!["b1"]);
+    assert_eq!(store.scan("b", Some("b1"), 10)?, vec!["b2"]);
+    assert_eq!(store.scan("b", Some("a"), 10)?, vec!["b1", "b2"]);
+    assert!(store.scan("d", None, 10)?.is_empty());
+
+    Ok(())
+}
+
+// A store opened read-only serves reads, refuses writes and adds no log file.
+#[test]
+fn open_read_only() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let store = KvStore::open(temp_dir.path())?;
+    store.set("key1".to_owned(), "value1".to_owned())?;
+    drop(store);
+    let files = std::fs::read_dir(temp_dir.path())?.count();
+
+    let store = KvStore::open_read_only(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(store.scan("", None, 10)?, vec!["key1"]);
+    assert!(store.set("key2".to_owned(), "value2".to_owned()).is_err());
+    assert!(store.remove("key1".to_owned()).is_err());
+    assert!(store.compaction_threshold().is_none());
+    assert_eq!(std::fs::read_dir(temp_dir.path())?.count(), files);
+    assert!(KvStore::open_read_only(temp_dir.path().join("missing")).is_err());
+    Ok(())
+}
+
 // Insert data until total size of the directory decreases.
 // Test data correctness after compaction.
 #[test]
@@ -0,0 +1,72 @@
+use assert_cmd::prelude::*;
+use kvs::{KvsError, Manifest, Result};
+use predicates::str::contains;
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+// A new data directory gets a manifest that is kept on later opens and refuses other engines.
+#[test]
+fn creates_and_validates_manifest() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let dir = temp_dir.path().join("data");
+    assert_eq!(Manifest::load(&dir)?, None);
+
+    let manifest = Manifest::open(&dir, "kvs")?;
+    assert_eq!(manifest.engine, "kvs");
+    assert_eq!(manifest.format_version, 1);
+    assert_eq!(manifest.store_id.len(), 32);
+    assert_eq!(Manifest::open(&dir, "kvs")?, manifest);
+    assert_eq!(Manifest::load(&dir)?, Some(manifest));
+
+    match Manifest::open(&dir, "sled") {
+        Err(KvsError::WrongEngine(created, requested)) => assert_eq!((created.as_str(), requested.as_str()), ("kvs", "sled")),
+        other => panic!("expected WrongEngine, got {:?}", other),
+    }
+    assert!(Manifest::open(&dir, "bogus").is_err());
+    Ok(())
+}
+
+// A directory with only the old `engine` file is upgraded, and unknown or newer formats are refused.
+#[test]
+fn upgrades_legacy_engine_file() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    fs::write(temp_dir.path().join("engine"), "sled")?;
+    assert_eq!(Manifest::load(temp_dir.path())?.map(|manifest| manifest.format_version), Some(0));
+    assert!(Manifest::open(temp_dir.path(), "kvs").is_err());
+
+    let manifest = Manifest::open(temp_dir.path(), "sled")?;
+    assert_eq!(manifest.format_version, 1);
+    assert!(!temp_dir.path().join("engine").exists());
+    assert_eq!(Manifest::load(temp_dir.path())?, Some(manifest.clone()));
+
+    let newer = Manifest { format_version: 2, ..manifest };
+    fs::write(temp_dir.path().join("MANIFEST"), serde_json::to_vec(&newer)?)?;
+    let err = Manifest::open(temp_dir.path(), "sled").unwrap_err();
+    assert!(err.to_string().contains("newer than the supported version"), "{}", err);
+
+    fs::write(temp_dir.path().join("MANIFEST"), "garbage")?;
+    assert!(Manifest::load(temp_dir.path()).is_err());
+    fs::remove_file(temp_dir.path().join("MANIFEST"))?;
+    fs::write(temp_dir.path().join("engine"), "garbage")?;
+    assert!(Manifest::load(temp_dir.path()).is_err());
+    Ok(())
+}
+
+// `kvs-server --data-dir` stores data outside the working directory and refuses a directory of another engine.
+#[test]
+fn cli_data_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("data");
+    Manifest::open(&data_dir, "sled").unwrap();
+
+    Command::cargo_bin("kvs-server")
+        .unwrap()
+        .args(["--engine", "kvs", "--addr", "127.0.0.1:4142", "--data-dir"])
+        .arg(&data_dir)
+        .current_dir(&temp_dir)
+        .assert()
+        .failure()
+        .stderr(contains("Data directory was created by the sled engine"));
+    assert!(!temp_dir.path().join("MANIFEST").exists());
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::start_server;
+use kvs::{KvsClient, Result, ServerConfig};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+// Sends one request and returns the raw response, the server closes the connection afterwards.
+fn scrape(addr: SocketAddr, method: &str, path: &str) -> Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n", method, path)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+fn metric(body: &str, name: &str) -> Option<f64> {
+    body.lines()
+        .find(|line| line.split(' ').next() == Some(name))
+        .and_then(|line| line.rsplit(' ').next())
+        .and_then(|value| value.parse().ok())
+}
+
+// Commands, bytes, compactions and pool activity all show up in the scrape.
+#[test]
+fn metrics_scrape() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4080".parse().unwrap();
+    let metrics_addr: SocketAddr = "127.0.0.1:4081".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { metrics_addr: Some(metrics_addr), ..ServerConfig::default() })?;
+    let mut client = KvsClient::connect(addr)?;
+    for i in 0..20 {
+        client.set(format!("key{}", i % 5), format!("value{}", i))?;
+    }
+    assert_eq!(client.get("key1".to_owned())?, Some("value16".to_owned()));
+    client.compact()?;
+
+    let response = scrape(metrics_addr, "GET", "/metrics")?;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+    let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+
+    assert!(metric(body, "kvs_commands_total{protocol=\"native\",command=\"set\"}").unwrap() >= 20.0);
+    assert!(metric(body, "kvs_commands_total{protocol=\"native\",command=\"get\"}").unwrap() >= 1.0);
+    assert!(body.contains("kvs_command_duration_seconds_bucket{protocol=\"native\",command=\"set\",le=\"+Inf\"}"));
+    assert!(body.contains("# TYPE kvs_command_duration_seconds histogram"));
+    assert!(metric(body, "kvs_network_read_bytes_total").unwrap() > 0.0);
+    assert!(metric(body, "kvs_network_written_bytes_total").unwrap() > 0.0);
+    assert!(metric(body, "kvs_engine_read_bytes_total").unwrap() > 0.0);
+    assert!(metric(body, "kvs_engine_written_bytes_total").unwrap() > 0.0);
+    assert!(metric(body, "kvs_compactions_total").unwrap() >= 1.0);
+    assert!(metric(body, "kvs_compaction_duration_seconds_count").unwrap() >= 1.0);
+    assert_eq!(metric(body, "kvs_engine_keys"), Some(5.0));
+    assert_eq!(metric(body, "kvs_engine_dead_bytes_ratio"), Some(0.0));
+    assert!(metric(body, "kvs_connections_open").unwrap() >= 1.0);
+    assert!(metric(body, "kvs_thread_pool_queue_depth").is_some());
+    assert!(metric(body, "kvs_thread_pool_jobs_total").unwrap() >= 20.0);
+    Ok(())
+}
+
+// The metrics port serves nothing but GET /metrics.
+#[test]
+fn metrics_other_routes() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4082".parse().unwrap();
+    let metrics_addr: SocketAddr = "127.0.0.1:4083".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { metrics_addr: Some(metrics_addr), ..ServerConfig::default() })?;
+
+    assert!(scrape(metrics_addr, "GET", "/keys/key1")?.starts_with("HTTP/1.1 404"));
+    assert!(scrape(metrics_addr, "POST", "/metrics")?.starts_with("HTTP/1.1 405"));
+    Ok(())
+}
+
+fn read_bytes(metrics_addr: SocketAddr) -> Result<f64> {
+    let response = scrape(metrics_addr, "GET", "/metrics")?;
+    Ok(metric(&response, "kvs_network_read_bytes_total").unwrap())
+}
+
+// A client that pipelines requests without reading the responses stops being read
+// from once the server's buffers are full, and is read from again as it catches up.
+#[test]
+fn pipelined_requests_backpressure() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4170".parse().unwrap();
+    let metrics_addr: SocketAddr = "127.0.0.1:4171".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { metrics_addr: Some(metrics_addr), ..ServerConfig::default() })?;
+    let value = "x".repeat(16 * 1024);
+    KvsClient::connect(addr)?.set("big".to_owned(), value.clone())?;
+
+    let stream = TcpStream::connect(addr)?;
+    let mut writer = stream.try_clone()?;
+    let request = format!("{{\"Get\":{{\"key\":\"big\"}}}}{}", " ".repeat(80));
+    let before = read_bytes(metrics_addr)?;
+    thread::spawn(move || {
+        // Fails once the test closes the connection.
+        for _ in 0..40000 {
+            if writer.write_all(request.as_bytes()).is_err() {
+                break;
+            }
+        }
+    });
+    thread::sleep(Duration::from_secs(1));
+    let paused = read_bytes(metrics_addr)?;
+    assert!(paused - before < 1024.0 * 1024.0, "read {} bytes", paused - before);
+
+    let response = format!("{{\"Ok\":\"{}\"}}", value);
+    let mut responses = vec![0; response.len() * 1000];
+    let mut reader = stream.try_clone()?;
+    reader.set_read_timeout(Some(Duration::from_secs(10)))?;
+    reader.read_exact(&mut responses)?;
+    assert!(responses.chunks(response.len()).all(|resp| resp == response.as_bytes()));
+    thread::sleep(Duration::from_millis(500));
+    assert!(read_bytes(metrics_addr)? > paused);
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use assert_cmd::prelude::*;
+use kvs::{checksum, migrate, KvStore, KvsEngine, Manifest, Result, SledKvsEngine};
+use predicates::prelude::*;
+use predicates::str::contains;
+use std::fs;
+use std::process::Command;
+use tempfile::TempDir;
+
+// All live pairs are copied, and overwritten or removed keys are not.
+#[test]
+fn migrate_kvs_to_sled() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let source = KvStore::open(temp_dir.path().join("kvs"))?;
+    for i in 0..2500 {
+        source.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    source.set("key1".to_owned(), "updated".to_owned())?;
+    source.remove("key2".to_owned())?;
+    source.set("".to_owned(), "empty key".to_owned())?;
+
+    let target = SledKvsEngine::new(sled::open(temp_dir.path().join("sled"))?);
+    let stats = migrate(&source, &target)?;
+    assert_eq!(stats.keys, 2500);
+    assert_eq!(stats, checksum(&source)?);
+    assert_eq!(target.get("key1".to_owned())?, Some("updated".to_owned()));
+    assert_eq!(target.get("key2".to_owned())?, None);
+    assert_eq!(target.get("".to_owned())?, Some("empty key".to_owned()));
+
+    // A target that already holds other data fails verification.
+    let dirty = KvStore::open(temp_dir.path().join("dirty"))?;
+    dirty.set("stray".to_owned(), "value".to_owned())?;
+    let err = migrate(&source, &dirty).unwrap_err();
+    assert!(err.to_string().contains("verification failed"), "{}", err);
+    Ok(())
+}
+
+// `kvs-admin migrate` copies a kvs directory to sled and back, keeping the store id in the new manifests.
+#[test]
+fn cli_migrate_round_trip() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let kvs_dir = temp_dir.path().join("kvs");
+    let manifest = Manifest::open(&kvs_dir, "kvs")?;
+    let source = KvStore::open(&kvs_dir)?;
+    for i in 0..100 {
+        source.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    drop(source);
+
+    let files = fs::read_dir(&kvs_dir)?.count();
+    let sled_dir = temp_dir.path().join("sled");
+    let back_dir = temp_dir.path().join("back");
+    let store = |engine: &str, dir: &std::path::Path| format!("{}:{}", engine, dir.display());
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["migrate", "--from", &store("kvs", &kvs_dir), "--to", &store("sled", &sled_dir)])
+        .assert()
+        .success()
+        .stdout(contains("keys: 100\n").and(contains("checksum: ")));
+    assert_eq!(fs::read_dir(&kvs_dir)?.count(), files);
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["migrate", "--from", &store("sled", &sled_dir), "--to", &store("kvs", &back_dir)])
+        .assert()
+        .success()
+        .stdout(contains("keys: 100\n"));
+
+    let migrated = Manifest::load(&sled_dir)?.unwrap();
+    assert_eq!((migrated.engine.as_str(), migrated.store_id.as_str()), ("sled", manifest.store_id.as_str()));
+    assert_eq!(Manifest::load(&back_dir)?.unwrap().engine, "kvs");
+    let back = KvStore::open(&back_dir)?;
+    assert_eq!(back.get("key42".to_owned())?, Some("value42".to_owned()));
+    assert_eq!(checksum(&back)?, checksum(&KvStore::open(&kvs_dir)?)?);
+    Ok(())
+}
+
+// Migration refuses a source of another engine and a target directory that is not empty.
+#[test]
+fn cli_migrate_refuses_mismatch() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let kvs_dir = temp_dir.path().join("kvs");
+    Manifest::open(&kvs_dir, "kvs")?;
+    let target = temp_dir.path().join("target");
+    fs::create_dir(&target)?;
+    fs::write(target.join("file"), "data")?;
+
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["migrate", "--from", &format!("sled:{}", kvs_dir.display()), "--to"])
+        .arg(format!("kvs:{}", temp_dir.path().join("new").display()))
+        .assert()
+        .failure()
+        .stderr(contains("created by the kvs engine"));
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["migrate", "--from", &format!("kvs:{}", kvs_dir.display()), "--to"])
+        .arg(format!("sled:{}", target.display()))
+        .assert()
+        .failure()
+        .stderr(contains("is not empty"));
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["migrate", "--from", "bogus", "--to", "kvs:x"])
+        .assert()
+        .failure();
+    Ok(())
+}
+
+// The source is only read: an old or newer format is refused instead of upgraded, and nothing is written to it.
+#[test]
+fn cli_migrate_reads_source_only() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let legacy = temp_dir.path().join("legacy");
+    fs::create_dir(&legacy)?;
+    fs::write(legacy.join("engine"), "kvs")?;
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["migrate", "--from", &format!("kvs:{}", legacy.display()), "--to"])
+        .arg(format!("sled:{}", temp_dir.path().join("new").display()))
+        .assert()
+        .failure()
+        .stderr(contains("is older than the supported version"));
+    assert!(!legacy.join("MANIFEST").exists());
+    assert_eq!(fs::read_dir(&legacy)?.count(), 1);
+
+    let newer = temp_dir.path().join("newer");
+    let manifest = Manifest::open(&newer, "kvs")?;
+    fs::write(newer.join("MANIFEST"), serde_json::to_vec(&Manifest { format_version: 99, ..manifest })?)?;
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["export", "--from", &format!("kvs:{}", newer.display())])
+        .assert()
+        .failure()
+        .stderr(contains("is newer than the supported version"));
+    assert!(!temp_dir.path().join("new").exists());
+    Ok(())
+}
@@ -0,0 +1,108 @@
+mod common;
+
+use common::{start_engine, start_server_in};
+use kvs::{
+    KvsClient, KvsClientBuilder, KvsError, ProxyEngine, ProxyRouting, Result, ServerAddr, ServerConfig, ShardedKvsClient
+};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn start_backend(port: u16) -> Result<(ServerAddr, TempDir)> {
+    let temp_dir = TempDir::new()?;
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    start_server_in(addr, ServerConfig::default(), temp_dir.path())?;
+    Ok((addr.into(), temp_dir))
+}
+
+fn start_proxy(port: u16, routing: ProxyRouting) -> Result<KvsClient> {
+    let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+    let builder = KvsClientBuilder::new().connect_timeout(Duration::from_secs(1)).read_timeout(Duration::from_secs(5));
+    start_engine(addr, ProxyEngine::new(routing, builder, 4)?, ServerConfig::default())?;
+    KvsClient::connect(addr)
+}
+
+// Hash routing sends each key to the same backend as the sharded client would.
+#[test]
+fn routes_by_consistent_hash() -> Result<()> {
+    let (a, _a_dir) = start_backend(4120)?;
+    let (b, _b_dir) = start_backend(4121)?;
+    let mut proxy = start_proxy(4122, ProxyRouting::Hash(vec![a.clone(), b.clone()]))?;
+    for i in 0..100 {
+        proxy.set(format!("key{}", i), format!("value{}", i))?;
+    }
+
+    let sharded = ShardedKvsClient::new(vec![a.clone(), b.clone()])?;
+    let mut backends = [KvsClient::connect(&a)?, KvsClient::connect(&b)?];
+    for i in 0..100 {
+        let key = format!("key{}", i);
+        let owner = if sharded.node_for(&key) == &a { 0 } else { 1 };
+        assert_eq!(backends[owner].get(key.clone())?, Some(format!("value{}", i)));
+        assert_eq!(backends[1 - owner].get(key.clone())?, None);
+        assert_eq!(proxy.get(key)?, Some(format!("value{}", i)));
+    }
+
+    proxy.remove("key1".to_owned())?;
+    assert_eq!(proxy.get("key1".to_owned())?, None);
+    assert!(matches!(proxy.remove("key1".to_owned()), Err(KvsError::KeyNotFound)));
+    assert_eq!(proxy.engine_stats()?.keys, 99);
+    Ok(())
+}
+
+// Range routing splits the key space by start key, and scans merge all backends in key order.
+#[test]
+fn routes_by_key_range_and_scans_in_order() -> Result<()> {
+    let (a, _a_dir) = start_backend(4123)?;
+    let (b, _b_dir) = start_backend(4124)?;
+    let routing = ProxyRouting::Ranges(vec![(String::new(), a.clone()), ("m".to_owned(), b.clone())]);
+    let mut proxy = start_proxy(4125, routing)?;
+    let mut expected = Vec::new();
+    for word in ["apple", "kiwi", "lemon", "mango", "melon", "peach", "zebra"] {
+        for i in 0..10 {
+            let key = format!("{}{}", word, i);
+            proxy.set(key.clone(), "value".to_owned())?;
+            expected.push(key);
+        }
+    }
+    expected.sort();
+
+    assert_eq!(KvsClient::connect(&a)?.get("lemon3".to_owned())?, Some("value".to_owned()));
+    assert_eq!(KvsClient::connect(&b)?.get("mango3".to_owned())?, Some("value".to_owned()));
+    assert_eq!(KvsClient::connect(&a)?.get("mango3".to_owned())?, None);
+
+    let mut keys = Vec::new();
+    let mut after = None;
+    loop {
+        let page = proxy.scan(String::new(), after, 15)?;
+        keys.extend(page.keys);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(keys, expected);
+    assert_eq!(proxy.scan("me".to_owned(), None, 100)?.keys.len(), 10);
+
+    let unsorted = ProxyRouting::Ranges(vec![(String::new(), a.clone()), ("m".to_owned(), b), ("c".to_owned(), a)]);
+    assert!(ProxyEngine::new(unsorted, KvsClientBuilder::new(), 1).is_err());
+    Ok(())
+}
+
+// A backend that is down fails only the keys it owns, with an error naming the backend.
+#[test]
+fn reports_unavailable_backend() -> Result<()> {
+    let (a, _a_dir) = start_backend(4126)?;
+    let down: ServerAddr = "127.0.0.1:4127".parse()?;
+    let routing = ProxyRouting::Ranges(vec![(String::new(), a), ("m".to_owned(), down)]);
+    let mut proxy = start_proxy(4128, routing)?;
+
+    proxy.set("apple".to_owned(), "value".to_owned())?;
+    assert_eq!(proxy.get("apple".to_owned())?, Some("value".to_owned()));
+    let err = proxy.set("zebra".to_owned(), "value".to_owned()).unwrap_err();
+    assert!(err.to_string().contains("Backend 127.0.0.1:4127 is unavailable"), "{}", err);
+    assert!(proxy.get("zebra".to_owned()).is_err());
+    assert!(proxy.scan(String::new(), None, 10).is_err());
+    // The proxy keeps serving after backend errors.
+    assert_eq!(proxy.get("apple".to_owned())?, Some("value".to_owned()));
+    Ok(())
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::{start_server, start_server_in};
+use kvs::{KvStore, KvsClient, KvsClientBuilder, KvsEngine, Protocol, Result, ServerConfig};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+fn replica_of(leader: SocketAddr) -> ServerConfig {
+    ServerConfig {
+        replica_of: Some(KvsClientBuilder::new().addr(leader).read_timeout(Duration::from_secs(5))),
+        ..ServerConfig::default()
+    }
+}
+
+// Waits until the follower has applied everything the leader has committed.
+fn wait_for_sync(leader: &mut KvsClient, follower: &mut KvsClient) -> Result<()> {
+    let seq = leader.info()?.replication.seq;
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let replication = follower.info()?.replication;
+        if replication.seq >= seq && replication.lag == Some(0) {
+            return Ok(());
+        }
+        assert!(Instant::now() < deadline, "follower did not catch up: {:?}", replication);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// The follower starts with a full snapshot, replacing its own data, then tails the leader's writes.
+#[test]
+fn follower_syncs_and_tails() -> Result<()> {
+    let leader_addr: SocketAddr = "127.0.0.1:4090".parse().unwrap();
+    let follower_addr: SocketAddr = "127.0.0.1:4091".parse().unwrap();
+    let _leader_dir = start_server(leader_addr, ServerConfig::default())?;
+    let mut leader = KvsClient::connect(leader_addr)?;
+    for i in 0..50 {
+        leader.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    leader.remove("key0".to_owned())?;
+
+    let follower_dir = TempDir::new()?;
+    {
+        let store = KvStore::open(follower_dir.path())?;
+        store.set("stale".to_owned(), "value".to_owned())?;
+        store.set("key1".to_owned(), "old".to_owned())?;
+    }
+    start_server_in(follower_addr, replica_of(leader_addr), follower_dir.path())?;
+    let mut follower = KvsClient::connect(follower_addr)?;
+    wait_for_sync(&mut leader, &mut follower)?;
+
+    assert_eq!(follower.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(follower.get("key49".to_owned())?, Some("value49".to_owned()));
+    assert_eq!(follower.get("key0".to_owned())?, None);
+    assert_eq!(follower.get("stale".to_owned())?, None);
+
+    leader.set("key1".to_owned(), "updated".to_owned())?;
+    leader.remove("key2".to_owned())?;
+    leader.set("new".to_owned(), "value".to_owned())?;
+    wait_for_sync(&mut leader, &mut follower)?;
+    assert_eq!(follower.get("key1".to_owned())?, Some("updated".to_owned()));
+    assert_eq!(follower.get("key2".to_owned())?, None);
+    assert_eq!(follower.get("new".to_owned())?, Some("value".to_owned()));
+
+    let info = follower.info()?.replication;
+    assert_eq!(info.role, "follower");
+    assert_eq!(info.leader, Some(leader_addr.to_string()));
+    assert_eq!(info.link_up, Some(true));
+    assert_eq!(info.replication_id, leader.info()?.replication.replication_id);
+    assert_eq!(leader.info()?.replication.role, "leader");
+    Ok(())
+}
+
+// Followers reject writes until promoted, after which they accept them and stop following.
+#[test]
+fn follower_is_read_only_until_promoted() -> Result<()> {
+    let leader_addr: SocketAddr = "127.0.0.1:4092".parse().unwrap();
+    let follower_addr: SocketAddr = "127.0.0.1:4093".parse().unwrap();
+    let _leader_dir = start_server(leader_addr, ServerConfig::default())?;
+    let config = ServerConfig { protocol: Protocol::Auto, ..replica_of(leader_addr) };
+    let _follower_dir = start_server(follower_addr, config)?;
+    let mut leader = KvsClient::connect(leader_addr)?;
+    let mut follower = KvsClient::connect(follower_addr)?;
+    leader.set("key1".to_owned(), "value1".to_owned())?;
+    wait_for_sync(&mut leader, &mut follower)?;
+
+    let err = follower.set("key1".to_owned(), "value2".to_owned()).unwrap_err();
+    assert!(err.to_string().contains("read-only replica"));
+    assert!(follower.remove("key1".to_owned()).is_err());
+
+    let stream = TcpStream::connect(follower_addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.write_all(b"*3\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$1\r\nx\r\n")?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    assert!(line.starts_with("-READONLY"));
+    assert_eq!(hello_role(&mut reader, &mut writer)?, "replica");
+    writer.write_all(b"*3\r\n$9\r\nREPLICAOF\r\n$2\r\nNO\r\n$3\r\nONE\r\n")?;
+    line.clear();
+    reader.read_line(&mut line)?;
+    assert_eq!(line, "+OK\r\n");
+    assert_eq!(hello_role(&mut reader, &mut writer)?, "master");
+    // Expiration times are not replicated, so a node that took part in replication refuses them.
+    writer.write_all(b"*5\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$1\r\nx\r\n$2\r\nEX\r\n$2\r\n10\r\n")?;
+    line.clear();
+    reader.read_line(&mut line)?;
+    assert!(line.starts_with("-ERR Expiration is not supported in cluster or replicated mode"), "{}", line);
+
+    follower.set("key1".to_owned(), "value2".to_owned())?;
+    leader.set("key1".to_owned(), "value3".to_owned())?;
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(follower.get("key1".to_owned())?, Some("value2".to_owned()));
+    assert_eq!(follower.info()?.replication.role, "leader");
+    assert!(follower.promote().is_err());
+    Ok(())
+}
+
+// Sends a RESP2 `HELLO` and returns the role it reports.
+fn hello_role(reader: &mut BufReader<TcpStream>, writer: &mut TcpStream) -> Result<String> {
+    writer.write_all(b"*1\r\n$5\r\nHELLO\r\n")?;
+    let mut lines: Vec<String> = Vec::new();
+    // The reply is a flat array ending with the empty `modules` array.
+    while lines.last().map(String::as_str) != Some("*0\r\n") {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        lines.push(line);
+    }
+    let at = lines.iter().position(|line| line == "role\r\n").unwrap();
+    Ok(lines[at + 2].trim_end().to_owned())
+}
+
+// A follower that falls behind the leader's backlog resyncs from a fresh snapshot.
+#[test]
+fn follower_resyncs_after_backlog_overflow() -> Result<()> {
+    let leader_addr: SocketAddr = "127.0.0.1:4094".parse().unwrap();
+    let follower_addr: SocketAddr = "127.0.0.1:4095".parse().unwrap();
+    let config = ServerConfig { replication_backlog: 5, ..ServerConfig::default() };
+    let _leader_dir = start_server(leader_addr, config)?;
+    let _follower_dir = start_server(follower_addr, replica_of(leader_addr))?;
+    let mut leader = KvsClient::connect(leader_addr)?;
+    let mut follower = KvsClient::connect(follower_addr)?;
+    for i in 0..200 {
+        leader.set(format!("key{}", i % 20), format!("value{}", i))?;
+    }
+    wait_for_sync(&mut leader, &mut follower)?;
+    for i in 180..200 {
+        assert_eq!(follower.get(format!("key{}", i % 20))?, Some(format!("value{}", i)));
+    }
+    Ok(())
+}
@@ -0,0 +1,272 @@
+mod common;
+
+use common::start_server;
+use kvs::{KvsClient, Protocol, Result, ServerConfig};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+    Null,
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>),
+}
+
+// A minimal RESP client speaking raw frames over TCP.
+struct RespClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RespClient {
+    fn connect(addr: SocketAddr) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        Ok(RespClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    fn send(&mut self, args: &[&str]) -> Result<()> {
+        let mut frame = format!("*{}\r\n", args.len());
+        for arg in args {
+            frame.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        self.writer.write_all(frame.as_bytes())?;
+        Ok(())
+    }
+
+    fn call(&mut self, args: &[&str]) -> Result<Reply> {
+        self.send(args)?;
+        self.read()
+    }
+
+    fn read(&mut self) -> Result<Reply> {
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        let line = line.trim_end();
+        let (kind, rest) = line.split_at(1);
+        Ok(match kind {
+            "+" => Reply::Simple(rest.to_owned()),
+            "-" => Reply::Error(rest.to_owned()),
+            ":" => Reply::Integer(rest.parse().unwrap()),
+            "_" => Reply::Null,
+            "$" if rest == "-1" => Reply::Bulk(None),
+            "$" => {
+                let mut buf = vec![0; rest.parse::<usize>().unwrap() + 2];
+                self.reader.read_exact(&mut buf)?;
+                buf.truncate(buf.len() - 2);
+                Reply::Bulk(Some(String::from_utf8(buf).unwrap()))
+            }
+            "*" => Reply::Array(
+                (0..rest.parse().unwrap())
+                    .map(|_| self.read())
+                    .collect::<Result<_>>()?,
+            ),
+            "%" => Reply::Map(
+                (0..rest.parse().unwrap())
+                    .map(|_| Ok((self.read()?, self.read()?)))
+                    .collect::<Result<_>>()?,
+            ),
+            _ => panic!("unexpected reply {}", line),
+        })
+    }
+}
+
+fn bulk(s: &str) -> Reply {
+    Reply::Bulk(Some(s.to_owned()))
+}
+
+fn ok() -> Reply {
+    Reply::Simple("OK".to_owned())
+}
+
+// Basic commands map onto the engine with Redis reply types.
+#[test]
+fn resp_basic_commands() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4050".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { protocol: Protocol::Resp, ..ServerConfig::default() })?;
+    let mut client = RespClient::connect(addr)?;
+
+    assert_eq!(client.call(&["PING"])?, Reply::Simple("PONG".to_owned()));
+    assert_eq!(client.call(&["ping", "hello"])?, bulk("hello"));
+    assert_eq!(client.call(&["SET", "key1", "value1"])?, ok());
+    assert_eq!(client.call(&["SET", "key2", "value2"])?, ok());
+    assert_eq!(client.call(&["GET", "key1"])?, bulk("value1"));
+    assert_eq!(client.call(&["GET", "missing"])?, Reply::Bulk(None));
+    assert_eq!(
+        client.call(&["EXISTS", "key1", "key2", "missing"])?,
+        Reply::Integer(2)
+    );
+    assert_eq!(
+        client.call(&["DEL", "key1", "missing"])?,
+        Reply::Integer(1)
+    );
+    assert_eq!(client.call(&["GET", "key1"])?, Reply::Bulk(None));
+
+    assert!(matches!(client.call(&["NOSUCH"])?, Reply::Error(e) if e.starts_with("ERR unknown command")));
+    assert!(matches!(client.call(&["GET"])?, Reply::Error(e) if e.starts_with("ERR wrong number")));
+    let info = match client.call(&["INFO", "server"])? {
+        Reply::Bulk(Some(info)) => info,
+        reply => panic!("unexpected INFO reply {:?}", reply),
+    };
+    assert!(info.contains("redis_version:"));
+
+    // Pipelined commands are answered in order, inline commands work too.
+    client.send(&["SET", "key3", "value3"])?;
+    client.send(&["GET", "key3"])?;
+    client.writer.write_all(b"PING\r\n")?;
+    assert_eq!(client.read()?, ok());
+    assert_eq!(client.read()?, bulk("value3"));
+    assert_eq!(client.read()?, Reply::Simple("PONG".to_owned()));
+    Ok(())
+}
+
+// SET honours NX/XX conditions and EX/PX expiry.
+#[test]
+fn resp_set_options() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4051".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { protocol: Protocol::Resp, ..ServerConfig::default() })?;
+    let mut client = RespClient::connect(addr)?;
+
+    assert_eq!(client.call(&["SET", "key", "a", "XX"])?, Reply::Bulk(None));
+    assert_eq!(client.call(&["SET", "key", "a", "NX"])?, ok());
+    assert_eq!(client.call(&["SET", "key", "b", "NX"])?, Reply::Bulk(None));
+    assert_eq!(client.call(&["SET", "key", "c", "XX"])?, ok());
+    assert_eq!(client.call(&["GET", "key"])?, bulk("c"));
+    assert!(matches!(client.call(&["SET", "key", "d", "NX", "XX"])?, Reply::Error(_)));
+    assert!(matches!(client.call(&["SET", "key", "d", "EX", "0"])?, Reply::Error(_)));
+
+    assert_eq!(client.call(&["SET", "temp", "value", "PX", "300"])?, ok());
+    assert_eq!(client.call(&["SET", "gone", "value", "EX", "1"])?, ok());
+    assert_eq!(client.call(&["GET", "temp"])?, bulk("value"));
+    thread::sleep(Duration::from_millis(500));
+    assert_eq!(client.call(&["GET", "temp"])?, Reply::Bulk(None));
+    assert_eq!(client.call(&["EXISTS", "temp"])?, Reply::Integer(0));
+    assert_eq!(client.call(&["SET", "temp", "again", "NX"])?, ok());
+
+    // Keys expire in the background even if never read again.
+    thread::sleep(Duration::from_millis(1000));
+    let scan = client.call(&["SCAN", "0", "MATCH", "gone"])?;
+    assert_eq!(
+        scan,
+        Reply::Array(vec![bulk("0"), Reply::Array(Vec::new())])
+    );
+    Ok(())
+}
+
+// SCAN pages through all keys with a numeric cursor and filters by MATCH.
+#[test]
+fn resp_scan() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4052".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { protocol: Protocol::Resp, ..ServerConfig::default() })?;
+    let mut client = RespClient::connect(addr)?;
+    for i in 0..25 {
+        client.call(&["SET", &format!("user:{:02}", i), "x"])?;
+        client.call(&["SET", &format!("order:{:02}", i), "x"])?;
+    }
+
+    let scan_all = |client: &mut RespClient, pattern: &str| -> Result<Vec<String>> {
+        let mut cursor = "0".to_owned();
+        let mut keys = Vec::new();
+        loop {
+            let reply = client.call(&["SCAN", &cursor, "MATCH", pattern, "COUNT", "7"])?;
+            let (next, page) = match reply {
+                Reply::Array(mut items) if items.len() == 2 => {
+                    let page = items.pop().unwrap();
+                    (items.pop().unwrap(), page)
+                }
+                reply => panic!("unexpected SCAN reply {:?}", reply),
+            };
+            if let Reply::Array(page) = page {
+                for key in page {
+                    if let Reply::Bulk(Some(key)) = key {
+                        keys.push(key);
+                    }
+                }
+            }
+            match next {
+                Reply::Bulk(Some(next)) if next == "0" => return Ok(keys),
+                Reply::Bulk(Some(next)) => cursor = next,
+                reply => panic!("unexpected cursor {:?}", reply),
+            }
+        }
+    };
+
+    assert_eq!(scan_all(&mut client, "user:*")?.len(), 25);
+    assert_eq!(scan_all(&mut client, "*")?.len(), 50);
+    assert_eq!(scan_all(&mut client, "*:1?")?.len(), 20);
+    assert_eq!(
+        scan_all(&mut client, "order:0[1-3]")?,
+        vec!["order:01", "order:02", "order:03"]
+    );
+    assert_eq!(scan_all(&mut client, "order:\\0[^0-8]")?, vec!["order:09"]);
+
+    // Patterns with many stars take linear rather than exponential backtracking.
+    client.call(&["SET", &"a".repeat(64), "x"])?;
+    assert!(scan_all(&mut client, "a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b")?.is_empty());
+
+    // The cursor is the last key scanned, so keys removed before it or added after
+    // the scan started do not shift the remaining keys.
+    let reply = client.call(&["SCAN", "0", "MATCH", "user:*", "COUNT", "10"])?;
+    let cursor = match reply {
+        Reply::Array(items) => match &items[0] {
+            Reply::Bulk(Some(cursor)) => cursor.clone(),
+            reply => panic!("unexpected cursor {:?}", reply),
+        },
+        reply => panic!("unexpected SCAN reply {:?}", reply),
+    };
+    for i in 0..5 {
+        client.call(&["DEL", &format!("user:{:02}", i)])?;
+    }
+    client.call(&["SET", "user:", "x"])?;
+    let reply = client.call(&["SCAN", &cursor, "MATCH", "user:*", "COUNT", "100000"])?;
+    let keys: Vec<Reply> = (10..25).map(|i| bulk(&format!("user:{:02}", i))).collect();
+    assert_eq!(reply, Reply::Array(vec![bulk("0"), Reply::Array(keys)]));
+    assert!(matches!(client.call(&["SCAN", "12"])?, Reply::Error(e) if e == "ERR invalid cursor"));
+    assert!(matches!(client.call(&["SCAN", "abc"])?, Reply::Error(e) if e == "ERR invalid cursor"));
+    Ok(())
+}
+
+// HELLO 3 switches the connection to RESP3 encoding.
+#[test]
+fn resp3_hello() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4053".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { protocol: Protocol::Resp, ..ServerConfig::default() })?;
+    let mut client = RespClient::connect(addr)?;
+
+    let hello = match client.call(&["HELLO", "3"])? {
+        Reply::Map(entries) => entries,
+        reply => panic!("unexpected HELLO reply {:?}", reply),
+    };
+    assert!(hello.contains(&(bulk("proto"), Reply::Integer(3))));
+    assert!(hello.contains(&(bulk("role"), bulk("master"))));
+    assert_eq!(client.call(&["GET", "missing"])?, Reply::Null);
+    assert!(matches!(client.call(&["HELLO", "4"])?, Reply::Error(e) if e.starts_with("NOPROTO")));
+    assert!(matches!(client.call(&["HELLO", "2"])?, Reply::Array(_)));
+    assert_eq!(client.call(&["GET", "missing"])?, Reply::Bulk(None));
+    Ok(())
+}
+
+// In auto mode native and RESP clients share one port and one keyspace.
+#[test]
+fn auto_detect_protocol() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4054".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig { protocol: Protocol::Auto, ..ServerConfig::default() })?;
+
+    let mut native = KvsClient::connect(addr)?;
+    native.set("key".to_owned(), "native".to_owned())?;
+
+    let mut resp = RespClient::connect(addr)?;
+    assert_eq!(resp.call(&["GET", "key"])?, bulk("native"));
+    assert_eq!(resp.call(&["SET", "key", "resp"])?, ok());
+    assert_eq!(native.get("key".to_owned())?, Some("resp".to_owned()));
+    Ok(())
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use common::{start_server, wait_until_listening};
+use kvs::thread_pool::{RayonThreadPool, ThreadPool};
+use kvs::{KvStore, KvsClient, KvsEngine, KvsServer, Result, ServerConfig};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+// Idle connections must not pin pool threads: with a 2-thread pool, a client
+// opened after many idle ones should still be served.
+#[test]
+fn idle_connections_do_not_block_server() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4010".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+
+    let idle: Vec<TcpStream> = (0..64)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect();
+
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key1".to_owned(), "value1".to_owned())?;
+    assert_eq!(client.get("key1".to_owned())?, Some("value1".to_owned()));
+
+    drop(idle);
+    Ok(())
+}
+
+// Requests from many connections are interleaved on the same event loop.
+#[test]
+fn many_clients_interleaved() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4011".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig::default())?;
+
+    let mut clients: Vec<KvsClient> = (0..16)
+        .map(|_| KvsClient::connect(addr).unwrap())
+        .collect();
+    for round in 0..10 {
+        for (i, client) in clients.iter_mut().enumerate() {
+            client.set(format!("key{}", i), format!("value{}", round))?;
+        }
+    }
+    for (i, client) in clients.iter_mut().enumerate() {
+        assert_eq!(client.get(format!("key{}", i))?, Some("value9".to_owned()));
+    }
+    Ok(())
+}
+
+// Shutting down stops the event loop, returns from `run` and leaves the
+// written data on disk.
+#[test]
+fn graceful_shutdown() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let addr: SocketAddr = "127.0.0.1:4012".parse().unwrap();
+    let server = KvsServer::new(KvStore::open(temp_dir.path())?, RayonThreadPool::new(2)?)
+        .with_config(ServerConfig {
+            shutdown_timeout: Duration::from_secs(1),
+            ..ServerConfig::default()
+        });
+    let handle = server.shutdown_handle();
+    let server_thread = thread::spawn(move || server.run(addr));
+    wait_until_listening(addr);
+
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key1".to_owned(), "value1".to_owned())?;
+    // an idle but still open connection must not keep the server alive
+    let _idle = TcpStream::connect(addr)?;
+
+    handle.shutdown();
+    server_thread.join().unwrap()?;
+    assert!(TcpStream::connect(addr).is_err());
+
+    let store = KvStore::open(temp_dir.path())?;
+    assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+    Ok(())
+}
+
+// A shutdown requested before `run` makes it return right away.
+#[test]
+fn shutdown_before_run() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let server = KvsServer::new(KvStore::open(temp_dir.path())?, RayonThreadPool::new(1)?);
+    server.shutdown_handle().shutdown();
+    server.run("127.0.0.1:4013")
+}
+
+// Connections over the limit get a "Server busy" error.
+#[test]
+fn max_connections() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4014".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        max_connections: 2,
+        ..ServerConfig::default()
+    })?;
+    // let the server drop the connection used to wait for it to start
+    thread::sleep(Duration::from_millis(200));
+
+    let mut first = KvsClient::connect(addr)?;
+    let mut second = KvsClient::connect(addr)?;
+    thread::sleep(Duration::from_millis(200));
+    let mut third = KvsClient::connect(addr)?;
+    let err = third.get("key".to_owned()).unwrap_err();
+    assert!(err.to_string().contains("Server busy"));
+
+    first.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(second.get("key".to_owned())?, Some("value".to_owned()));
+
+    // Rejected connections are not counted as open or accepted.
+    let info = first.info()?;
+    assert_eq!(info.connected_clients, 2);
+    assert_eq!(info.total_connections, 3);
+    Ok(())
+}
+
+// Idle connections are closed by the server.
+#[test]
+fn idle_timeout() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4015".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        idle_timeout: Some(Duration::from_millis(500)),
+        ..ServerConfig::default()
+    })?;
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut buf = [0; 16];
+    assert_eq!(stream.read(&mut buf)?, 0);
+    Ok(())
+}
+
+// Incomplete requests are cut off after the request timeout.
+#[test]
+fn request_timeout() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4016".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        request_timeout: Some(Duration::from_millis(500)),
+        ..ServerConfig::default()
+    })?;
+
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.write_all(b"{\"Get\":{\"key\":")?;
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp)?;
+    assert!(resp.contains("Request timed out"));
+    Ok(())
+}
+
+// Requests larger than the limit are rejected.
+#[test]
+fn max_request_size() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4017".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        max_request_size: 1024,
+        ..ServerConfig::default()
+    })?;
+
+    let mut client = KvsClient::connect(addr)?;
+    client.set("key".to_owned(), "small".to_owned())?;
+    let err = client.set("key".to_owned(), "x".repeat(4096)).unwrap_err();
+    assert!(err.to_string().contains("Request too large"));
+
+    let mut client = KvsClient::connect(addr)?;
+    assert_eq!(client.get("key".to_owned())?, Some("small".to_owned()));
+    Ok(())
+}
+
+// Clients exceeding their request rate get an error but stay connected.
+#[test]
+fn rate_limit() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4018".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        rate_limit: Some(5),
+        ..ServerConfig::default()
+    })?;
+
+    let mut client = KvsClient::connect(addr)?;
+    let mut limited = 0;
+    for _ in 0..20 {
+        if let Err(e) = client.get("key".to_owned()) {
+            assert!(e.to_string().contains("Rate limit exceeded"));
+            limited += 1;
+        }
+    }
+    assert!(limited > 0);
+
+    thread::sleep(Duration::from_secs(1));
+    assert_eq!(client.get("key".to_owned())?, None);
+    Ok(())
+}
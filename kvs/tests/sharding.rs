@@ -0,0 +1,121 @@
+mod common;
+
+use common::start_server;
+use kvs::{KvsClient, Result, ServerAddr, ServerConfig, ShardedKvsClient};
+use std::net::SocketAddr;
+use tempfile::TempDir;
+
+fn start_servers(ports: std::ops::Range<u16>) -> Result<(Vec<ServerAddr>, Vec<TempDir>)> {
+    let mut addrs = Vec::new();
+    let mut dirs = Vec::new();
+    for port in ports {
+        let addr: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+        dirs.push(start_server(addr, ServerConfig::default())?);
+        addrs.push(addr.into());
+    }
+    Ok((addrs, dirs))
+}
+
+// Lists every key stored on a single node.
+fn node_keys(addr: &ServerAddr) -> Result<Vec<String>> {
+    let mut client = KvsClient::connect(addr)?;
+    let mut keys = Vec::new();
+    let mut after = None;
+    loop {
+        let page = client.scan(String::new(), after, 100)?;
+        keys.extend(page.keys);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => return Ok(keys),
+        }
+    }
+}
+
+// Keys are spread over all nodes and each key lives only on the node the ring assigns it to.
+#[test]
+fn routes_keys_by_consistent_hash() -> Result<()> {
+    let (addrs, _dirs) = start_servers(4110..4113)?;
+    let mut client = ShardedKvsClient::new(addrs.clone())?;
+    for i in 0..300 {
+        client.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    for i in 0..300 {
+        assert_eq!(client.get(format!("key{}", i))?, Some(format!("value{}", i)));
+    }
+
+    let mut total = 0;
+    for addr in &addrs {
+        let keys = node_keys(addr)?;
+        assert!(keys.len() > 30, "node {} only has {} keys", addr, keys.len());
+        assert!(keys.iter().all(|key| client.node_for(key) == addr));
+        total += keys.len();
+    }
+    assert_eq!(total, 300);
+
+    // The ring only depends on the node addresses, not on their order.
+    let reversed = ShardedKvsClient::new(addrs.iter().rev().cloned().collect())?;
+    assert!((0..300).all(|i| reversed.node_for(&format!("key{}", i)) == client.node_for(&format!("key{}", i))));
+
+    client.remove("key1".to_owned())?;
+    assert_eq!(client.get("key1".to_owned())?, None);
+    assert!(ShardedKvsClient::new(vec![addrs[0].clone(), addrs[0].clone()]).is_err());
+    Ok(())
+}
+
+// Scans fan out to every node and page through the merged keys in order.
+#[test]
+fn scan_merges_nodes_in_key_order() -> Result<()> {
+    let (addrs, _dirs) = start_servers(4113..4116)?;
+    let mut client = ShardedKvsClient::new(addrs)?;
+    let mut expected = Vec::new();
+    for i in 0..250 {
+        let key = format!("user:{:04}", i * 7 % 250);
+        client.set(key.clone(), "value".to_owned())?;
+        expected.push(key);
+        client.set(format!("order:{}", i), "value".to_owned())?;
+    }
+    expected.sort();
+
+    let mut keys = Vec::new();
+    let mut after = None;
+    loop {
+        let page = client.scan("user:".to_owned(), after, 40)?;
+        assert!(page.keys.len() <= 40);
+        keys.extend(page.keys);
+        match page.next {
+            Some(next) => after = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(keys, expected);
+    Ok(())
+}
+
+// Adding a node moves only the keys it now owns; removing it moves them back.
+#[test]
+fn rebalance_after_adding_and_removing_nodes() -> Result<()> {
+    let (addrs, _dirs) = start_servers(4116..4120)?;
+    let (old_nodes, new_node) = (addrs[..3].to_vec(), addrs[3].clone());
+    let mut client = ShardedKvsClient::new(old_nodes.clone())?;
+    for i in 0..400 {
+        client.set(format!("key{}", i), format!("value{}", i))?;
+    }
+
+    let mut grown = ShardedKvsClient::new(addrs.clone())?;
+    let owned = (0..400).filter(|i| grown.node_for(&format!("key{}", i)) == &new_node).count();
+    assert!(owned > 40 && owned < 200, "new node owns {} keys", owned);
+    assert_eq!(grown.rebalance(Vec::new())?, owned);
+    assert_eq!(node_keys(&new_node)?.len(), owned);
+    for i in 0..400 {
+        assert_eq!(grown.get(format!("key{}", i))?, Some(format!("value{}", i)));
+    }
+    assert_eq!(grown.rebalance(Vec::new())?, 0);
+
+    let mut shrunk = ShardedKvsClient::new(old_nodes)?;
+    assert_eq!(shrunk.rebalance(vec![new_node.clone()])?, owned);
+    assert!(node_keys(&new_node)?.is_empty());
+    for i in 0..400 {
+        assert_eq!(shrunk.get(format!("key{}", i))?, Some(format!("value{}", i)));
+    }
+    Ok(())
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::start_server;
+use kvs::{KvsClient, Protocol, Result, ServerConfig};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// With a zero threshold every request is recorded, newest first, noting compactions and fsyncs.
+#[test]
+fn slowlog_records_requests() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4088".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        protocol: Protocol::Auto,
+        slowlog_threshold: Some(Duration::ZERO),
+        ..ServerConfig::default()
+    })?;
+    let mut client = KvsClient::connect(addr)?;
+
+    client.set("key1".to_owned(), "value1".to_owned())?;
+    client.config_set("compaction-threshold".to_owned(), "1".to_owned())?;
+    client.set("key1".to_owned(), "value2".to_owned())?;
+    client.flush()?;
+
+    let entries = client.slowlog(None)?;
+    assert_eq!(entries[0].command, "flush");
+    assert!(entries[0].synced);
+    assert_eq!(entries[1].command, "set");
+    assert_eq!(entries[1].key.as_deref(), Some("key1"));
+    assert_eq!(entries[1].protocol, "native");
+    assert!(entries[1].compacted);
+    assert!(entries[1].client.starts_with("127.0.0.1:"));
+    assert!(!entries[3].compacted);
+    assert!(entries[0].id > entries[1].id);
+    assert_eq!(client.slowlog(Some(2))?.len(), 2);
+
+    client.slowlog_reset()?;
+    let entries = client.slowlog(None)?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].command, "slowlog_reset");
+    Ok(())
+}
+
+// The threshold and capacity can be changed at runtime, also over RESP.
+#[test]
+fn slowlog_runtime_config() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4089".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        protocol: Protocol::Auto,
+        slowlog_threshold: None,
+        ..ServerConfig::default()
+    })?;
+    let mut client = KvsClient::connect(addr)?;
+
+    client.set("key1".to_owned(), "value1".to_owned())?;
+    assert!(client.slowlog(None)?.is_empty());
+    assert_eq!(client.config_get("slowlog-threshold".to_owned())?, "off");
+
+    client.config_set("slowlog-max-len".to_owned(), "2".to_owned())?;
+    client.config_set("slowlog-threshold".to_owned(), "0".to_owned())?;
+    for i in 0..5 {
+        client.set(format!("key{}", i), "value".to_owned())?;
+    }
+    let entries = client.slowlog(Some(10))?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].key.as_deref(), Some("key4"));
+    assert!(client.config_set("slowlog-threshold".to_owned(), "soon".to_owned()).is_err());
+
+    let stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    writer.write_all(b"*2\r\n$7\r\nSLOWLOG\r\n$3\r\nLEN\r\n")?;
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    assert_eq!(line, ":2\r\n");
+    writer.write_all(b"*3\r\n$7\r\nSLOWLOG\r\n$3\r\nGET\r\n$1\r\n1\r\n")?;
+    line.clear();
+    reader.read_line(&mut line)?;
+    assert_eq!(line, "*1\r\n");
+    line.clear();
+    reader.read_line(&mut line)?;
+    assert_eq!(line, "*6\r\n");
+    Ok(())
+}
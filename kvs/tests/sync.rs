@@ -0,0 +1,92 @@
+mod common;
+
+use common::start_server;
+use assert_cmd::prelude::*;
+use kvs::{KvsClient, Result, ServerConfig, SyncStats};
+use predicates::prelude::*;
+use predicates::str::contains;
+use std::net::SocketAddr;
+use std::process::Command;
+use tempfile::TempDir;
+
+fn start_pair(source_port: u16, target_port: u16) -> Result<(KvsClient, KvsClient, Vec<TempDir>)> {
+    let source_addr: SocketAddr = format!("127.0.0.1:{}", source_port).parse().unwrap();
+    let target_addr: SocketAddr = format!("127.0.0.1:{}", target_port).parse().unwrap();
+    let dirs = vec![
+        start_server(source_addr, ServerConfig::default())?,
+        start_server(target_addr, ServerConfig::default())?,
+    ];
+    Ok((KvsClient::connect(source_addr)?, KvsClient::connect(target_addr)?, dirs))
+}
+
+// Identical stores produce identical trees and nothing is transferred.
+#[test]
+fn identical_stores_transfer_nothing() -> Result<()> {
+    let (mut source, mut target, _dirs) = start_pair(4130, 4131)?;
+    for i in 0..500 {
+        source.set(format!("key{}", i), format!("value{}", i))?;
+        target.set(format!("key{}", i), format!("value{}", i))?;
+    }
+    assert_eq!(source.merkle_children(String::new())?, target.merkle_children(String::new())?);
+
+    let stats = source.sync_to(&mut target)?;
+    assert_eq!(stats.transferred_ranges, 0);
+    assert_eq!(stats.updated_keys, 0);
+    assert_eq!(stats.removed_keys, 0);
+    Ok(())
+}
+
+// Changed, missing and extra keys are repaired by transferring only the ranges that differ.
+#[test]
+fn repairs_drifted_store() -> Result<()> {
+    let (mut source, mut target, _dirs) = start_pair(4132, 4133)?;
+    for i in 0..2000 {
+        source.set(format!("user:{:04}", i), format!("value{}", i))?;
+        target.set(format!("user:{:04}", i), format!("value{}", i))?;
+    }
+    for key in ["", "u", "us", "user:", "é", "éa"] {
+        source.set(key.to_owned(), "edge".to_owned())?;
+        target.set(key.to_owned(), "edge".to_owned())?;
+    }
+    target.set("user:0007".to_owned(), "stale".to_owned())?;
+    target.set("user:1500".to_owned(), "stale".to_owned())?;
+    target.set("us".to_owned(), "stale".to_owned())?;
+    target.remove("user:0999".to_owned())?;
+    target.remove("é".to_owned())?;
+    target.set("user:9999".to_owned(), "extra".to_owned())?;
+    target.set("zzz".to_owned(), "extra".to_owned())?;
+
+    let stats = source.sync_to(&mut target)?;
+    assert_eq!(stats.updated_keys, 5);
+    assert_eq!(stats.removed_keys, 2);
+    assert!(stats.transferred_ranges <= 10, "{:?}", stats);
+
+    assert_eq!(target.get("user:0007".to_owned())?, Some("value7".to_owned()));
+    assert_eq!(target.get("user:0999".to_owned())?, Some("value999".to_owned()));
+    assert_eq!(target.get("us".to_owned())?, Some("edge".to_owned()));
+    assert_eq!(target.get("é".to_owned())?, Some("edge".to_owned()));
+    assert_eq!(target.get("user:9999".to_owned())?, None);
+    assert_eq!(target.get("zzz".to_owned())?, None);
+    assert_eq!(source.merkle_children(String::new())?, target.merkle_children(String::new())?);
+    assert_eq!(source.sync_to(&mut target)?, SyncStats { compared_nodes: 3, ..SyncStats::default() });
+    Ok(())
+}
+
+// `kvs-admin sync` runs the same comparison between two servers.
+#[test]
+fn cli_admin_sync() -> Result<()> {
+    let (mut source, mut target, _dirs) = start_pair(4134, 4135)?;
+    source.set("key1".to_owned(), "value1".to_owned())?;
+    source.set("key2".to_owned(), "value2".to_owned())?;
+    target.set("key3".to_owned(), "value3".to_owned())?;
+
+    Command::cargo_bin("kvs-admin")
+        .unwrap()
+        .args(["sync", "--from", "127.0.0.1:4134", "--to", "127.0.0.1:4135"])
+        .assert()
+        .success()
+        .stdout(contains("updated_keys: 2\n").and(contains("removed_keys: 1\n")));
+    assert_eq!(target.get("key2".to_owned())?, Some("value2".to_owned()));
+    assert_eq!(target.get("key3".to_owned())?, None);
+    Ok(())
+}
@@ -67,4 +67,24 @@ fn rayon_thread_pool_spawn_counter() -> Result<()> {
 #[test]
 fn shared_queue_thread_pool_panic_task() -> Result<()> {
     spawn_panic_task::<SharedQueueThreadPool>()
-}
\ No newline at end of file
+}
+
+#[test]
+fn boxed_thread_pool_spawn_counter() -> Result<()> {
+    for name in PoolKind::NAMES {
+        let kind: PoolKind = name.parse()?;
+        assert_eq!(kind.to_string(), name);
+        spawn_counter(BoxedThreadPool::with_kind(kind, 4)?)?;
+    }
+    assert_eq!("shared-queue".parse::<PoolKind>()?, PoolKind::SharedQueue);
+    assert!("shared".parse::<PoolKind>().is_err());
+    Ok(())
+}
+
+#[test]
+fn thread_pool_without_threads() {
+    assert!(SharedQueueThreadPool::new(0).is_err());
+    for name in PoolKind::NAMES {
+        assert!(BoxedThreadPool::with_kind(name.parse().unwrap(), 0).is_err());
+    }
+}
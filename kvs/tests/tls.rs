@@ -0,0 +1,173 @@
+mod common;
+
+use common::start_server;
+use kvs::{
+    KvsClientBuilder, Result, ServerConfig, TlsClientConfig, TlsServerConfig,
+};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+struct Ca {
+    cert: Certificate,
+    key: KeyPair,
+}
+
+// Generates a self-signed CA and writes it to `dir/name.pem`.
+fn generate_ca(dir: &Path, name: &str) -> (Ca, PathBuf) {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::new()).unwrap();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let cert = params.self_signed(&key).unwrap();
+    let path = dir.join(format!("{}.pem", name));
+    fs::write(&path, cert.pem()).unwrap();
+    (Ca { cert, key }, path)
+}
+
+// Issues a certificate signed by `ca` and writes it to `dir/name.pem` and `dir/name.key`.
+fn issue(
+    ca: &Ca,
+    dir: &Path,
+    name: &str,
+    sans: &[&str],
+    usage: ExtendedKeyUsagePurpose,
+) -> (PathBuf, PathBuf) {
+    let key = KeyPair::generate().unwrap();
+    let mut params =
+        CertificateParams::new(sans.iter().map(|s| s.to_string()).collect::<Vec<_>>()).unwrap();
+    params.distinguished_name.push(DnType::CommonName, name);
+    params.extended_key_usages = vec![usage];
+    let cert = params.signed_by(&key, &ca.cert, &ca.key).unwrap();
+    let cert_path = dir.join(format!("{}.pem", name));
+    let key_path = dir.join(format!("{}.key", name));
+    fs::write(&cert_path, cert.pem()).unwrap();
+    fs::write(&key_path, key.serialize_pem()).unwrap();
+    (cert_path, key_path)
+}
+
+fn issue_server(ca: &Ca, dir: &Path) -> (PathBuf, PathBuf) {
+    issue(
+        ca,
+        dir,
+        "server",
+        &["localhost", "127.0.0.1"],
+        ExtendedKeyUsagePurpose::ServerAuth,
+    )
+}
+
+// Requests round-trip over TLS, verified against the pinned CA by IP and by name.
+#[test]
+fn tls_round_trip() -> Result<()> {
+    let certs = TempDir::new().unwrap();
+    let (ca, ca_path) = generate_ca(certs.path(), "ca");
+    let (cert, key) = issue_server(&ca, certs.path());
+    let addr: SocketAddr = "127.0.0.1:4030".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        tls: Some(TlsServerConfig::from_pem_files(cert, key, None)?),
+        ..ServerConfig::default()
+    })?;
+
+    let mut client = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(TlsClientConfig::from_pem_file(&ca_path, "127.0.0.1")?)
+        .build()?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+
+    let mut client = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(TlsClientConfig::from_pem_file(&ca_path, "localhost")?)
+        .build()?;
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+    client.remove("key".to_owned())?;
+    Ok(())
+}
+
+// A server certificate from another CA, a wrong server name or a plain client all fail.
+#[test]
+fn tls_rejects_untrusted_server() -> Result<()> {
+    let certs = TempDir::new().unwrap();
+    let (ca, _) = generate_ca(certs.path(), "ca");
+    let (_, other_ca_path) = generate_ca(certs.path(), "other-ca");
+    let (cert, key) = issue_server(&ca, certs.path());
+    let addr: SocketAddr = "127.0.0.1:4031".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        tls: Some(TlsServerConfig::from_pem_files(cert, key, None)?),
+        ..ServerConfig::default()
+    })?;
+
+    let client = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(TlsClientConfig::from_pem_file(&other_ca_path, "127.0.0.1")?)
+        .build();
+    assert!(client.is_err());
+
+    let client = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(TlsClientConfig::from_pem_file(certs.path().join("ca.pem"), "example.com")?)
+        .build();
+    assert!(client.is_err());
+
+    let mut client = KvsClientBuilder::new().addr(addr).build()?;
+    assert!(client.get("key".to_owned()).is_err());
+    Ok(())
+}
+
+// With a client CA configured the server only accepts clients holding a certificate from it.
+#[test]
+fn mutual_tls() -> Result<()> {
+    let certs = TempDir::new().unwrap();
+    let (ca, ca_path) = generate_ca(certs.path(), "ca");
+    let (client_ca, client_ca_path) = generate_ca(certs.path(), "client-ca");
+    let (cert, key) = issue_server(&ca, certs.path());
+    let (alice_cert, alice_key) = issue(
+        &client_ca,
+        certs.path(),
+        "alice",
+        &[],
+        ExtendedKeyUsagePurpose::ClientAuth,
+    );
+    let (mallory_cert, mallory_key) = issue(
+        &ca,
+        certs.path(),
+        "mallory",
+        &[],
+        ExtendedKeyUsagePurpose::ClientAuth,
+    );
+    let addr: SocketAddr = "127.0.0.1:4032".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        tls: Some(TlsServerConfig::from_pem_files(cert, key, Some(&client_ca_path))?),
+        ..ServerConfig::default()
+    })?;
+
+    let tls = TlsClientConfig::from_pem_file(&ca_path, "localhost")?;
+    let mut client = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(tls.clone().with_client_cert(alice_cert, alice_key)?)
+        .build()?;
+    client.set("key".to_owned(), "value".to_owned())?;
+    assert_eq!(client.get("key".to_owned())?, Some("value".to_owned()));
+
+    // With TLS 1.3 the server checks the client certificate after the handshake,
+    // so the rejection surfaces on the first request.
+    let anonymous = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(tls.clone())
+        .build()
+        .and_then(|mut client| client.get("key".to_owned()));
+    assert!(anonymous.is_err());
+
+    let untrusted = KvsClientBuilder::new()
+        .addr(addr)
+        .tls(tls.with_client_cert(mallory_cert, mallory_key)?)
+        .build()
+        .and_then(|mut client| client.get("key".to_owned()));
+    assert!(untrusted.is_err());
+    Ok(())
+}
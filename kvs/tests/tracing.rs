@@ -0,0 +1,64 @@
+mod common;
+
+use common::start_server;
+use kvs::{KvsClient, Result, ServerConfig};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// Sends one HTTP request and returns the raw response.
+fn http_get(addr: SocketAddr, path: &str, request_id: Option<&str>) -> Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let header = request_id.map(|id| format!("X-Request-Id: {}\r\n", id)).unwrap_or_default();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n{}Connection: close\r\n\r\n", path, header)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+// The id given for a request comes back with its response and applies to that request only.
+#[test]
+fn native_request_id_echoed() -> Result<()> {
+    let addr: SocketAddr = "127.0.0.1:4084".parse().unwrap();
+    let _dir = start_server(addr, ServerConfig {
+        http_addr: Some("127.0.0.1:4085".parse().unwrap()),
+        ..ServerConfig::default()
+    })?;
+    let mut client = KvsClient::connect(addr)?;
+
+    client.with_request_id("set-1").set("key1".to_owned(), "value1".to_owned())?;
+    assert_eq!(client.last_request_id(), Some("set-1"));
+    assert_eq!(client.with_request_id("get-1").get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(client.last_request_id(), Some("get-1"));
+    assert_eq!(client.get("key1".to_owned())?, Some("value1".to_owned()));
+    assert_eq!(client.last_request_id(), None);
+
+    // Errors are still reported for tagged requests.
+    assert!(client.with_request_id("rm-1").remove("missing".to_owned()).is_err());
+    assert_eq!(client.last_request_id(), Some("rm-1"));
+    Ok(())
+}
+
+// HTTP responses carry the X-Request-Id of the request, or one generated by the server.
+#[test]
+fn http_request_id_header() -> Result<()> {
+    let http_addr: SocketAddr = "127.0.0.1:4087".parse().unwrap();
+    let _dir = start_server("127.0.0.1:4086".parse().unwrap(), ServerConfig {
+        http_addr: Some(http_addr),
+        ..ServerConfig::default()
+    })?;
+
+    let response = http_get(http_addr, "/admin/ping", Some("ping-1"))?;
+    assert!(response.starts_with("HTTP/1.1 200"));
+    assert!(response.contains("X-Request-Id: ping-1\r\n"));
+
+    let response = http_get(http_addr, "/keys/missing", None)?;
+    assert!(response.starts_with("HTTP/1.1 404"));
+    let id = response
+        .lines()
+        .find_map(|line| line.strip_prefix("X-Request-Id: "))
+        .expect("no generated request id");
+    assert!(id.ends_with("-1"));
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use kvs::thread_pool::{RayonThreadPool, ThreadPool};
+use kvs::{KvStore, KvsClient, KvsClientBuilder, KvsServer, Result, ServerAddr, ServerConfig};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn server(dir: &Path, mode: Option<u32>) -> Result<KvsServer<KvStore, RayonThreadPool>> {
+    Ok(KvsServer::new(KvStore::open(dir)?, RayonThreadPool::new(2)?).with_config(ServerConfig {
+        unix_socket_mode: mode,
+        ..ServerConfig::default()
+    }))
+}
+
+// Clients reach the server through a socket file created with the given mode,
+// which is removed again on shutdown.
+#[test]
+fn unix_socket_round_trip() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path().join("kvs.sock");
+    let server = server(temp_dir.path(), Some(0o600))?;
+    let handle = server.shutdown_handle();
+    let addr = format!("unix:{}", path.display());
+    let server_thread = thread::spawn(move || server.run(addr));
+    thread::sleep(Duration::from_secs(1));
+
+    let mode = fs::metadata(&path)?.permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let mut client = KvsClient::connect(format!("unix:{}", path.display()))?;
+    client.set("key1".to_owned(), "value1".to_owned())?;
+    assert_eq!(client.get("key1".to_owned())?, Some("value1".to_owned()));
+    client.remove("key1".to_owned())?;
+    assert_eq!(client.get("key1".to_owned())?, None);
+
+    let mut builder_client = KvsClientBuilder::new()
+        .addr(ServerAddr::Unix(path.clone()))
+        .read_timeout(Duration::from_secs(1))
+        .build()?;
+    builder_client.ping()?;
+
+    handle.shutdown();
+    server_thread.join().unwrap()?;
+    assert!(!path.exists());
+    Ok(())
+}
+
+// A socket file left behind by a crashed server is replaced, one still in use is not.
+#[test]
+fn unix_socket_stale_file() -> Result<()> {
+    let temp_dir = TempDir::new().expect("unable to create temporary working directory");
+    let path = temp_dir.path().join("kvs.sock");
+    drop(UnixListener::bind(&path)?);
+    assert!(path.exists());
+
+    let first = server(temp_dir.path(), None)?;
+    let addr = ServerAddr::Unix(path.clone());
+    thread::spawn(move || first.run(addr).unwrap());
+    thread::sleep(Duration::from_secs(1));
+    KvsClient::connect(ServerAddr::Unix(path.clone()))?.ping()?;
+
+    let other_dir = TempDir::new().expect("unable to create temporary working directory");
+    let second = server(other_dir.path(), None)?;
+    assert!(second.run(ServerAddr::Unix(path.clone())).is_err());
+    KvsClient::connect(ServerAddr::Unix(path))?.ping()?;
+    Ok(())
+}
+
+#[test]
+fn parse_server_addr() {
+    assert_eq!(
+        "unix:/run/kvs.sock".parse::<ServerAddr>().unwrap(),
+        ServerAddr::Unix("/run/kvs.sock".into())
+    );
+    assert_eq!(
+        "127.0.0.1:4000".parse::<ServerAddr>().unwrap(),
+        ServerAddr::Tcp("127.0.0.1:4000".parse().unwrap())
+    );
+    assert!("unix:".parse::<ServerAddr>().is_err());
+    assert_eq!(ServerAddr::Unix("/tmp/a.sock".into()).to_string(), "unix:/tmp/a.sock");
+}